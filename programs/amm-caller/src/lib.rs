@@ -0,0 +1,117 @@
+#![allow(unexpected_cfgs)]
+//! Minimal downstream program composing with the amm: it swaps by CPI with the
+//! typed accounts from `amm::composable` and passes the output amount back up.
+
+use amm::{
+    composable::{swap, Swap},
+    program::Amm,
+};
+use anchor_lang::prelude::*;
+
+declare_id!("CtXdD4hsGHmx9LJecAdY2bP8MxgDJVAfNRukntCR6UUq");
+
+#[program]
+pub mod amm_caller {
+    use super::*;
+
+    /// Sells `amount_in` through the amm and returns what it paid out.
+    pub fn swap_through_amm(
+        ctx: Context<SwapThroughAmm>,
+        x_to_y: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<u64> {
+        let amount_out = swap(
+            ctx.accounts.swap_context(),
+            x_to_y,
+            amount_in,
+            min_amount_out,
+            0,
+            0,
+            0,
+        )?
+        .get();
+        msg!("amount_out: {}", amount_out);
+        Ok(amount_out)
+    }
+}
+
+/// The amm's swap accounts, all checked by the amm itself.
+#[derive(Accounts)]
+pub struct SwapThroughAmm<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: checked by the amm
+    pub mint_x: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    pub mint_y: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    pub mint_lp: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    #[account(mut)]
+    pub config: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    pub protocol: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    pub vault_authority: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    #[account(mut)]
+    pub vault_x: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    #[account(mut)]
+    pub vault_y: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    #[account(mut)]
+    pub fee_vault_x: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    #[account(mut)]
+    pub fee_vault_y: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    #[account(mut)]
+    pub user_x: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    #[account(mut)]
+    pub user_y: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    pub token_program_x: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    pub token_program_y: UncheckedAccount<'info>,
+    /// CHECK: checked by the amm
+    pub associated_token_program: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: checked by the amm
+    pub event_authority: UncheckedAccount<'info>,
+    pub amm_program: Program<'info, Amm>,
+}
+
+impl<'info> SwapThroughAmm<'info> {
+    fn swap_context(&self) -> CpiContext<'_, '_, '_, 'info, Swap<'info>> {
+        let accounts = Swap {
+            user: self.user.to_account_info(),
+            mint_x: self.mint_x.to_account_info(),
+            mint_y: self.mint_y.to_account_info(),
+            mint_lp: self.mint_lp.to_account_info(),
+            config: self.config.to_account_info(),
+            protocol: self.protocol.to_account_info(),
+            vault_authority: self.vault_authority.to_account_info(),
+            vault_x: self.vault_x.to_account_info(),
+            vault_y: self.vault_y.to_account_info(),
+            fee_vault_x: self.fee_vault_x.to_account_info(),
+            fee_vault_y: self.fee_vault_y.to_account_info(),
+            user_x: self.user_x.to_account_info(),
+            user_y: self.user_y.to_account_info(),
+            referral: None,
+            recipient: None,
+            source: None,
+            allowlist: None,
+            fee_exemptions: None,
+            token_program_x: self.token_program_x.to_account_info(),
+            token_program_y: self.token_program_y.to_account_info(),
+            associated_token_program: self.associated_token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            event_authority: self.event_authority.to_account_info(),
+            program: self.amm_program.to_account_info(),
+        };
+        CpiContext::new(self.amm_program.to_account_info(), accounts)
+    }
+}
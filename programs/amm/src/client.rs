@@ -0,0 +1,253 @@
+//! Quotes for bots and UIs, built off-chain from the same [`crate::math`] the
+//! handlers price with so they cannot drift from what a transaction does. They
+//! assume a constant product pool with the fee on the input, and leave out transfer
+//! fees of the mints, which the `quote` instruction accounts for.
+//!
+//! Use the lib target with the `no-entrypoint` feature to link it into a client.
+
+use crate::{
+    constants::MINIMUM_LIQUIDITY,
+    math::{fee_amount, get_amount_out, mul_div_ceil, mul_div_floor},
+    utils::sqrt,
+};
+
+/// What `swap` pays out for an input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quote {
+    pub amount_out: u64,
+    /// Taken off the input, protocol share included.
+    pub fee: u64,
+    /// Share of the input reserve the priced input adds, rounded up, so it is within
+    /// a `max_price_impact_bps` exactly when the swap passes that limit.
+    pub price_impact_bps: u16,
+}
+
+/// What `deposit_amounts` takes from each side and mints for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepositQuote {
+    pub lp: u64,
+    pub amount_x: u64,
+    pub amount_y: u64,
+}
+
+/// What `withdraw` pays out of each reserve for burning LP.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WithdrawQuote {
+    pub amount_x: u64,
+    pub amount_y: u64,
+}
+
+/// Quote for selling `amount_in`, `None` where the swap would fail: an empty pool, an
+/// out of range fee, an overflow or nothing paid out.
+pub fn quote_swap(
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_bps: u16,
+    amount_in: u64,
+) -> Option<Quote> {
+    let amount_out = get_amount_out(reserve_in, reserve_out, amount_in, fee_bps)?;
+    if amount_out == 0 {
+        return None;
+    }
+    let fee = fee_amount(amount_in, fee_bps)?;
+    let impact = ((amount_in - fee) as u128 * 10_000).div_ceil(reserve_in as u128);
+    Some(Quote {
+        amount_out,
+        fee,
+        price_impact_bps: impact.try_into().unwrap_or(u16::MAX),
+    })
+}
+
+/// Quote for depositing at most `amount_x` and `amount_y`, `None` where the deposit
+/// would fail. The first deposit takes both amounts in full and locks
+/// MINIMUM_LIQUIDITY of the LP it prices.
+pub fn quote_deposit(
+    reserve_x: u64,
+    reserve_y: u64,
+    lp_supply: u64,
+    amount_x: u64,
+    amount_y: u64,
+) -> Option<DepositQuote> {
+    if amount_x == 0 || amount_y == 0 {
+        return None;
+    }
+    if lp_supply == 0 && reserve_x == 0 && reserve_y == 0 {
+        let liquidity = sqrt(amount_x as u128 * amount_y as u128) as u64;
+        return (liquidity > MINIMUM_LIQUIDITY).then_some(DepositQuote {
+            lp: liquidity - MINIMUM_LIQUIDITY,
+            amount_x,
+            amount_y,
+        });
+    }
+
+    let lp = mul_div_floor(amount_x, lp_supply, reserve_x)?
+        .min(mul_div_floor(amount_y, lp_supply, reserve_y)?);
+    if lp == 0 {
+        return None;
+    }
+    Some(DepositQuote {
+        lp,
+        amount_x: mul_div_ceil(reserve_x, lp, lp_supply)?,
+        amount_y: mul_div_ceil(reserve_y, lp, lp_supply)?,
+    })
+}
+
+/// Quote for burning `lp`, `None` for more than the supply.
+pub fn quote_withdraw(
+    reserve_x: u64,
+    reserve_y: u64,
+    lp_supply: u64,
+    lp: u64,
+) -> Option<WithdrawQuote> {
+    if lp == 0 || lp > lp_supply {
+        return None;
+    }
+    Some(WithdrawQuote {
+        amount_x: mul_div_floor(reserve_x, lp, lp_supply)?,
+        amount_y: mul_div_floor(reserve_y, lp, lp_supply)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constants::CONFIG_VERSION,
+        state::{Config, CurveType, FeeMode},
+    };
+    use anchor_lang::prelude::Pubkey;
+
+    const RESERVES: [u64; 5] = [1, 1_000, 1_000_003, u32::MAX as u64, u64::MAX / 3];
+    const AMOUNTS: [u64; 5] = [1, 2, 1_000, 123_456_789, u32::MAX as u64];
+    const FEES: [u16; 3] = [0, 30, 1_000];
+
+    /// A constant product pool as initialize creates it, with the given reserves.
+    fn pool(reserve_x: u64, reserve_y: u64, fee: u16, max_price_impact_bps: u16) -> Config {
+        Config {
+            authority: None,
+            pending_authority: None,
+            mint_x: Pubkey::default(),
+            mint_y: Pubkey::default(),
+            mint_lp: Pubkey::default(),
+            fee,
+            fee_tier: fee,
+            fee_mode: FeeMode::Input,
+            protocol_fee_bps: 0,
+            fee_recipient: Pubkey::default(),
+            reserve_x,
+            reserve_y,
+            price_cumulative_x: 0,
+            price_cumulative_y: 0,
+            last_update_ts: 0,
+            pause_flags: 0,
+            pending_change: None,
+            config_bump: 0,
+            lp_bump: 0,
+            fee_vault_x_bump: 0,
+            fee_vault_y_bump: 0,
+            auth_bump: 0,
+            observation_bump: 0,
+            cumulative_volume_x: 0,
+            cumulative_volume_y: 0,
+            cumulative_fees_x: 0,
+            cumulative_fees_y: 0,
+            swap_count: 0,
+            allowlist: None,
+            mint_x_freezable: false,
+            mint_y_freezable: false,
+            max_price_impact_bps,
+            open_time: 0,
+            skim_to_lps: false,
+            curve: CurveType::ConstantProduct,
+            last_fee_update_ts: 0,
+            max_volume_per_slot_x: 0,
+            max_volume_per_slot_y: 0,
+            current_slot: 0,
+            slot_volume_x: 0,
+            slot_volume_y: 0,
+            circuit_breaker_bps: 0,
+            breaker_tripped: false,
+            version: CONFIG_VERSION,
+            fee_exemptions: None,
+            vault_x: Pubkey::default(),
+            vault_y: Pubkey::default(),
+            min_trade_size: 0,
+            last_swap_slot: 0,
+            last_swap_price_q64: 0,
+            trades_in_last_slot: 0,
+            locked_until: 0,
+            amp_ramp: None,
+            decimals_x: 6,
+            decimals_y: 6,
+        }
+    }
+
+    #[test]
+    fn matches_the_swap_handler() {
+        for fee in FEES {
+            for reserve_x in RESERVES {
+                for reserve_y in RESERVES {
+                    for amount in AMOUNTS {
+                        let config = pool(reserve_x, reserve_y, fee, 0);
+                        let onchain = config.quote_swap(true, amount).ok();
+                        let quote = quote_swap(reserve_x, reserve_y, fee, amount);
+                        assert_eq!(quote.map(|q| q.amount_out), onchain.map(|q| q.amount_out));
+                        assert_eq!(quote.map(|q| q.fee), onchain.map(|q| q.fee));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn price_impact_is_the_tightest_passing_limit() {
+        for fee in FEES {
+            for reserve in RESERVES {
+                for amount in AMOUNTS {
+                    let Some(quote) = quote_swap(reserve, reserve, fee, amount) else {
+                        continue;
+                    };
+                    let limit = quote.price_impact_bps;
+                    if limit == 0 || limit > 10_000 {
+                        continue;
+                    }
+                    let passes =
+                        |limit| pool(reserve, reserve, fee, limit).quote_swap(true, amount);
+                    assert!(passes(limit).is_ok());
+                    if limit > 1 {
+                        assert!(passes(limit - 1).is_err());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deposits_and_withdrawals_round_for_the_pool() {
+        // the first deposit takes what it is given and locks part of the LP
+        assert_eq!(
+            quote_deposit(0, 0, 0, 4_000_000, 1_000_000),
+            Some(DepositQuote {
+                lp: 2_000_000 - MINIMUM_LIQUIDITY,
+                amount_x: 4_000_000,
+                amount_y: 1_000_000,
+            })
+        );
+        assert_eq!(quote_deposit(0, 0, 0, 1_000, 1_000), None);
+
+        // later ones take the pool ratio's rounded-up cost of the LP
+        let deposit = quote_deposit(3_000, 1_001, 1_000, 300, 300).unwrap();
+        assert_eq!(
+            deposit,
+            DepositQuote {
+                lp: 100,
+                amount_x: 300,
+                amount_y: 101,
+            }
+        );
+        // and burning it pays back no more than was paid in
+        let withdraw = quote_withdraw(3_300, 1_102, 1_100, deposit.lp).unwrap();
+        assert!(withdraw.amount_x <= deposit.amount_x && withdraw.amount_y <= deposit.amount_y);
+        assert_eq!(quote_withdraw(3_300, 1_102, 1_100, 1_101), None);
+    }
+}
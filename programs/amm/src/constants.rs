@@ -2,3 +2,88 @@ use anchor_lang::prelude::*;
 
 #[constant]
 pub const SEED: &str = "anchor";
+
+#[constant]
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+#[constant]
+pub const MAX_REFERRAL_FEE_BPS: u16 = 500;
+
+/// Bounds of the amplification of a stable swap pool.
+#[constant]
+pub const MIN_AMP: u64 = 1;
+#[constant]
+pub const MAX_AMP: u64 = 10_000;
+
+/// Shortest an amp ramp can run, and most it can multiply or divide the amp by.
+#[constant]
+pub const MIN_RAMP_DURATION: i64 = 24 * 60 * 60;
+#[constant]
+pub const MAX_AMP_CHANGE: u64 = 10;
+
+/// Bounds of x's weight in a weighted pool, y's is what's left of 10_000 bps.
+#[constant]
+pub const MIN_WEIGHT_BPS: u16 = 100;
+#[constant]
+pub const MAX_WEIGHT_BPS: u16 = 9_900;
+
+/// LP units minted to the pool itself on the first deposit and never redeemable.
+#[constant]
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+/// Most a pool's minimum trade size can be set to, in base units of the input mint.
+#[constant]
+pub const MAX_MIN_TRADE_SIZE: u64 = 1_000_000;
+
+/// Seconds a scheduled fee or authority change waits before it can be executed.
+#[constant]
+pub const TIMELOCK_SECONDS: i64 = 24 * 60 * 60;
+
+/// Seconds that have to pass after a fee change before the next one can be made.
+#[constant]
+pub const FEE_UPDATE_COOLDOWN: i64 = 3 * 24 * 60 * 60;
+
+/// Most a single fee change can move the fee by, either way.
+#[constant]
+pub const MAX_FEE_STEP_BPS: u16 = 50;
+
+/// Longest a set_lock can lock a pool for, from when it is sent.
+#[constant]
+pub const MAX_LOCK_DURATION: i64 = 7 * 24 * 60 * 60;
+
+/// Most legs a single swap_batch runs.
+#[constant]
+pub const MAX_SWAP_LEGS: u8 = 4;
+
+/// Layout version of Config written by initialize. Older pools fail with
+/// OutdatedConfig until migrate_config brings them up to it.
+#[constant]
+pub const CONFIG_VERSION: u8 = 8;
+
+/// Most members a FeeExemptions account holds.
+#[constant]
+pub const MAX_FEE_EXEMPTIONS: u8 = 16;
+
+/// Longest URI the token metadata program takes.
+pub const MAX_METADATA_URI_LENGTH: usize = 200;
+
+/// Length of the observation ring, must match ObservationState::observations.
+pub const OBSERVATION_CAPACITY: usize = 32;
+
+/// Pause flag halting swaps, including swap legs of other instructions.
+#[constant]
+pub const PAUSE_SWAPS: u8 = 1 << 0;
+
+/// Pause flag halting deposits.
+#[constant]
+pub const PAUSE_DEPOSITS: u8 = 1 << 1;
+
+/// Emergency flag halting withdrawals. LPs can otherwise always exit, so this is
+/// never set by lock_pool and only meant for an exploit in progress.
+#[constant]
+pub const PAUSE_WITHDRAWALS: u8 = 1 << 2;
+
+pub const PAUSE_ALL: u8 = PAUSE_SWAPS | PAUSE_DEPOSITS | PAUSE_WITHDRAWALS;
+
+/// Set between begin_flash_swap and end_flash_swap while the vaults are short.
+pub const FLASH_SWAP_ACTIVE: u8 = 1 << 7;
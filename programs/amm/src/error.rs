@@ -1,5 +1,4 @@
 use anchor_lang::error_code;
-use constant_product_curve::CurveError;
 
 #[error_code]
 pub enum AmmError {
@@ -19,7 +18,7 @@ pub enum AmmError {
     InvalidToken,
     #[msg("Actual liquidity is less than minimum.")]
     LiquidityLessThanMinimum,
-    #[msg("No liquidity in pool.")]
+    #[msg("Pool has no liquidity to trade against.")]
     NoLiquidityInPool,
     #[msg("Bump error.")]
     BumpError,
@@ -33,24 +32,144 @@ pub enum AmmError {
     NoAuthoritySet,
     #[msg("Invalid amount.")]
     InvalidAmount,
-    #[msg("Invalid precision.")]
-    InvalidPrecision,
     #[msg("Insufficient balance.")]
     InsufficientBalance,
-    #[msg("Zero balance.")]
-    ZeroBalance,
-}
-
-impl From<CurveError> for AmmError {
-    fn from(error: CurveError) -> AmmError {
-        match error {
-            CurveError::InvalidPrecision => AmmError::InvalidPrecision,
-            CurveError::Overflow => AmmError::Overflow,
-            CurveError::Underflow => AmmError::Underflow,
-            CurveError::InvalidFeeAmount => AmmError::InvalidFee,
-            CurveError::InsufficientBalance => AmmError::InsufficientBalance,
-            CurveError::ZeroBalance => AmmError::ZeroBalance,
-            CurveError::SlippageLimitExceeded => AmmError::SlippageExceeded,
-        }
-    }
+    #[msg("Pool still has liquidity.")]
+    LiquidityRemaining,
+    #[msg("Pool vaults are not empty.")]
+    PoolNotEmpty,
+    #[msg("Referral fee exceeds the maximum.")]
+    InvalidReferralFee,
+    #[msg("Transaction deadline exceeded.")]
+    DeadlineExceeded,
+    #[msg("Pool mints must be different.")]
+    SameMint,
+    #[msg("Pool mints must be ordered with mint_x < mint_y.")]
+    InvalidMintOrder,
+    #[msg("Flash swap must be closed by end_flash_swap in the same transaction.")]
+    FlashSwapNotClosed,
+    #[msg("Flash swap was not repaid.")]
+    FlashSwapNotRepaid,
+    #[msg("Withdrawal below the minimum amounts.")]
+    WithdrawSlippageExceeded,
+    #[msg("Swaps are paused.")]
+    SwapsPaused,
+    #[msg("Deposits are paused.")]
+    DepositsPaused,
+    #[msg("Withdrawals are halted by the emergency pause.")]
+    WithdrawalsPaused,
+    #[msg("Unknown pause flags.")]
+    InvalidPauseFlags,
+    #[msg("Timelock has not elapsed.")]
+    TimelockNotElapsed,
+    #[msg("No parameter change scheduled.")]
+    NoPendingChange,
+    #[msg("Swap would decrease the pool invariant.")]
+    InvariantViolated,
+    #[msg("Swap input must be greater than zero.")]
+    ZeroAmountIn,
+    #[msg("Swap output rounds down to zero.")]
+    AmountOutTooSmall,
+    /// No longer returned, empty pools fail with NoLiquidityInPool. Kept so the codes
+    /// after it stay the same.
+    #[msg("Pool has no reserves to trade against.")]
+    ZeroReserves,
+    #[msg("Minimum amount out exceeds what the pool holds.")]
+    SlippageParamTooLarge,
+    #[msg("Existing vault has a delegate or close authority.")]
+    InvalidVault,
+    #[msg("Swap batch needs between one and MAX_SWAP_LEGS legs.")]
+    InvalidLegCount,
+    #[msg("User is not the delegate of the source account.")]
+    InvalidDelegate,
+    #[msg("Swap exceeds the delegated allowance.")]
+    DelegateAllowanceExceeded,
+    #[msg("Delegated swaps pay out to the owner's associated token account.")]
+    InvalidDelegateRecipient,
+    #[msg("User is not on the pool's allowlist.")]
+    NotAllowlisted,
+    #[msg("User is already on the pool's allowlist.")]
+    AlreadyAllowlisted,
+    #[msg("Mint has an extension the pool does not support.")]
+    UnsupportedMintExtension,
+    #[msg("Swap moves the price more than the pool allows.")]
+    PriceImpactTooHigh,
+    #[msg("Price impact limit must be at most 10000 bps.")]
+    InvalidPriceImpactLimit,
+    #[msg("Pool is not open for trading yet.")]
+    PoolNotOpenYet,
+    #[msg("Open time can only be moved earlier.")]
+    InvalidOpenTime,
+    #[msg("LP balance left after withdrawing all of it.")]
+    LpBalanceRemaining,
+    #[msg("Stable swap amplification is out of range.")]
+    InvalidAmplification,
+    #[msg("Weighted pool weight is out of range.")]
+    InvalidWeight,
+    #[msg("Fee was changed too recently.")]
+    FeeUpdateTooSoon,
+    #[msg("Fee change is larger than the maximum step.")]
+    FeeStepTooLarge,
+    #[msg("Swap volume of this slot is over the pool's limit.")]
+    RateLimitExceeded,
+    #[msg("Swap moves the price past the circuit breaker.")]
+    PriceMoveTooLarge,
+    #[msg("Circuit breaker tripped, swaps are halted until it is reset.")]
+    CircuitBreakerTripped,
+    #[msg("Circuit breaker threshold is out of range.")]
+    InvalidCircuitBreaker,
+    #[msg("Config is of an older layout, migrate it first.")]
+    OutdatedConfig,
+    #[msg("Config is already of the current layout.")]
+    ConfigUpToDate,
+    #[msg("LP mint already has metadata.")]
+    LpMetadataExists,
+    #[msg("Metadata URI is too long.")]
+    InvalidMetadataUri,
+    #[msg("Wallet is already fee exempt.")]
+    AlreadyFeeExempt,
+    #[msg("Wallet is not fee exempt.")]
+    NotFeeExempt,
+    #[msg("Fee exemption list is full.")]
+    FeeExemptionsFull,
+    #[msg("Fee exemptions account is not the pool's.")]
+    InvalidFeeExemptions,
+    #[msg("Invalid protocol authority.")]
+    InvalidProtocolAuthority,
+    #[msg("Requested output is more than the pool holds.")]
+    InsufficientPoolLiquidity,
+    #[msg("Swap input is below the pool's minimum trade size.")]
+    AmountTooSmall,
+    #[msg("Minimum trade size is above MAX_MIN_TRADE_SIZE.")]
+    InvalidMinTradeSize,
+    #[msg("Limit prices are only supported on constant product pools.")]
+    UnsupportedLimitCurve,
+    #[msg("Pool price is already past the limit.")]
+    PriceLimitReached,
+    #[msg("Pool is locked until its lock expires.")]
+    PoolLockedUntil,
+    #[msg("Lock must end in the future and within MAX_LOCK_DURATION.")]
+    InvalidLockDuration,
+    #[msg("Amp ramp needs a stable pool and may move amp at most 10x, over a day or more.")]
+    InvalidAmpRamp,
+    #[msg("Pool has no LP to donate to.")]
+    NoLiquidityToDonate,
+    #[msg("Creator lock needs a positive duration and both lock accounts.")]
+    InvalidCreatorLock,
+    #[msg("Locked LP can't be claimed before its unlock time.")]
+    LpStillLocked,
+    #[msg("Only the owner of a lock position can claim or extend it.")]
+    NotLockOwner,
+    #[msg("A lock can only be extended, never shortened.")]
+    LockShortened,
+    #[msg("Mint decimals are too far apart to price the pair.")]
+    DecimalsTooFarApart,
+    #[msg("No protocol authority proposed.")]
+    NoPendingProtocolAuthority,
+    #[msg("Signer is not the pool's authority.")]
+    Unauthorized,
+    #[msg("A route has to go through two different pools.")]
+    SamePool,
+    #[msg("Swap pricing overflowed.")]
+    MathOverflow,
 }
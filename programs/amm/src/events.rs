@@ -0,0 +1,124 @@
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct PoolLockUpdated {
+    pub config: Pubkey,
+    pub authority: Pubkey,
+    pub pause_flags: u8,
+    pub slot: u64,
+    pub locked_until: i64,
+}
+
+#[event]
+pub struct PoolCreated {
+    pub config: Pubkey,
+    pub mint_x: Pubkey,
+    pub mint_y: Pubkey,
+    pub fee: u16,
+    pub mint_x_freezable: bool,
+    pub mint_y_freezable: bool,
+}
+
+#[event]
+pub struct FeeUpdated {
+    pub config: Pubkey,
+    pub old: u16,
+    pub new: u16,
+}
+
+/// A ramp_amp, or a stop_ramp with both amps and timestamps equal.
+#[event]
+pub struct AmpRampUpdated {
+    pub config: Pubkey,
+    pub initial_amp: u64,
+    pub target_amp: u64,
+    pub start_ts: i64,
+    pub end_ts: i64,
+}
+
+#[event]
+pub struct FeeRecipientUpdated {
+    pub config: Pubkey,
+    pub old: Pubkey,
+    pub new: Pubkey,
+}
+
+#[event]
+pub struct ProtocolFeeUpdated {
+    pub protocol_fee_enabled: bool,
+    pub default_protocol_fee_bps: u16,
+}
+
+#[event]
+pub struct ProtocolAuthorityProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct ProtocolAuthorityUpdated {
+    pub old: Pubkey,
+    pub new: Pubkey,
+}
+
+#[event]
+pub struct CircuitBreakerTripped {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    pub x_to_y: bool,
+    pub amount_in: u64,
+    /// How far the swap would have moved the spot price.
+    pub price_move_bps: u64,
+}
+
+/// Emitted by the deposit instructions, the supply read back after the mint.
+#[event]
+pub struct LiquidityAdded {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    /// Taken from the user, before any transfer fee.
+    pub amount_x: u64,
+    pub amount_y: u64,
+    /// Minted to the user, without the MINIMUM_LIQUIDITY a first deposit locks.
+    pub lp_minted: u64,
+    pub lp_supply_after: u64,
+}
+
+/// Emitted by donate, with what reached the vaults and the reserves after it.
+#[event]
+pub struct Donation {
+    pub config: Pubkey,
+    pub donor: Pubkey,
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+}
+
+/// Emitted by withdraw and withdraw_all, the supply read back after the burn.
+#[event]
+pub struct LiquidityRemoved {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    /// Paid out of the vaults, before any transfer fee.
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub lp_burned: u64,
+    pub lp_supply_after: u64,
+}
+
+#[event]
+pub struct SwapEvent {
+    pub user: Pubkey,
+    /// Token account the output was delivered to.
+    pub recipient: Pubkey,
+    pub x_to_y: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+    /// Whether the user was on the pool's fee exemption list, `fee_amount` being
+    /// zero then.
+    pub fee_exempt: bool,
+    pub vault_x: u64,
+    pub vault_y: u64,
+}
@@ -0,0 +1,128 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    error::AmmError,
+    state::{Allowlist, Config},
+};
+
+#[derive(Accounts)]
+pub struct CreateAllowlist<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"config",
+            config.mint_x.as_ref(),
+            config.mint_y.as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = user,
+        space = Allowlist::space(0),
+        seeds = [b"allowlist", config.key().as_ref()],
+        bump,
+    )]
+    pub allowlist: Account<'info, Allowlist>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Grows the allowlist by one entry, the authority pays the rent.
+#[derive(Accounts)]
+pub struct AddToAllowlist<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [
+            b"config",
+            config.mint_x.as_ref(),
+            config.mint_y.as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        has_one = config,
+        seeds = [b"allowlist", config.key().as_ref()],
+        bump = allowlist.bump,
+        realloc = Allowlist::space(allowlist.members.len() + 1),
+        realloc::payer = user,
+        realloc::zero = false,
+    )]
+    pub allowlist: Account<'info, Allowlist>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Shrinks the allowlist by one entry, refunding the rent to the authority.
+#[derive(Accounts)]
+pub struct RemoveFromAllowlist<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [
+            b"config",
+            config.mint_x.as_ref(),
+            config.mint_y.as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        has_one = config,
+        seeds = [b"allowlist", config.key().as_ref()],
+        bump = allowlist.bump,
+        realloc = Allowlist::space(allowlist.members.len().saturating_sub(1)),
+        realloc::payer = user,
+        realloc::zero = false,
+    )]
+    pub allowlist: Account<'info, Allowlist>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> CreateAllowlist<'info> {
+    /// Turns the pool permissioned, from now on only members of the (still empty)
+    /// allowlist can swap.
+    pub fn create(&mut self, bumps: CreateAllowlistBumps) -> Result<()> {
+        self.config.check_authority(self.user.key())?;
+        self.allowlist.set_inner(Allowlist {
+            config: self.config.key(),
+            members: Vec::new(),
+            bump: bumps.allowlist,
+        });
+        self.config.allowlist = Some(self.allowlist.key());
+        Ok(())
+    }
+}
+
+impl<'info> AddToAllowlist<'info> {
+    pub fn add(&mut self, member: Pubkey) -> Result<()> {
+        self.config.check_authority(self.user.key())?;
+        require!(
+            !self.allowlist.contains(&member),
+            AmmError::AlreadyAllowlisted
+        );
+        self.allowlist.members.push(member);
+        Ok(())
+    }
+}
+
+impl<'info> RemoveFromAllowlist<'info> {
+    pub fn remove(&mut self, member: Pubkey) -> Result<()> {
+        self.config.check_authority(self.user.key())?;
+        let members = &mut self.allowlist.members;
+        let index = members
+            .iter()
+            .position(|m| *m == member)
+            .ok_or(AmmError::NotAllowlisted)?;
+        members.swap_remove(index);
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{
+        burn, close_account, Burn, CloseAccount, Mint, TokenAccount, TokenInterface,
+        TransferChecked,
+    },
+};
+
+use crate::{
+    error::AmmError,
+    state::{Config, ObservationState, PoolRegistryEntry},
+    utils::transfer_checked_with_hook,
+};
+
+#[derive(Accounts)]
+pub struct ClosePool<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut)]
+    pub recipient: SystemAccount<'info>,
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+        mint::token_program = token_program,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        close = recipient,
+        has_one = mint_x,
+        has_one = mint_y,
+        has_one = vault_x,
+        has_one = vault_y,
+        has_one = mint_lp,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        close = recipient,
+        seeds = [b"observation", config.key().as_ref()],
+        bump = config.observation_bump,
+    )]
+    pub observation: AccountLoader<'info, ObservationState>,
+    #[account(
+        mut,
+        close = recipient,
+        has_one = config,
+        seeds = [
+            b"registry",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = registry.bump,
+    )]
+    pub registry: Box<Account<'info, PoolRegistryEntry>>,
+    #[account(mut)]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub locked_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault_x", config.key().as_ref()],
+        bump = config.fee_vault_x_bump,
+        token::mint = mint_x,
+        token::authority = vault_authority,
+        token::token_program = token_program_x,
+    )]
+    pub fee_vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault_y", config.key().as_ref()],
+        bump = config.fee_vault_y_bump,
+        token::mint = mint_y,
+        token::authority = vault_authority,
+        token::token_program = token_program_y,
+    )]
+    pub fee_vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_x,
+        associated_token::authority = recipient,
+        associated_token::token_program = token_program_x,
+    )]
+    pub recipient_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_y,
+        associated_token::authority = recipient,
+        associated_token::token_program = token_program_y,
+    )]
+    pub recipient_y: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> ClosePool<'info> {
+    pub fn close(&mut self, hook_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        self.config.check_authority(self.user.key())?;
+        // only the permanently locked minimum liquidity may be left
+        require!(
+            self.mint_lp.supply == self.locked_lp.amount,
+            AmmError::LiquidityRemaining
+        );
+
+        // what is left backs the locked liquidity, plus any dust and uncollected fees
+        let (vault_x, vault_y) = (
+            self.vault_x.to_account_info(),
+            self.vault_y.to_account_info(),
+        );
+        let (fee_vault_x, fee_vault_y) = (
+            self.fee_vault_x.to_account_info(),
+            self.fee_vault_y.to_account_info(),
+        );
+        let (amount_x, amount_y) = (self.vault_x.amount, self.vault_y.amount);
+        let (fees_x, fees_y) = (self.fee_vault_x.amount, self.fee_vault_y.amount);
+        self.sweep_vault(true, vault_x.clone(), amount_x, hook_accounts)?;
+        self.sweep_vault(false, vault_y.clone(), amount_y, hook_accounts)?;
+        self.sweep_vault(true, fee_vault_x.clone(), fees_x, hook_accounts)?;
+        self.sweep_vault(false, fee_vault_y.clone(), fees_y, hook_accounts)?;
+        self.burn_locked_lp()?;
+
+        for (account, token_program) in [
+            (vault_x, self.token_program_x.to_account_info()),
+            (vault_y, self.token_program_y.to_account_info()),
+            (fee_vault_x, self.token_program_x.to_account_info()),
+            (fee_vault_y, self.token_program_y.to_account_info()),
+        ] {
+            self.close_token_account(account, token_program)?;
+        }
+        self.close_token_account(
+            self.locked_lp.to_account_info(),
+            self.token_program.to_account_info(),
+        )
+    }
+
+    pub fn sweep_vault(
+        &mut self,
+        is_x: bool,
+        from: AccountInfo<'info>,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (mint, decimals, to, token_program) = match is_x {
+            true => (
+                self.mint_x.to_account_info(),
+                self.mint_x.decimals,
+                self.recipient_x.to_account_info(),
+                self.token_program_x.to_account_info(),
+            ),
+            false => (
+                self.mint_y.to_account_info(),
+                self.mint_y.decimals,
+                self.recipient_y.to_account_info(),
+                self.token_program_y.to_account_info(),
+            ),
+        };
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let accounts = TransferChecked {
+            from,
+            mint,
+            to,
+            authority: self.vault_authority.to_account_info(),
+        };
+
+        let config_key = self.config.key();
+        let seeds = &[&b"auth"[..], config_key.as_ref(), &[self.config.auth_bump]];
+
+        let signer_seeds = &[&seeds[..]];
+
+        let ctx = CpiContext::new_with_signer(token_program, accounts, signer_seeds)
+            .with_remaining_accounts(hook_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)
+    }
+
+    pub fn burn_locked_lp(&mut self) -> Result<()> {
+        if self.locked_lp.amount == 0 {
+            return Ok(());
+        }
+
+        let accounts = Burn {
+            mint: self.mint_lp.to_account_info(),
+            from: self.locked_lp.to_account_info(),
+            authority: self.vault_authority.to_account_info(),
+        };
+
+        let config_key = self.config.key();
+        let seeds = &[&b"auth"[..], config_key.as_ref(), &[self.config.auth_bump]];
+
+        let signer_seeds = &[&seeds[..]];
+
+        let ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            signer_seeds,
+        );
+        burn(ctx, self.locked_lp.amount)
+    }
+
+    pub fn close_token_account(
+        &mut self,
+        account: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+    ) -> Result<()> {
+        let accounts = CloseAccount {
+            account,
+            destination: self.recipient.to_account_info(),
+            authority: self.vault_authority.to_account_info(),
+        };
+
+        let config_key = self.config.key();
+        let seeds = &[&b"auth"[..], config_key.as_ref(), &[self.config.auth_bump]];
+
+        let signer_seeds = &[&seeds[..]];
+
+        let ctx = CpiContext::new_with_signer(token_program, accounts, signer_seeds);
+        close_account(ctx)
+    }
+}
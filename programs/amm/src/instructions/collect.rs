@@ -0,0 +1,131 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{state::Config, utils::transfer_checked_with_hook};
+
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    /// Anyone, pays for the recipient token accounts if they do not exist yet.
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    /// CHECK: only used as the authority of the recipient token accounts
+    pub fee_recipient: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        has_one = fee_recipient,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault_x", config.key().as_ref()],
+        bump = config.fee_vault_x_bump,
+        token::mint = mint_x,
+        token::authority = vault_authority,
+        token::token_program = token_program_x,
+    )]
+    pub fee_vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault_y", config.key().as_ref()],
+        bump = config.fee_vault_y_bump,
+        token::mint = mint_y,
+        token::authority = vault_authority,
+        token::token_program = token_program_y,
+    )]
+    pub fee_vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_x,
+        associated_token::authority = fee_recipient,
+        associated_token::token_program = token_program_x,
+    )]
+    pub recipient_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_y,
+        associated_token::authority = fee_recipient,
+        associated_token::token_program = token_program_y,
+    )]
+    pub recipient_y: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> CollectProtocolFees<'info> {
+    /// Sends the protocol's share to the fee recipient. Permissionless, so fees never
+    /// wait on an inactive authority, as the recipient accounts are pinned to the
+    /// ATAs of the configured fee_recipient.
+    pub fn collect(&mut self, hook_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        let (fees_x, fees_y) = (self.fee_vault_x.amount, self.fee_vault_y.amount);
+        self.withdraw_fees(true, fees_x, hook_accounts)?;
+        self.withdraw_fees(false, fees_y, hook_accounts)
+    }
+
+    pub fn withdraw_fees(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let (mint, decimals, from, to, token_program) = match is_x {
+            true => (
+                self.mint_x.to_account_info(),
+                self.mint_x.decimals,
+                self.fee_vault_x.to_account_info(),
+                self.recipient_x.to_account_info(),
+                self.token_program_x.to_account_info(),
+            ),
+            false => (
+                self.mint_y.to_account_info(),
+                self.mint_y.decimals,
+                self.fee_vault_y.to_account_info(),
+                self.recipient_y.to_account_info(),
+                self.token_program_y.to_account_info(),
+            ),
+        };
+
+        let account = TransferChecked {
+            from,
+            mint,
+            to,
+            authority: self.vault_authority.to_account_info(),
+        };
+
+        let config_key = self.config.key();
+        let seeds = &[&b"auth"[..], config_key.as_ref(), &[self.config.auth_bump]];
+
+        let signer_seeds = &[&seeds[..]];
+
+        let ctx = CpiContext::new_with_signer(token_program, account, signer_seeds)
+            .with_remaining_accounts(hook_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)
+    }
+}
@@ -1,131 +1,262 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{mint_to, transfer, Mint, MintTo, Token, TokenAccount, Transfer},
+    token_interface::{mint_to, Mint, MintTo, TokenAccount, TokenInterface, TransferChecked},
 };
-use constant_product_curve::ConstantProduct;
 
-use crate::{error::AmmError, state::Config};
+use crate::{
+    constants::{MINIMUM_LIQUIDITY, PAUSE_DEPOSITS},
+    error::AmmError,
+    events::LiquidityAdded,
+    state::{Config, ProtocolState},
+    utils::{emit_event_cpi, transfer_checked_with_hook},
+};
 
+/// Takes the event authority and this program last, like Swap, for self-CPI events.
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Deposit<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
-    pub mint_x: Account<'info, Mint>,
-    pub mint_y: Account<'info, Mint>,
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: InterfaceAccount<'info, Mint>,
     #[account(
         mut,
         seeds = [b"lp",config.key().as_ref()],
         bump = config.lp_bump,
+        mint::token_program = token_program,
     )]
-    pub mint_lp: Account<'info, Mint>,
+    pub mint_lp: InterfaceAccount<'info, Mint>,
     #[account(
+        mut,
         has_one = mint_x,
         has_one = mint_y,
-        seeds =[b"config",config.seed.to_le_bytes().as_ref()],
+        has_one = vault_x,
+        has_one = vault_y,
+        has_one = mint_lp,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
         bump = config.config_bump
     )]
     pub config: Account<'info, Config>,
-    #[account(
-        mut,
-        associated_token::mint = mint_x,
-        associated_token::authority = config,
-    )]
-    pub vault_x: Account<'info, TokenAccount>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
 
-    #[account(
-        mut,
-        associated_token::mint = mint_y,
-        associated_token::authority = config,
-    )]
-    pub vault_y: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
         associated_token::mint = mint_x,
         associated_token::authority = user,
+        associated_token::token_program = token_program_x,
     )]
-    pub user_x: Account<'info, TokenAccount>,
+    pub user_x: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
         associated_token::mint = mint_y,
         associated_token::authority = user,
+        associated_token::token_program = token_program_y,
     )]
-    pub user_y: Account<'info, TokenAccount>,
+    pub user_y: InterfaceAccount<'info, TokenAccount>,
     #[account(
         init_if_needed,
         payer = user,
         associated_token::mint = mint_lp,
         associated_token::authority = user,
+        associated_token::token_program = token_program,
+    )]
+    pub user_lp: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program,
     )]
-    pub user_lp: Account<'info, TokenAccount>,
+    pub locked_lp: InterfaceAccount<'info, TokenAccount>,
+    /// Only touched by the swap leg of deposit_single.
+    #[account(
+        mut,
+        seeds = [b"fee_vault_x", config.key().as_ref()],
+        bump = config.fee_vault_x_bump,
+        token::mint = mint_x,
+        token::authority = vault_authority,
+        token::token_program = token_program_x,
+    )]
+    pub fee_vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault_y", config.key().as_ref()],
+        bump = config.fee_vault_y_bump,
+        token::mint = mint_y,
+        token::authority = vault_authority,
+        token::token_program = token_program_y,
+    )]
+    pub fee_vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Only read by the swap leg of deposit_single, for the protocol's share of its fee.
+    #[account(seeds = [b"protocol"], bump = protocol.bump)]
+    pub protocol: Box<Account<'info, ProtocolState>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
 impl<'info> Deposit<'info> {
-    pub fn deposit(&mut self, amount: u64, max_x: u64, max_y: u64) -> Result<()> {
-        require!(self.config.locked == false, AmmError::PoolLocked);
+    pub fn deposit(
+        &mut self,
+        amount: u64,
+        max_x: u64,
+        max_y: u64,
+        min_lp_out: u64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<u64> {
+        self.config
+            .check_not_paused(PAUSE_DEPOSITS, AmmError::DepositsPaused)?;
         require!(amount > 0, AmmError::InvalidAmount);
 
-        let (x, y) = match self.mint_lp.supply == 0
-            && self.vault_x.amount == 0
-            && self.vault_y.amount == 0
-        {
+        let (reserve_x, reserve_y) = (self.config.reserve_x, self.config.reserve_y);
+        self.config.update_twap(reserve_x, reserve_y)?;
+
+        let supply = self.mint_lp.supply;
+        let first_deposit = supply == 0 && reserve_x == 0 && reserve_y == 0;
+        // what `amount` LP is worth in each token
+        let (x, y) = match first_deposit {
             true => (max_x, max_y),
-            false => {
-                let amounts = ConstantProduct::xy_deposit_amounts_from_l(
-                    self.vault_x.amount,
-                    self.vault_y.amount,
-                    self.mint_lp.supply,
-                    amount,
-                    6,
-                )
-                .unwrap();
-                (amounts.x, amounts.y)
-            }
+            false => self.config.deposit_cost(amount, supply)?,
         };
         require!(x <= max_x && y <= max_y, AmmError::SlippageExceeded);
-        self.deposit_tokens(true, x)?;
-        self.deposit_tokens(false, y)?;
-        self.mint_lp_token(amount)
+        self.deposit_tokens(true, x, hook_accounts)?;
+        self.deposit_tokens(false, y, hook_accounts)?;
+
+        // book what the vaults actually received, which is less for mints with a
+        // transfer fee
+        let (before_x, before_y) = (self.vault_x.amount, self.vault_y.amount);
+        self.vault_x.reload()?;
+        self.vault_y.reload()?;
+        let (received_x, received_y) = (
+            self.vault_x.amount - before_x,
+            self.vault_y.amount - before_y,
+        );
+
+        let lp = match first_deposit {
+            true => {
+                // the first LP is priced off what was paid in, and part of it is locked
+                // in the pool for good so a single LP unit can never be inflated to
+                // price out everyone after
+                let liquidity = Config::initial_liquidity(received_x, received_y)?;
+                self.mint_lp_token(self.locked_lp.to_account_info(), MINIMUM_LIQUIDITY)?;
+                liquidity - MINIMUM_LIQUIDITY
+            }
+            // a transfer fee leaves the vaults short of x and y, only what arrived is
+            // minted for
+            false => amount.min(self.config.lp_for(received_x, received_y, supply)?),
+        };
+        require!(lp >= min_lp_out, AmmError::SlippageExceeded);
+
+        self.config.reserve_x = reserve_x
+            .checked_add(received_x)
+            .ok_or(AmmError::Overflow)?;
+        self.config.reserve_y = reserve_y
+            .checked_add(received_y)
+            .ok_or(AmmError::Overflow)?;
+
+        self.mint_lp_token(self.user_lp.to_account_info(), lp)?;
+        self.emit_liquidity_added(x, y, lp, event_authority_bump)?;
+        Ok(lp)
     }
 
-    pub fn deposit_tokens(&mut self, is_x: bool, amount: u64) -> Result<()> {
-        let (from, to) = match is_x {
+    /// Deposits up to `amount_x` and `amount_y`, minting as much LP as the scarcer
+    /// side pays for. The LP is sized before anything moves, so only what it costs
+    /// is pulled and the excess of the other side never leaves the user.
+    pub fn deposit_amounts(
+        &mut self,
+        amount_x: u64,
+        amount_y: u64,
+        min_lp_out: u64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<u64> {
+        require!(amount_x > 0 && amount_y > 0, AmmError::InvalidAmount);
+        let (reserve_x, reserve_y) = (self.config.reserve_x, self.config.reserve_y);
+        let supply = self.mint_lp.supply;
+
+        let amount = match supply == 0 && reserve_x == 0 && reserve_y == 0 {
+            // the first deposit sets the ratio and takes both amounts in full, the LP
+            // amount is not used for it
+            true => 1,
+            // rounded down, so the rounded-up cost of each side stays within its amount
+            false => self.config.lp_for(amount_x, amount_y, supply)?,
+        };
+        self.deposit(
+            amount,
+            amount_x,
+            amount_y,
+            min_lp_out,
+            hook_accounts,
+            event_authority_bump,
+        )
+    }
+
+    pub fn deposit_tokens(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (mint, decimals, from, to, cpi_program) = match is_x {
             true => (
+                self.mint_x.to_account_info(),
+                self.mint_x.decimals,
                 self.user_x.to_account_info(),
                 self.vault_x.to_account_info(),
+                self.token_program_x.to_account_info(),
             ),
             false => (
+                self.mint_y.to_account_info(),
+                self.mint_y.decimals,
                 self.user_y.to_account_info(),
                 self.vault_y.to_account_info(),
+                self.token_program_y.to_account_info(),
             ),
         };
 
-        let cpi_program = self.token_program.to_account_info();
-        let cpi_account = Transfer {
+        let cpi_account = TransferChecked {
             from,
+            mint,
             to,
             authority: self.user.to_account_info(),
         };
-        let ctx = CpiContext::new(cpi_program, cpi_account);
-        transfer(ctx, amount)
+        let ctx = CpiContext::new(cpi_program, cpi_account)
+            .with_remaining_accounts(hook_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)
     }
-    pub fn mint_lp_token(&mut self, amount: u64) -> Result<()> {
+    pub fn mint_lp_token(&mut self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
         let cpi_program = self.token_program.to_account_info();
 
         let cpi_account = MintTo {
             mint: self.mint_lp.to_account_info(),
-            to: self.user_lp.to_account_info(),
+            to,
             authority: self.config.to_account_info(),
         };
         let seeds = &[
             &b"config"[..],
-            &self.config.seed.to_le_bytes(),
+            self.config.mint_x.as_ref(),
+            self.config.mint_y.as_ref(),
+            &self.config.fee_tier.to_le_bytes(),
             &[self.config.config_bump],
         ];
 
@@ -134,4 +265,24 @@ impl<'info> Deposit<'info> {
         let ctx = CpiContext::new_with_signer(cpi_program, cpi_account, signer_seeds);
         mint_to(ctx, amount)
     }
+
+    pub fn emit_liquidity_added(
+        &mut self,
+        amount_x: u64,
+        amount_y: u64,
+        lp_minted: u64,
+        event_authority_bump: u8,
+    ) -> Result<()> {
+        // the mint account still holds the pre-mint supply
+        self.mint_lp.reload()?;
+        let event = LiquidityAdded {
+            config: self.config.key(),
+            user: self.user.key(),
+            amount_x,
+            amount_y,
+            lp_minted,
+            lp_supply_after: self.mint_lp.supply,
+        };
+        emit_event_cpi(&self.event_authority, event_authority_bump, &event)
+    }
 }
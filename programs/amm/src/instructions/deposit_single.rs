@@ -0,0 +1,232 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::TransferChecked;
+
+use crate::{
+    constants::{PAUSE_DEPOSITS, PAUSE_SWAPS},
+    error::AmmError,
+    instructions::Deposit,
+    math::{mul_div_ceil, mul_div_floor},
+    utils::{sqrt, transfer_checked_with_hook, transfer_protocol_share},
+};
+
+impl<'info> Deposit<'info> {
+    /// Deposits `amount` of a single token: the optimal part of it is swapped at the
+    /// normal fee, the rest is added alongside the swap output in the pool ratio.
+    /// `hook_accounts` are forwarded to every transfer, for hooks on either mint.
+    pub fn deposit_single(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        min_lp_out: u64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<()> {
+        // the swap leg trades against the pool, so both flags apply
+        self.config
+            .check_not_paused(PAUSE_DEPOSITS, AmmError::DepositsPaused)?;
+        self.config
+            .check_not_paused(PAUSE_SWAPS, AmmError::SwapsPaused)?;
+        self.config.check_open()?;
+        self.config.check_breaker()?;
+        self.config.check_permissionless()?;
+        if amount == 0 {
+            msg!("amount_in: {}", amount);
+            return err!(AmmError::ZeroAmountIn);
+        }
+
+        let (reserve_x, reserve_y) = (self.config.reserve_x, self.config.reserve_y);
+        self.config.update_twap(reserve_x, reserve_y)?;
+        if reserve_x == 0 || reserve_y == 0 || self.mint_lp.supply == 0 {
+            msg!(
+                "reserve_x: {}, reserve_y: {}, lp_supply: {}",
+                reserve_x,
+                reserve_y,
+                self.mint_lp.supply
+            );
+            return err!(AmmError::NoLiquidityInPool);
+        }
+
+        let reserve_in = match is_x {
+            true => reserve_x,
+            false => reserve_y,
+        };
+        let swap_amount = single_sided_swap_amount(reserve_in, amount, self.config.fee)?;
+
+        // swap leg, the output stays in the vault since it is deposited right back.
+        // The split is only exact for FeeMode::Input, with the fee on the output a
+        // little more of the output is refunded
+        let received = self.deposit_and_measure(is_x, swap_amount, hook_accounts)?;
+        let quote = self.config.quote_swap(is_x, received)?;
+        let protocol_fee_bps = self.protocol.protocol_fee_bps(self.config.protocol_fee_bps);
+        let share = self
+            .config
+            .book_swap_at(is_x, received, &quote, protocol_fee_bps)?;
+        self.transfer_protocol_share(self.config.fee_is_x(is_x), share, hook_accounts)?;
+        let swapped = quote.amount_out;
+
+        // deposit leg, against the reserves after the swap
+        let (reserve_in, reserve_out) = match is_x {
+            true => (self.config.reserve_x, self.config.reserve_y),
+            false => (self.config.reserve_y, self.config.reserve_x),
+        };
+        let supply = self.mint_lp.supply;
+        let remaining = amount.checked_sub(swap_amount).ok_or(AmmError::Underflow)?;
+        let lp = mul_div_floor(remaining, supply, reserve_in)
+            .ok_or(AmmError::Overflow)?
+            .min(mul_div_floor(swapped, supply, reserve_out).ok_or(AmmError::Overflow)?);
+
+        let amount_in = mul_div_ceil(lp, reserve_in, supply).ok_or(AmmError::Overflow)?;
+        let received = self.deposit_and_measure(is_x, amount_in, hook_accounts)?;
+        // a transfer fee on the input mint can leave the vault short of amount_in
+        let lp = lp.min(mul_div_floor(received, supply, reserve_in).ok_or(AmmError::Overflow)?);
+        let amount_out = mul_div_ceil(lp, reserve_out, supply).ok_or(AmmError::Overflow)?;
+
+        require!(lp > 0, AmmError::LiquidityLessThanMinimum);
+        require!(lp >= min_lp_out, AmmError::SlippageExceeded);
+
+        let (reserve_in, reserve_out) = match is_x {
+            true => (&mut self.config.reserve_x, &mut self.config.reserve_y),
+            false => (&mut self.config.reserve_y, &mut self.config.reserve_x),
+        };
+        *reserve_in = reserve_in.checked_add(received).ok_or(AmmError::Overflow)?;
+        *reserve_out = reserve_out
+            .checked_add(amount_out)
+            .ok_or(AmmError::Overflow)?;
+
+        // hand back the bit of swap output the LP rounding could not use
+        if swapped > amount_out {
+            self.refund_token(!is_x, swapped - amount_out, hook_accounts)?;
+        }
+        self.mint_lp_token(self.user_lp.to_account_info(), lp)?;
+        // all of it paid in on one side, the refund aside
+        let paid = swap_amount
+            .checked_add(amount_in)
+            .ok_or(AmmError::Overflow)?;
+        let (amount_x, amount_y) = match is_x {
+            true => (paid, 0),
+            false => (0, paid),
+        };
+        self.emit_liquidity_added(amount_x, amount_y, lp, event_authority_bump)
+    }
+
+    pub fn deposit_and_measure(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<u64> {
+        let before = match is_x {
+            true => self.vault_x.amount,
+            false => self.vault_y.amount,
+        };
+        self.deposit_tokens(is_x, amount, hook_accounts)?;
+
+        let after = match is_x {
+            true => {
+                self.vault_x.reload()?;
+                self.vault_x.amount
+            }
+            false => {
+                self.vault_y.reload()?;
+                self.vault_y.amount
+            }
+        };
+        Ok(after.checked_sub(before).ok_or(AmmError::Underflow)?)
+    }
+
+    pub fn transfer_protocol_share(
+        &mut self,
+        is_x: bool,
+        share: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (mint, vault, fee_vault, token_program) = match is_x {
+            true => (
+                &self.mint_x,
+                self.vault_x.to_account_info(),
+                self.fee_vault_x.to_account_info(),
+                self.token_program_x.to_account_info(),
+            ),
+            false => (
+                &self.mint_y,
+                self.vault_y.to_account_info(),
+                self.fee_vault_y.to_account_info(),
+                self.token_program_y.to_account_info(),
+            ),
+        };
+        transfer_protocol_share(
+            &self.config,
+            self.vault_authority.to_account_info(),
+            mint,
+            vault,
+            fee_vault,
+            token_program,
+            hook_accounts,
+            share,
+        )?;
+
+        // deposit_and_measure diffs against the cached balance
+        match is_x {
+            true => self.vault_x.reload(),
+            false => self.vault_y.reload(),
+        }
+    }
+
+    pub fn refund_token(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (mint, decimals, from, to, token_program) = match is_x {
+            true => (
+                self.mint_x.to_account_info(),
+                self.mint_x.decimals,
+                self.vault_x.to_account_info(),
+                self.user_x.to_account_info(),
+                self.token_program_x.to_account_info(),
+            ),
+            false => (
+                self.mint_y.to_account_info(),
+                self.mint_y.decimals,
+                self.vault_y.to_account_info(),
+                self.user_y.to_account_info(),
+                self.token_program_y.to_account_info(),
+            ),
+        };
+        let accounts = TransferChecked {
+            from,
+            mint,
+            to,
+            authority: self.vault_authority.to_account_info(),
+        };
+
+        let config_key = self.config.key();
+        let seeds = &[&b"auth"[..], config_key.as_ref(), &[self.config.auth_bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let ctx = CpiContext::new_with_signer(token_program, accounts, signer_seeds)
+            .with_remaining_accounts(hook_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)
+    }
+}
+
+/// Part of `amount` to swap so the remainder matches the pool ratio after the swap.
+/// Positive root of `r s^2 + R (1 + r) s - R A = 0`, with `r` the share kept after the fee.
+fn single_sided_swap_amount(reserve: u64, amount: u64, fee: u16) -> Result<u64> {
+    let (reserve, amount) = (reserve as u128, amount as u128);
+    let b = 20_000 - fee as u128;
+    let d = 10_000 - fee as u128;
+
+    let inner = reserve
+        .checked_mul(b * b)
+        .and_then(|n| n.checked_add(4 * d * 10_000 * amount))
+        .ok_or(AmmError::Overflow)?;
+    // R * inner only overflows for very deep pools, where splitting the root costs
+    // a little precision
+    let root = match reserve.checked_mul(inner) {
+        Some(n) => sqrt(n),
+        None => sqrt(reserve) * sqrt(inner),
+    };
+    Ok((root.saturating_sub(reserve * b) / (2 * d)) as u64)
+}
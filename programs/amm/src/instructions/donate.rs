@@ -0,0 +1,62 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    constants::PAUSE_DEPOSITS, error::AmmError, events::Donation, instructions::Deposit,
+    utils::emit_event_cpi,
+};
+
+impl<'info> Deposit<'info> {
+    /// Adds `amount_x` and `amount_y` to the reserves without minting LP, so they
+    /// accrue to the LPs already in the pool. A pool without LP has nobody to give
+    /// them to, and its first deposit would take them, so it is refused.
+    pub fn donate(
+        &mut self,
+        amount_x: u64,
+        amount_y: u64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<()> {
+        self.config
+            .check_not_paused(PAUSE_DEPOSITS, AmmError::DepositsPaused)?;
+        require!(amount_x > 0 || amount_y > 0, AmmError::InvalidAmount);
+        if self.mint_lp.supply == 0 {
+            msg!("lp_supply: {}", self.mint_lp.supply);
+            return err!(AmmError::NoLiquidityToDonate);
+        }
+
+        // the price so far accrues at the old reserves, the donation only moves it
+        // from here on
+        let (reserve_x, reserve_y) = (self.config.reserve_x, self.config.reserve_y);
+        self.config.update_twap(reserve_x, reserve_y)?;
+
+        let (before_x, before_y) = (self.vault_x.amount, self.vault_y.amount);
+        if amount_x > 0 {
+            self.deposit_tokens(true, amount_x, hook_accounts)?;
+        }
+        if amount_y > 0 {
+            self.deposit_tokens(false, amount_y, hook_accounts)?;
+        }
+        self.vault_x.reload()?;
+        self.vault_y.reload()?;
+        let (received_x, received_y) = (
+            self.vault_x.amount - before_x,
+            self.vault_y.amount - before_y,
+        );
+        self.config.reserve_x = reserve_x
+            .checked_add(received_x)
+            .ok_or(AmmError::Overflow)?;
+        self.config.reserve_y = reserve_y
+            .checked_add(received_y)
+            .ok_or(AmmError::Overflow)?;
+
+        let event = Donation {
+            config: self.config.key(),
+            donor: self.user.key(),
+            amount_x: received_x,
+            amount_y: received_y,
+            reserve_x: self.config.reserve_x,
+            reserve_y: self.config.reserve_y,
+        };
+        emit_event_cpi(&self.event_authority, event_authority_bump, &event)
+    }
+}
@@ -0,0 +1,132 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    constants::MAX_FEE_EXEMPTIONS,
+    error::AmmError,
+    state::{Config, FeeExemptions},
+};
+
+#[derive(Accounts)]
+pub struct CreateFeeExemptions<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"config",
+            config.mint_x.as_ref(),
+            config.mint_y.as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        init,
+        payer = user,
+        space = FeeExemptions::space(0),
+        seeds = [b"fee_exemptions", config.key().as_ref()],
+        bump,
+    )]
+    pub fee_exemptions: Account<'info, FeeExemptions>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Grows the exemption list by one entry, the authority pays the rent.
+#[derive(Accounts)]
+pub struct AddFeeExemption<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [
+            b"config",
+            config.mint_x.as_ref(),
+            config.mint_y.as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        has_one = config,
+        seeds = [b"fee_exemptions", config.key().as_ref()],
+        bump = fee_exemptions.bump,
+        realloc = FeeExemptions::space(fee_exemptions.members.len() + 1),
+        realloc::payer = user,
+        realloc::zero = false,
+    )]
+    pub fee_exemptions: Account<'info, FeeExemptions>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Shrinks the exemption list by one entry, refunding the rent to the authority.
+#[derive(Accounts)]
+pub struct RemoveFeeExemption<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [
+            b"config",
+            config.mint_x.as_ref(),
+            config.mint_y.as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        has_one = config,
+        seeds = [b"fee_exemptions", config.key().as_ref()],
+        bump = fee_exemptions.bump,
+        realloc = FeeExemptions::space(fee_exemptions.members.len().saturating_sub(1)),
+        realloc::payer = user,
+        realloc::zero = false,
+    )]
+    pub fee_exemptions: Account<'info, FeeExemptions>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> CreateFeeExemptions<'info> {
+    /// Points the pool at a still empty exemption list.
+    pub fn create(&mut self, bumps: CreateFeeExemptionsBumps) -> Result<()> {
+        self.config.check_authority(self.user.key())?;
+        self.fee_exemptions.set_inner(FeeExemptions {
+            config: self.config.key(),
+            members: Vec::new(),
+            bump: bumps.fee_exemptions,
+        });
+        self.config.fee_exemptions = Some(self.fee_exemptions.key());
+        Ok(())
+    }
+}
+
+impl<'info> AddFeeExemption<'info> {
+    pub fn add(&mut self, member: Pubkey) -> Result<()> {
+        self.config.check_authority(self.user.key())?;
+        require!(
+            !self.fee_exemptions.contains(&member),
+            AmmError::AlreadyFeeExempt
+        );
+        require!(
+            self.fee_exemptions.members.len() < MAX_FEE_EXEMPTIONS as usize,
+            AmmError::FeeExemptionsFull
+        );
+        self.fee_exemptions.members.push(member);
+        Ok(())
+    }
+}
+
+impl<'info> RemoveFeeExemption<'info> {
+    pub fn remove(&mut self, member: Pubkey) -> Result<()> {
+        self.config.check_authority(self.user.key())?;
+        let members = &mut self.fee_exemptions.members;
+        let index = members
+            .iter()
+            .position(|m| *m == member)
+            .ok_or(AmmError::NotFeeExempt)?;
+        members.swap_remove(index);
+        Ok(())
+    }
+}
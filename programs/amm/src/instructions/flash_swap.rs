@@ -0,0 +1,337 @@
+use anchor_lang::{
+    prelude::*,
+    solana_program::sysvar::instructions::{
+        load_current_index_checked, load_instruction_at_checked, ID as INSTRUCTIONS_ID,
+    },
+    Discriminator,
+};
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{
+    constants::{FLASH_SWAP_ACTIVE, PAUSE_SWAPS},
+    error::AmmError,
+    math::{fee_amount, mul_div_floor},
+    state::{Config, FlashSwapState, ProtocolState},
+    utils::{transfer_checked_with_hook, transfer_protocol_share},
+};
+
+#[derive(Accounts)]
+pub struct BeginFlashSwap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        has_one = vault_x,
+        has_one = vault_y,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"flash", config.key().as_ref()],
+        bump,
+        space = 8 + FlashSwapState::INIT_SPACE,
+    )]
+    pub flash: Account<'info, FlashSwapState>,
+    #[account(mut)]
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_x,
+    )]
+    pub user_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_y,
+    )]
+    pub user_y: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: the instructions sysvar, used to find the matching end_flash_swap
+    #[account(address = INSTRUCTIONS_ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> BeginFlashSwap<'info> {
+    /// `hook_accounts` are forwarded to the loan transfers, for hooks on either mint.
+    pub fn begin(
+        &mut self,
+        amount_x: u64,
+        amount_y: u64,
+        hook_accounts: &[AccountInfo<'info>],
+        bumps: BeginFlashSwapBumps,
+    ) -> Result<()> {
+        self.config
+            .check_not_paused(PAUSE_SWAPS, AmmError::SwapsPaused)?;
+        self.config.check_open()?;
+        self.config.check_breaker()?;
+        self.config.check_permissionless()?;
+        require!(amount_x > 0 || amount_y > 0, AmmError::InvalidAmount);
+        self.check_end_instruction()?;
+
+        let (reserve_x, reserve_y) = (self.config.reserve_x, self.config.reserve_y);
+        self.config.update_twap(reserve_x, reserve_y)?;
+        if amount_x >= reserve_x || amount_y >= reserve_y {
+            msg!(
+                "amount_x: {}, amount_y: {}, reserve_x: {}, reserve_y: {}",
+                amount_x,
+                amount_y,
+                reserve_x,
+                reserve_y
+            );
+            return err!(AmmError::InsufficientBalance);
+        }
+
+        self.flash.set_inner(FlashSwapState {
+            config: self.config.key(),
+            user: self.user.key(),
+            reserve_x,
+            reserve_y,
+            amount_x,
+            amount_y,
+            vault_x: self.vault_x.amount - amount_x,
+            vault_y: self.vault_y.amount - amount_y,
+            bump: bumps.flash,
+        });
+        self.config.reserve_x = reserve_x - amount_x;
+        self.config.reserve_y = reserve_y - amount_y;
+
+        // the vaults are short until end_flash_swap, so nothing else may trade
+        // against them in between
+        self.config.pause_flags |= FLASH_SWAP_ACTIVE;
+
+        if amount_x > 0 {
+            self.lend_token(true, amount_x, hook_accounts)?;
+        }
+        if amount_y > 0 {
+            self.lend_token(false, amount_y, hook_accounts)?;
+        }
+        Ok(())
+    }
+
+    /// Requires an end_flash_swap for this pool later in the transaction, so the
+    /// loan can never outlive it.
+    fn check_end_instruction(&self) -> Result<()> {
+        let instructions = self.instructions.to_account_info();
+        let mut index = load_current_index_checked(&instructions)? as usize + 1;
+
+        while let Ok(ix) = load_instruction_at_checked(index, &instructions) {
+            if ix.program_id == crate::ID
+                && ix
+                    .data
+                    .starts_with(crate::instruction::EndFlashSwap::DISCRIMINATOR)
+                && ix
+                    .accounts
+                    .iter()
+                    .any(|meta| meta.pubkey == self.config.key())
+            {
+                return Ok(());
+            }
+            index += 1;
+        }
+        err!(AmmError::FlashSwapNotClosed)
+    }
+
+    pub fn lend_token(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (mint, decimals, from, to, token_program) = match is_x {
+            true => (
+                self.mint_x.to_account_info(),
+                self.mint_x.decimals,
+                self.vault_x.to_account_info(),
+                self.user_x.to_account_info(),
+                self.token_program_x.to_account_info(),
+            ),
+            false => (
+                self.mint_y.to_account_info(),
+                self.mint_y.decimals,
+                self.vault_y.to_account_info(),
+                self.user_y.to_account_info(),
+                self.token_program_y.to_account_info(),
+            ),
+        };
+        let accounts = TransferChecked {
+            from,
+            mint,
+            to,
+            authority: self.vault_authority.to_account_info(),
+        };
+
+        let config_key = self.config.key();
+        let seeds = &[&b"auth"[..], config_key.as_ref(), &[self.config.auth_bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let ctx = CpiContext::new_with_signer(token_program, accounts, signer_seeds)
+            .with_remaining_accounts(hook_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)
+    }
+}
+
+#[derive(Accounts)]
+pub struct EndFlashSwap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        has_one = vault_x,
+        has_one = vault_y,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        close = user,
+        has_one = config,
+        has_one = user,
+        seeds = [b"flash", config.key().as_ref()],
+        bump = flash.bump,
+    )]
+    pub flash: Account<'info, FlashSwapState>,
+    #[account(mut)]
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault_x", config.key().as_ref()],
+        bump = config.fee_vault_x_bump,
+        token::mint = mint_x,
+        token::authority = vault_authority,
+        token::token_program = token_program_x,
+    )]
+    pub fee_vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault_y", config.key().as_ref()],
+        bump = config.fee_vault_y_bump,
+        token::mint = mint_y,
+        token::authority = vault_authority,
+        token::token_program = token_program_y,
+    )]
+    pub fee_vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Decides whether the protocol's share of the fee is routed, and how much.
+    #[account(seeds = [b"protocol"], bump = protocol.bump)]
+    pub protocol: Box<Account<'info, ProtocolState>>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+}
+
+impl<'info> EndFlashSwap<'info> {
+    /// `hook_accounts` are forwarded to the protocol share transfers.
+    pub fn end(&mut self, hook_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        // whatever came back on either side is input, and pays the pool fee
+        let amount_in_x = self.vault_x.amount.saturating_sub(self.flash.vault_x);
+        let amount_in_y = self.vault_y.amount.saturating_sub(self.flash.vault_y);
+        let fee_x = self.input_fee(amount_in_x)?;
+        let fee_y = self.input_fee(amount_in_y)?;
+
+        let after = (
+            self.config
+                .reserve_x
+                .checked_add(amount_in_x - fee_x)
+                .ok_or(AmmError::Overflow)?,
+            self.config
+                .reserve_y
+                .checked_add(amount_in_y - fee_y)
+                .ok_or(AmmError::Overflow)?,
+        );
+        let repaid = self
+            .config
+            .curve_now()?
+            .invariant_holds(
+                (self.flash.reserve_x, self.flash.reserve_y),
+                after,
+                self.config.precision()?,
+            )
+            .ok_or(AmmError::Overflow)?;
+        require!(repaid, AmmError::FlashSwapNotRepaid);
+
+        let share_x = self.protocol_share(fee_x)?;
+        let share_y = self.protocol_share(fee_y)?;
+        self.config.apply_swap(true, amount_in_x, share_x, 0)?;
+        self.config.apply_swap(false, amount_in_y, share_y, 0)?;
+        self.config.pause_flags &= !FLASH_SWAP_ACTIVE;
+
+        transfer_protocol_share(
+            &self.config,
+            self.vault_authority.to_account_info(),
+            &self.mint_x,
+            self.vault_x.to_account_info(),
+            self.fee_vault_x.to_account_info(),
+            self.token_program_x.to_account_info(),
+            hook_accounts,
+            share_x,
+        )?;
+        transfer_protocol_share(
+            &self.config,
+            self.vault_authority.to_account_info(),
+            &self.mint_y,
+            self.vault_y.to_account_info(),
+            self.fee_vault_y.to_account_info(),
+            self.token_program_y.to_account_info(),
+            hook_accounts,
+            share_y,
+        )
+    }
+
+    /// Pool fee on `amount_in`, rounded up.
+    fn input_fee(&self, amount_in: u64) -> Result<u64> {
+        Ok(fee_amount(amount_in, self.config.fee).ok_or(AmmError::MathOverflow)?)
+    }
+
+    /// The protocol's part of `fee`, as the protocol state caps the pool's share.
+    fn protocol_share(&self, fee: u64) -> Result<u64> {
+        let protocol_fee_bps = self.protocol.protocol_fee_bps(self.config.protocol_fee_bps);
+        Ok(mul_div_floor(fee, protocol_fee_bps as u64, 10_000).ok_or(AmmError::MathOverflow)?)
+    }
+}
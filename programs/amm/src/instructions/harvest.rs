@@ -0,0 +1,103 @@
+use anchor_lang::{prelude::*, solana_program::program::invoke};
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_2022::spl_token_2022::extension::transfer_fee::instruction::harvest_withheld_tokens_to_mint,
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+use crate::{state::Config, utils::withholds_transfer_fee};
+
+#[derive(Accounts)]
+pub struct HarvestWithheldFees<'info> {
+    #[account(mut, mint::token_program = token_program_x)]
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    #[account(mut, mint::token_program = token_program_y)]
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    #[account(
+        has_one = mint_x,
+        has_one = mint_y,
+        has_one = vault_x,
+        has_one = vault_y,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault_x", config.key().as_ref()],
+        bump = config.fee_vault_x_bump,
+        token::mint = mint_x,
+        token::authority = vault_authority,
+        token::token_program = token_program_x,
+    )]
+    pub fee_vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault_y", config.key().as_ref()],
+        bump = config.fee_vault_y_bump,
+        token::mint = mint_y,
+        token::authority = vault_authority,
+        token::token_program = token_program_y,
+    )]
+    pub fee_vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> HarvestWithheldFees<'info> {
+    /// Moves the transfer fees withheld in the vaults and fee vaults to their mints,
+    /// where the mint's withdraw authority can claim them. A side without the
+    /// TransferFeeConfig extension is left alone.
+    ///
+    /// Withheld fees are never part of a token account's `amount`, so this changes
+    /// neither the vault balances sync reads nor the tracked reserves.
+    pub fn harvest(&mut self) -> Result<()> {
+        self.harvest_side(true)?;
+        self.harvest_side(false)
+    }
+
+    pub fn harvest_side(&mut self, is_x: bool) -> Result<()> {
+        let (mint, vault, fee_vault, token_program) = match is_x {
+            true => (
+                &self.mint_x,
+                self.vault_x.to_account_info(),
+                self.fee_vault_x.to_account_info(),
+                self.token_program_x.to_account_info(),
+            ),
+            false => (
+                &self.mint_y,
+                self.vault_y.to_account_info(),
+                self.fee_vault_y.to_account_info(),
+                self.token_program_y.to_account_info(),
+            ),
+        };
+        if !withholds_transfer_fee(mint)? {
+            return Ok(());
+        }
+
+        let ix = harvest_withheld_tokens_to_mint(
+            token_program.key,
+            &mint.key(),
+            &[vault.key, fee_vault.key],
+        )?;
+        invoke(
+            &ix,
+            &[mint.to_account_info(), vault, fee_vault, token_program],
+        )?;
+        Ok(())
+    }
+}
@@ -1,18 +1,57 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
-    associated_token::AssociatedToken,
-    token::{Mint, Token, TokenAccount},
+    associated_token::{
+        create_idempotent, get_associated_token_address_with_program_id, AssociatedToken, Create,
+    },
+    token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
-use crate::state::Config;
+use crate::{
+    constants::{
+        CONFIG_VERSION, MAX_AMP, MAX_FEE_BPS, MAX_MIN_TRADE_SIZE, MAX_WEIGHT_BPS, MIN_AMP,
+        MIN_WEIGHT_BPS,
+    },
+    error::AmmError,
+    events::PoolCreated,
+    math::precision_scales,
+    state::{Config, CurveType, FeeMode, ObservationState, PoolRegistryEntry},
+    utils::{check_mint_extensions, load_token_account},
+};
+
+/// Everything a pool is created with. Pools of the same mints are told apart by
+/// their fee, which is also the fee tier in the config address.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct InitializeParams {
+    pub fee: u16,
+    /// None creates a pool that can never be updated.
+    pub authority: Option<Pubkey>,
+    pub fee_mode: FeeMode,
+    pub allow_unsafe_extensions: bool,
+    /// Unix timestamp trading starts at, zero or one in the past opens it right away.
+    pub open_time: i64,
+    pub curve: CurveType,
+    /// Most of the fee, in bps of it, the protocol may take from this pool.
+    pub protocol_fee_bps: u16,
+    /// Smallest swap input, in base units of the mint sold, at most
+    /// MAX_MIN_TRADE_SIZE.
+    pub min_trade_size: u64,
+}
 
 #[derive(Accounts)]
-#[instruction(seed:u64)]
+#[instruction(params: InitializeParams)]
 pub struct Initialize<'info> {
     #[account(mut)]
     pub initializer: Signer<'info>,
-    pub mint_x: Account<'info, Mint>,
-    pub mint_y: Account<'info, Mint>,
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    /// Checked here rather than in the handler, since equal mints make both vault
+    /// derivations collide and the second `init` would fail first.
+    #[account(
+        mint::token_program = token_program_y,
+        constraint = mint_y.key() != mint_x.key() @ AmmError::SameMint,
+        constraint = mint_x.key() < mint_y.key() @ AmmError::InvalidMintOrder,
+    )]
+    pub mint_y: InterfaceAccount<'info, Mint>,
     #[account(
         init,
         payer= initializer,
@@ -20,55 +59,256 @@ pub struct Initialize<'info> {
         bump,
         mint::decimals = 6,
         mint::authority = config,
+        mint::token_program = token_program,
     )]
-    pub mint_lp: Account<'info, Mint>,
+    pub mint_lp: InterfaceAccount<'info, Mint>,
+    /// Plain `init` like every pool account here, so initializing the same mints and
+    /// fee again fails with the system program's AccountAlreadyInUse instead of
+    /// resetting the pool. Mint order is enforced above, so the reversed pair can't
+    /// derive a second config either.
     #[account(
         init,
         payer = initializer,
-        seeds = [b"config",seed.to_le_bytes().as_ref()],
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            params.fee.to_le_bytes().as_ref(),
+        ],
         bump,
         space = 8+Config::INIT_SPACE,
     )]
     pub config: Account<'info, Config>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump)]
+    pub vault_authority: UncheckedAccount<'info>,
     #[account(
         init,
         payer = initializer,
-        associated_token::mint = mint_x,
-        associated_token::authority = config,
+        seeds = [b"observation", config.key().as_ref()],
+        bump,
+        space = 8 + std::mem::size_of::<ObservationState>(),
     )]
-    pub vault_x: Account<'info, TokenAccount>,
-
+    pub observation: AccountLoader<'info, ObservationState>,
+    /// CHECK: the vault authority's ATA of mint_x, created if it does not exist yet,
+    /// or any token account of mint_x the vault authority owns; open_vault checks it
+    #[account(mut)]
+    pub vault_x: UncheckedAccount<'info>,
+    /// CHECK: as vault_x, of mint_y
+    #[account(mut)]
+    pub vault_y: UncheckedAccount<'info>,
+    /// Holds the MINIMUM_LIQUIDITY locked by the first deposit.
+    #[account(
+        init,
+        payer = initializer,
+        associated_token::mint = mint_lp,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub locked_lp: InterfaceAccount<'info, TokenAccount>,
+    /// Protocol fee vaults, kept apart from the reserves.
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [b"fee_vault_x", config.key().as_ref()],
+        bump,
+        token::mint = mint_x,
+        token::authority = vault_authority,
+        token::token_program = token_program_x,
+    )]
+    pub fee_vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         init,
         payer = initializer,
-        associated_token::mint = mint_y,
-        associated_token::authority = config,
+        seeds = [b"fee_vault_y", config.key().as_ref()],
+        bump,
+        token::mint = mint_y,
+        token::authority = vault_authority,
+        token::token_program = token_program_y,
     )]
-    pub vault_y: Account<'info, TokenAccount>,
+    pub fee_vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [
+            b"registry",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            params.fee.to_le_bytes().as_ref(),
+        ],
+        bump,
+        space = 8 + PoolRegistryEntry::INIT_SPACE,
+    )]
+    pub registry: Box<Account<'info, PoolRegistryEntry>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
 impl<'info> Initialize<'info> {
-    pub fn init(
-        &mut self,
-        seed: u64,
-        fee: u16,
-        authority: Option<Pubkey>,
-        bumps: InitializeBumps,
-    ) -> Result<()> {
+    pub fn init(&mut self, params: InitializeParams, bumps: InitializeBumps) -> Result<()> {
+        let InitializeParams {
+            fee,
+            authority,
+            fee_mode,
+            allow_unsafe_extensions,
+            open_time,
+            curve,
+            protocol_fee_bps,
+            min_trade_size,
+        } = params;
+        // MAX_FEE_BPS < 10_000, so a fee that zeroes every swap output is rejected too
+        require!(fee <= MAX_FEE_BPS, AmmError::InvalidFee);
+        require!(protocol_fee_bps <= 10_000, AmmError::InvalidFee);
+        if min_trade_size > MAX_MIN_TRADE_SIZE {
+            msg!("min_trade_size: {}", min_trade_size);
+            return err!(AmmError::InvalidMinTradeSize);
+        }
+        // nobody can sign for the default key, an immutable pool is asked for with None
+        if authority == Some(Pubkey::default()) {
+            msg!("authority: {:?}", authority);
+            return err!(AmmError::InvalidAuthority);
+        }
+        match curve {
+            CurveType::ConstantProduct => {}
+            CurveType::Stable { amp } => require!(
+                (MIN_AMP..=MAX_AMP).contains(&amp),
+                AmmError::InvalidAmplification
+            ),
+            CurveType::Weighted { weight_x_bps } => require!(
+                (MIN_WEIGHT_BPS..=MAX_WEIGHT_BPS).contains(&weight_x_bps),
+                AmmError::InvalidWeight
+            ),
+        }
+        // prices and the stable curve are normalized by the decimals
+        let (decimals_x, decimals_y) = (self.mint_x.decimals, self.mint_y.decimals);
+        if precision_scales(decimals_x, decimals_y).is_none() {
+            msg!("decimals_x: {}, decimals_y: {}", decimals_x, decimals_y);
+            return err!(AmmError::DecimalsTooFarApart);
+        }
+        check_mint_extensions(&self.mint_x, allow_unsafe_extensions)?;
+        check_mint_extensions(&self.mint_y, allow_unsafe_extensions)?;
+        self.open_vault(true)?;
+        self.open_vault(false)?;
+
         self.config.set_inner(Config {
-            seed,
             authority,
+            pending_authority: None,
             mint_x: self.mint_x.key(),
             mint_y: self.mint_y.key(),
+            mint_lp: self.mint_lp.key(),
             fee,
-            locked: false,
+            fee_tier: fee,
+            fee_mode,
+            protocol_fee_bps,
+            fee_recipient: authority.unwrap_or(self.initializer.key()),
+            reserve_x: 0,
+            reserve_y: 0,
+            price_cumulative_x: 0,
+            price_cumulative_y: 0,
+            last_update_ts: Clock::get()?.unix_timestamp,
+            pause_flags: 0,
+            pending_change: None,
             config_bump: bumps.config,
             lp_bump: bumps.mint_lp,
+            fee_vault_x_bump: bumps.fee_vault_x,
+            fee_vault_y_bump: bumps.fee_vault_y,
+            auth_bump: bumps.vault_authority,
+            observation_bump: bumps.observation,
+            cumulative_volume_x: 0,
+            cumulative_volume_y: 0,
+            cumulative_fees_x: 0,
+            cumulative_fees_y: 0,
+            swap_count: 0,
+            allowlist: None,
+            mint_x_freezable: self.mint_x.freeze_authority.is_some(),
+            mint_y_freezable: self.mint_y.freeze_authority.is_some(),
+            max_price_impact_bps: 0,
+            open_time,
+            skim_to_lps: false,
+            curve,
+            last_fee_update_ts: 0,
+            max_volume_per_slot_x: 0,
+            max_volume_per_slot_y: 0,
+            current_slot: 0,
+            slot_volume_x: 0,
+            slot_volume_y: 0,
+            circuit_breaker_bps: 0,
+            breaker_tripped: false,
+            version: CONFIG_VERSION,
+            fee_exemptions: None,
+            vault_x: self.vault_x.key(),
+            vault_y: self.vault_y.key(),
+            min_trade_size,
+            last_swap_slot: 0,
+            last_swap_price_q64: 0,
+            trades_in_last_slot: 0,
+            locked_until: 0,
+            amp_ramp: None,
+            decimals_x,
+            decimals_y,
         });
+
+        self.observation.load_init()?.config = self.config.key();
+        self.registry.set_inner(PoolRegistryEntry {
+            config: self.config.key(),
+            curve,
+            bump: bumps.registry,
+        });
+
+        emit!(PoolCreated {
+            config: self.config.key(),
+            mint_x: self.config.mint_x,
+            mint_y: self.config.mint_y,
+            fee,
+            mint_x_freezable: self.config.mint_x_freezable,
+            mint_y_freezable: self.config.mint_y_freezable,
+        });
+        Ok(())
+    }
+
+    /// Sets up the vault of one side. The vault authority's ATA is created unless
+    /// someone did so ahead of the pool, any other account has to be a token account
+    /// of the mint that already belongs to the vault authority.
+    pub fn open_vault(&self, is_x: bool) -> Result<()> {
+        let (vault, mint, token_program) = match is_x {
+            true => (&self.vault_x, &self.mint_x, &self.token_program_x),
+            false => (&self.vault_y, &self.mint_y, &self.token_program_y),
+        };
+        let ata = get_associated_token_address_with_program_id(
+            &self.vault_authority.key(),
+            &mint.key(),
+            &token_program.key(),
+        );
+        if vault.key() == ata {
+            let accounts = Create {
+                payer: self.initializer.to_account_info(),
+                associated_token: vault.to_account_info(),
+                authority: self.vault_authority.to_account_info(),
+                mint: mint.to_account_info(),
+                system_program: self.system_program.to_account_info(),
+                token_program: token_program.to_account_info(),
+            };
+            create_idempotent(CpiContext::new(
+                self.associated_token_program.to_account_info(),
+                accounts,
+            ))?;
+        }
+
+        // only the vault authority could hand out control of an existing vault, and it
+        // has never signed; this just makes sure nothing else can move its balance
+        let account = load_token_account(vault, &token_program.key())?;
+        if account.mint != mint.key()
+            || account.owner != self.vault_authority.key()
+            || account.delegate.is_some()
+            || account.close_authority.is_some()
+        {
+            msg!("vault: {}", vault.key());
+            return err!(AmmError::InvalidVault);
+        }
         Ok(())
     }
 }
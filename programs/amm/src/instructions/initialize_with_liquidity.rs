@@ -0,0 +1,205 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{mint_to, MintTo, TokenAccount, TransferChecked},
+};
+
+use crate::{
+    constants::MINIMUM_LIQUIDITY,
+    error::AmmError,
+    instructions::{Initialize, InitializeParams},
+    state::{Config, LockPosition},
+    utils::{load_token_account, transfer_checked_with_hook},
+};
+
+/// Initialize plus the creator's first deposit, so nobody can get in between and set
+/// the opening price.
+#[derive(Accounts)]
+#[instruction(params: InitializeParams)]
+pub struct InitializeWithLiquidity<'info> {
+    pub pool: Initialize<'info>,
+    #[account(
+        mut,
+        associated_token::mint = pool.mint_x,
+        associated_token::authority = pool.initializer,
+        associated_token::token_program = pool.token_program_x,
+    )]
+    pub initializer_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = pool.mint_y,
+        associated_token::authority = pool.initializer,
+        associated_token::token_program = pool.token_program_y,
+    )]
+    pub initializer_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Only for a pool created without a creator lock, as a locked pool's LP never
+    /// reaches the initializer here.
+    #[account(
+        init,
+        payer = pool.initializer,
+        associated_token::mint = pool.mint_lp,
+        associated_token::authority = pool.initializer,
+        associated_token::token_program = pool.token_program,
+    )]
+    pub initializer_lp: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    /// Only for a pool created with a creator lock, which mints the initializer's LP
+    /// to the position's ATA instead of initializer_lp.
+    #[account(
+        init,
+        payer = pool.initializer,
+        seeds = [
+            b"lock",
+            pool.config.key().as_ref(),
+            pool.initializer.key().as_ref(),
+        ],
+        bump,
+        space = 8 + LockPosition::INIT_SPACE,
+    )]
+    pub lock_position: Option<Box<Account<'info, LockPosition>>>,
+    #[account(
+        init,
+        payer = pool.initializer,
+        associated_token::mint = pool.mint_lp,
+        associated_token::authority = lock_position,
+        associated_token::token_program = pool.token_program,
+    )]
+    pub lock_position_lp: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    // initializer_lp is created out of this struct, so it needs these at its level
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeWithLiquidity<'info> {
+    /// A positive `creator_lock_duration` puts the initializer's LP in a
+    /// LockPosition for that many seconds, zero hands it over right away.
+    pub fn init_with_liquidity(
+        &mut self,
+        params: InitializeParams,
+        amount_x: u64,
+        amount_y: u64,
+        creator_lock_duration: i64,
+        bumps: InitializeWithLiquidityBumps,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<u64> {
+        let locked = match (
+            creator_lock_duration,
+            &self.initializer_lp,
+            &self.lock_position,
+            &self.lock_position_lp,
+        ) {
+            (0, Some(_), None, None) => false,
+            (duration, None, Some(_), Some(_)) if duration > 0 => true,
+            _ => {
+                msg!("creator_lock_duration: {}", creator_lock_duration);
+                return err!(AmmError::InvalidCreatorLock);
+            }
+        };
+        self.pool.init(params, bumps.pool)?;
+
+        // the vaults may have been created and funded ahead of the pool, only what
+        // the initializer pays in here is booked
+        let (before_x, before_y) = self.vault_amounts()?;
+        self.deposit_token(true, amount_x, hook_accounts)?;
+        self.deposit_token(false, amount_y, hook_accounts)?;
+        let (after_x, after_y) = self.vault_amounts()?;
+        let (received_x, received_y) = (after_x - before_x, after_y - before_y);
+
+        // priced and locked exactly like the first deposit
+        let liquidity = Config::initial_liquidity(received_x, received_y)?;
+        self.pool.config.reserve_x = received_x;
+        self.pool.config.reserve_y = received_y;
+        self.mint_lp_token(self.pool.locked_lp.to_account_info(), MINIMUM_LIQUIDITY)?;
+        let lp = liquidity - MINIMUM_LIQUIDITY;
+        if !locked {
+            if let Some(to) = self.initializer_lp.as_ref().map(|a| a.to_account_info()) {
+                self.mint_lp_token(to, lp)?;
+            }
+            return Ok(lp);
+        }
+
+        let unlock_ts = Clock::get()?
+            .unix_timestamp
+            .checked_add(creator_lock_duration)
+            .ok_or(AmmError::Overflow)?;
+        let (config, owner) = (self.pool.config.key(), self.pool.initializer.key());
+        if let (Some(position), Some(bump)) = (&mut self.lock_position, bumps.lock_position) {
+            position.set_inner(LockPosition {
+                config,
+                owner,
+                unlock_ts,
+                bump,
+            });
+        }
+        if let Some(escrow) = self.lock_position_lp.as_ref().map(|e| e.to_account_info()) {
+            self.mint_lp_token(escrow, lp)?;
+        }
+        Ok(lp)
+    }
+
+    /// The vaults are only checked to be token accounts by open_vault, so they are
+    /// read from their data.
+    pub fn vault_amounts(&self) -> Result<(u64, u64)> {
+        Ok((
+            load_token_account(&self.pool.vault_x, &self.pool.token_program_x.key())?.amount,
+            load_token_account(&self.pool.vault_y, &self.pool.token_program_y.key())?.amount,
+        ))
+    }
+
+    pub fn deposit_token(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (mint, decimals, from, to, cpi_program) = match is_x {
+            true => (
+                self.pool.mint_x.to_account_info(),
+                self.pool.mint_x.decimals,
+                self.initializer_x.to_account_info(),
+                self.pool.vault_x.to_account_info(),
+                self.pool.token_program_x.to_account_info(),
+            ),
+            false => (
+                self.pool.mint_y.to_account_info(),
+                self.pool.mint_y.decimals,
+                self.initializer_y.to_account_info(),
+                self.pool.vault_y.to_account_info(),
+                self.pool.token_program_y.to_account_info(),
+            ),
+        };
+
+        let cpi_account = TransferChecked {
+            from,
+            mint,
+            to,
+            authority: self.pool.initializer.to_account_info(),
+        };
+        let ctx = CpiContext::new(cpi_program, cpi_account)
+            .with_remaining_accounts(hook_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)
+    }
+
+    pub fn mint_lp_token(&mut self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+        let cpi_program = self.pool.token_program.to_account_info();
+
+        let cpi_account = MintTo {
+            mint: self.pool.mint_lp.to_account_info(),
+            to,
+            authority: self.pool.config.to_account_info(),
+        };
+        let config = &self.pool.config;
+        let seeds = &[
+            &b"config"[..],
+            config.mint_x.as_ref(),
+            config.mint_y.as_ref(),
+            &config.fee_tier.to_le_bytes(),
+            &[config.config_bump],
+        ];
+
+        let signer_seeds = &[&seeds[..]];
+
+        let ctx = CpiContext::new_with_signer(cpi_program, cpi_account, signer_seeds);
+        mint_to(ctx, amount)
+    }
+}
@@ -0,0 +1,126 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{
+        close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
+        TransferChecked,
+    },
+};
+
+use crate::{
+    error::AmmError,
+    state::{Config, LockPosition},
+};
+
+#[derive(Accounts)]
+pub struct ClaimLockedLp<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(has_one = mint_lp)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(mint::token_program = token_program)]
+    pub mint_lp: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        close = owner,
+        has_one = config,
+        has_one = owner @ AmmError::NotLockOwner,
+        seeds = [b"lock", config.key().as_ref(), lock_position.owner.as_ref()],
+        bump = lock_position.bump,
+    )]
+    pub lock_position: Account<'info, LockPosition>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = lock_position,
+        associated_token::token_program = token_program,
+    )]
+    pub lock_position_lp: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint_lp,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program,
+    )]
+    pub owner_lp: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> ClaimLockedLp<'info> {
+    /// Pays the whole position out to its owner once unlock_ts has passed, closing
+    /// the position and its LP account to them.
+    pub fn claim(&mut self) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        if now < self.lock_position.unlock_ts {
+            msg!("now: {}, unlock_ts: {}", now, self.lock_position.unlock_ts);
+            return err!(AmmError::LpStillLocked);
+        }
+
+        let config = self.config.key();
+        let seeds = &[
+            &b"lock"[..],
+            config.as_ref(),
+            self.lock_position.owner.as_ref(),
+            &[self.lock_position.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let accounts = TransferChecked {
+            from: self.lock_position_lp.to_account_info(),
+            mint: self.mint_lp.to_account_info(),
+            to: self.owner_lp.to_account_info(),
+            authority: self.lock_position.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            signer_seeds,
+        );
+        transfer_checked(ctx, self.lock_position_lp.amount, self.mint_lp.decimals)?;
+
+        let accounts = CloseAccount {
+            account: self.lock_position_lp.to_account_info(),
+            destination: self.owner.to_account_info(),
+            authority: self.lock_position.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            signer_seeds,
+        );
+        close_account(ctx)
+    }
+}
+
+#[derive(Accounts)]
+pub struct ExtendLpLock<'info> {
+    pub owner: Signer<'info>,
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        has_one = config,
+        has_one = owner @ AmmError::NotLockOwner,
+        seeds = [b"lock", config.key().as_ref(), lock_position.owner.as_ref()],
+        bump = lock_position.bump,
+    )]
+    pub lock_position: Account<'info, LockPosition>,
+}
+
+impl<'info> ExtendLpLock<'info> {
+    pub fn extend(&mut self, unlock_ts: i64) -> Result<()> {
+        if unlock_ts < self.lock_position.unlock_ts {
+            msg!(
+                "unlock_ts: {}, current: {}",
+                unlock_ts,
+                self.lock_position.unlock_ts
+            );
+            return err!(AmmError::LockShortened);
+        }
+        self.lock_position.unlock_ts = unlock_ts;
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    metadata::{
+        create_metadata_accounts_v3, mpl_token_metadata::types::DataV2, CreateMetadataAccountsV3,
+        Metadata,
+    },
+    token_interface::Mint,
+};
+
+use crate::{constants::MAX_METADATA_URI_LENGTH, error::AmmError, state::Config};
+
+#[derive(Accounts)]
+pub struct CreateLpMetadata<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(seeds = [b"lp", config.key().as_ref()], bump = config.lp_bump)]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        has_one = mint_x,
+        has_one = mint_y,
+        has_one = mint_lp,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+    /// CHECK: the LP mint's metadata PDA, created by the metadata program
+    #[account(
+        mut,
+        seeds = [b"metadata", Metadata::id().as_ref(), mint_lp.key().as_ref()],
+        bump,
+        seeds::program = Metadata::id(),
+    )]
+    pub metadata: UncheckedAccount<'info>,
+    pub metadata_program: Program<'info, Metadata>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+impl<'info> CreateLpMetadata<'info> {
+    /// Names the LP token after the pair for wallets, once per pool. Pools with an
+    /// authority leave it to the authority, since the metadata is immutable and
+    /// anyone else could pin any `uri` to it.
+    pub fn create_lp_metadata(&mut self, uri: String) -> Result<()> {
+        if self.config.authority.is_some() {
+            self.config.check_authority(self.payer.key())?;
+        }
+        require!(self.metadata.data_is_empty(), AmmError::LpMetadataExists);
+        require!(
+            uri.len() <= MAX_METADATA_URI_LENGTH,
+            AmmError::InvalidMetadataUri
+        );
+
+        // mints have no symbol on-chain, so the pair is named by its addresses
+        let (x, y) = (self.mint_x.key().to_string(), self.mint_y.key().to_string());
+        let data = DataV2 {
+            name: format!("TRUBIN-LP {}/{}", &x[..4], &y[..4]),
+            symbol: "TRB-LP".to_string(),
+            uri,
+            seller_fee_basis_points: 0,
+            creators: None,
+            collection: None,
+            uses: None,
+        };
+
+        let accounts = CreateMetadataAccountsV3 {
+            metadata: self.metadata.to_account_info(),
+            mint: self.mint_lp.to_account_info(),
+            mint_authority: self.config.to_account_info(),
+            payer: self.payer.to_account_info(),
+            update_authority: self.config.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            rent: self.rent.to_account_info(),
+        };
+        let seeds = &[
+            &b"config"[..],
+            self.config.mint_x.as_ref(),
+            self.config.mint_y.as_ref(),
+            &self.config.fee_tier.to_le_bytes(),
+            &[self.config.config_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        let ctx = CpiContext::new_with_signer(
+            self.metadata_program.to_account_info(),
+            accounts,
+            signer_seeds,
+        );
+        // immutable, so the config never has to sign for it again
+        create_metadata_accounts_v3(ctx, data, false, true, None)
+    }
+}
@@ -0,0 +1,112 @@
+use anchor_lang::{
+    prelude::*,
+    system_program::{transfer, Transfer},
+    Discriminator,
+};
+use anchor_spl::{
+    associated_token::get_associated_token_address_with_program_id, token_interface::Mint,
+};
+
+use crate::{constants::CONFIG_VERSION, error::AmmError, state::Config};
+
+#[derive(Accounts)]
+pub struct MigrateConfig<'info> {
+    /// Anyone can migrate a pool, paying the rent of the added space.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: an outdated layout need not deserialize as the current Config, so it
+    /// is only checked for the owner here and the discriminator in the handler
+    #[account(mut, owner = crate::ID)]
+    pub config: UncheckedAccount<'info>,
+    /// CHECK: compared to the config in the handler, only read for its token program
+    /// and decimals
+    pub mint_x: UncheckedAccount<'info>,
+    /// CHECK: as mint_x
+    pub mint_y: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MigrateConfig<'info> {
+    /// Grows the config to the current layout and sets its version. Every field
+    /// added since that version is set to its off or empty default, except for the
+    /// vault addresses which older pools always had at the vault authority's ATAs
+    /// and the decimals, read off the mints. Zeroing the new space is not enough,
+    /// as a shorter Option written over an old layout leaves its end behind.
+    pub fn migrate(&mut self) -> Result<()> {
+        let info = self.config.to_account_info();
+        require!(
+            info.try_borrow_data()?.starts_with(Config::DISCRIMINATOR),
+            ErrorCode::AccountDiscriminatorMismatch
+        );
+
+        let (old_len, new_len) = (info.data_len(), 8 + Config::INIT_SPACE);
+        if old_len < new_len {
+            let rent = Rent::get()?
+                .minimum_balance(new_len)
+                .saturating_sub(info.lamports());
+            if rent > 0 {
+                let accounts = Transfer {
+                    from: self.payer.to_account_info(),
+                    to: info.clone(),
+                };
+                transfer(
+                    CpiContext::new(self.system_program.to_account_info(), accounts),
+                    rent,
+                )?;
+            }
+            info.resize(new_len)?;
+            info.try_borrow_mut_data()?[old_len..].fill(0);
+        }
+
+        let mut config = Config::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        require!(config.version < CONFIG_VERSION, AmmError::ConfigUpToDate);
+        require_keys_eq!(self.mint_x.key(), config.mint_x, AmmError::InvalidToken);
+        require_keys_eq!(self.mint_y.key(), config.mint_y, AmmError::InvalidToken);
+        // what each version added after the one before
+        let version = config.version;
+        if version < 2 {
+            config.fee_exemptions = None;
+        }
+        if version < 3 {
+            let vault_authority = Pubkey::create_program_address(
+                &[b"auth", info.key.as_ref(), &[config.auth_bump]],
+                &crate::ID,
+            )
+            .map_err(|_| AmmError::BumpError)?;
+            config.vault_x = get_associated_token_address_with_program_id(
+                &vault_authority,
+                &config.mint_x,
+                self.mint_x.owner,
+            );
+            config.vault_y = get_associated_token_address_with_program_id(
+                &vault_authority,
+                &config.mint_y,
+                self.mint_y.owner,
+            );
+        }
+        if version < 4 {
+            config.min_trade_size = 0;
+        }
+        if version < 5 {
+            config.last_swap_slot = 0;
+            config.last_swap_price_q64 = 0;
+            config.trades_in_last_slot = 0;
+        }
+        if version < 6 {
+            config.locked_until = 0;
+        }
+        if version < 7 {
+            config.amp_ramp = None;
+        }
+        config.decimals_x =
+            Mint::try_deserialize(&mut &self.mint_x.try_borrow_data()?[..])?.decimals;
+        config.decimals_y =
+            Mint::try_deserialize(&mut &self.mint_y.try_borrow_data()?[..])?.decimals;
+        config.version = CONFIG_VERSION;
+        // nothing left behind for the next migration to read either
+        let mut data = info.try_borrow_mut_data()?;
+        data.fill(0);
+        config.try_serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+}
@@ -1,11 +1,84 @@
 pub mod initialize;
 pub use initialize::*;
 
+pub mod initialize_with_liquidity;
+pub use initialize_with_liquidity::*;
+
+pub mod lock_position;
+pub use lock_position::*;
+
 pub mod deposit;
 pub use deposit::*;
 
+pub mod deposit_single;
+
+pub mod donate;
+
 pub mod swap;
 pub use swap::*;
 
+pub mod swap_exact_out;
+
+pub mod swap_to_limit;
+pub use swap_to_limit::*;
+
+pub mod swap_partial;
+
+pub mod swap_batch;
+pub use swap_batch::*;
+
+pub mod swap_sol;
+
 pub mod withdraw;
 pub use withdraw::*;
+
+pub mod update;
+pub use update::*;
+
+pub mod collect;
+pub use collect::*;
+
+pub mod close;
+pub use close::*;
+
+pub mod quote;
+pub use quote::*;
+
+pub mod preview;
+pub use preview::*;
+
+pub mod pool_state;
+pub use pool_state::*;
+
+pub mod swap_route;
+pub use swap_route::*;
+
+pub mod observe;
+pub use observe::*;
+
+pub mod flash_swap;
+pub use flash_swap::*;
+
+pub mod sync;
+pub use sync::*;
+
+pub mod skim;
+pub use skim::*;
+
+pub mod harvest;
+pub use harvest::*;
+
+pub mod allowlist;
+pub use allowlist::*;
+
+pub mod fee_exemptions;
+pub use fee_exemptions::*;
+
+pub mod protocol;
+pub use protocol::*;
+
+pub mod migrate;
+pub use migrate::*;
+
+pub mod lp_metadata;
+pub use lp_metadata::*;
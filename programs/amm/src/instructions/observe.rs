@@ -0,0 +1,62 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+use crate::state::{Config, ObservationState};
+
+#[derive(Accounts)]
+pub struct UpdateObservation<'info> {
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        has_one = vault_x,
+        has_one = vault_y,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        has_one = config,
+        seeds = [b"observation", config.key().as_ref()],
+        bump = config.observation_bump,
+    )]
+    pub observation: AccountLoader<'info, ObservationState>,
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> UpdateObservation<'info> {
+    pub fn update_observation(&mut self) -> Result<()> {
+        // bring the accumulators up to the current timestamp before recording them
+        let (reserve_x, reserve_y) = (self.config.reserve_x, self.config.reserve_y);
+        self.config.update_twap(reserve_x, reserve_y)?;
+
+        let mut observation = self.observation.load_mut()?;
+        observation.write(
+            self.config.last_update_ts,
+            self.config.price_cumulative_x,
+            self.config.price_cumulative_y,
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use anchor_lang::prelude::*;
+
+use crate::instructions::Quote;
+
+/// A pool as get_pool_state finds it, every field read in the same transaction so
+/// they all hold at `slot`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct PoolSnapshot {
+    pub slot: u64,
+    /// Tracked reserves, which swaps price against.
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    /// Vault balances, above the reserves by whatever sync has not counted yet.
+    pub vault_x: u64,
+    pub vault_y: u64,
+    pub lp_supply: u64,
+    pub fee: u16,
+    /// The pool's cap on the protocol's share of the fee.
+    pub protocol_fee_bps: u16,
+    pub pause_flags: u8,
+    /// Unix timestamp of a set_lock, zero when unlocked.
+    pub locked_until: i64,
+    pub breaker_tripped: bool,
+    /// Price of x in y as Q64.64 in whole tokens, zero for an empty pool.
+    pub spot_price_q64: u128,
+    /// When the TWAP accumulators were last brought up to date.
+    pub last_update_ts: i64,
+}
+
+impl<'info> Quote<'info> {
+    pub fn pool_state(&self) -> Result<PoolSnapshot> {
+        let config = &self.config;
+        Ok(PoolSnapshot {
+            slot: Clock::get()?.slot,
+            reserve_x: config.reserve_x,
+            reserve_y: config.reserve_y,
+            vault_x: self.vault_x.amount,
+            vault_y: self.vault_y.amount,
+            lp_supply: self.mint_lp.supply,
+            fee: config.fee,
+            protocol_fee_bps: config.protocol_fee_bps,
+            pause_flags: config.pause_flags,
+            locked_until: config.locked_until,
+            breaker_tripped: config.breaker_tripped,
+            spot_price_q64: config.price_x_q64(config.reserve_x, config.reserve_y)?,
+            last_update_ts: config.last_update_ts,
+        })
+    }
+}
@@ -0,0 +1,66 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    constants::MINIMUM_LIQUIDITY, error::AmmError, instructions::Quote, state::Config,
+    utils::transfer_fee,
+};
+
+/// What `deposit_amounts` would take from the user and mint to them.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct DepositPreview {
+    pub lp_out: u64,
+    /// Taken from the user, before any transfer fee.
+    pub used_x: u64,
+    pub used_y: u64,
+}
+
+/// What `withdraw` would land in the user's accounts, net of transfer fees.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct WithdrawPreview {
+    pub x_out: u64,
+    pub y_out: u64,
+}
+
+impl<'info> Quote<'info> {
+    /// Walks through deposit_amounts with the pool as it is, a transfer fee on either
+    /// mint shrinking what the vault is credited with just like the real deposit.
+    pub fn preview_deposit(&self, amount_x: u64, amount_y: u64) -> Result<DepositPreview> {
+        require!(amount_x > 0 && amount_y > 0, AmmError::InvalidAmount);
+        let supply = self.mint_lp.supply;
+        let first_deposit = supply == 0 && self.config.reserve_x == 0 && self.config.reserve_y == 0;
+
+        // the LP the amounts are sized to, unused by the first deposit
+        let lp = match first_deposit {
+            true => 1,
+            false => self.config.lp_for(amount_x, amount_y, supply)?,
+        };
+        require!(lp > 0, AmmError::InvalidAmount);
+        let (used_x, used_y) = match first_deposit {
+            true => (amount_x, amount_y),
+            false => self.config.deposit_cost(lp, supply)?,
+        };
+        let received_x = used_x - transfer_fee(&self.mint_x, used_x)?;
+        let received_y = used_y - transfer_fee(&self.mint_y, used_y)?;
+
+        let lp_out = match first_deposit {
+            true => Config::initial_liquidity(received_x, received_y)? - MINIMUM_LIQUIDITY,
+            false => lp.min(self.config.lp_for(received_x, received_y, supply)?),
+        };
+        Ok(DepositPreview {
+            lp_out,
+            used_x,
+            used_y,
+        })
+    }
+
+    pub fn preview_withdraw(&self, lp_amount: u64) -> Result<WithdrawPreview> {
+        require!(lp_amount > 0, AmmError::InvalidAmount);
+        let (x, y) = self
+            .config
+            .withdraw_amounts(lp_amount, self.mint_lp.supply)?;
+        Ok(WithdrawPreview {
+            x_out: x - transfer_fee(&self.mint_x, x)?,
+            y_out: y - transfer_fee(&self.mint_y, y)?,
+        })
+    }
+}
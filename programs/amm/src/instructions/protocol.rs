@@ -0,0 +1,129 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    error::AmmError,
+    events::{ProtocolAuthorityProposed, ProtocolAuthorityUpdated, ProtocolFeeUpdated},
+    program::Amm,
+    state::ProtocolState,
+};
+
+/// Creates the protocol state, once, with the program's upgrade authority as its
+/// authority, so nobody can claim it ahead of the deployer. The upgrade authority
+/// is checked ahead of the init, which any other signer never gets to.
+#[derive(Accounts)]
+pub struct InitializeProtocol<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key())
+            @ AmmError::InvalidProtocolAuthority,
+    )]
+    pub program: Program<'info, Amm>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ AmmError::InvalidProtocolAuthority,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ProtocolState::INIT_SPACE,
+        seeds = [b"protocol"],
+        bump,
+    )]
+    pub protocol: Account<'info, ProtocolState>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProtocol<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority @ AmmError::InvalidProtocolAuthority,
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Account<'info, ProtocolState>,
+}
+
+/// Signed by the proposed authority, which takes over the protocol state.
+#[derive(Accounts)]
+pub struct AcceptProtocolAuthority<'info> {
+    pub pending_authority: Signer<'info>,
+    #[account(mut, seeds = [b"protocol"], bump = protocol.bump)]
+    pub protocol: Account<'info, ProtocolState>,
+}
+
+impl<'info> InitializeProtocol<'info> {
+    pub fn init(
+        &mut self,
+        protocol_fee_enabled: bool,
+        default_protocol_fee_bps: u16,
+        bumps: InitializeProtocolBumps,
+    ) -> Result<()> {
+        require!(default_protocol_fee_bps <= 10_000, AmmError::InvalidFee);
+        self.protocol.set_inner(ProtocolState {
+            authority: self.authority.key(),
+            pending_authority: None,
+            protocol_fee_enabled,
+            default_protocol_fee_bps,
+            bump: bumps.protocol,
+        });
+        Ok(())
+    }
+}
+
+impl<'info> UpdateProtocol<'info> {
+    /// Takes effect with the next swap of every pool, without a timelock: it only
+    /// moves part of the fee between the LPs and the protocol, never what traders pay.
+    pub fn set_protocol_fee(
+        &mut self,
+        protocol_fee_enabled: bool,
+        default_protocol_fee_bps: u16,
+    ) -> Result<()> {
+        require!(default_protocol_fee_bps <= 10_000, AmmError::InvalidFee);
+        self.protocol.protocol_fee_enabled = protocol_fee_enabled;
+        self.protocol.default_protocol_fee_bps = default_protocol_fee_bps;
+
+        emit!(ProtocolFeeUpdated {
+            protocol_fee_enabled,
+            default_protocol_fee_bps,
+        });
+        Ok(())
+    }
+
+    /// Proposes `authority`, which still has to accept_protocol_authority. A later
+    /// proposal replaces an unaccepted one.
+    pub fn propose_protocol_authority(&mut self, authority: Pubkey) -> Result<()> {
+        self.protocol.pending_authority = Some(authority);
+
+        emit!(ProtocolAuthorityProposed {
+            authority: self.protocol.authority,
+            pending_authority: authority,
+        });
+        Ok(())
+    }
+}
+
+impl<'info> AcceptProtocolAuthority<'info> {
+    pub fn accept_protocol_authority(&mut self) -> Result<()> {
+        match self.protocol.pending_authority {
+            Some(pending) => require_keys_eq!(
+                pending,
+                self.pending_authority.key(),
+                AmmError::InvalidProtocolAuthority
+            ),
+            None => return err!(AmmError::NoPendingProtocolAuthority),
+        }
+        let old = self.protocol.authority;
+        self.protocol.authority = self.pending_authority.key();
+        self.protocol.pending_authority = None;
+
+        emit!(ProtocolAuthorityUpdated {
+            old,
+            new: self.protocol.authority,
+        });
+        Ok(())
+    }
+}
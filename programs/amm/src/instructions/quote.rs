@@ -0,0 +1,64 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+use crate::{error::AmmError, state::Config, utils::transfer_fee};
+
+#[derive(Accounts)]
+pub struct Quote<'info> {
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [b"lp", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: InterfaceAccount<'info, Mint>,
+    #[account(
+        has_one = mint_x,
+        has_one = mint_y,
+        has_one = vault_x,
+        has_one = vault_y,
+        has_one = mint_lp,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> Quote<'info> {
+    /// Amount the user would receive from `swap`, net of transfer fees on both legs.
+    pub fn quote(&self, is_x: bool, amount: u64) -> Result<u64> {
+        if amount == 0 {
+            msg!("amount_in: {}", amount);
+            return err!(AmmError::ZeroAmountIn);
+        }
+
+        let (mint_in, mint_out) = match is_x {
+            true => (&self.mint_x, &self.mint_y),
+            false => (&self.mint_y, &self.mint_x),
+        };
+
+        let received = amount - transfer_fee(mint_in, amount)?;
+        let quote = self.config.quote_swap(is_x, received)?;
+
+        Ok(quote.amount_out - transfer_fee(mint_out, quote.amount_out)?)
+    }
+}
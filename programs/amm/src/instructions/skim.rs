@@ -0,0 +1,130 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{state::Config, utils::transfer_checked_with_hook};
+
+#[derive(Accounts)]
+pub struct Skim<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    /// CHECK: only used as the authority of the recipient token accounts
+    pub fee_recipient: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        has_one = vault_x,
+        has_one = vault_y,
+        has_one = fee_recipient,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_x,
+        associated_token::authority = fee_recipient,
+        associated_token::token_program = token_program_x,
+    )]
+    pub recipient_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_y,
+        associated_token::authority = fee_recipient,
+        associated_token::token_program = token_program_y,
+    )]
+    pub recipient_y: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> Skim<'info> {
+    /// Sweeps whatever the vaults hold above the reserves to the fee recipient, or
+    /// into the reserves like `sync` when the pool has `skim_to_lps` set. Callable by
+    /// anyone, and a no-op without a surplus.
+    pub fn skim(&mut self, hook_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        // mid flash swap the reserves are short of the loan while the vaults may
+        // already hold its repayment, which is no surplus either way
+        self.config.check_settled()?;
+        if self.config.skim_to_lps {
+            self.config
+                .update_twap(self.config.reserve_x, self.config.reserve_y)?;
+            self.config.reserve_x = self.vault_x.amount;
+            self.config.reserve_y = self.vault_y.amount;
+            return Ok(());
+        }
+
+        let surplus_x = self.vault_x.amount.saturating_sub(self.config.reserve_x);
+        let surplus_y = self.vault_y.amount.saturating_sub(self.config.reserve_y);
+        self.skim_token(true, surplus_x, hook_accounts)?;
+        self.skim_token(false, surplus_y, hook_accounts)
+    }
+
+    pub fn skim_token(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let (mint, decimals, from, to, token_program) = match is_x {
+            true => (
+                self.mint_x.to_account_info(),
+                self.mint_x.decimals,
+                self.vault_x.to_account_info(),
+                self.recipient_x.to_account_info(),
+                self.token_program_x.to_account_info(),
+            ),
+            false => (
+                self.mint_y.to_account_info(),
+                self.mint_y.decimals,
+                self.vault_y.to_account_info(),
+                self.recipient_y.to_account_info(),
+                self.token_program_y.to_account_info(),
+            ),
+        };
+
+        let account = TransferChecked {
+            from,
+            mint,
+            to,
+            authority: self.vault_authority.to_account_info(),
+        };
+
+        let config_key = self.config.key();
+        let seeds = &[&b"auth"[..], config_key.as_ref(), &[self.config.auth_bump]];
+
+        let signer_seeds = &[&seeds[..]];
+
+        let ctx = CpiContext::new_with_signer(token_program, account, signer_seeds)
+            .with_remaining_accounts(hook_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)
+    }
+}
@@ -1,123 +1,542 @@
-use anchor_lang::prelude::*;
+use anchor_lang::{prelude::*, solana_program::program_option::COption};
 use anchor_spl::{
-    associated_token::AssociatedToken,
-    token::{transfer, transfer_checked, Mint, Token, TokenAccount, Transfer, TransferChecked},
+    associated_token::{get_associated_token_address_with_program_id, AssociatedToken},
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
 };
-use constant_product_curve::{ConstantProduct, LiquidityPair};
 
-use crate::{error::AmmError, state::Config};
+use crate::{
+    constants::{MAX_REFERRAL_FEE_BPS, PAUSE_SWAPS},
+    error::AmmError,
+    events::{CircuitBreakerTripped, SwapEvent},
+    math::mul_div_floor,
+    state::{Allowlist, Config, FeeExemptions, ProtocolState},
+    utils::{
+        check_deadline, check_invariant, emit_event_cpi, load_token_account,
+        transfer_checked_with_hook, transfer_fee, transfer_protocol_share,
+    },
+};
 
+/// Kept to what a swap reads, as aggregators budget around 60-80k compute units a
+/// hop: the LP mint is only matched against the config, so its token program is not
+/// passed either, and the larger accounts are boxed to keep them off the stack. The
+/// mints are still deserialized for their decimals and transfer fees; the ATA
+/// derivations of the vaults and user accounts are most of what is left.
+///
+/// Events are recorded by self-CPI, which adds the event authority PDA
+/// (`[b"__event_authority"]`) and this program as the last two accounts.
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Swap<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
-    pub mint_x: Account<'info, Mint>,
-    pub mint_y: Account<'info, Mint>,
-    #[account(
-        seeds = [b"lp",config.key().as_ref()],
-        bump=config.lp_bump,
-        mint::decimals = 6,
-        mint::authority = config,
-    )]
-    pub mint_lp: Account<'info, Mint>,
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+    /// CHECK: only has to be the pool's, which has_one checks on the config
+    pub mint_lp: UncheckedAccount<'info>,
     #[account(
+        mut,
         has_one = mint_x,
         has_one = mint_y,
-        seeds =[b"config",config.seed.to_le_bytes().as_ref()],
+        has_one = vault_x,
+        has_one = vault_y,
+        has_one = mint_lp,
+        // derived from the mints passed in, so another pair's mints fail the seeds
+        // before has_one is even checked
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
         bump = config.config_bump
     )]
-    pub config: Account<'info, Config>,
+    pub config: Box<Account<'info, Config>>,
+    /// Decides whether the protocol's share of the fee is routed, and how much.
+    #[account(seeds = [b"protocol"], bump = protocol.bump)]
+    pub protocol: Box<Account<'info, ProtocolState>>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
-        associated_token::mint = mint_x,
-        associated_token::authority = config,
+        seeds = [b"fee_vault_x", config.key().as_ref()],
+        bump = config.fee_vault_x_bump,
+        token::mint = mint_x,
+        token::authority = vault_authority,
+        token::token_program = token_program_x,
     )]
-    pub vault_x: Account<'info, TokenAccount>,
+    pub fee_vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
-        associated_token::mint = mint_y,
-        associated_token::authority = config,
+        seeds = [b"fee_vault_y", config.key().as_ref()],
+        bump = config.fee_vault_y_bump,
+        token::mint = mint_y,
+        token::authority = vault_authority,
+        token::token_program = token_program_y,
     )]
-    pub vault_y: Account<'info, TokenAccount>,
+    pub fee_vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Both user accounts are created on first use with the user paying the rent, so
+    /// swapping into a token never held before needs no extra instruction.
     #[account(
-        mut,
+        init_if_needed,
+        payer = user,
         associated_token::mint = mint_x,
         associated_token::authority = user,
+        associated_token::token_program = token_program_x,
     )]
-    pub user_x: Account<'info, TokenAccount>,
+    pub user_x: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
-        mut,
+        init_if_needed,
+        payer = user,
         associated_token::mint = mint_y,
         associated_token::authority = user,
+        associated_token::token_program = token_program_y,
     )]
-    pub user_y: Account<'info, TokenAccount>,
+    pub user_y: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Optional aggregator account receiving a share of the output, must be of the output mint.
+    #[account(mut)]
+    pub referral: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Optional account the output is delivered to instead of the user's, must be of
+    /// the output mint.
+    #[account(mut)]
+    pub recipient: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Optional account of the input mint the user spends from as its delegate, in
+    /// place of its own. The output then has to go to the owner's ATA as recipient.
+    #[account(mut)]
+    pub source: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Required by permissioned pools, the user has to be on it.
+    pub allowlist: Option<Account<'info, Allowlist>>,
+    /// The pool's exemption list, swaps at a zero fee if the user is on it.
+    pub fee_exemptions: Option<Account<'info, FeeExemptions>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
 impl<'info> Swap<'info> {
-    pub fn swap(&mut self, is_x: bool, amount: u64, min: u64) -> Result<()> {
-        require!(self.config.locked == false, AmmError::PoolLocked);
-        require!(amount != 0, AmmError::InvalidAmount);
-
-        let mut curve = ConstantProduct::init(
-            self.vault_x.amount,
-            self.vault_y.amount,
-            self.mint_lp.supply,
-            self.config.fee,
-            None,
-        )
-        .map_err(AmmError::from)?;
+    /// Passes for pools without an allowlist, otherwise the user has to be on the one
+    /// the config points at.
+    pub fn check_allowlisted(&self) -> Result<()> {
+        let Some(expected) = self.config.allowlist else {
+            return Ok(());
+        };
+        let allowlist = self.allowlist.as_ref().ok_or(AmmError::NotAllowlisted)?;
+        require_keys_eq!(allowlist.key(), expected, AmmError::NotAllowlisted);
+        require!(
+            allowlist.contains(&self.user.key()),
+            AmmError::NotAllowlisted
+        );
+        Ok(())
+    }
 
-        let p = match is_x {
-            true => LiquidityPair::X,
-            false => LiquidityPair::Y,
+    /// Whether the user is on the exemption list the config points at, never without
+    /// the account.
+    pub fn fee_exempt(&self) -> Result<bool> {
+        let (Some(expected), Some(exemptions)) =
+            (self.config.fee_exemptions, self.fee_exemptions.as_ref())
+        else {
+            return Ok(false);
         };
+        require_keys_eq!(exemptions.key(), expected, AmmError::InvalidFeeExemptions);
+        Ok(exemptions.contains(&self.user.key()))
+    }
 
-        let result = curve.swap(p, amount, min).map_err(AmmError::from)?;
+    /// The fee the user's swaps are charged, the pool fee unless fee_exempt.
+    pub fn swap_fee(&self) -> Result<u16> {
+        Ok(match self.fee_exempt()? {
+            true => 0,
+            false => self.config.fee,
+        })
+    }
 
-        self.deposit_token(is_x, result.deposit)?;
-        self.withdraw_token(is_x, result.withdraw)
+    /// The protocol's share of the fee, in bps, the pool's own being the cap.
+    pub fn protocol_fee_bps(&self) -> u16 {
+        self.protocol.protocol_fee_bps(self.config.protocol_fee_bps)
     }
 
-    pub fn deposit_token(&mut self, is_x: bool, amount: u64) -> Result<()> {
-        let (mint, from, to) = match is_x {
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        min_amount_out: u64,
+        referral_fee_bps: u16,
+        deadline: i64,
+        max_price_impact_bps: u16,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<u64> {
+        check_deadline(deadline)?;
+        self.config
+            .check_not_paused(PAUSE_SWAPS, AmmError::SwapsPaused)?;
+        self.config.check_open()?;
+        self.config.check_breaker()?;
+        self.check_allowlisted()?;
+        if amount == 0 {
+            msg!("amount_in: {}", amount);
+            return err!(AmmError::ZeroAmountIn);
+        }
+        require!(self.config.fee <= 10_000, AmmError::InvalidFee);
+        let fee = self.swap_fee()?;
+        require!(
+            referral_fee_bps <= MAX_REFERRAL_FEE_BPS,
+            AmmError::InvalidReferralFee
+        );
+        // past 10_000 the limit could never bind, which a client would not mean
+        require!(
+            max_price_impact_bps <= 10_000,
+            AmmError::InvalidPriceImpactLimit
+        );
+
+        let (reserve_x, reserve_y) = (self.config.reserve_x, self.config.reserve_y);
+        self.config.update_twap(reserve_x, reserve_y)?;
+        if reserve_x == 0 || reserve_y == 0 {
+            msg!("reserve_x: {}, reserve_y: {}", reserve_x, reserve_y);
+            return err!(AmmError::NoLiquidityInPool);
+        }
+        // a minimum the pool can never pay is a malformed parameter, not slippage
+        let reserve_out = match is_x {
+            true => reserve_y,
+            false => reserve_x,
+        };
+        if min_amount_out >= reserve_out {
+            msg!(
+                "min_amount_out: {}, reserve_out: {}",
+                min_amount_out,
+                reserve_out
+            );
+            return err!(AmmError::SlippageParamTooLarge);
+        }
+
+        if self.trip_breaker(is_x, amount, fee, event_authority_bump)? {
+            return Ok(0);
+        }
+
+        // price against what the vault actually received, which is less than
+        // amount for mints with a transfer fee
+        let vaults_before = (self.vault_x.amount, self.vault_y.amount);
+        let received = self.deposit_and_measure(is_x, amount, hook_accounts)?;
+        self.config
+            .check_price_impact(is_x, received, max_price_impact_bps, fee)?;
+        let quote = self.config.quote_swap_at_fee(is_x, received, fee)?;
+
+        let mint_out = match is_x {
+            true => &self.mint_y,
+            false => &self.mint_x,
+        };
+        // the whole output of a delegated swap belongs to the owner
+        require!(
+            self.source.is_none() || self.referral.is_none(),
+            AmmError::InvalidDelegateRecipient
+        );
+        let referral_amount = match &self.referral {
+            Some(referral) => {
+                require_keys_eq!(referral.mint, mint_out.key(), AmmError::InvalidToken);
+                mul_div_floor(quote.amount_out, referral_fee_bps as u64, 10_000)
+                    .ok_or(AmmError::MathOverflow)?
+            }
+            None => 0,
+        };
+
+        // and check the minimum against what the user, or the recipient, will actually
+        // receive
+        let user_amount = quote.amount_out - referral_amount;
+        let amount_out = user_amount - transfer_fee(mint_out, user_amount)?;
+        if amount_out < min_amount_out {
+            msg!(
+                "amount_in: {}, amount_out: {}, min_amount_out: {}",
+                amount,
+                amount_out,
+                min_amount_out
+            );
+            return err!(AmmError::SlippageExceeded);
+        }
+
+        let share = self
+            .config
+            .book_swap_at(is_x, received, &quote, self.protocol_fee_bps())?;
+        self.transfer_protocol_share(self.config.fee_is_x(is_x), share, hook_accounts)?;
+        if let Some(referral) = self.referral.as_ref().map(|r| r.to_account_info()) {
+            if referral_amount > 0 {
+                self.withdraw_token_to(is_x, referral, referral_amount, hook_accounts)?;
+            }
+        }
+        self.withdraw_token(is_x, user_amount, hook_accounts)?;
+        // emit_swap reloads the vaults
+        self.emit_swap(is_x, received, user_amount, quote.fee, event_authority_bump)?;
+        check_invariant(
+            self.config.curve_now()?,
+            self.config.precision()?,
+            vaults_before,
+            (self.vault_x.amount, self.vault_y.amount),
+        )?;
+        Ok(user_amount)
+    }
+
+    /// Trips the circuit breaker if selling `amount` at `fee` would move the price
+    /// past it. Checked before anything moves, so the swap can then succeed without
+    /// trading and the flag is kept.
+    pub fn trip_breaker(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        fee: u16,
+        event_authority_bump: u8,
+    ) -> Result<bool> {
+        if self.config.circuit_breaker_bps == 0 {
+            return Ok(false);
+        }
+        let mint_in = match is_x {
+            true => &self.mint_x,
+            false => &self.mint_y,
+        };
+        let received = amount - transfer_fee(mint_in, amount)?;
+
+        // booked on a copy with the breaker off, which would otherwise fail it
+        let mut after = Config::clone(&self.config);
+        after.circuit_breaker_bps = 0;
+        let quote = after.quote_swap_at_fee(is_x, received, fee)?;
+        after.book_swap_at(is_x, received, &quote, self.protocol_fee_bps())?;
+        after.circuit_breaker_bps = self.config.circuit_breaker_bps;
+        let before = (self.config.reserve_x, self.config.reserve_y);
+        let Some(price_move_bps) = after.breaker_move(before)? else {
+            return Ok(false);
+        };
+
+        self.config.breaker_tripped = true;
+        emit_event_cpi(
+            &self.event_authority,
+            event_authority_bump,
+            &CircuitBreakerTripped {
+                config: self.config.key(),
+                user: self.user.key(),
+                x_to_y: is_x,
+                amount_in: amount,
+                price_move_bps,
+            },
+        )?;
+        Ok(true)
+    }
+
+    pub fn deposit_and_measure(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<u64> {
+        let before = match is_x {
+            true => self.vault_x.amount,
+            false => self.vault_y.amount,
+        };
+        self.deposit_token(is_x, amount, hook_accounts)?;
+
+        let after = match is_x {
+            true => {
+                self.vault_x.reload()?;
+                self.vault_x.amount
+            }
+            false => {
+                self.vault_y.reload()?;
+                self.vault_y.amount
+            }
+        };
+        Ok(after.checked_sub(before).ok_or(AmmError::Underflow)?)
+    }
+
+    pub fn transfer_protocol_share(
+        &mut self,
+        is_x: bool,
+        share: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (mint, vault, fee_vault, token_program) = match is_x {
+            true => (
+                &self.mint_x,
+                self.vault_x.to_account_info(),
+                self.fee_vault_x.to_account_info(),
+                self.token_program_x.to_account_info(),
+            ),
+            false => (
+                &self.mint_y,
+                self.vault_y.to_account_info(),
+                self.fee_vault_y.to_account_info(),
+                self.token_program_y.to_account_info(),
+            ),
+        };
+        transfer_protocol_share(
+            &self.config,
+            self.vault_authority.to_account_info(),
+            mint,
+            vault,
+            fee_vault,
+            token_program,
+            hook_accounts,
+            share,
+        )
+    }
+
+    pub fn emit_swap(
+        &mut self,
+        is_x: bool,
+        amount_in: u64,
+        amount_out: u64,
+        fee_amount: u64,
+        event_authority_bump: u8,
+    ) -> Result<()> {
+        // the account structs still hold the pre-transfer snapshot
+        self.vault_x.reload()?;
+        self.vault_y.reload()?;
+
+        let event = SwapEvent {
+            user: self.user.key(),
+            recipient: self.output_account(is_x)?.key(),
+            x_to_y: is_x,
+            amount_in,
+            amount_out,
+            fee_amount,
+            fee_exempt: self.fee_exempt()?,
+            vault_x: self.vault_x.amount,
+            vault_y: self.vault_y.amount,
+        };
+        emit_event_cpi(&self.event_authority, event_authority_bump, &event)
+    }
+
+    pub fn deposit_token(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let from = self.input_account(is_x, amount)?;
+        let (mint, decimals, to, token_program) = match is_x {
             true => (
                 self.mint_x.to_account_info(),
-                self.user_x.to_account_info(),
+                self.mint_x.decimals,
                 self.vault_x.to_account_info(),
+                self.token_program_x.to_account_info(),
             ),
             false => (
                 self.mint_y.to_account_info(),
-                self.user_y.to_account_info(),
+                self.mint_y.decimals,
                 self.vault_y.to_account_info(),
+                self.token_program_y.to_account_info(),
             ),
         };
 
+        // read from the account itself, an earlier swap of the same batch may have
+        // moved its balance
+        let balance = load_token_account(&from, token_program.key)?.amount;
+        if balance < amount {
+            msg!("amount_in: {}, balance: {}", amount, balance);
+            return err!(AmmError::InsufficientBalance);
+        }
+
         let account = TransferChecked {
             from,
             mint,
             to,
             authority: self.user.to_account_info(),
         };
-        let ctx = CpiContext::new(self.token_program.to_account_info(), account);
-        transfer_checked(ctx, amount, 6)
+        let ctx =
+            CpiContext::new(token_program, account).with_remaining_accounts(hook_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)
+    }
+
+    pub fn withdraw_token(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let to = self.output_account(is_x)?;
+        self.withdraw_token_to(is_x, to, amount, hook_accounts)
+    }
+
+    /// Where the input of a swap selling x if `is_x` comes from, the source if one was
+    /// passed and the user's account otherwise. The token program enforces the
+    /// allowance as well, checking it here only makes for a clearer error.
+    pub fn input_account(&self, is_x: bool, amount: u64) -> Result<AccountInfo<'info>> {
+        let (mint_in, user_in) = match is_x {
+            true => (self.mint_x.key(), &self.user_x),
+            false => (self.mint_y.key(), &self.user_y),
+        };
+        match &self.source {
+            Some(source) => {
+                require_keys_eq!(source.mint, mint_in, AmmError::InvalidToken);
+                require!(
+                    source.delegate == COption::Some(self.user.key()),
+                    AmmError::InvalidDelegate
+                );
+                require!(
+                    source.delegated_amount >= amount,
+                    AmmError::DelegateAllowanceExceeded
+                );
+                Ok(source.to_account_info())
+            }
+            None => Ok(user_in.to_account_info()),
+        }
     }
 
-    pub fn withdraw_token(&mut self, is_x: bool, amount: u64) -> Result<()> {
-        let (mint, from, to) = match is_x {
+    /// Where the output of a swap selling x if `is_x` goes, the recipient if one was
+    /// passed and the user's account otherwise.
+    pub fn output_account(&self, is_x: bool) -> Result<AccountInfo<'info>> {
+        let (mint_out, user_out, token_program_out) = match is_x {
+            true => (self.mint_y.key(), &self.user_y, &self.token_program_y),
+            false => (self.mint_x.key(), &self.user_x, &self.token_program_x),
+        };
+        match &self.recipient {
+            Some(recipient) => {
+                require_keys_eq!(recipient.mint, mint_out, AmmError::InvalidToken);
+                // a delegate spends on the owner's behalf, so it cannot redirect the output
+                if let Some(source) = &self.source {
+                    let owner_out = get_associated_token_address_with_program_id(
+                        &source.owner,
+                        &mint_out,
+                        &token_program_out.key(),
+                    );
+                    require_keys_eq!(
+                        recipient.key(),
+                        owner_out,
+                        AmmError::InvalidDelegateRecipient
+                    );
+                }
+                Ok(recipient.to_account_info())
+            }
+            None => {
+                require!(self.source.is_none(), AmmError::InvalidDelegateRecipient);
+                Ok(user_out.to_account_info())
+            }
+        }
+    }
+
+    pub fn withdraw_token_to(
+        &mut self,
+        is_x: bool,
+        to: AccountInfo<'info>,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (mint, decimals, from, token_program) = match is_x {
             true => (
-                self.mint_x.to_account_info(),
+                self.mint_y.to_account_info(),
+                self.mint_y.decimals,
                 self.vault_y.to_account_info(),
-                self.user_y.to_account_info(),
+                self.token_program_y.to_account_info(),
             ),
             false => (
-                self.mint_y.to_account_info(),
+                self.mint_x.to_account_info(),
+                self.mint_x.decimals,
                 self.vault_x.to_account_info(),
-                self.user_x.to_account_info(),
+                self.token_program_x.to_account_info(),
             ),
         };
 
@@ -125,24 +544,17 @@ impl<'info> Swap<'info> {
             from,
             mint,
             to,
-            authority: self.config.to_account_info(),
+            authority: self.vault_authority.to_account_info(),
         };
 
-        // [b"config",config.seed.to_le_bytes().as_ref()]
-        let seeds = &[
-            &b"config"[..],
-            &self.config.seed.to_le_bytes(),
-            &[self.config.config_bump],
-        ];
+        let config_key = self.config.key();
+        let seeds = &[&b"auth"[..], config_key.as_ref(), &[self.config.auth_bump]];
 
         let signer_seeds = &[&seeds[..]];
 
-        let ctx = CpiContext::new_with_signer(
-            self.token_program.to_account_info(),
-            account,
-            signer_seeds,
-        );
-        transfer_checked(ctx, amount, 6)
+        let ctx = CpiContext::new_with_signer(token_program, account, signer_seeds)
+            .with_remaining_accounts(hook_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)
         // todo!()
     }
 }
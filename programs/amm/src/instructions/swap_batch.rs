@@ -0,0 +1,51 @@
+use anchor_lang::prelude::*;
+
+use crate::{constants::MAX_SWAP_LEGS, error::AmmError, instructions::Swap, utils::check_deadline};
+
+/// One swap of a batch, with the same meaning as the arguments of `swap`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct SwapLeg {
+    pub x_to_y: bool,
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+}
+
+impl<'info> Swap<'info> {
+    /// Runs up to MAX_SWAP_LEGS swaps against the pool in order, each priced against
+    /// the reserves the previous one left. Any leg below its minimum fails them all,
+    /// while a leg that trips the circuit breaker ends the batch there, the legs before
+    /// it standing. The referral and recipient apply to every leg, so they only fit
+    /// batches in a single direction.
+    pub fn swap_batch(
+        &mut self,
+        legs: Vec<SwapLeg>,
+        deadline: i64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+        require!(
+            !legs.is_empty() && legs.len() <= MAX_SWAP_LEGS as usize,
+            AmmError::InvalidLegCount
+        );
+
+        // swap reloads the vaults after every leg
+        for leg in legs {
+            self.swap(
+                leg.x_to_y,
+                leg.amount_in,
+                leg.min_amount_out,
+                0,
+                0,
+                0,
+                hook_accounts,
+                event_authority_bump,
+            )?;
+            // the next leg would fail on the breaker, rolling back the trip with it
+            if self.config.breaker_tripped {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    constants::PAUSE_SWAPS,
+    error::AmmError,
+    instructions::Swap,
+    math::mul_div_ceil,
+    state::FeeMode,
+    utils::{check_deadline, check_invariant},
+};
+
+impl<'info> Swap<'info> {
+    pub fn swap_exact_out(
+        &mut self,
+        is_x: bool,
+        amount_out: u64,
+        max_amount_in: u64,
+        deadline: i64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+        self.config
+            .check_not_paused(PAUSE_SWAPS, AmmError::SwapsPaused)?;
+        self.config.check_open()?;
+        self.config.check_breaker()?;
+        self.check_allowlisted()?;
+        if amount_out == 0 {
+            msg!("amount_out: {}", amount_out);
+            return err!(AmmError::AmountOutTooSmall);
+        }
+        require!(self.config.fee <= 10_000, AmmError::InvalidFee);
+        let fee = self.swap_fee()?;
+
+        let (reserve_x, reserve_y) = (self.config.reserve_x, self.config.reserve_y);
+        self.config.update_twap(reserve_x, reserve_y)?;
+        let (reserve_in, reserve_out) = match is_x {
+            true => (reserve_x, reserve_y),
+            false => (reserve_y, reserve_x),
+        };
+        if reserve_in == 0 || reserve_out == 0 {
+            msg!("reserve_in: {}, reserve_out: {}", reserve_in, reserve_out);
+            return err!(AmmError::NoLiquidityInPool);
+        }
+        require!(
+            amount_out < reserve_out,
+            AmmError::InsufficientPoolLiquidity
+        );
+
+        let fee_denominator = 10_000u64
+            .checked_sub(fee as u64)
+            .filter(|d| *d > 0)
+            .ok_or(AmmError::InvalidFee)?;
+        let (curve, scales) = (self.config.curve_now()?, self.config.precision()?);
+        let amount_in = match self.config.fee_mode {
+            // grossed up for the fee taken on the input side
+            FeeMode::Input => {
+                curve.get_amount_in(is_x, reserve_in, reserve_out, amount_out, fee, scales)
+            }
+            FeeMode::Output => {
+                // the output before its fee, such that taking the rounded-up fee off it
+                // still leaves amount_out
+                let gross = mul_div_ceil(amount_out, 10_000, fee_denominator)
+                    .ok_or(AmmError::MathOverflow)?;
+                require!(gross < reserve_out, AmmError::InsufficientPoolLiquidity);
+                curve.get_amount_in(is_x, reserve_in, reserve_out, gross, 0, scales)
+            }
+        }
+        .ok_or(AmmError::MathOverflow)?;
+
+        if amount_in > max_amount_in {
+            msg!(
+                "amount_out: {}, amount_in: {}, max_amount_in: {}",
+                amount_out,
+                amount_in,
+                max_amount_in
+            );
+            return err!(AmmError::SlippageExceeded);
+        }
+
+        // settle through the same quote as swap so both paths can never diverge; the
+        // rounded-up input always yields at least amount_out, unless a transfer fee on
+        // the input mint shrinks what the vault receives
+        let vaults_before = (self.vault_x.amount, self.vault_y.amount);
+        let received = self.deposit_and_measure(is_x, amount_in, hook_accounts)?;
+        let quote = self.config.quote_swap_at_fee(is_x, received, fee)?;
+        if quote.amount_out < amount_out {
+            msg!(
+                "received: {}, amount_out: {}, requested: {}",
+                received,
+                quote.amount_out,
+                amount_out
+            );
+            return err!(AmmError::SlippageExceeded);
+        }
+
+        let share = self
+            .config
+            .book_swap_at(is_x, received, &quote, self.protocol_fee_bps())?;
+        self.transfer_protocol_share(self.config.fee_is_x(is_x), share, hook_accounts)?;
+        self.withdraw_token(is_x, quote.amount_out, hook_accounts)?;
+        // emit_swap reloads the vaults
+        self.emit_swap(
+            is_x,
+            received,
+            quote.amount_out,
+            quote.fee,
+            event_authority_bump,
+        )?;
+        check_invariant(
+            curve,
+            scales,
+            vaults_before,
+            (self.vault_x.amount, self.vault_y.amount),
+        )
+    }
+}
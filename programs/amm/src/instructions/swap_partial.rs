@@ -0,0 +1,58 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    error::AmmError,
+    instructions::{Swap, SwapFill},
+    math::mul_div_ceil,
+};
+
+impl<'info> Swap<'info> {
+    /// Swaps as much of `amount` as the tighter of the pool's and the caller's price
+    /// impact limit allows, where `swap` would fail on the whole of it. The minimum
+    /// out is a rate rather than an amount: it is scaled by the share of `amount`
+    /// sold, rounded up, so a partial fill is held to the same price.
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_partial(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        min_amount_out: u64,
+        max_price_impact_bps: u16,
+        deadline: i64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<SwapFill> {
+        if amount == 0 {
+            msg!("amount_in: {}", amount);
+            return err!(AmmError::ZeroAmountIn);
+        }
+        let fee = self.swap_fee()?;
+        let amount_in = self
+            .config
+            .fill_within_impact(is_x, amount, max_price_impact_bps, fee)?;
+        if amount_in == 0 {
+            msg!(
+                "amount_in: {}, max_price_impact_bps: {}",
+                amount,
+                max_price_impact_bps
+            );
+            return err!(AmmError::PriceImpactTooHigh);
+        }
+        let min_amount_out =
+            mul_div_ceil(min_amount_out, amount_in, amount).ok_or(AmmError::Overflow)?;
+        let amount_out = self.swap(
+            is_x,
+            amount_in,
+            min_amount_out,
+            0,
+            deadline,
+            max_price_impact_bps,
+            hook_accounts,
+            event_authority_bump,
+        )?;
+        Ok(SwapFill {
+            amount_in,
+            amount_out,
+        })
+    }
+}
@@ -0,0 +1,353 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{
+    constants::PAUSE_SWAPS,
+    error::AmmError,
+    state::{Config, FeeMode, ProtocolState, SwapQuote},
+    utils::{check_invariant, transfer_checked_with_hook, transfer_protocol_share},
+};
+
+#[derive(Accounts)]
+pub struct SwapRoute<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mint::token_program = token_program_in)]
+    pub mint_in: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_mid)]
+    pub mint_mid: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_out)]
+    pub mint_out: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = config_a.has_pair(mint_in.key(), mint_mid.key()) @ AmmError::InvalidToken,
+        seeds = [
+            b"config",
+            config_a.mint_x.as_ref(),
+            config_a.mint_y.as_ref(),
+            config_a.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config_a.config_bump
+    )]
+    pub config_a: Box<Account<'info, Config>>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config_a.key().as_ref()], bump = config_a.auth_bump)]
+    pub vault_authority_a: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"lp", config_a.key().as_ref()],
+        bump = config_a.lp_bump,
+    )]
+    pub mint_lp_a: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, address = config_a.vault(mint_in.key()))]
+    pub vault_a_in: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = config_a.vault(mint_mid.key()))]
+    pub vault_a_mid: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Protocol fee vault of pool a in the mint its fee mode charges the first hop in.
+    #[account(
+        mut,
+        seeds = [
+            config_a.fee_vault_seed(config_a.fee_mint(mint_in.key(), mint_mid.key())),
+            config_a.key().as_ref(),
+        ],
+        bump = config_a.fee_vault_bump(config_a.fee_mint(mint_in.key(), mint_mid.key())),
+        token::authority = vault_authority_a,
+    )]
+    pub fee_vault_a: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Another pool than config_a, whose booking would otherwise be written over.
+    #[account(
+        mut,
+        constraint = config_b.key() != config_a.key() @ AmmError::SamePool,
+        constraint = config_b.has_pair(mint_mid.key(), mint_out.key()) @ AmmError::InvalidToken,
+        seeds = [
+            b"config",
+            config_b.mint_x.as_ref(),
+            config_b.mint_y.as_ref(),
+            config_b.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config_b.config_bump
+    )]
+    pub config_b: Box<Account<'info, Config>>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config_b.key().as_ref()], bump = config_b.auth_bump)]
+    pub vault_authority_b: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"lp", config_b.key().as_ref()],
+        bump = config_b.lp_bump,
+    )]
+    pub mint_lp_b: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut, address = config_b.vault(mint_mid.key()))]
+    pub vault_b_mid: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = config_b.vault(mint_out.key()))]
+    pub vault_b_out: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Protocol fee vault of pool b in the mint its fee mode charges the second hop in.
+    #[account(
+        mut,
+        seeds = [
+            config_b.fee_vault_seed(config_b.fee_mint(mint_mid.key(), mint_out.key())),
+            config_b.key().as_ref(),
+        ],
+        bump = config_b.fee_vault_bump(config_b.fee_mint(mint_mid.key(), mint_out.key())),
+        token::authority = vault_authority_b,
+    )]
+    pub fee_vault_b: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Decides whether the protocol's share of each hop's fee is routed, and how much.
+    #[account(seeds = [b"protocol"], bump = protocol.bump)]
+    pub protocol: Box<Account<'info, ProtocolState>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_in,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_in,
+    )]
+    pub user_in: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_mid,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_mid,
+    )]
+    pub user_mid: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_out,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_out,
+    )]
+    pub user_out: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program_in: Interface<'info, TokenInterface>,
+    pub token_program_mid: Interface<'info, TokenInterface>,
+    pub token_program_out: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SwapRoute<'info> {
+    /// `hook_accounts` are forwarded to every transfer, for hooks on any of the mints.
+    pub fn swap_route(
+        &mut self,
+        amount: u64,
+        min_amount_out: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        self.config_a
+            .check_not_paused(PAUSE_SWAPS, AmmError::SwapsPaused)?;
+        self.config_b
+            .check_not_paused(PAUSE_SWAPS, AmmError::SwapsPaused)?;
+        self.config_a.check_open()?;
+        self.config_b.check_open()?;
+        self.config_a.check_breaker()?;
+        self.config_b.check_breaker()?;
+        self.config_a.check_permissionless()?;
+        self.config_b.check_permissionless()?;
+        if amount == 0 {
+            msg!("amount_in: {}", amount);
+            return err!(AmmError::ZeroAmountIn);
+        }
+        let reserve_out = match self.config_b.mint_x == self.mint_out.key() {
+            true => self.config_b.reserve_x,
+            false => self.config_b.reserve_y,
+        };
+        if min_amount_out >= reserve_out {
+            msg!(
+                "min_amount_out: {}, reserve_out: {}",
+                min_amount_out,
+                reserve_out
+            );
+            return err!(AmmError::SlippageParamTooLarge);
+        }
+
+        // first hop: in -> mid through pool a
+        let in_is_x = self.config_a.mint_x == self.mint_in.key();
+        let vaults_a = (self.vault_a_in.amount, self.vault_a_mid.amount);
+        let vaults_b = (self.vault_b_mid.amount, self.vault_b_out.amount);
+        let before = self.vault_a_in.amount;
+        transfer_tokens(
+            self.user_in.to_account_info(),
+            self.vault_a_in.to_account_info(),
+            &self.mint_in,
+            self.user.to_account_info(),
+            self.token_program_in.to_account_info(),
+            None,
+            hook_accounts,
+            amount,
+        )?;
+        self.vault_a_in.reload()?;
+        let received = self.vault_a_in.amount - before;
+
+        let quote = quote_hop(&mut self.config_a, in_is_x, received)?;
+        let protocol_fee_bps = self
+            .protocol
+            .protocol_fee_bps(self.config_a.protocol_fee_bps);
+        let share = self
+            .config_a
+            .book_swap_at(in_is_x, received, &quote, protocol_fee_bps)?;
+        let (fee_mint, fee_source, fee_program) = match self.config_a.fee_mode {
+            FeeMode::Input => (&self.mint_in, &self.vault_a_in, &self.token_program_in),
+            FeeMode::Output => (&self.mint_mid, &self.vault_a_mid, &self.token_program_mid),
+        };
+        transfer_protocol_share(
+            &self.config_a,
+            self.vault_authority_a.to_account_info(),
+            fee_mint,
+            fee_source.to_account_info(),
+            self.fee_vault_a.to_account_info(),
+            fee_program.to_account_info(),
+            hook_accounts,
+            share,
+        )?;
+
+        let before = self.user_mid.amount;
+        transfer_tokens(
+            self.vault_a_mid.to_account_info(),
+            self.user_mid.to_account_info(),
+            &self.mint_mid,
+            self.vault_authority_a.to_account_info(),
+            self.token_program_mid.to_account_info(),
+            Some((self.config_a.key(), self.config_a.auth_bump)),
+            hook_accounts,
+            quote.amount_out,
+        )?;
+        self.user_mid.reload()?;
+        let amount_mid = self.user_mid.amount - before;
+
+        // second hop: mid -> out through pool b, fed with what actually arrived
+        let mid_is_x = self.config_b.mint_x == self.mint_mid.key();
+        let before = self.vault_b_mid.amount;
+        transfer_tokens(
+            self.user_mid.to_account_info(),
+            self.vault_b_mid.to_account_info(),
+            &self.mint_mid,
+            self.user.to_account_info(),
+            self.token_program_mid.to_account_info(),
+            None,
+            hook_accounts,
+            amount_mid,
+        )?;
+        self.vault_b_mid.reload()?;
+        let received = self.vault_b_mid.amount - before;
+
+        let quote = quote_hop(&mut self.config_b, mid_is_x, received)?;
+        let protocol_fee_bps = self
+            .protocol
+            .protocol_fee_bps(self.config_b.protocol_fee_bps);
+        let share = self
+            .config_b
+            .book_swap_at(mid_is_x, received, &quote, protocol_fee_bps)?;
+        let (fee_mint, fee_source, fee_program) = match self.config_b.fee_mode {
+            FeeMode::Input => (&self.mint_mid, &self.vault_b_mid, &self.token_program_mid),
+            FeeMode::Output => (&self.mint_out, &self.vault_b_out, &self.token_program_out),
+        };
+        transfer_protocol_share(
+            &self.config_b,
+            self.vault_authority_b.to_account_info(),
+            fee_mint,
+            fee_source.to_account_info(),
+            self.fee_vault_b.to_account_info(),
+            fee_program.to_account_info(),
+            hook_accounts,
+            share,
+        )?;
+
+        let before = self.user_out.amount;
+        transfer_tokens(
+            self.vault_b_out.to_account_info(),
+            self.user_out.to_account_info(),
+            &self.mint_out,
+            self.vault_authority_b.to_account_info(),
+            self.token_program_out.to_account_info(),
+            Some((self.config_b.key(), self.config_b.auth_bump)),
+            hook_accounts,
+            quote.amount_out,
+        )?;
+        self.user_out.reload()?;
+
+        for vault in [
+            &mut self.vault_a_in,
+            &mut self.vault_a_mid,
+            &mut self.vault_b_mid,
+            &mut self.vault_b_out,
+        ] {
+            vault.reload()?;
+        }
+        // the vaults are held as (in, out), the invariants take (x, y)
+        check_invariant(
+            self.config_a.curve_now()?,
+            self.config_a.precision()?,
+            as_x_y(in_is_x, vaults_a),
+            as_x_y(in_is_x, (self.vault_a_in.amount, self.vault_a_mid.amount)),
+        )?;
+        check_invariant(
+            self.config_b.curve_now()?,
+            self.config_b.precision()?,
+            as_x_y(mid_is_x, vaults_b),
+            as_x_y(mid_is_x, (self.vault_b_mid.amount, self.vault_b_out.amount)),
+        )?;
+
+        let amount_out = self.user_out.amount - before;
+        if amount_out < min_amount_out {
+            msg!(
+                "amount_in: {}, amount_out: {}, min_amount_out: {}",
+                amount,
+                amount_out,
+                min_amount_out
+            );
+            return err!(AmmError::SlippageExceeded);
+        }
+        Ok(())
+    }
+}
+
+fn quote_hop(config: &mut Config, in_is_x: bool, amount: u64) -> Result<SwapQuote> {
+    let (reserve_x, reserve_y) = (config.reserve_x, config.reserve_y);
+    config.update_twap(reserve_x, reserve_y)?;
+    config.quote_swap(in_is_x, amount)
+}
+
+fn transfer_tokens<'info>(
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    authority: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    signer: Option<(Pubkey, u8)>,
+    hook_accounts: &[AccountInfo<'info>],
+    amount: u64,
+) -> Result<()> {
+    let accounts = TransferChecked {
+        from,
+        mint: mint.to_account_info(),
+        to,
+        authority,
+    };
+
+    match signer {
+        Some((config, auth_bump)) => {
+            let seeds = &[&b"auth"[..], config.as_ref(), &[auth_bump]];
+
+            let signer_seeds = &[&seeds[..]];
+
+            let ctx = CpiContext::new_with_signer(token_program, accounts, signer_seeds)
+                .with_remaining_accounts(hook_accounts.to_vec());
+            transfer_checked_with_hook(ctx, amount, mint.decimals)
+        }
+        None => {
+            let ctx = CpiContext::new(token_program, accounts)
+                .with_remaining_accounts(hook_accounts.to_vec());
+            transfer_checked_with_hook(ctx, amount, mint.decimals)
+        }
+    }
+}
+
+/// Balances held as `(in, out)` in `(x, y)` order.
+fn as_x_y(in_is_x: bool, (amount_in, amount_out): (u64, u64)) -> (u64, u64) {
+    match in_is_x {
+        true => (amount_in, amount_out),
+        false => (amount_out, amount_in),
+    }
+}
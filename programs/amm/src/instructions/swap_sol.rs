@@ -0,0 +1,129 @@
+use anchor_lang::{
+    prelude::*,
+    system_program::{transfer, Transfer},
+};
+use anchor_spl::{
+    token::spl_token::native_mint,
+    token_interface::{close_account, sync_native, CloseAccount, SyncNative, TokenAccount},
+};
+
+use crate::{error::AmmError, instructions::Swap};
+
+impl<'info> Swap<'info> {
+    /// Wraps `amount` lamports into the user's WSOL account and swaps them.
+    pub fn swap_sol_in(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        min_amount_out: u64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<()> {
+        // a WSOL account the user already held a balance in is left open
+        let close = self.native_account(is_x)?.amount == 0;
+
+        let (wsol, token_program) = match is_x {
+            true => (
+                self.user_x.to_account_info(),
+                self.token_program_x.to_account_info(),
+            ),
+            false => (
+                self.user_y.to_account_info(),
+                self.token_program_y.to_account_info(),
+            ),
+        };
+
+        let accounts = Transfer {
+            from: self.user.to_account_info(),
+            to: wsol.clone(),
+        };
+        let ctx = CpiContext::new(self.system_program.to_account_info(), accounts);
+        transfer(ctx, amount)?;
+
+        let ctx = CpiContext::new(token_program, SyncNative { account: wsol });
+        sync_native(ctx)?;
+
+        match is_x {
+            true => self.user_x.reload()?,
+            false => self.user_y.reload()?,
+        }
+
+        self.swap(
+            is_x,
+            amount,
+            min_amount_out,
+            0,
+            0,
+            0,
+            hook_accounts,
+            event_authority_bump,
+        )?;
+
+        match close {
+            true => self.close_native(is_x),
+            false => Ok(()),
+        }
+    }
+
+    /// Swaps into WSOL and unwraps the output back to lamports.
+    pub fn swap_sol_out(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        min_amount_out: u64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<()> {
+        // a WSOL account the user already held a balance in keeps the output wrapped
+        let close = self.native_account(!is_x)?.amount == 0;
+
+        self.swap(
+            is_x,
+            amount,
+            min_amount_out,
+            0,
+            0,
+            0,
+            hook_accounts,
+            event_authority_bump,
+        )?;
+
+        match close {
+            true => self.close_native(!is_x),
+            false => Ok(()),
+        }
+    }
+
+    fn native_account(&self, is_x: bool) -> Result<&InterfaceAccount<'info, TokenAccount>> {
+        let (mint, account) = match is_x {
+            true => (self.mint_x.key(), &self.user_x),
+            false => (self.mint_y.key(), &self.user_y),
+        };
+        require_keys_eq!(mint, native_mint::ID, AmmError::InvalidToken);
+        // lamports are wrapped from the signer, there is nothing to spend as a delegate
+        require!(self.source.is_none(), AmmError::InvalidDelegate);
+        Ok(account)
+    }
+
+    fn close_native(&mut self, is_x: bool) -> Result<()> {
+        let (account, token_program) = match is_x {
+            true => (
+                self.user_x.to_account_info(),
+                self.token_program_x.to_account_info(),
+            ),
+            false => (
+                self.user_y.to_account_info(),
+                self.token_program_y.to_account_info(),
+            ),
+        };
+
+        // the rent and any unwrapped lamports go back to the signer
+        let accounts = CloseAccount {
+            account,
+            destination: self.user.to_account_info(),
+            authority: self.user.to_account_info(),
+        };
+        let ctx = CpiContext::new(token_program, accounts);
+        close_account(ctx)
+    }
+}
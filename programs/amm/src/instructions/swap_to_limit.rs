@@ -0,0 +1,52 @@
+use anchor_lang::prelude::*;
+
+use crate::instructions::Swap;
+
+/// What a swap_to_limit or swap_partial sold, before any transfer fee, and paid out.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct SwapFill {
+    pub amount_in: u64,
+    pub amount_out: u64,
+}
+
+impl<'info> Swap<'info> {
+    /// Swaps the part of `amount` that takes the pool's price down to `limit`, as
+    /// `(num, denom)` of the output mint per input, and no further. The limit is
+    /// checked against the reserves before the swap, so it is `swap` that fails on
+    /// anything else that is wrong with it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_to_limit(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        min_amount_out: u64,
+        limit: (u64, u64),
+        deadline: i64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<SwapFill> {
+        let fee = self.swap_fee()?;
+        let amount_in = self.config.fill_to_limit(
+            is_x,
+            amount,
+            fee,
+            self.protocol_fee_bps(),
+            limit.0,
+            limit.1,
+        )?;
+        let amount_out = self.swap(
+            is_x,
+            amount_in,
+            min_amount_out,
+            0,
+            deadline,
+            0,
+            hook_accounts,
+            event_authority_bump,
+        )?;
+        Ok(SwapFill {
+            amount_in,
+            amount_out,
+        })
+    }
+}
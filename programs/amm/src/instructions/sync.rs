@@ -0,0 +1,55 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+use crate::state::Config;
+
+#[derive(Accounts)]
+pub struct SyncReserves<'info> {
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        has_one = vault_x,
+        has_one = vault_y,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> SyncReserves<'info> {
+    /// Folds tokens sent straight to the vaults into the reserves, so donations
+    /// go to the liquidity providers instead of being stranded.
+    pub fn sync(&mut self) -> Result<()> {
+        self.config.check_settled()?;
+        // the elapsed time is still priced at the old reserves
+        self.config
+            .update_twap(self.config.reserve_x, self.config.reserve_y)?;
+
+        // protocol fees sit in their own vaults, so the vaults are all reserves
+        self.config.reserve_x = self.vault_x.amount;
+        self.config.reserve_y = self.vault_y.amount;
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    constants::{
+        FEE_UPDATE_COOLDOWN, FLASH_SWAP_ACTIVE, MAX_AMP, MAX_AMP_CHANGE, MAX_FEE_BPS,
+        MAX_FEE_STEP_BPS, MAX_LOCK_DURATION, MIN_AMP, MIN_RAMP_DURATION, PAUSE_ALL, PAUSE_DEPOSITS,
+        PAUSE_SWAPS, TIMELOCK_SECONDS,
+    },
+    error::AmmError,
+    events::{AmpRampUpdated, FeeRecipientUpdated, FeeUpdated, PoolLockUpdated},
+    state::{AmpRamp, Config, CurveType, ParameterChange, PendingChange},
+};
+
+#[derive(Accounts)]
+pub struct Update<'info> {
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"config",
+            config.mint_x.as_ref(),
+            config.mint_y.as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
+        bump = config.config_bump
+    )]
+    pub config: Account<'info, Config>,
+}
+
+impl<'info> Update<'info> {
+    /// Pauses swaps and deposits, withdrawals stay open.
+    pub fn lock(&mut self) -> Result<()> {
+        self.check_authority()?;
+        self.config.pause_flags |= PAUSE_SWAPS | PAUSE_DEPOSITS;
+        self.emit_lock_update()
+    }
+
+    /// Resumes swaps and deposits, ending a timed lock early too. The emergency
+    /// withdrawal pause is left as is.
+    pub fn unlock(&mut self) -> Result<()> {
+        self.check_authority()?;
+        self.config.pause_flags &= !(PAUSE_SWAPS | PAUSE_DEPOSITS);
+        self.config.locked_until = 0;
+        self.emit_lock_update()
+    }
+
+    /// Pauses swaps and deposits until `until`, after which they resume without
+    /// another transaction.
+    pub fn set_lock(&mut self, until: i64) -> Result<()> {
+        self.check_authority()?;
+        let now = Clock::get()?.unix_timestamp;
+        if until <= now || until - now > MAX_LOCK_DURATION {
+            msg!("now: {}, until: {}", now, until);
+            return err!(AmmError::InvalidLockDuration);
+        }
+        self.config.locked_until = until;
+        self.emit_lock_update()
+    }
+
+    pub fn set_pause_flags(&mut self, flags: u8) -> Result<()> {
+        self.check_authority()?;
+        require!(flags & !PAUSE_ALL == 0, AmmError::InvalidPauseFlags);
+        self.config.pause_flags = flags | (self.config.pause_flags & FLASH_SWAP_ACTIVE);
+        self.emit_lock_update()
+    }
+
+    /// Points protocol fee collection at `fee_recipient`. Takes effect right away,
+    /// it only decides where the protocol's share goes, never what LPs earn.
+    pub fn set_fee_recipient(&mut self, fee_recipient: Pubkey) -> Result<()> {
+        self.check_authority()?;
+        let old = self.config.fee_recipient;
+        self.config.fee_recipient = fee_recipient;
+
+        emit!(FeeRecipientUpdated {
+            config: self.config.key(),
+            old,
+            new: fee_recipient,
+        });
+        Ok(())
+    }
+
+    /// Lets swaps through again after the circuit breaker tripped.
+    pub fn reset_breaker(&mut self) -> Result<()> {
+        self.check_authority()?;
+        self.config.breaker_tripped = false;
+        Ok(())
+    }
+
+    /// Starts moving a stable pool's amp from where it is now to `target_amp` at
+    /// `ramp_end_ts`, replacing any ramp underway. An instant change would move the
+    /// invariant under the LPs, so a ramp runs at least MIN_RAMP_DURATION and moves
+    /// the amp at most MAX_AMP_CHANGE times up or down.
+    pub fn ramp_amp(&mut self, target_amp: u64, ramp_end_ts: i64) -> Result<()> {
+        self.check_authority()?;
+        require!(
+            (MIN_AMP..=MAX_AMP).contains(&target_amp),
+            AmmError::InvalidAmplification
+        );
+        let CurveType::Stable { amp } = self.config.curve_now()? else {
+            return err!(AmmError::InvalidAmpRamp);
+        };
+        let now = Clock::get()?.unix_timestamp;
+        if ramp_end_ts.saturating_sub(now) < MIN_RAMP_DURATION
+            || target_amp > amp.saturating_mul(MAX_AMP_CHANGE)
+            || amp > target_amp.saturating_mul(MAX_AMP_CHANGE)
+        {
+            msg!(
+                "amp: {}, target_amp: {}, now: {}, ramp_end_ts: {}",
+                amp,
+                target_amp,
+                now,
+                ramp_end_ts
+            );
+            return err!(AmmError::InvalidAmpRamp);
+        }
+        self.set_amp_ramp(AmpRamp {
+            initial_amp: amp,
+            target_amp,
+            start_ts: now,
+            end_ts: ramp_end_ts,
+        })
+    }
+
+    /// Ends a ramp with the amp frozen at its current value.
+    pub fn stop_ramp(&mut self) -> Result<()> {
+        self.check_authority()?;
+        let CurveType::Stable { amp } = self.config.curve_now()? else {
+            return err!(AmmError::InvalidAmpRamp);
+        };
+        let now = Clock::get()?.unix_timestamp;
+        self.set_amp_ramp(AmpRamp {
+            initial_amp: amp,
+            target_amp: amp,
+            start_ts: now,
+            end_ts: now,
+        })
+    }
+
+    /// Moves the start of trading to `open_time`. Only ever earlier, so a pool can
+    /// never be kept closed for longer than it was announced.
+    pub fn set_open_time(&mut self, open_time: i64) -> Result<()> {
+        self.check_authority()?;
+        require!(
+            open_time <= self.config.open_time,
+            AmmError::InvalidOpenTime
+        );
+        self.config.open_time = open_time;
+        Ok(())
+    }
+
+    /// Schedules `change` to be executable after TIMELOCK_SECONDS, replacing any
+    /// change already pending. A pool nobody has deposited into has no LPs to warn,
+    /// so there it is applied right away.
+    pub fn schedule_update(&mut self, change: ParameterChange) -> Result<()> {
+        self.check_authority()?;
+        match change {
+            ParameterChange::Fee { fee } => {
+                require!(fee <= MAX_FEE_BPS, AmmError::InvalidFee);
+                self.check_fee_change(fee)?;
+            }
+            ParameterChange::ProtocolFee { protocol_fee_bps } => {
+                require!(protocol_fee_bps <= 10_000, AmmError::InvalidFee)
+            }
+            ParameterChange::MaxPriceImpact {
+                max_price_impact_bps,
+            } => require!(
+                max_price_impact_bps <= 10_000,
+                AmmError::InvalidPriceImpactLimit
+            ),
+            ParameterChange::CircuitBreaker {
+                circuit_breaker_bps,
+            } => require!(
+                circuit_breaker_bps <= 10_000,
+                AmmError::InvalidCircuitBreaker
+            ),
+            ParameterChange::MaxVolumePerSlot { .. }
+            | ParameterChange::SkimToLps { .. }
+            | ParameterChange::Authority { .. } => {}
+        }
+
+        if self.config.reserve_x == 0 && self.config.reserve_y == 0 {
+            self.config.pending_change = None;
+            return self.apply_change(change);
+        }
+
+        let effective_ts = Clock::get()?
+            .unix_timestamp
+            .checked_add(TIMELOCK_SECONDS)
+            .ok_or(AmmError::Overflow)?;
+        self.config.pending_change = Some(PendingChange {
+            change,
+            effective_ts,
+        });
+        Ok(())
+    }
+
+    /// Applies the pending change once its timelock has elapsed, callable by anyone.
+    pub fn execute_update(&mut self) -> Result<()> {
+        let pending = self
+            .config
+            .pending_change
+            .ok_or(AmmError::NoPendingChange)?;
+        require!(
+            Clock::get()?.unix_timestamp >= pending.effective_ts,
+            AmmError::TimelockNotElapsed
+        );
+        self.config.pending_change = None;
+        self.apply_change(pending.change)
+    }
+
+    fn apply_change(&mut self, change: ParameterChange) -> Result<()> {
+        match change {
+            ParameterChange::Fee { fee } => {
+                self.check_fee_change(fee)?;
+                let old = self.config.fee;
+                self.config.fee = fee;
+                self.config.last_fee_update_ts = Clock::get()?.unix_timestamp;
+
+                emit!(FeeUpdated {
+                    config: self.config.key(),
+                    old,
+                    new: fee,
+                });
+            }
+            ParameterChange::ProtocolFee { protocol_fee_bps } => {
+                self.config.protocol_fee_bps = protocol_fee_bps;
+            }
+            ParameterChange::MaxPriceImpact {
+                max_price_impact_bps,
+            } => {
+                self.config.max_price_impact_bps = max_price_impact_bps;
+            }
+            ParameterChange::CircuitBreaker {
+                circuit_breaker_bps,
+            } => {
+                self.config.circuit_breaker_bps = circuit_breaker_bps;
+            }
+            ParameterChange::MaxVolumePerSlot {
+                max_volume_per_slot_x,
+                max_volume_per_slot_y,
+            } => {
+                self.config.max_volume_per_slot_x = max_volume_per_slot_x;
+                self.config.max_volume_per_slot_y = max_volume_per_slot_y;
+            }
+            ParameterChange::SkimToLps { skim_to_lps } => {
+                self.config.skim_to_lps = skim_to_lps;
+            }
+            ParameterChange::Authority { authority } => {
+                self.config.pending_authority = Some(authority);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn accept_authority(&mut self) -> Result<()> {
+        match self.config.pending_authority {
+            Some(pending) => {
+                require_keys_eq!(pending, self.user.key(), AmmError::InvalidAuthority);
+            }
+            None => return err!(AmmError::NoAuthoritySet),
+        }
+        self.config.authority = self.config.pending_authority.take();
+        Ok(())
+    }
+
+    pub fn renounce_authority(&mut self) -> Result<()> {
+        self.check_authority()?;
+        self.config.authority = None;
+        self.config.pending_authority = None;
+        self.config.pending_change = None;
+        Ok(())
+    }
+
+    /// A fee change moves the fee by at most MAX_FEE_STEP_BPS, and only once
+    /// FEE_UPDATE_COOLDOWN has passed since the last one. Checked when it is
+    /// scheduled and again when it is applied.
+    fn check_fee_change(&self, fee: u16) -> Result<()> {
+        let step = fee.abs_diff(self.config.fee);
+        if step > MAX_FEE_STEP_BPS {
+            msg!("old: {}, new: {}", self.config.fee, fee);
+            return err!(AmmError::FeeStepTooLarge);
+        }
+        let elapsed = Clock::get()?
+            .unix_timestamp
+            .saturating_sub(self.config.last_fee_update_ts);
+        if elapsed < FEE_UPDATE_COOLDOWN {
+            msg!("elapsed: {}", elapsed);
+            return err!(AmmError::FeeUpdateTooSoon);
+        }
+        Ok(())
+    }
+
+    pub fn check_authority(&self) -> Result<()> {
+        self.config.check_authority(self.user.key())
+    }
+
+    /// Starts `ramp` from the curve, a stopped one, with nothing left to move, being
+    /// stored as no ramp at all.
+    fn set_amp_ramp(&mut self, ramp: AmpRamp) -> Result<()> {
+        self.config.curve = CurveType::Stable {
+            amp: ramp.initial_amp,
+        };
+        self.config.amp_ramp = (ramp.initial_amp != ramp.target_amp).then_some(ramp);
+        emit!(AmpRampUpdated {
+            config: self.config.key(),
+            initial_amp: ramp.initial_amp,
+            target_amp: ramp.target_amp,
+            start_ts: ramp.start_ts,
+            end_ts: ramp.end_ts,
+        });
+        Ok(())
+    }
+
+    fn emit_lock_update(&self) -> Result<()> {
+        emit!(PoolLockUpdated {
+            config: self.config.key(),
+            authority: self.user.key(),
+            pause_flags: self.config.pause_flags,
+            slot: Clock::get()?.slot,
+            locked_until: self.config.locked_until,
+        });
+        Ok(())
+    }
+}
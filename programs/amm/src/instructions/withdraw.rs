@@ -1,122 +1,211 @@
+use crate::{
+    constants::PAUSE_WITHDRAWALS,
+    error::AmmError,
+    events::LiquidityRemoved,
+    state::Config,
+    utils::{emit_event_cpi, transfer_checked_with_hook, transfer_fee},
+};
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{burn, transfer, Burn, Mint, Token, TokenAccount, Transfer},
+    token_interface::{
+        burn, close_account, Burn, CloseAccount, Mint, TokenAccount, TokenInterface,
+        TransferChecked,
+    },
 };
-use constant_product_curve::ConstantProduct;
 
-use crate::{error::AmmError, state::Config};
+/// What a withdraw paid out of each vault, before any transfer fee.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct WithdrawAmounts {
+    pub amount_x: u64,
+    pub amount_y: u64,
+}
 
+/// Takes the event authority and this program last, like Swap, for self-CPI events.
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Withdraw<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
-    pub mint_x: Account<'info, Mint>,
-    pub mint_y: Account<'info, Mint>,
+    #[account(mint::token_program = token_program_x)]
+    pub mint_x: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program_y)]
+    pub mint_y: InterfaceAccount<'info, Mint>,
     #[account(
         mut,
         seeds = [b"lp",config.key().as_ref()],
         bump = config.lp_bump,
+        mint::token_program = token_program,
     )]
-    pub mint_lp: Account<'info, Mint>,
+    pub mint_lp: InterfaceAccount<'info, Mint>,
     #[account(
+        mut,
         has_one = mint_x,
         has_one = mint_y,
-        seeds =[b"config",config.seed.to_le_bytes().as_ref()],
+        has_one = vault_x,
+        has_one = vault_y,
+        has_one = mint_lp,
+        seeds = [
+            b"config",
+            mint_x.key().as_ref(),
+            mint_y.key().as_ref(),
+            config.fee_tier.to_le_bytes().as_ref(),
+        ],
         bump = config.config_bump
     )]
     pub config: Account<'info, Config>,
-    #[account(
-        mut,
-        associated_token::mint = mint_x,
-        associated_token::authority = config,
-    )]
-    pub vault_x: Account<'info, TokenAccount>,
+    /// CHECK: PDA owning the vaults, holds no data
+    #[account(seeds = [b"auth", config.key().as_ref()], bump = config.auth_bump)]
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub vault_x: InterfaceAccount<'info, TokenAccount>,
 
-    #[account(
-        mut,
-        associated_token::mint = mint_y,
-        associated_token::authority = config,
-    )]
-    pub vault_y: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault_y: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
         associated_token::mint = mint_x,
         associated_token::authority = user,
+        associated_token::token_program = token_program_x,
     )]
-    pub user_x: Account<'info, TokenAccount>,
+    pub user_x: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
         associated_token::mint = mint_y,
         associated_token::authority = user,
+        associated_token::token_program = token_program_y,
     )]
-    pub user_y: Account<'info, TokenAccount>,
+    pub user_y: InterfaceAccount<'info, TokenAccount>,
     #[account(
         mut,
         associated_token::mint = mint_lp,
         associated_token::authority = user,
+        associated_token::token_program = token_program,
     )]
-    pub user_lp: Account<'info, TokenAccount>,
+    pub user_lp: InterfaceAccount<'info, TokenAccount>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_program_x: Interface<'info, TokenInterface>,
+    pub token_program_y: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
 impl<'info> Withdraw<'info> {
-    pub fn withdraw(&mut self, amount: u64, min_x: u64, min_y: u64) -> Result<()> {
-        require!(self.config.locked == false, AmmError::PoolLocked);
+    pub fn withdraw(
+        &mut self,
+        amount: u64,
+        min_x: u64,
+        min_y: u64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<WithdrawAmounts> {
+        self.config
+            .check_not_paused(PAUSE_WITHDRAWALS, AmmError::WithdrawalsPaused)?;
         require!(amount > 0, AmmError::InvalidAmount);
 
-        let amounts = ConstantProduct::xy_withdraw_amounts_from_l(
-            self.vault_x.amount,
-            self.vault_y.amount,
-            self.mint_lp.supply,
-            amount,
-            6,
-        )
-        .map_err(AmmError::from)?;
+        let (reserve_x, reserve_y) = (self.config.reserve_x, self.config.reserve_y);
+        self.config.update_twap(reserve_x, reserve_y)?;
+
+        // proportional share of the reserves at execution time
+        let (x, y) = self.config.withdraw_amounts(amount, self.mint_lp.supply)?;
 
+        // check the minimums against what actually lands in the user accounts
+        let received_x = x - transfer_fee(&self.mint_x, x)?;
+        let received_y = y - transfer_fee(&self.mint_y, y)?;
         require!(
-            amounts.x >= min_x && amounts.y >= min_y,
-            AmmError::SlippageExceeded
+            received_x >= min_x && received_y >= min_y,
+            AmmError::WithdrawSlippageExceeded
         );
 
-        todo!()
+        self.config.reserve_x = reserve_x - x;
+        self.config.reserve_y = reserve_y - y;
+
+        self.withdraw_token(true, x, hook_accounts)?;
+        self.withdraw_token(false, y, hook_accounts)?;
+        self.burn_lp(amount)?;
+
+        // the mint account still holds the pre-burn supply
+        self.mint_lp.reload()?;
+        let event = LiquidityRemoved {
+            config: self.config.key(),
+            user: self.user.key(),
+            amount_x: x,
+            amount_y: y,
+            lp_burned: amount,
+            lp_supply_after: self.mint_lp.supply,
+        };
+        emit_event_cpi(&self.event_authority, event_authority_bump, &event)?;
+        Ok(WithdrawAmounts {
+            amount_x: x,
+            amount_y: y,
+        })
     }
-    pub fn withdraw_token(&mut self, is_x: bool, amount: u64) -> Result<()> {
-        let (from, to) = match is_x {
+
+    /// Burns the user's whole LP balance for its share of both reserves, then closes
+    /// the emptied LP account and refunds its rent to the user.
+    pub fn withdraw_all(
+        &mut self,
+        min_x: u64,
+        min_y: u64,
+        hook_accounts: &[AccountInfo<'info>],
+        event_authority_bump: u8,
+    ) -> Result<WithdrawAmounts> {
+        let amounts = self.withdraw(
+            self.user_lp.amount,
+            min_x,
+            min_y,
+            hook_accounts,
+            event_authority_bump,
+        )?;
+
+        // closing needs an empty account, anything left means the burn fell short
+        self.user_lp.reload()?;
+        if self.user_lp.amount != 0 {
+            msg!("user_lp: {}", self.user_lp.amount);
+            return err!(AmmError::LpBalanceRemaining);
+        }
+        self.close_lp_account()?;
+        Ok(amounts)
+    }
+    pub fn withdraw_token(
+        &mut self,
+        is_x: bool,
+        amount: u64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (mint, decimals, from, to, token_program) = match is_x {
             true => (
+                self.mint_x.to_account_info(),
+                self.mint_x.decimals,
                 self.vault_x.to_account_info(),
                 self.user_x.to_account_info(),
+                self.token_program_x.to_account_info(),
             ),
             false => (
+                self.mint_y.to_account_info(),
+                self.mint_y.decimals,
                 self.vault_y.to_account_info(),
                 self.user_y.to_account_info(),
+                self.token_program_y.to_account_info(),
             ),
         };
-        let cpi_accounts = Transfer {
+        let cpi_accounts = TransferChecked {
             from,
+            mint,
             to,
-            authority: self.config.to_account_info(),
+            authority: self.vault_authority.to_account_info(),
         };
 
-        //[b"config",config.seed.to_le_bytes().as_ref()],
-        let seed = &[
-            &b"config"[..],
-            &self.config.seed.to_le_bytes(),
-            &[self.config.config_bump],
-        ];
+        let config_key = self.config.key();
+        let seed = &[&b"auth"[..], config_key.as_ref(), &[self.config.auth_bump]];
         let signer_seeds = &[&seed[..]];
-        let ctx = CpiContext::new_with_signer(
-            self.token_program.to_account_info(),
-            cpi_accounts,
-            signer_seeds,
-        );
+        let ctx = CpiContext::new_with_signer(token_program, cpi_accounts, signer_seeds)
+            .with_remaining_accounts(hook_accounts.to_vec());
 
-        transfer(ctx, amount)
+        transfer_checked_with_hook(ctx, amount, decimals)
         // todo!()
     }
 
@@ -131,4 +220,15 @@ impl<'info> Withdraw<'info> {
         burn(ctx, amount)
         // todo!()
     }
+
+    pub fn close_lp_account(&mut self) -> Result<()> {
+        let cpi_accounts = CloseAccount {
+            account: self.user_lp.to_account_info(),
+            destination: self.user.to_account_info(),
+            authority: self.user.to_account_info(),
+        };
+
+        let ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        close_account(ctx)
+    }
 }
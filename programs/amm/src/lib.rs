@@ -1,9 +1,17 @@
+// token CPIs all go through transfer_checked; what is left deprecated is the
+// AccountInfo::realloc that Anchor generates for realloc constraints and the IDL
+// instructions, which only an Anchor upgrade can move to resize
 #![allow(deprecated)]
 #![allow(unexpected_cfgs)]
+#[cfg(not(target_os = "solana"))]
+pub mod client;
 pub mod constants;
 pub mod error;
+pub mod events;
 pub mod instructions;
+pub mod math;
 pub mod state;
+pub mod utils;
 
 use anchor_lang::prelude::*;
 
@@ -11,26 +19,485 @@ pub use constants::*;
 pub use instructions::*;
 pub use state::*;
 
+/// Typed CPI into the pool for other Anchor programs, which depend on this crate with
+/// `features = ["cpi"]`. The account structs share their names with the instructions'
+/// own, so they live here rather than at the crate root.
+#[cfg(feature = "cpi")]
+pub mod composable {
+    pub use crate::cpi::accounts::{Deposit, Swap, Withdraw};
+    pub use crate::cpi::{deposit, swap, withdraw};
+}
+
 declare_id!("CdG5T7mPU7fENrgMaPEpfqEBxFS7czM12BrjS49EAcKv");
 
 #[program]
 pub mod amm {
     use super::*;
 
-    pub fn initialize(
-        ctx: Context<Initialize>,
-        seed: u64,
-        fee: u16,
-        authority: Option<Pubkey>,
+    /// Creates the global protocol state, once, the program's upgrade authority
+    /// becoming its authority.
+    pub fn initialize_protocol(
+        ctx: Context<InitializeProtocol>,
+        protocol_fee_enabled: bool,
+        default_protocol_fee_bps: u16,
+    ) -> Result<()> {
+        ctx.accounts
+            .init(protocol_fee_enabled, default_protocol_fee_bps, ctx.bumps)
+    }
+
+    pub fn set_protocol_fee(
+        ctx: Context<UpdateProtocol>,
+        protocol_fee_enabled: bool,
+        default_protocol_fee_bps: u16,
+    ) -> Result<()> {
+        ctx.accounts
+            .set_protocol_fee(protocol_fee_enabled, default_protocol_fee_bps)
+    }
+
+    pub fn propose_protocol_authority(
+        ctx: Context<UpdateProtocol>,
+        authority: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.propose_protocol_authority(authority)
+    }
+
+    pub fn accept_protocol_authority(ctx: Context<AcceptProtocolAuthority>) -> Result<()> {
+        ctx.accounts.accept_protocol_authority()
+    }
+
+    pub fn initialize(ctx: Context<Initialize>, params: InitializeParams) -> Result<()> {
+        ctx.accounts.init(params, ctx.bumps)
+    }
+
+    /// Creates the pool and makes its first deposit in one go, returning the LP
+    /// minted to the initializer, or locked for it for `creator_lock_duration`
+    /// seconds. Remaining accounts are passed on to the transfer hooks of the mints.
+    pub fn initialize_with_liquidity<'info>(
+        ctx: Context<'_, '_, '_, 'info, InitializeWithLiquidity<'info>>,
+        params: InitializeParams,
+        amount_x: u64,
+        amount_y: u64,
+        creator_lock_duration: i64,
+    ) -> Result<u64> {
+        ctx.accounts.init_with_liquidity(
+            params,
+            amount_x,
+            amount_y,
+            creator_lock_duration,
+            ctx.bumps,
+            ctx.remaining_accounts,
+        )
+    }
+
+    /// Pays a creator's locked LP out once its lock is over.
+    pub fn claim_locked_lp(ctx: Context<ClaimLockedLp>) -> Result<()> {
+        ctx.accounts.claim()
+    }
+
+    /// Moves a lock's unlock time to `unlock_ts`, which can't be earlier.
+    pub fn extend_lp_lock(ctx: Context<ExtendLpLock>, unlock_ts: i64) -> Result<()> {
+        ctx.accounts.extend(unlock_ts)
+    }
+
+    /// Returns the LP minted. Remaining accounts are passed on to the transfer hooks
+    /// of the mints.
+    pub fn deposit<'info>(
+        ctx: Context<'_, '_, '_, 'info, Deposit<'info>>,
+        amount: u64,
+        max_x: u64,
+        max_y: u64,
+        min_lp_out: u64,
+    ) -> Result<u64> {
+        ctx.accounts.deposit(
+            amount,
+            max_x,
+            max_y,
+            min_lp_out,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// Deposits at most `amount_x` and `amount_y` at the pool ratio and returns the LP
+    /// minted. Remaining accounts are passed on to the transfer hooks of the mints.
+    pub fn deposit_amounts<'info>(
+        ctx: Context<'_, '_, '_, 'info, Deposit<'info>>,
+        amount_x: u64,
+        amount_y: u64,
+        min_lp_out: u64,
+    ) -> Result<u64> {
+        ctx.accounts.deposit_amounts(
+            amount_x,
+            amount_y,
+            min_lp_out,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// Remaining accounts are passed on to the transfer hooks of the mints.
+    pub fn deposit_single<'info>(
+        ctx: Context<'_, '_, '_, 'info, Deposit<'info>>,
+        is_x: bool,
+        amount_in: u64,
+        min_lp_out: u64,
+    ) -> Result<()> {
+        ctx.accounts.deposit_single(
+            is_x,
+            amount_in,
+            min_lp_out,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// Adds to the reserves without minting LP, for the pool's LPs. Remaining accounts
+    /// are passed on to the transfer hooks of the mints.
+    pub fn donate<'info>(
+        ctx: Context<'_, '_, '_, 'info, Deposit<'info>>,
+        amount_x: u64,
+        amount_y: u64,
+    ) -> Result<()> {
+        ctx.accounts.donate(
+            amount_x,
+            amount_y,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// Returns the amounts paid out. Remaining accounts are passed on to the transfer
+    /// hooks of the mints.
+    pub fn withdraw<'info>(
+        ctx: Context<'_, '_, '_, 'info, Withdraw<'info>>,
+        amount: u64,
+        min_x: u64,
+        min_y: u64,
+    ) -> Result<WithdrawAmounts> {
+        ctx.accounts.withdraw(
+            amount,
+            min_x,
+            min_y,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// Withdraws the user's entire LP balance and closes the LP account, returning the
+    /// amounts paid out. Remaining accounts are passed on to the transfer hooks of the
+    /// mints.
+    pub fn withdraw_all<'info>(
+        ctx: Context<'_, '_, '_, 'info, Withdraw<'info>>,
+        min_x: u64,
+        min_y: u64,
+    ) -> Result<WithdrawAmounts> {
+        ctx.accounts.withdraw_all(
+            min_x,
+            min_y,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// Returns the amount sent to the user, or the recipient. Remaining accounts are
+    /// passed on to the transfer hooks of the mints.
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, Swap<'info>>,
+        x_to_y: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        referral_fee_bps: u16,
+        deadline: i64,
+        max_price_impact_bps: u16,
+    ) -> Result<u64> {
+        ctx.accounts.swap(
+            x_to_y,
+            amount_in,
+            min_amount_out,
+            referral_fee_bps,
+            deadline,
+            max_price_impact_bps,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// Remaining accounts are passed on to the transfer hooks of the mints.
+    pub fn swap_exact_out<'info>(
+        ctx: Context<'_, '_, '_, 'info, Swap<'info>>,
+        x_to_y: bool,
+        amount_out: u64,
+        max_amount_in: u64,
+        deadline: i64,
+    ) -> Result<()> {
+        ctx.accounts.swap_exact_out(
+            x_to_y,
+            amount_out,
+            max_amount_in,
+            deadline,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// Sells as much of `amount_in` as the pool takes before its price of the input
+    /// falls to `limit_price_num / limit_price_denom` whole tokens of the output,
+    /// returning what was sold and paid out. A zero limit sells all of it, like swap.
+    pub fn swap_to_limit<'info>(
+        ctx: Context<'_, '_, '_, 'info, Swap<'info>>,
+        x_to_y: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        limit_price_num: u64,
+        limit_price_denom: u64,
+        deadline: i64,
+    ) -> Result<SwapFill> {
+        ctx.accounts.swap_to_limit(
+            x_to_y,
+            amount_in,
+            min_amount_out,
+            (limit_price_num, limit_price_denom),
+            deadline,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// swap, selling only as much of `amount_in` as the price impact limits let
+    /// through rather than failing, and returning what was sold and paid out.
+    /// `min_amount_out` is scaled down with the input actually sold.
+    pub fn swap_partial<'info>(
+        ctx: Context<'_, '_, '_, 'info, Swap<'info>>,
+        x_to_y: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        max_price_impact_bps: u16,
+        deadline: i64,
+    ) -> Result<SwapFill> {
+        ctx.accounts.swap_partial(
+            x_to_y,
+            amount_in,
+            min_amount_out,
+            max_price_impact_bps,
+            deadline,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// Remaining accounts are passed on to the transfer hooks of the mints, for every leg.
+    pub fn swap_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, Swap<'info>>,
+        legs: Vec<SwapLeg>,
+        deadline: i64,
+    ) -> Result<()> {
+        ctx.accounts.swap_batch(
+            legs,
+            deadline,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    pub fn lock_pool(ctx: Context<Update>) -> Result<()> {
+        ctx.accounts.lock()
+    }
+
+    pub fn unlock_pool(ctx: Context<Update>) -> Result<()> {
+        ctx.accounts.unlock()
+    }
+
+    /// Locks the pool like lock_pool until `until`, at most MAX_LOCK_DURATION away.
+    pub fn set_lock(ctx: Context<Update>, until: i64) -> Result<()> {
+        ctx.accounts.set_lock(until)
+    }
+
+    pub fn set_pause_flags(ctx: Context<Update>, flags: u8) -> Result<()> {
+        ctx.accounts.set_pause_flags(flags)
+    }
+
+    pub fn schedule_update(ctx: Context<Update>, change: ParameterChange) -> Result<()> {
+        ctx.accounts.schedule_update(change)
+    }
+
+    pub fn execute_update(ctx: Context<Update>) -> Result<()> {
+        ctx.accounts.execute_update()
+    }
+
+    pub fn accept_authority(ctx: Context<Update>) -> Result<()> {
+        ctx.accounts.accept_authority()
+    }
+
+    pub fn renounce_authority(ctx: Context<Update>) -> Result<()> {
+        ctx.accounts.renounce_authority()
+    }
+
+    pub fn set_fee_recipient(ctx: Context<Update>, fee_recipient: Pubkey) -> Result<()> {
+        ctx.accounts.set_fee_recipient(fee_recipient)
+    }
+
+    pub fn create_lp_metadata(ctx: Context<CreateLpMetadata>, uri: String) -> Result<()> {
+        ctx.accounts.create_lp_metadata(uri)
+    }
+
+    /// Brings a config of an older layout up to CONFIG_VERSION, permissionless.
+    pub fn migrate_config(ctx: Context<MigrateConfig>) -> Result<()> {
+        ctx.accounts.migrate()
+    }
+
+    /// Moves a stable pool's amp linearly to `target_amp` by `ramp_end_ts`.
+    pub fn ramp_amp(ctx: Context<Update>, target_amp: u64, ramp_end_ts: i64) -> Result<()> {
+        ctx.accounts.ramp_amp(target_amp, ramp_end_ts)
+    }
+
+    pub fn stop_ramp(ctx: Context<Update>) -> Result<()> {
+        ctx.accounts.stop_ramp()
+    }
+
+    pub fn reset_breaker(ctx: Context<Update>) -> Result<()> {
+        ctx.accounts.reset_breaker()
+    }
+
+    pub fn set_open_time(ctx: Context<Update>, open_time: i64) -> Result<()> {
+        ctx.accounts.set_open_time(open_time)
+    }
+
+    /// Permissionless, the fees can only go to the configured fee recipient. Remaining
+    /// accounts are passed on to the transfer hooks of the mints.
+    pub fn collect_protocol_fees<'info>(
+        ctx: Context<'_, '_, '_, 'info, CollectProtocolFees<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.collect(ctx.remaining_accounts)
+    }
+
+    /// Remaining accounts are passed on to the transfer hook of the other mint.
+    pub fn swap_sol_in<'info>(
+        ctx: Context<'_, '_, '_, 'info, Swap<'info>>,
+        x_to_y: bool,
+        amount_in: u64,
+        min_amount_out: u64,
     ) -> Result<()> {
-        ctx.accounts.init(seed, fee, authority, ctx.bumps)
+        ctx.accounts.swap_sol_in(
+            x_to_y,
+            amount_in,
+            min_amount_out,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// Remaining accounts are passed on to the transfer hook of the other mint.
+    pub fn swap_sol_out<'info>(
+        ctx: Context<'_, '_, '_, 'info, Swap<'info>>,
+        x_to_y: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        ctx.accounts.swap_sol_out(
+            x_to_y,
+            amount_in,
+            min_amount_out,
+            ctx.remaining_accounts,
+            ctx.bumps.event_authority,
+        )
+    }
+
+    /// Remaining accounts are passed on to the transfer hooks of the mints.
+    pub fn close_pool<'info>(ctx: Context<'_, '_, '_, 'info, ClosePool<'info>>) -> Result<()> {
+        ctx.accounts.close(ctx.remaining_accounts)
+    }
+
+    pub fn quote(ctx: Context<Quote>, x_to_y: bool, amount_in: u64) -> Result<u64> {
+        ctx.accounts.quote(x_to_y, amount_in)
+    }
+
+    /// What deposit_amounts would mint and take, in return data, for simulation.
+    pub fn preview_deposit(
+        ctx: Context<Quote>,
+        amount_x: u64,
+        amount_y: u64,
+    ) -> Result<DepositPreview> {
+        ctx.accounts.preview_deposit(amount_x, amount_y)
+    }
+
+    /// What withdraw would pay into the user's accounts, in return data.
+    pub fn preview_withdraw(ctx: Context<Quote>, lp_amount: u64) -> Result<WithdrawPreview> {
+        ctx.accounts.preview_withdraw(lp_amount)
+    }
+
+    /// The pool's reserves, vaults, fees, locks and price read at once, in return
+    /// data.
+    pub fn get_pool_state(ctx: Context<Quote>) -> Result<PoolSnapshot> {
+        ctx.accounts.pool_state()
+    }
+
+    /// Remaining accounts are passed on to the transfer hooks of the mints.
+    pub fn swap_route<'info>(
+        ctx: Context<'_, '_, '_, 'info, SwapRoute<'info>>,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .swap_route(amount_in, min_amount_out, ctx.remaining_accounts)
+    }
+
+    pub fn update_observation(ctx: Context<UpdateObservation>) -> Result<()> {
+        ctx.accounts.update_observation()
+    }
+
+    /// Remaining accounts are passed on to the transfer hooks of the mints.
+    pub fn begin_flash_swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, BeginFlashSwap<'info>>,
+        amount_x: u64,
+        amount_y: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .begin(amount_x, amount_y, ctx.remaining_accounts, ctx.bumps)
+    }
+
+    /// Remaining accounts are passed on to the transfer hooks of the mints.
+    pub fn end_flash_swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, EndFlashSwap<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.end(ctx.remaining_accounts)
+    }
+
+    pub fn sync(ctx: Context<SyncReserves>) -> Result<()> {
+        ctx.accounts.sync()
+    }
+
+    /// Remaining accounts are passed on to the transfer hooks of the mints.
+    pub fn skim<'info>(ctx: Context<'_, '_, '_, 'info, Skim<'info>>) -> Result<()> {
+        ctx.accounts.skim(ctx.remaining_accounts)
+    }
+
+    /// Permissionless, the withheld fees can only go to the mints.
+    pub fn harvest_withheld_fees(ctx: Context<HarvestWithheldFees>) -> Result<()> {
+        ctx.accounts.harvest()
+    }
+
+    pub fn create_allowlist(ctx: Context<CreateAllowlist>) -> Result<()> {
+        ctx.accounts.create(ctx.bumps)
+    }
+
+    pub fn add_to_allowlist(ctx: Context<AddToAllowlist>, member: Pubkey) -> Result<()> {
+        ctx.accounts.add(member)
+    }
+
+    pub fn remove_from_allowlist(ctx: Context<RemoveFromAllowlist>, member: Pubkey) -> Result<()> {
+        ctx.accounts.remove(member)
+    }
+
+    pub fn create_fee_exemptions(ctx: Context<CreateFeeExemptions>) -> Result<()> {
+        ctx.accounts.create(ctx.bumps)
     }
 
-    pub fn deposit(ctx: Context<Deposit>, amount: u64, max_x: u64, max_y: u64) -> Result<()> {
-        ctx.accounts.deposit(amount, max_x, max_y)
+    pub fn add_fee_exemption(ctx: Context<AddFeeExemption>, member: Pubkey) -> Result<()> {
+        ctx.accounts.add(member)
     }
 
-    pub fn swap(ctx: Context<Swap>, x_to_y: bool, amount_in: u64, slippage: u16) -> Result<()> {
-        ctx.accounts.swap(x_to_y, amount_in, slippage)
+    pub fn remove_fee_exemption(ctx: Context<RemoveFeeExemption>, member: Pubkey) -> Result<()> {
+        ctx.accounts.remove(member)
     }
 }
@@ -0,0 +1,441 @@
+//! Pool pricing as plain integer functions, free of Anchor types so clients can
+//! reuse the exact on-chain math through the lib target. `None` means the inputs
+//! have no valid result: an empty pool, an out of range fee or an overflow.
+//!
+//! Every division rounds in the pool's favor: amounts paid out (swap outputs, LP
+//! minted, withdrawals) go through mul_div_floor, amounts paid in (inputs, fees,
+//! tokens owed for LP) through mul_div_ceil. A 1-unit trade then either pays its
+//! rounded-up fee or gets nothing, it can never come out ahead.
+//!
+//! Constant product is priced here, the stable swap curve in [`stable`] and weighted
+//! pools in [`weighted`] behind the same interface.
+
+pub mod stable;
+pub mod weighted;
+
+/// `a * b / denominator`, rounded down.
+pub fn mul_div_floor(a: u64, b: u64, denominator: u64) -> Option<u64> {
+    if denominator == 0 {
+        return None;
+    }
+    (a as u128 * b as u128 / denominator as u128)
+        .try_into()
+        .ok()
+}
+
+/// `a * b / denominator`, rounded up.
+pub fn mul_div_ceil(a: u64, b: u64, denominator: u64) -> Option<u64> {
+    if denominator == 0 {
+        return None;
+    }
+    (a as u128 * b as u128)
+        .div_ceil(denominator as u128)
+        .try_into()
+        .ok()
+}
+
+/// `a * b / denominator` rounded down, for products wider than 128 bits. `None` if
+/// the quotient does not fit either.
+pub fn mul_div_wide(a: u128, b: u128, denominator: u128) -> Option<u128> {
+    if denominator == 0 {
+        return None;
+    }
+    if let Some(product) = a.checked_mul(b) {
+        return Some(product / denominator);
+    }
+
+    // 256-bit product out of 64-bit halves
+    const LOW: u128 = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & LOW);
+    let (b_hi, b_lo) = (b >> 64, b & LOW);
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let mid = (lo_lo >> 64) + (hi_lo & LOW) + (lo_hi & LOW);
+    let lo = (lo_lo & LOW) | (mid << 64);
+    let hi = a_hi * b_hi + (hi_lo >> 64) + (lo_hi >> 64) + (mid >> 64);
+    if hi >= denominator {
+        return None;
+    }
+
+    // long division of the low half into the remainder left by the high one
+    let (mut quotient, mut remainder) = (0u128, hi);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((lo >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1;
+        }
+    }
+    Some(quotient)
+}
+
+/// Fee of `fee_bps` on `amount`, rounded up.
+pub fn fee_amount(amount: u64, fee_bps: u16) -> Option<u64> {
+    if fee_bps > 10_000 {
+        return None;
+    }
+    mul_div_ceil(amount, fee_bps as u64, 10_000)
+}
+
+/// Output of selling `amount_in` with the fee taken off the input first,
+/// `reserve_out * a / (reserve_in + a)` for the input `a` left after the fee.
+pub fn get_amount_out(
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_in: u64,
+    fee_bps: u16,
+) -> Option<u64> {
+    if reserve_in == 0 || reserve_out == 0 {
+        return None;
+    }
+    let priced = amount_in - fee_amount(amount_in, fee_bps)?;
+    mul_div_floor(reserve_out, priced, reserve_in.checked_add(priced)?)
+}
+
+/// Input that get_amount_out turns into at least `amount_out` for the same reserves
+/// and fee, rounded up at every step. The pool can never pay out its whole reserve,
+/// nor anything at a 100% fee.
+pub fn get_amount_in(
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_out: u64,
+    fee_bps: u16,
+) -> Option<u64> {
+    if reserve_in == 0 || amount_out >= reserve_out || fee_bps >= 10_000 {
+        return None;
+    }
+    let priced = mul_div_ceil(reserve_in, amount_out, reserve_out - amount_out)?;
+    mul_div_ceil(priced, 10_000, 10_000 - fee_bps as u64)
+}
+
+/// How far, in bps rounded down, the spot price `y / x` moves from the reserves
+/// `before` to `after`, in either direction.
+pub fn price_move_bps(before: (u64, u64), after: (u64, u64)) -> Option<u128> {
+    let (x0, y0) = (before.0 as u128, before.1 as u128);
+    let (x1, y1) = (after.0 as u128, after.1 as u128);
+    // after / before - 1 = (y1 * x0 - y0 * x1) / (y0 * x1)
+    let difference = (y1 * x0).abs_diff(y0 * x1);
+    mul_div_wide(difference, 10_000, y0 * x1)
+}
+
+/// Multipliers taking raw amounts of mints with `decimals_x` and `decimals_y` to the
+/// precision of the one with more decimals, where a unit of either side stands for
+/// the same fraction of a whole token. `None` if they are more than 19 apart.
+pub fn precision_scales(decimals_x: u8, decimals_y: u8) -> Option<(u64, u64)> {
+    let precision = decimals_x.max(decimals_y);
+    Some((
+        10u64.checked_pow((precision - decimals_x) as u32)?,
+        10u64.checked_pow((precision - decimals_y) as u32)?,
+    ))
+}
+
+/// Spot price `y / x` in whole tokens as Q64.64, from raw reserves and the
+/// [`precision_scales`] of the pair. `None` for an empty side or a price past the
+/// type.
+pub fn price_q64(reserve_x: u64, reserve_y: u64, scales: (u64, u64)) -> Option<u128> {
+    mul_div_wide(
+        reserve_y as u128 * scales.1 as u128,
+        1 << 64,
+        reserve_x as u128 * scales.0 as u128,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MAX_FEE_BPS;
+
+    const FEES: [u16; 4] = [0, 1, 30, MAX_FEE_BPS];
+
+    #[test]
+    fn rounds_mul_div_both_ways() {
+        assert_eq!(mul_div_floor(7, 3, 2), Some(10));
+        assert_eq!(mul_div_ceil(7, 3, 2), Some(11));
+        assert_eq!(mul_div_ceil(6, 3, 2), Some(9));
+        assert_eq!(mul_div_floor(1, 1, 0), None);
+        assert_eq!(mul_div_ceil(1, 1, 0), None);
+        assert_eq!(mul_div_floor(u64::MAX, u64::MAX, u64::MAX), Some(u64::MAX));
+        assert_eq!(mul_div_floor(u64::MAX, 2, 1), None);
+    }
+
+    #[test]
+    fn mul_div_overflows_to_none() {
+        let max = u64::MAX;
+        assert_eq!(mul_div_floor(max, max, 1), None);
+        assert_eq!(mul_div_ceil(max, max, 1), None);
+        assert_eq!(mul_div_ceil(max, 2, 1), None);
+        assert_eq!(mul_div_ceil(max, max, max), Some(max));
+        assert_eq!(mul_div_ceil(max, max - 1, max), Some(max - 1));
+        // just past u64 once rounded up
+        assert_eq!(mul_div_floor(max, max, max - 1), None);
+        assert_eq!(mul_div_ceil(max, max - 1, max - 1), Some(max));
+    }
+
+    #[test]
+    fn fee_of_max_amount() {
+        let max = u64::MAX;
+        assert_eq!(fee_amount(max, 0), Some(0));
+        assert_eq!(fee_amount(max, 10_000), Some(max));
+        assert_eq!(
+            fee_amount(max, MAX_FEE_BPS),
+            Some(max.div_ceil(10_000 / MAX_FEE_BPS as u64))
+        );
+        assert_eq!(fee_amount(max, 10_001), None);
+        assert_eq!(fee_amount(max, u16::MAX), None);
+    }
+
+    #[test]
+    fn protocol_share_of_max_fee() {
+        // taken as Config::book_swap_at takes it, never more than the fee
+        let max = u64::MAX;
+        for fee in [fee_amount(max, MAX_FEE_BPS).unwrap(), max] {
+            for bps in [0, 1, 2_000, 10_000] {
+                let share = mul_div_floor(fee, bps, 10_000).unwrap();
+                assert!(share <= fee);
+            }
+            assert_eq!(mul_div_floor(fee, 10_000, 10_000), Some(fee));
+        }
+        assert_eq!(mul_div_floor(max, u16::MAX as u64, 10_000), None);
+    }
+
+    #[test]
+    fn measures_price_moves_both_ways() {
+        assert_eq!(price_move_bps((1_000, 1_000), (1_000, 1_000)), Some(0));
+        // 1.0 to 0.99 and to 1.01
+        assert_eq!(price_move_bps((1_000, 1_000), (1_000, 990)), Some(100));
+        assert_eq!(price_move_bps((1_000, 1_000), (1_000, 1_010)), Some(100));
+        assert_eq!(price_move_bps((1_000, 1_000), (1_010, 1_000)), Some(99));
+        assert_eq!(
+            price_move_bps((u64::MAX, u64::MAX), (u64::MAX, u64::MAX / 2)),
+            Some(5_000)
+        );
+        assert_eq!(price_move_bps((1_000, 0), (1_000, 1_000)), None);
+    }
+
+    #[test]
+    fn prices_in_whole_tokens() {
+        assert_eq!(precision_scales(6, 6), Some((1, 1)));
+        assert_eq!(precision_scales(6, 9), Some((1_000, 1)));
+        assert_eq!(precision_scales(9, 0), Some((1, 1_000_000_000)));
+        assert_eq!(precision_scales(0, 20), None);
+        // 1 token of x with 6 decimals against 2 of y with 9
+        assert_eq!(
+            price_q64(1_000_000, 2_000_000_000, (1_000, 1)),
+            Some(2 << 64)
+        );
+        assert_eq!(price_q64(1_000, 2_000, (1, 1)), Some(2 << 64));
+        assert_eq!(price_q64(0, 2_000, (1, 1)), None);
+    }
+
+    #[test]
+    fn mul_div_wide_handles_wide_products() {
+        assert_eq!(
+            mul_div_wide(u128::MAX, u128::MAX, u128::MAX),
+            Some(u128::MAX)
+        );
+        assert_eq!(mul_div_wide(u128::MAX, 6, 3), None);
+        assert_eq!(mul_div_wide(1 << 100, 1 << 100, 1 << 90), Some(1 << 110));
+        assert_eq!(mul_div_wide(u128::MAX, 3, 4), Some(u128::MAX / 4 * 3 + 2));
+        assert_eq!(mul_div_wide(1, 1, 0), None);
+    }
+
+    #[test]
+    fn has_no_price_without_reserves() {
+        for fee in FEES {
+            assert_eq!(get_amount_out(0, 1_000, 10, fee), None);
+            assert_eq!(get_amount_out(1_000, 0, 10, fee), None);
+            assert_eq!(get_amount_in(0, 1_000, 10, fee), None);
+            assert_eq!(get_amount_in(1_000, 0, 10, fee), None);
+        }
+    }
+
+    #[test]
+    fn pays_nothing_for_one_unit() {
+        // the rounded-up fee takes the whole unit
+        for fee in &FEES[1..] {
+            assert_eq!(get_amount_out(1_000, 1_000, 1, *fee), Some(0));
+        }
+        // and without a fee a unit still only buys a fraction at a 1:1 price
+        assert_eq!(get_amount_out(1_000, 1_000, 1, 0), Some(0));
+        assert_eq!(get_amount_out(1, 1, 1, 0), Some(0));
+        // unless the output side is much deeper
+        assert_eq!(get_amount_out(1, 1_000, 1, 0), Some(500));
+    }
+
+    #[test]
+    fn handles_max_reserves() {
+        let max = u64::MAX;
+        assert_eq!(get_amount_out(max / 2, max, 1_000_000, 0), Some(1_999_999));
+        assert_eq!(
+            get_amount_out(max / 2, max / 2, 1_000_000, 30),
+            Some(996_999)
+        );
+        // the pooled input would no longer fit in a vault
+        assert_eq!(get_amount_out(max, max, 1, 0), None);
+        assert_eq!(get_amount_in(max, max, 1, 30), Some(3));
+        assert_eq!(get_amount_in(1, max, max - 1, 0), Some(max - 1));
+    }
+
+    #[test]
+    fn bounds_the_fee() {
+        assert_eq!(get_amount_out(1_000, 1_000, 100, 10_000), Some(0));
+        assert_eq!(get_amount_out(1_000, 1_000, 100, 10_001), None);
+        assert_eq!(get_amount_in(1_000, 1_000, 10, 10_000), None);
+        assert_eq!(get_amount_in(1_000, 1_000, 1_000, 0), None);
+        assert_eq!(fee_amount(1, MAX_FEE_BPS), Some(1));
+        assert_eq!(fee_amount(10_000, MAX_FEE_BPS), Some(1_000));
+    }
+
+    #[test]
+    fn amount_in_always_buys_amount_out() {
+        let reserves = [1, 2, 7, 1_000, 1_000_003, u32::MAX as u64, u64::MAX / 3];
+        for fee in FEES {
+            for reserve_in in reserves {
+                for reserve_out in reserves {
+                    for amount_out in [1, 2, 3, reserve_out / 2, reserve_out.saturating_sub(1)] {
+                        let Some(amount_in) =
+                            get_amount_in(reserve_in, reserve_out, amount_out, fee)
+                        else {
+                            continue;
+                        };
+                        let out = get_amount_out(reserve_in, reserve_out, amount_in, fee);
+                        match out {
+                            Some(out) => assert!(out >= amount_out),
+                            // only once the input no longer fits next to the reserve
+                            None => assert!(reserve_in.checked_add(amount_in).is_none()),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exact_out_settles_at_the_least_input() {
+        // what swap_exact_out charges on a constant product pool
+        let reserves = [1, 2, 7, 1_000, 1_000_003, u32::MAX as u64, u64::MAX / 3];
+        for fee in FEES {
+            for reserve_in in reserves {
+                for reserve_out in reserves {
+                    let amounts = [1, 2, 3, 10, reserve_out / 3, reserve_out / 2];
+                    for amount_out in amounts.into_iter().filter(|amount| *amount > 0) {
+                        let Some(amount_in) =
+                            get_amount_in(reserve_in, reserve_out, amount_out, fee)
+                        else {
+                            continue;
+                        };
+                        let Some(out) = get_amount_out(reserve_in, reserve_out, amount_in, fee)
+                        else {
+                            continue;
+                        };
+                        assert!(out >= amount_out);
+                        // one unit less would no longer buy it
+                        let short = get_amount_out(reserve_in, reserve_out, amount_in - 1, fee);
+                        assert!(short.unwrap() < amount_out);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn never_profits_from_round_trips() {
+        for fee in FEES {
+            for amount in [1, 2, 3, 10, 1_000, 123_456] {
+                let (mut reserve_x, mut reserve_y) = (1_000_000u64, 3_000_000u64);
+                let mut held = amount;
+                for _ in 0..10 {
+                    let y = get_amount_out(reserve_x, reserve_y, held, fee).unwrap();
+                    reserve_x += held;
+                    reserve_y -= y;
+                    let x = get_amount_out(reserve_y, reserve_x, y, fee).unwrap();
+                    reserve_y += y;
+                    reserve_x -= x;
+                    assert!(x <= held);
+                    held = x;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod props {
+    use super::*;
+    use crate::constants::MAX_FEE_BPS;
+    use proptest::prelude::*;
+
+    // deep enough for the extremes, shallow enough that a run of trades never
+    // overflows a vault
+    const MAX_RESERVE: u64 = u64::MAX / 4;
+
+    fn reserve() -> impl Strategy<Value = u64> {
+        prop_oneof![1..=1_000u64, 1..=MAX_RESERVE]
+    }
+
+    fn fee() -> impl Strategy<Value = u16> {
+        prop_oneof![Just(0u16), Just(MAX_FEE_BPS), 0..=MAX_FEE_BPS]
+    }
+
+    /// Books a sale of `amount_in` against the pool, returning what it paid out.
+    fn swap(reserves: &mut (u64, u64), x_to_y: bool, amount_in: u64, fee: u16) -> u64 {
+        let (reserve_in, reserve_out) = match x_to_y {
+            true => (&mut reserves.0, &mut reserves.1),
+            false => (&mut reserves.1, &mut reserves.0),
+        };
+        let out = get_amount_out(*reserve_in, *reserve_out, amount_in, fee).unwrap();
+        *reserve_in += amount_in;
+        *reserve_out -= out;
+        out
+    }
+
+    proptest! {
+        #[test]
+        fn k_never_decreases(
+            reserve_x in reserve(),
+            reserve_y in reserve(),
+            fee in fee(),
+            trades in prop::collection::vec((any::<bool>(), 1..=u32::MAX as u64), 1..20),
+        ) {
+            let mut reserves = (reserve_x, reserve_y);
+            for (x_to_y, amount_in) in trades {
+                let k = reserves.0 as u128 * reserves.1 as u128;
+                swap(&mut reserves, x_to_y, amount_in, fee);
+                prop_assert!(reserves.0 as u128 * reserves.1 as u128 >= k);
+            }
+        }
+
+        #[test]
+        fn round_trips_never_profit(
+            reserve_x in reserve(),
+            reserve_y in reserve(),
+            fee in fee(),
+            amount in 1..=u32::MAX as u64,
+        ) {
+            let mut reserves = (reserve_x, reserve_y);
+            let y = swap(&mut reserves, true, amount, fee);
+            let x = swap(&mut reserves, false, y, fee);
+            prop_assert!(x <= amount);
+        }
+
+        // get_amount_in(get_amount_out(a)) can fall short of `a`, since whatever
+        // get_amount_out rounds away was never needed; what holds is that the quoted
+        // input always buys the output it was quoted for
+        #[test]
+        fn amount_in_buys_amount_out(
+            reserve_in in reserve(),
+            reserve_out in 2..=MAX_RESERVE,
+            fee in fee(),
+            amount_out in 1..=u32::MAX as u64,
+        ) {
+            let amount_out = amount_out.min(reserve_out - 1);
+            let amount_in = get_amount_in(reserve_in, reserve_out, amount_out, fee);
+            prop_assume!(amount_in.is_some_and(|a| a <= MAX_RESERVE));
+            let out = get_amount_out(reserve_in, reserve_out, amount_in.unwrap(), fee);
+            prop_assert!(out.unwrap() >= amount_out);
+        }
+    }
+}
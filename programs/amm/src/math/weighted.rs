@@ -0,0 +1,308 @@
+//! Balancer-style weighted pool with the invariant `x^w * y^(1 - w)`, which for
+//! `w = 1/2` is constant product. Selling `a` pays
+//!
+//! `reserve_out * (1 - (reserve_in / (reserve_in + a))^(w_in / w_out))`.
+//!
+//! Powers are taken as `2^(e * log2(b))` in fixed point with FRACTION_BITS fractional
+//! bits. log2 rounds down by less than 4 units of 2^-60, and since the weight ratio
+//! scales it by up to 99 a power is good to 2^-53, absolute for the fractions a sale
+//! leaves and relative for the growth a purchase needs. Outputs and inputs are moved
+//! against the trader by `reserve >> MARGIN_BITS` plus a unit, 2^9 times that bound.
+
+use super::{fee_amount, mul_div_ceil, mul_div_wide};
+
+pub const FRACTION_BITS: u32 = 60;
+const ONE: u128 = 1 << FRACTION_BITS;
+/// ln(2) in fixed point, rounded down.
+const LN_2: u128 = 799_144_290_325_165_978;
+/// The rounding margin, 2^-44 of the reserve.
+const MARGIN_BITS: u32 = 44;
+/// How far [`invariant`] can read under the exact value, from the two rounded-down
+/// logarithms weighted by up to 10_000 bps in total.
+pub const INVARIANT_ROUNDING: u128 = 4 * 10_000;
+
+/// log2 of `v / ONE` for `v >= ONE`, in fixed point and rounded down.
+fn log2(v: u128) -> u128 {
+    let integer = 127 - v.leading_zeros() - FRACTION_BITS;
+    let mut result = (integer as u128) << FRACTION_BITS;
+    // each squaring of the mantissa in [1, 2) yields the next fractional bit
+    let mut mantissa = v >> integer;
+    for bit in (0..FRACTION_BITS).rev() {
+        mantissa = (mantissa * mantissa) >> FRACTION_BITS;
+        if mantissa >= 2 * ONE {
+            mantissa >>= 1;
+            result |= 1 << bit;
+        }
+    }
+    result
+}
+
+/// `2^f` in fixed point for a fractional `f < ONE`, by the series of `e^(f ln 2)`.
+fn exp2_fraction(f: u128) -> u128 {
+    let t = (f * LN_2) >> FRACTION_BITS;
+    let (mut term, mut sum) = (ONE, ONE);
+    let mut k = 1;
+    while term > 0 {
+        term = ((term * t) >> FRACTION_BITS) / k;
+        sum += term;
+        k += 1;
+    }
+    sum
+}
+
+/// `(base / ONE)^(numerator / denominator)` in fixed point for `base >= ONE`, `None`
+/// past 2^64.
+fn pow_up(base: u128, numerator: u128, denominator: u128) -> Option<u128> {
+    let exponent = mul_div_wide(log2(base), numerator, denominator)?;
+    let integer = exponent >> FRACTION_BITS;
+    if integer >= 64 {
+        return None;
+    }
+    Some(exp2_fraction(exponent & (ONE - 1)) << integer)
+}
+
+/// `(ONE / base)^(numerator / denominator)` in fixed point for `base >= ONE`.
+fn pow_down(base: u128, numerator: u128, denominator: u128) -> Option<u128> {
+    let exponent = mul_div_wide(log2(base), numerator, denominator)?;
+    let integer = exponent >> FRACTION_BITS;
+    if integer >= FRACTION_BITS as u128 {
+        return Some(0);
+    }
+    Some((ONE * ONE / exp2_fraction(exponent & (ONE - 1))) >> integer)
+}
+
+fn check_weight(weight_in_bps: u16) -> Option<(u128, u128)> {
+    if weight_in_bps == 0 || weight_in_bps >= 10_000 {
+        return None;
+    }
+    Some((weight_in_bps as u128, 10_000 - weight_in_bps as u128))
+}
+
+/// Output of selling `amount_in` into the side weighted `weight_in_bps`, with the fee
+/// taken off the input first. The weighted counterpart of [`super::get_amount_out`].
+pub fn get_amount_out(
+    weight_in_bps: u16,
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_in: u64,
+    fee_bps: u16,
+) -> Option<u64> {
+    let (weight_in, weight_out) = check_weight(weight_in_bps)?;
+    if reserve_in == 0 || reserve_out == 0 {
+        return None;
+    }
+    let priced = amount_in - fee_amount(amount_in, fee_bps)?;
+    let pooled = reserve_in.checked_add(priced)? as u128;
+
+    // (reserve_in / pooled)^(w_in / w_out), then the share of reserve_out it frees
+    let base = mul_div_wide(pooled, ONE, reserve_in as u128)?;
+    let kept = pow_down(base, weight_in, weight_out)?;
+    let out = mul_div_wide(reserve_out as u128, ONE - kept.min(ONE), ONE)? as u64;
+    Some(out.saturating_sub((reserve_out >> MARGIN_BITS) + 1))
+}
+
+/// Input that [`get_amount_out`] turns into at least `amount_out` for the same
+/// reserves, weight and fee. The weighted counterpart of [`super::get_amount_in`].
+pub fn get_amount_in(
+    weight_in_bps: u16,
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_out: u64,
+    fee_bps: u16,
+) -> Option<u64> {
+    let (weight_in, weight_out) = check_weight(weight_in_bps)?;
+    if reserve_in == 0 || amount_out >= reserve_out || fee_bps >= 10_000 {
+        return None;
+    }
+    // asked for with the margin get_amount_out holds back, and a unit more
+    let amount_out = amount_out.checked_add((reserve_out >> MARGIN_BITS) + 2)?;
+    if amount_out >= reserve_out {
+        return None;
+    }
+
+    // reserve_in * ((reserve_out / (reserve_out - out))^(w_out / w_in) - 1), with the
+    // base rounded up and the power's own margin on top
+    let base = mul_div_wide(reserve_out as u128, ONE, (reserve_out - amount_out) as u128)? + 1;
+    let grown = pow_up(base, weight_out, weight_in)?;
+    let grown = grown + (grown >> MARGIN_BITS);
+    let priced = mul_div_wide(reserve_in as u128, grown - ONE, ONE)? + 1;
+    let priced: u64 = priced.try_into().ok()?;
+    mul_div_ceil(priced, 10_000, 10_000 - fee_bps as u64)
+}
+
+/// The invariant as `w_x * log2(x) + w_y * log2(y)` in bps-weighted fixed point,
+/// which grows with the product the same way.
+pub fn invariant(weight_x_bps: u16, x: u64, y: u64) -> Option<u128> {
+    let (weight_x, weight_y) = check_weight(weight_x_bps)?;
+    if x == 0 || y == 0 {
+        return None;
+    }
+    Some(
+        weight_x * log2((x as u128) << FRACTION_BITS)
+            + weight_y * log2((y as u128) << FRACTION_BITS),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (weight_in_bps, reserve_in, reserve_out, amount, fee_bps, exact output rounded
+    // down, exact input for `amount` out rounded up), from mpmath at 60 digits
+    const REFERENCES: [(u16, u64, u64, u64, u16, u64, Option<u64>); 5] = [
+        (
+            8_000,
+            1_000_000_000_000,
+            250_000_000_000,
+            10_000_000_000,
+            30,
+            9_726_367_788,
+            Some(10_288_618_193),
+        ),
+        (
+            2_000,
+            250_000_000_000,
+            1_000_000_000_000,
+            10_000_000_000,
+            30,
+            9_728_697_503,
+            Some(10_285_946_762),
+        ),
+        (
+            100,
+            1_000_000_000_000_000_000,
+            1_000_000_000_000_000,
+            10_000_000_000_000_000,
+            0,
+            100_503_341_656,
+            None,
+        ),
+        (
+            9_900,
+            1_000_000_000,
+            1_000_000_000_000_000_000,
+            1_000_000,
+            100,
+            93_316_205_046_768_560,
+            Some(1),
+        ),
+        (
+            5_000,
+            123_456_789,
+            987_654_321,
+            1_000_000,
+            1_000,
+            7_147_891,
+            Some(139_030),
+        ),
+    ];
+
+    #[test]
+    fn matches_high_precision_references() {
+        for (weight, reserve_in, reserve_out, amount, fee, exact_out, exact_in) in REFERENCES {
+            let out = get_amount_out(weight, reserve_in, reserve_out, amount, fee).unwrap();
+            // under by the margin and the power's own error, far less than another
+            assert!(out <= exact_out);
+            assert!(out + 2 * ((reserve_out >> MARGIN_BITS) + 1) >= exact_out);
+
+            let amount_in = get_amount_in(weight, reserve_in, reserve_out, amount, fee);
+            match exact_in {
+                Some(exact_in) => {
+                    let amount_in = amount_in.unwrap();
+                    assert!(amount_in >= exact_in);
+                    assert!(amount_in - exact_in <= exact_in / 1_000_000_000 + 4);
+                }
+                None => assert_eq!(amount_in, None),
+            }
+        }
+    }
+
+    #[test]
+    fn half_weights_are_constant_product() {
+        for (reserve_in, reserve_out, amount) in [
+            (1_000_000, 1_000_000, 1_000),
+            (7, 1_000_000_007, 3),
+            (u32::MAX as u64, 1 << 50, 1 << 40),
+        ] {
+            let weighted = get_amount_out(5_000, reserve_in, reserve_out, amount, 30).unwrap();
+            let constant_product =
+                super::super::get_amount_out(reserve_in, reserve_out, amount, 30).unwrap();
+            assert!(weighted <= constant_product);
+            assert!(weighted + (reserve_out >> MARGIN_BITS) + 2 >= constant_product);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_weights() {
+        assert_eq!(get_amount_out(0, 1_000, 1_000, 10, 0), None);
+        assert_eq!(get_amount_out(10_000, 1_000, 1_000, 10, 0), None);
+        assert_eq!(get_amount_in(0, 1_000, 1_000, 10, 0), None);
+        assert_eq!(invariant(10_000, 1_000, 1_000), None);
+        assert_eq!(invariant(5_000, 0, 1_000), None);
+    }
+}
+
+#[cfg(test)]
+mod props {
+    use super::*;
+    use crate::constants::{MAX_FEE_BPS, MAX_WEIGHT_BPS, MIN_WEIGHT_BPS};
+    use proptest::prelude::*;
+
+    const MAX_RESERVE: u64 = u64::MAX / 4;
+
+    fn weight() -> impl Strategy<Value = u16> {
+        prop_oneof![
+            Just(MIN_WEIGHT_BPS),
+            Just(MAX_WEIGHT_BPS),
+            MIN_WEIGHT_BPS..=MAX_WEIGHT_BPS
+        ]
+    }
+
+    fn fee() -> impl Strategy<Value = u16> {
+        prop_oneof![Just(0u16), Just(MAX_FEE_BPS), 0..=MAX_FEE_BPS]
+    }
+
+    proptest! {
+        #[test]
+        fn swaps_never_lower_the_invariant(
+            weight_x in weight(),
+            reserve_x in 1..=MAX_RESERVE,
+            reserve_y in 1..=MAX_RESERVE,
+            fee in fee(),
+            trades in prop::collection::vec((any::<bool>(), 1..=u32::MAX as u64), 1..10),
+        ) {
+            let mut reserves = (reserve_x, reserve_y);
+            for (x_to_y, amount_in) in trades {
+                let (weight_in, reserve_in, reserve_out) = match x_to_y {
+                    true => (weight_x, reserves.0, reserves.1),
+                    false => (10_000 - weight_x, reserves.1, reserves.0),
+                };
+                let out = get_amount_out(weight_in, reserve_in, reserve_out, amount_in, fee).unwrap();
+                prop_assume!(out < reserve_out);
+                let before = invariant(weight_x, reserves.0, reserves.1).unwrap();
+                let next = (reserve_in + amount_in, reserve_out - out);
+                reserves = match x_to_y {
+                    true => next,
+                    false => (next.1, next.0),
+                };
+                let after = invariant(weight_x, reserves.0, reserves.1).unwrap();
+                prop_assert!(after + INVARIANT_ROUNDING >= before);
+            }
+        }
+
+        #[test]
+        fn amount_in_buys_amount_out(
+            weight_in in weight(),
+            reserve_in in 1..=MAX_RESERVE,
+            reserve_out in 4..=MAX_RESERVE,
+            fee in fee(),
+            amount_out in 1..=u32::MAX as u64,
+        ) {
+            let amount_out = amount_out.min(reserve_out - 4);
+            let amount_in = get_amount_in(weight_in, reserve_in, reserve_out, amount_out, fee);
+            prop_assume!(amount_in.is_some_and(|a| a <= MAX_RESERVE));
+            let out = get_amount_out(weight_in, reserve_in, reserve_out, amount_in.unwrap(), fee);
+            prop_assert!(out.unwrap() >= amount_out);
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use anchor_lang::prelude::*;
+
+/// Wallets allowed to swap in a permissioned pool, see `Config::allowlist`. Grows
+/// and shrinks by one entry with every add and remove.
+#[account]
+pub struct Allowlist {
+    pub config: Pubkey,
+    pub members: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl Allowlist {
+    /// Account size, discriminator included, for `members` entries.
+    pub fn space(members: usize) -> usize {
+        8 + 32 + 4 + 32 * members + 1
+    }
+
+    pub fn contains(&self, user: &Pubkey) -> bool {
+        self.members.contains(user)
+    }
+}
@@ -0,0 +1,250 @@
+use anchor_lang::prelude::*;
+
+use crate::math::{self, stable, weighted};
+
+/// Pricing curve of a pool, chosen at initialize.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum CurveType {
+    /// `x * y = k`.
+    ConstantProduct,
+    /// Curve-style stable swap for pegged pairs, flatter around 1:1 the higher `amp`.
+    Stable { amp: u64 },
+    /// Balancer-style `x^w * y^(1 - w)`, with `w` as x's share of the pool value in bps.
+    Weighted { weight_x_bps: u16 },
+}
+
+/// A stable pool's amp moving from `initial_amp` to `target_amp` between the two
+/// timestamps, set by ramp_amp.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct AmpRamp {
+    pub initial_amp: u64,
+    pub target_amp: u64,
+    pub start_ts: i64,
+    pub end_ts: i64,
+}
+
+impl AmpRamp {
+    pub fn amp_at(&self, now: i64) -> u64 {
+        stable::ramp_amp(
+            self.initial_amp,
+            self.target_amp,
+            self.start_ts,
+            self.end_ts,
+            now,
+        )
+    }
+}
+
+/// `scales` of the two sides as `(in, out)`.
+fn side_scales(scales: (u64, u64), is_x: bool) -> (u64, u64) {
+    match is_x {
+        true => scales,
+        false => (scales.1, scales.0),
+    }
+}
+
+// Every method takes the pool's math::precision_scales as `scales`, `(x, y)`. Only
+// the stable curve is solved at that common precision: constant product and the
+// weighted curve price off ratios of each side to itself, which come out the same
+// in raw units.
+impl CurveType {
+    /// math::get_amount_out on this curve, for selling x when `is_x`.
+    pub fn get_amount_out(
+        &self,
+        is_x: bool,
+        reserve_in: u64,
+        reserve_out: u64,
+        amount_in: u64,
+        fee_bps: u16,
+        scales: (u64, u64),
+    ) -> Option<u64> {
+        match *self {
+            CurveType::ConstantProduct => {
+                math::get_amount_out(reserve_in, reserve_out, amount_in, fee_bps)
+            }
+            CurveType::Stable { amp } => stable::get_amount_out_scaled(
+                amp,
+                reserve_in,
+                reserve_out,
+                amount_in,
+                fee_bps,
+                side_scales(scales, is_x),
+            ),
+            CurveType::Weighted { weight_x_bps } => weighted::get_amount_out(
+                weight_in_bps(weight_x_bps, is_x),
+                reserve_in,
+                reserve_out,
+                amount_in,
+                fee_bps,
+            ),
+        }
+    }
+
+    /// math::get_amount_in on this curve, for selling x when `is_x`.
+    pub fn get_amount_in(
+        &self,
+        is_x: bool,
+        reserve_in: u64,
+        reserve_out: u64,
+        amount_out: u64,
+        fee_bps: u16,
+        scales: (u64, u64),
+    ) -> Option<u64> {
+        match *self {
+            CurveType::ConstantProduct => {
+                math::get_amount_in(reserve_in, reserve_out, amount_out, fee_bps)
+            }
+            CurveType::Stable { amp } => stable::get_amount_in_scaled(
+                amp,
+                reserve_in,
+                reserve_out,
+                amount_out,
+                fee_bps,
+                side_scales(scales, is_x),
+            ),
+            CurveType::Weighted { weight_x_bps } => weighted::get_amount_in(
+                weight_in_bps(weight_x_bps, is_x),
+                reserve_in,
+                reserve_out,
+                amount_out,
+                fee_bps,
+            ),
+        }
+    }
+
+    /// Whether balances `after` are worth at least `before` under the curve's
+    /// invariant, `None` if it cannot be evaluated. Both are `(x, y)`.
+    pub fn invariant_holds(
+        &self,
+        before: (u64, u64),
+        after: (u64, u64),
+        scales: (u64, u64),
+    ) -> Option<bool> {
+        match *self {
+            CurveType::ConstantProduct => {
+                Some(after.0 as u128 * after.1 as u128 >= before.0 as u128 * before.1 as u128)
+            }
+            // an emptied side is worth nothing on this curve, rather than no answer
+            CurveType::Stable { .. } if after.0 == 0 || after.1 == 0 => Some(false),
+            // d is only solved to a unit, a swap that barely moves it can read one lower
+            CurveType::Stable { amp } => {
+                let d = |(x, y): (u64, u64)| {
+                    stable::compute_d(amp, x.checked_mul(scales.0)?, y.checked_mul(scales.1)?)
+                };
+                Some(d(after)? + 1 >= d(before)?)
+            }
+            CurveType::Weighted { .. } if after.0 == 0 || after.1 == 0 => Some(false),
+            // the logarithms are rounded down, a swap that barely moves the invariant
+            // can read a few units lower
+            CurveType::Weighted { weight_x_bps } => Some(
+                weighted::invariant(weight_x_bps, after.0, after.1)? + weighted::INVARIANT_ROUNDING
+                    >= weighted::invariant(weight_x_bps, before.0, before.1)?,
+            ),
+        }
+    }
+}
+
+/// The weight of the side sold into.
+fn weight_in_bps(weight_x_bps: u16, is_x: bool) -> u16 {
+    match is_x {
+        true => weight_x_bps,
+        false => 10_000u16.saturating_sub(weight_x_bps),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNSCALED: (u64, u64) = (1, 1);
+
+    #[test]
+    fn constant_product_invariant_has_no_margin() {
+        let curve = CurveType::ConstantProduct;
+        let before = (1_000_000, 1_000_000);
+        assert_eq!(curve.invariant_holds(before, before, UNSCALED), Some(true));
+        assert_eq!(
+            curve.invariant_holds(before, (1_000_001, 999_999), UNSCALED),
+            Some(false)
+        );
+        assert_eq!(
+            curve.invariant_holds(before, (1_000_002, 999_999), UNSCALED),
+            Some(true)
+        );
+        assert_eq!(
+            curve.invariant_holds(before, (u64::MAX, u64::MAX), UNSCALED),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn stable_invariant_allows_one_unit_of_d() {
+        let curve = CurveType::Stable { amp: 100 };
+        // d of the balanced pair is its sum, 2_000_000
+        let before = (1_000_000, 1_000_000);
+        assert_eq!(curve.invariant_holds(before, before, UNSCALED), Some(true));
+        // d one unit lower, within what solving it can read low
+        assert_eq!(
+            curve.invariant_holds(before, (1_000_000, 999_999), UNSCALED),
+            Some(true)
+        );
+        // and two lower, a real decrease
+        assert_eq!(
+            curve.invariant_holds(before, (999_999, 999_999), UNSCALED),
+            Some(false)
+        );
+        assert_eq!(
+            curve.invariant_holds(before, (0, 2_000_000), UNSCALED),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn stable_invariant_is_taken_in_whole_tokens() {
+        let curve = CurveType::Stable { amp: 100 };
+        // x with 3 decimals fewer, so a unit of it is worth 1_000 of y
+        let scales = (1_000, 1);
+        let before = (1_000, 1_000_000);
+        assert_eq!(curve.invariant_holds(before, before, scales), Some(true));
+        assert_eq!(
+            curve.invariant_holds(before, (1_000, 999_999), scales),
+            Some(true)
+        );
+        // a unit of x is far more than the margin once scaled
+        assert_eq!(
+            curve.invariant_holds(before, (999, 1_000_500), scales),
+            Some(false)
+        );
+        assert_eq!(
+            curve.invariant_holds(before, (1_000, u64::MAX), (u64::MAX, 2)),
+            None
+        );
+    }
+
+    #[test]
+    fn weighted_invariant_allows_its_rounding() {
+        // a light y side, whose small moves stay well apart from the log2 rounding
+        let curve = CurveType::Weighted {
+            weight_x_bps: 9_000,
+        };
+        let before = (1 << 62, 1 << 62);
+        assert_eq!(curve.invariant_holds(before, before, UNSCALED), Some(true));
+
+        // 50 units of y lower the invariant by about 18 units of log2 at 1_000 bps,
+        // under INVARIANT_ROUNDING
+        let after = (1 << 62, (1 << 62) - 50);
+        let invariant = |(x, y): (u64, u64)| weighted::invariant(9_000, x, y).unwrap();
+        assert!(invariant(after) < invariant(before));
+        assert_eq!(curve.invariant_holds(before, after, UNSCALED), Some(true));
+
+        // 1_000 units lower it by nearly ten times the margin
+        assert_eq!(
+            curve.invariant_holds(before, (1 << 62, (1 << 62) - 1_000), UNSCALED),
+            Some(false)
+        );
+        assert_eq!(
+            curve.invariant_holds(before, (1 << 63, 0), UNSCALED),
+            Some(false)
+        );
+    }
+}
@@ -0,0 +1,21 @@
+use anchor_lang::prelude::*;
+
+/// Wallets that swap without the pool fee, see `Config::fee_exemptions`. Grows and
+/// shrinks by one entry with every add and remove, up to MAX_FEE_EXEMPTIONS.
+#[account]
+pub struct FeeExemptions {
+    pub config: Pubkey,
+    pub members: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl FeeExemptions {
+    /// Account size, discriminator included, for `members` entries.
+    pub fn space(members: usize) -> usize {
+        8 + 32 + 4 + 32 * members + 1
+    }
+
+    pub fn contains(&self, user: &Pubkey) -> bool {
+        self.members.contains(user)
+    }
+}
@@ -0,0 +1,19 @@
+use anchor_lang::prelude::*;
+
+/// Debt of an open flash swap. Lives only between `begin_flash_swap` and
+/// `end_flash_swap` of the same transaction.
+#[account]
+#[derive(InitSpace)]
+pub struct FlashSwapState {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    /// Reserves before anything was lent out, the invariant is checked against them.
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub amount_x: u64,
+    pub amount_y: u64,
+    /// Vault balances once the loan went out, anything above them at the end is input.
+    pub vault_x: u64,
+    pub vault_y: u64,
+    pub bump: u8,
+}
@@ -0,0 +1,13 @@
+use anchor_lang::prelude::*;
+
+/// LP of a pool's creator held back until `unlock_ts`, from `[b"lock", config,
+/// owner]`. The LP sits in this account's ATA of the LP mint, and claim_locked_lp
+/// pays it out to the owner and closes both.
+#[account]
+#[derive(InitSpace)]
+pub struct LockPosition {
+    pub config: Pubkey,
+    pub owner: Pubkey,
+    pub unlock_ts: i64,
+    pub bump: u8,
+}
@@ -1,14 +1,779 @@
 use anchor_lang::prelude::*;
 
+use crate::{
+    constants::{
+        CONFIG_VERSION, FLASH_SWAP_ACTIVE, MINIMUM_LIQUIDITY, PAUSE_DEPOSITS, PAUSE_SWAPS,
+    },
+    error::AmmError,
+    math::{
+        fee_amount, mul_div_ceil, mul_div_floor, mul_div_wide, precision_scales, price_move_bps,
+        price_q64,
+    },
+    utils::sqrt,
+};
+
+pub mod allowlist;
+pub use allowlist::*;
+
+pub mod curve;
+pub use curve::*;
+
+pub mod fee_exemptions;
+pub use fee_exemptions::*;
+
+pub mod flash;
+pub use flash::*;
+
+pub mod lock_position;
+pub use lock_position::*;
+
+pub mod observation;
+pub use observation::*;
+
+pub mod pending_change;
+pub use pending_change::*;
+
+pub mod protocol;
+pub use protocol::*;
+
+pub mod registry;
+pub use registry::*;
+
+/// Which side of a swap the pool fee is taken from.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum FeeMode {
+    /// Deducted from the input before pricing, in the input mint.
+    Input,
+    /// Deducted from the priced output, in the output mint.
+    Output,
+}
+
+/// What selling into the pool pays out, with `fee` in the mint FeeMode charges it in.
+pub struct SwapQuote {
+    pub amount_out: u64,
+    pub fee: u64,
+}
+
 #[account]
 #[derive(InitSpace)]
 pub struct Config {
-    pub seed: u64,
     pub authority: Option<Pubkey>,
+    pub pending_authority: Option<Pubkey>,
     pub mint_x: Pubkey,
     pub mint_y: Pubkey,
+    pub mint_lp: Pubkey,
     pub fee: u16,
-    pub locked: bool,
+    /// Fee the pool was created with. Part of the config address, so unlike `fee`
+    /// it never changes.
+    pub fee_tier: u16,
+    pub fee_mode: FeeMode,
+    pub protocol_fee_bps: u16,
+    pub fee_recipient: Pubkey,
+    /// Liquidity the pool prices against. Tokens sent straight to a vault are not
+    /// counted until `sync`.
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub price_cumulative_x: u128,
+    pub price_cumulative_y: u128,
+    pub last_update_ts: i64,
+    /// PAUSE_* bits plus FLASH_SWAP_ACTIVE. Takes the byte of the former `locked`
+    /// flag, so a pool locked under the old layout comes up with swaps paused.
+    pub pause_flags: u8,
     pub config_bump: u8,
     pub lp_bump: u8,
+    pub fee_vault_x_bump: u8,
+    pub fee_vault_y_bump: u8,
+    /// Bump of the `[b"auth", config]` PDA that owns the vaults and signs everything
+    /// leaving them, the config itself only signs for the LP mint. Applies to pools
+    /// created from this layout on, older pools keep config-owned vaults and have to
+    /// be withdrawn from and recreated.
+    pub auth_bump: u8,
+    pub observation_bump: u8,
+    /// Fee or authority change waiting out the timelock, a new schedule replaces it.
+    pub pending_change: Option<PendingChange>,
+    /// Swap statistics for off-chain readers, never read for pricing. Volume counts
+    /// each mint as it is sold into the pool, fees as they are charged in that mint,
+    /// protocol share included. All saturate instead of failing a swap.
+    pub cumulative_volume_x: u128,
+    pub cumulative_volume_y: u128,
+    pub cumulative_fees_x: u64,
+    pub cumulative_fees_y: u64,
+    pub swap_count: u64,
+    /// Allowlist account of a permissioned pool, only its members can swap. None for
+    /// pools open to everyone.
+    pub allowlist: Option<Pubkey>,
+    /// Whether the mint had a freeze authority at initialize, which could freeze the
+    /// vault and strand every LP. Only recorded for UIs to warn about, not enforced.
+    pub mint_x_freezable: bool,
+    pub mint_y_freezable: bool,
+    /// Largest share of the input reserve, in bps, a single swap may sell into the
+    /// pool, counting only what is priced after an input fee. Zero disables it.
+    pub max_price_impact_bps: u16,
+    /// Unix timestamp trading starts at, liquidity can be added before it.
+    pub open_time: i64,
+    /// Whether `skim` folds vault surpluses into the reserves instead of sending
+    /// them to the fee recipient.
+    pub skim_to_lps: bool,
+    pub curve: CurveType,
+    /// Unix timestamp the fee last changed at, zero if it never has.
+    pub last_fee_update_ts: i64,
+    /// Most of each mint that swaps may sell into the pool within one slot, so a
+    /// thin pool can't be drained in a single block. Zero disables either.
+    pub max_volume_per_slot_x: u64,
+    pub max_volume_per_slot_y: u64,
+    /// Slot the volumes below were sold in, they start over with the next one.
+    pub current_slot: u64,
+    pub slot_volume_x: u64,
+    pub slot_volume_y: u64,
+    /// Largest move of the spot price, in bps of the reserves after fees, a single
+    /// swap may make. `swap` trips the breaker instead of trading past it, the other
+    /// swap paths fail with PriceMoveTooLarge. Zero disables it.
+    pub circuit_breaker_bps: u16,
+    /// Set by a swap past the breaker, halts swaps until the authority resets it.
+    pub breaker_tripped: bool,
+    /// Layout the account was written with, zero for pools from before versioning.
+    /// Fields only ever go after it, so an older account decodes with zeroed ones.
+    pub version: u8,
+    /// FeeExemptions account whose members swap at a zero fee. None if nobody is
+    /// exempt.
+    pub fee_exemptions: Option<Pubkey>,
+    /// Token accounts holding the reserves, the vault authority's ATAs unless
+    /// initialize was given accounts set up ahead of the pool.
+    pub vault_x: Pubkey,
+    pub vault_y: Pubkey,
+    /// Smallest input a swap can price, in base units of the mint sold, so dust
+    /// trades can't churn the accumulators. Zero only refuses empty inputs.
+    pub min_trade_size: u64,
+    /// Slot of the last booked swap, and the Q64.64 price of x in y it left.
+    pub last_swap_slot: u64,
+    pub last_swap_price_q64: u128,
+    /// Swaps booked in last_swap_slot so far, saturating.
+    pub trades_in_last_slot: u16,
+    /// Unix timestamp a set_lock halts swaps and deposits until, zero when unlocked.
+    pub locked_until: i64,
+    /// Ramp the amp of a stable pool is on, read through curve_now. `curve` holds
+    /// the amp it started from.
+    pub amp_ramp: Option<AmpRamp>,
+    /// Decimals of mint_x and mint_y, which prices and the stable curve are
+    /// normalized by. Read off the mints by initialize and migrate_config.
+    pub decimals_x: u8,
+    pub decimals_y: u8,
+}
+
+impl Config {
+    pub fn check_authority(&self, signer: Pubkey) -> Result<()> {
+        match self.authority {
+            Some(authority) => {
+                require_keys_eq!(authority, signer, AmmError::Unauthorized);
+                Ok(())
+            }
+            None => err!(AmmError::NoAuthoritySet),
+        }
+    }
+
+    /// Fails until the pool's open_time has come.
+    pub fn check_open(&self) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        if now < self.open_time {
+            msg!("now: {}, open_time: {}", now, self.open_time);
+            return err!(AmmError::PoolNotOpenYet);
+        }
+        Ok(())
+    }
+
+    /// The curve swaps price on now, with a ramping amp at its current value.
+    pub fn curve_now(&self) -> Result<CurveType> {
+        Ok(match (self.curve, self.amp_ramp) {
+            (CurveType::Stable { .. }, Some(ramp)) => CurveType::Stable {
+                amp: ramp.amp_at(Clock::get()?.unix_timestamp),
+            },
+            (curve, _) => curve,
+        })
+    }
+
+    /// math::precision_scales of the pool's mints, `(x, y)`.
+    pub fn precision(&self) -> Result<(u64, u64)> {
+        Ok(precision_scales(self.decimals_x, self.decimals_y)
+            .ok_or(AmmError::DecimalsTooFarApart)?)
+    }
+
+    /// Spot price of x in y as Q64.64 in whole tokens, saturating for a price past
+    /// 2^64 and zero for an empty side. `(y / w_y) / (x / w_x)` on a weighted pool
+    /// and the reserve ratio on the others, which a stable pool's price is only close
+    /// to near balance.
+    pub fn price_x_q64(&self, reserve_x: u64, reserve_y: u64) -> Result<u128> {
+        self.spot_price_q64(true, reserve_x, reserve_y)
+    }
+
+    /// price_x_q64 the other way round, the price of y in x.
+    pub fn price_y_q64(&self, reserve_x: u64, reserve_y: u64) -> Result<u128> {
+        self.spot_price_q64(false, reserve_x, reserve_y)
+    }
+
+    fn spot_price_q64(&self, of_x: bool, reserve_x: u64, reserve_y: u64) -> Result<u128> {
+        if reserve_x == 0 || reserve_y == 0 {
+            return Ok(0);
+        }
+        let (scale_x, scale_y) = self.precision()?;
+        let (weight_x, weight_y) = match self.curve {
+            CurveType::Weighted { weight_x_bps } => {
+                (weight_x_bps as u128, 10_000 - weight_x_bps as u128)
+            }
+            _ => (1, 1),
+        };
+        let price = match of_x {
+            true => price_q64(reserve_x, reserve_y, (scale_x, scale_y))
+                .and_then(|price| mul_div_wide(price, weight_x, weight_y)),
+            false => price_q64(reserve_y, reserve_x, (scale_y, scale_x))
+                .and_then(|price| mul_div_wide(price, weight_y, weight_x)),
+        };
+        Ok(price.unwrap_or(u128::MAX))
+    }
+
+    /// Fails unless the pool is open to everyone. For the paths that trade against the
+    /// pool without taking the allowlist account.
+    pub fn check_permissionless(&self) -> Result<()> {
+        require!(self.allowlist.is_none(), AmmError::NotAllowlisted);
+        Ok(())
+    }
+
+    /// Fails with `error` while any of `flag` is set, or with PoolLocked while a flash
+    /// swap has the vaults short. Being the check every swap, deposit and withdrawal
+    /// makes, it also holds them off with OutdatedConfig until a migration.
+    pub fn check_not_paused(&self, flag: u8, error: AmmError) -> Result<()> {
+        self.check_settled()?;
+        if self.pause_flags & flag != 0 {
+            return Err(error.into());
+        }
+        // a timed lock covers what lock_pool does, so withdrawals stay open
+        if flag & (PAUSE_SWAPS | PAUSE_DEPOSITS) != 0 {
+            let now = Clock::get()?.unix_timestamp;
+            if now < self.locked_until {
+                msg!("now: {}, locked_until: {}", now, self.locked_until);
+                return err!(AmmError::PoolLockedUntil);
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails with OutdatedConfig until a migration, or with PoolLocked while a flash
+    /// swap has the vaults short. For the paths that read the vaults into the
+    /// reserves, which mid flash swap would count a repayment twice.
+    pub fn check_settled(&self) -> Result<()> {
+        require!(self.version == CONFIG_VERSION, AmmError::OutdatedConfig);
+        require!(
+            self.pause_flags & FLASH_SWAP_ACTIVE == 0,
+            AmmError::PoolLocked
+        );
+        Ok(())
+    }
+
+    /// Fails while the circuit breaker is tripped.
+    pub fn check_breaker(&self) -> Result<()> {
+        require!(!self.breaker_tripped, AmmError::CircuitBreakerTripped);
+        Ok(())
+    }
+
+    /// How far moving the reserves from `before` to the current ones moves the spot
+    /// price, if that is past the circuit breaker.
+    pub fn breaker_move(&self, before: (u64, u64)) -> Result<Option<u64>> {
+        if self.circuit_breaker_bps == 0 {
+            return Ok(None);
+        }
+        let moved =
+            price_move_bps(before, (self.reserve_x, self.reserve_y)).ok_or(AmmError::Overflow)?;
+        Ok(
+            (moved > self.circuit_breaker_bps as u128)
+                .then_some(moved.min(u64::MAX as u128) as u64),
+        )
+    }
+
+    /// Seed of the fee vault holding the protocol's share in `mint`.
+    pub fn fee_vault_seed(&self, mint: Pubkey) -> &'static [u8] {
+        match mint == self.mint_x {
+            true => b"fee_vault_x",
+            false => b"fee_vault_y",
+        }
+    }
+
+    pub fn fee_vault_bump(&self, mint: Pubkey) -> u8 {
+        match mint == self.mint_x {
+            true => self.fee_vault_x_bump,
+            false => self.fee_vault_y_bump,
+        }
+    }
+
+    /// The vault of `mint`, which has to be one of the pair.
+    pub fn vault(&self, mint: Pubkey) -> Pubkey {
+        match mint == self.mint_x {
+            true => self.vault_x,
+            false => self.vault_y,
+        }
+    }
+
+    /// Whether this pool trades `mint_a` against `mint_b`, in either order.
+    pub fn has_pair(&self, mint_a: Pubkey, mint_b: Pubkey) -> bool {
+        (self.mint_x == mint_a && self.mint_y == mint_b)
+            || (self.mint_x == mint_b && self.mint_y == mint_a)
+    }
+
+    /// Accumulates the pre-trade spot prices (Q64.64, in whole tokens) weighted by the
+    /// seconds since the last update. The accumulators wrap by design, consumers take
+    /// differences.
+    pub fn update_twap(&mut self, reserve_x: u64, reserve_y: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let elapsed = now.saturating_sub(self.last_update_ts);
+
+        if elapsed > 0 && reserve_x > 0 && reserve_y > 0 {
+            let price_x = self.price_x_q64(reserve_x, reserve_y)?;
+            let price_y = self.price_y_q64(reserve_x, reserve_y)?;
+            self.price_cumulative_x = self
+                .price_cumulative_x
+                .wrapping_add(price_x.wrapping_mul(elapsed as u128));
+            self.price_cumulative_y = self
+                .price_cumulative_y
+                .wrapping_add(price_y.wrapping_mul(elapsed as u128));
+        }
+
+        self.last_update_ts = now;
+        Ok(())
+    }
+
+    /// Whether the fee of an `is_x` swap is charged in mint x.
+    pub fn fee_is_x(&self, is_x: bool) -> bool {
+        match self.fee_mode {
+            FeeMode::Input => is_x,
+            FeeMode::Output => !is_x,
+        }
+    }
+
+    /// Mint the fee of a `mint_in` to `mint_out` swap is charged in.
+    pub fn fee_mint(&self, mint_in: Pubkey, mint_out: Pubkey) -> Pubkey {
+        match self.fee_mode {
+            FeeMode::Input => mint_in,
+            FeeMode::Output => mint_out,
+        }
+    }
+
+    /// The tighter of the pool's price impact limit and `limit_bps`, zero disabling
+    /// either and zero returned for no limit at all.
+    pub fn price_impact_limit(&self, limit_bps: u16) -> u16 {
+        match (self.max_price_impact_bps, limit_bps) {
+            (0, limit) | (limit, 0) => limit,
+            (pool, caller) => pool.min(caller),
+        }
+    }
+
+    /// Most of `amount_in` that check_price_impact lets through for the same
+    /// `limit_bps` and `fee`, which may be nothing.
+    pub fn fill_within_impact(
+        &self,
+        is_x: bool,
+        amount_in: u64,
+        limit_bps: u16,
+        fee: u16,
+    ) -> Result<u64> {
+        let limit = self.price_impact_limit(limit_bps);
+        if limit == 0 {
+            return Ok(amount_in);
+        }
+        let reserve_in = match is_x {
+            true => self.reserve_x,
+            false => self.reserve_y,
+        };
+        let priced = limit as u128 * reserve_in as u128 / 10_000;
+        // what fits would be all fee
+        if priced == 0 {
+            return Ok(0);
+        }
+        Ok(self.gross_input(priced, fee)?.min(amount_in as u128) as u64)
+    }
+
+    /// The largest input of which `priced` or less is left to price after the fee.
+    fn gross_input(&self, priced: u128, fee: u16) -> Result<u128> {
+        Ok(match self.fee_mode {
+            // the fee is rounded up, so each unit of fee covers a fraction of a unit
+            FeeMode::Input => ((priced + 1) * 10_000 - 1)
+                .checked_div(10_000u128.saturating_sub(fee as u128))
+                .ok_or(AmmError::InvalidFee)?,
+            FeeMode::Output => priced,
+        })
+    }
+
+    /// Fails when selling `amount_in` would move more of the input reserve than the
+    /// tighter of the pool's limit and `limit_bps` allows, zero disabling either. So
+    /// a caller can only ever tighten the pool's limit. `fee` is what the swap is
+    /// charged, the pool fee unless the trader is exempt.
+    pub fn check_price_impact(
+        &self,
+        is_x: bool,
+        amount_in: u64,
+        limit_bps: u16,
+        fee: u16,
+    ) -> Result<()> {
+        let limit = self.price_impact_limit(limit_bps);
+        if limit == 0 {
+            return Ok(());
+        }
+
+        let reserve_in = match is_x {
+            true => self.reserve_x,
+            false => self.reserve_y,
+        };
+        let priced = match self.fee_mode {
+            FeeMode::Input => {
+                amount_in - fee_amount(amount_in, fee).ok_or(AmmError::MathOverflow)?
+            }
+            FeeMode::Output => amount_in,
+        };
+        if priced as u128 * 10_000 > limit as u128 * reserve_in as u128 {
+            msg!(
+                "amount_in: {}, reserve_in: {}, limit_bps: {}",
+                amount_in,
+                reserve_in,
+                limit
+            );
+            return err!(AmmError::PriceImpactTooHigh);
+        }
+        Ok(())
+    }
+
+    /// Prices selling `amount_in`, already received by the vault, against the
+    /// tracked reserves, within the pool's price impact limit.
+    pub fn quote_swap(&self, is_x: bool, amount_in: u64) -> Result<SwapQuote> {
+        self.quote_swap_at_fee(is_x, amount_in, self.fee)
+    }
+
+    /// quote_swap charging `fee` instead of the pool fee, zero for exempt traders.
+    pub fn quote_swap_at_fee(&self, is_x: bool, amount_in: u64, fee: u16) -> Result<SwapQuote> {
+        let (reserve_x, reserve_y) = (self.reserve_x, self.reserve_y);
+        if reserve_x == 0 || reserve_y == 0 {
+            msg!("reserve_x: {}, reserve_y: {}", reserve_x, reserve_y);
+            return err!(AmmError::NoLiquidityInPool);
+        }
+        if amount_in < self.min_trade_size {
+            msg!(
+                "amount_in: {}, min_trade_size: {}",
+                amount_in,
+                self.min_trade_size
+            );
+            return err!(AmmError::AmountTooSmall);
+        }
+        self.check_price_impact(is_x, amount_in, 0, fee)?;
+
+        let (reserve_in, reserve_out) = match is_x {
+            true => (reserve_x, reserve_y),
+            false => (reserve_y, reserve_x),
+        };
+        let (curve, scales) = (self.curve_now()?, self.precision()?);
+        // the fee rounds up and the output down, both in the pool's favor
+        let quote = match self.fee_mode {
+            FeeMode::Input => SwapQuote {
+                amount_out: curve
+                    .get_amount_out(is_x, reserve_in, reserve_out, amount_in, fee, scales)
+                    .ok_or(AmmError::MathOverflow)?,
+                fee: fee_amount(amount_in, fee).ok_or(AmmError::MathOverflow)?,
+            },
+            FeeMode::Output => {
+                // the whole input is priced, then the fee is taken off the output
+                let gross = curve
+                    .get_amount_out(is_x, reserve_in, reserve_out, amount_in, 0, scales)
+                    .ok_or(AmmError::MathOverflow)?;
+                let fee = fee_amount(gross, fee).ok_or(AmmError::MathOverflow)?;
+                SwapQuote {
+                    amount_out: gross - fee,
+                    fee,
+                }
+            }
+        };
+        if quote.amount_out == 0 {
+            msg!(
+                "amount_in: {}, reserve_x: {}, reserve_y: {}",
+                amount_in,
+                reserve_x,
+                reserve_y
+            );
+            return err!(AmmError::AmountOutTooSmall);
+        }
+        Ok(quote)
+    }
+
+    /// Most of `amount_in` a swap can sell before the pool's price of the input mint,
+    /// in whole tokens of the output mint, falls to `limit_num / limit_denom`. The
+    /// price is the marginal one of the reserves after the trade, with whatever of
+    /// the fee stays in the pool counted. A zero on either side of the limit leaves
+    /// `amount_in` whole. Constant product pools only.
+    pub fn fill_to_limit(
+        &self,
+        is_x: bool,
+        amount_in: u64,
+        fee: u16,
+        protocol_fee_bps: u16,
+        limit_num: u64,
+        limit_denom: u64,
+    ) -> Result<u64> {
+        if limit_num == 0 || limit_denom == 0 {
+            return Ok(amount_in);
+        }
+        require!(
+            self.curve == CurveType::ConstantProduct,
+            AmmError::UnsupportedLimitCurve
+        );
+        let (reserve_in, reserve_out) = match is_x {
+            true => (self.reserve_x, self.reserve_y),
+            false => (self.reserve_y, self.reserve_x),
+        };
+        if reserve_in == 0 || reserve_out == 0 {
+            msg!("reserve_in: {}, reserve_out: {}", reserve_in, reserve_out);
+            return err!(AmmError::NoLiquidityInPool);
+        }
+
+        // the marginal price is k / reserve_in^2, so the limit is reached with
+        // sqrt(k / limit) of the input in the pool, the limit taken to raw units by
+        // the scales; a limit too low to compute that for can never be reached
+        let (scale_x, scale_y) = self.precision()?;
+        let (scale_in, scale_out) = match is_x {
+            true => (scale_x, scale_y),
+            false => (scale_y, scale_x),
+        };
+        let Some(squared) = mul_div_wide(
+            reserve_in as u128 * reserve_out as u128,
+            limit_denom as u128 * scale_out as u128,
+            limit_num as u128 * scale_in as u128,
+        ) else {
+            return Ok(amount_in);
+        };
+        let target = sqrt(squared);
+        if target <= reserve_in as u128 {
+            msg!(
+                "reserve_in: {}, reserve_out: {}, limit: {}/{}",
+                reserve_in,
+                reserve_out,
+                limit_num,
+                limit_denom
+            );
+            return err!(AmmError::PriceLimitReached);
+        }
+
+        // the output left is at least k over the input priced, so the price stays at
+        // the limit as long as that times the input kept is within target^2. Selling
+        // target - reserve_in keeps no more than target, and pricing past target
+        // never does, which brackets the most that can be sold
+        let within = |gross: u128| -> Result<bool> {
+            let (priced, kept) = self.limit_reserves(reserve_in, gross, fee, protocol_fee_bps)?;
+            Ok(priced
+                .checked_mul(kept)
+                .is_some_and(|reached| reached <= squared))
+        };
+        let most = self
+            .gross_input(target - reserve_in as u128, fee)?
+            .min(amount_in as u128);
+        if within(most)? {
+            return Ok(most as u64);
+        }
+        let (mut low, mut high) = (target - reserve_in as u128, most);
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            match within(mid)? {
+                true => low = mid,
+                false => high = mid,
+            }
+        }
+        Ok(low as u64)
+    }
+
+    /// The input reserve a sale of `gross` is priced against and the one it leaves,
+    /// which keeps the LP part of the fee when that is taken from the input.
+    fn limit_reserves(
+        &self,
+        reserve_in: u64,
+        gross: u128,
+        fee: u16,
+        protocol_fee_bps: u16,
+    ) -> Result<(u128, u128)> {
+        let gross = u64::try_from(gross).map_err(|_| AmmError::MathOverflow)?;
+        let (priced, kept) = match self.fee_mode {
+            FeeMode::Input => {
+                let fee = fee_amount(gross, fee).ok_or(AmmError::InvalidFee)?;
+                let share = mul_div_floor(fee, protocol_fee_bps as u64, 10_000)
+                    .ok_or(AmmError::MathOverflow)?;
+                (gross - fee, gross - share)
+            }
+            FeeMode::Output => (gross, gross),
+        };
+        Ok((
+            reserve_in as u128 + priced as u128,
+            reserve_in as u128 + kept as u128,
+        ))
+    }
+
+    /// LP the first deposit prices off what it paid in, MINIMUM_LIQUIDITY of which is
+    /// locked in the pool.
+    pub fn initial_liquidity(amount_x: u64, amount_y: u64) -> Result<u64> {
+        let liquidity = sqrt(amount_x as u128 * amount_y as u128) as u64;
+        require!(
+            liquidity > MINIMUM_LIQUIDITY,
+            AmmError::LiquidityLessThanMinimum
+        );
+        Ok(liquidity)
+    }
+
+    /// What `lp` out of `supply` costs in each token, rounded up as it is paid in.
+    pub fn deposit_cost(&self, lp: u64, supply: u64) -> Result<(u64, u64)> {
+        Ok((
+            mul_div_ceil(self.reserve_x, lp, supply).ok_or(AmmError::Overflow)?,
+            mul_div_ceil(self.reserve_y, lp, supply).ok_or(AmmError::Overflow)?,
+        ))
+    }
+
+    /// LP out of `supply` that `amount_x` and `amount_y` pay for at the pool ratio,
+    /// rounded down and set by the scarcer side.
+    pub fn lp_for(&self, amount_x: u64, amount_y: u64, supply: u64) -> Result<u64> {
+        Ok(mul_div_floor(amount_x, supply, self.reserve_x)
+            .ok_or(AmmError::Overflow)?
+            .min(mul_div_floor(amount_y, supply, self.reserve_y).ok_or(AmmError::Overflow)?))
+    }
+
+    /// Share of each reserve that burning `lp` out of `supply` pays, rounded down so
+    /// the pool never pays out more than the LP is worth.
+    pub fn withdraw_amounts(&self, lp: u64, supply: u64) -> Result<(u64, u64)> {
+        if lp > supply {
+            msg!("lp: {}, supply: {}", lp, supply);
+            return err!(AmmError::InsufficientBalance);
+        }
+        Ok((
+            mul_div_floor(self.reserve_x, lp, supply).ok_or(AmmError::Overflow)?,
+            mul_div_floor(self.reserve_y, lp, supply).ok_or(AmmError::Overflow)?,
+        ))
+    }
+
+    /// Books a quoted swap into the reserves according to the fee mode, the protocol
+    /// taking `protocol_fee_bps` of the fee as the global protocol state caps the
+    /// pool's own share. Returns that share, which is in the mint `fee_is_x` names.
+    pub fn book_swap_at(
+        &mut self,
+        is_x: bool,
+        amount_in: u64,
+        quote: &SwapQuote,
+        protocol_fee_bps: u16,
+    ) -> Result<u64> {
+        let before = (self.reserve_x, self.reserve_y);
+        let share = mul_div_floor(quote.fee, protocol_fee_bps as u64, 10_000)
+            .ok_or(AmmError::MathOverflow)?;
+        match self.fee_mode {
+            FeeMode::Input => self.apply_swap(is_x, amount_in, share, quote.amount_out),
+            FeeMode::Output => self.apply_swap_output_fee(is_x, amount_in, share, quote.amount_out),
+        }?;
+        if let Some(moved) = self.breaker_move(before)? {
+            msg!(
+                "price_move_bps: {}, limit: {}",
+                moved,
+                self.circuit_breaker_bps
+            );
+            return err!(AmmError::PriceMoveTooLarge);
+        }
+        self.track_slot_volume(is_x, amount_in)?;
+        self.record_swap(is_x, amount_in, quote.fee);
+        self.record_trade_slot()?;
+        Ok(share)
+    }
+
+    /// Adds `amount_in` to what was sold of the input mint this slot, failing once
+    /// that passes the pool's per-slot limit.
+    pub fn track_slot_volume(&mut self, is_x: bool, amount_in: u64) -> Result<()> {
+        let slot = Clock::get()?.slot;
+        if slot != self.current_slot {
+            self.current_slot = slot;
+            self.slot_volume_x = 0;
+            self.slot_volume_y = 0;
+        }
+        let (volume, limit) = match is_x {
+            true => (&mut self.slot_volume_x, self.max_volume_per_slot_x),
+            false => (&mut self.slot_volume_y, self.max_volume_per_slot_y),
+        };
+        *volume = volume.saturating_add(amount_in);
+        if limit != 0 && *volume > limit {
+            msg!("slot_volume: {}, limit: {}", volume, limit);
+            return err!(AmmError::RateLimitExceeded);
+        }
+        Ok(())
+    }
+
+    /// Adds a booked swap to the statistics.
+    pub fn record_swap(&mut self, is_x: bool, amount_in: u64, fee: u64) {
+        let volume = match is_x {
+            true => &mut self.cumulative_volume_x,
+            false => &mut self.cumulative_volume_y,
+        };
+        *volume = volume.saturating_add(amount_in as u128);
+        let fees = match self.fee_is_x(is_x) {
+            true => &mut self.cumulative_fees_x,
+            false => &mut self.cumulative_fees_y,
+        };
+        *fees = fees.saturating_add(fee);
+        self.swap_count = self.swap_count.saturating_add(1);
+    }
+
+    /// Stamps a booked swap with its slot and the price it left, counting the swaps
+    /// of that slot from one.
+    pub fn record_trade_slot(&mut self) -> Result<()> {
+        let slot = Clock::get()?.slot;
+        if slot != self.last_swap_slot {
+            self.last_swap_slot = slot;
+            self.trades_in_last_slot = 0;
+        }
+        self.trades_in_last_slot = self.trades_in_last_slot.saturating_add(1);
+        // a swap never takes a reserve to zero, the check is for the type's sake
+        self.last_swap_price_q64 = self.price_x_q64(self.reserve_x, self.reserve_y)?;
+        Ok(())
+    }
+
+    /// Books a trade into the tracked reserves. `amount_in` arrived on the input side
+    /// carrying the protocol's `share` of the fee, which stays out of the reserves.
+    /// Returns that share, which the caller moves into the input side's fee vault.
+    pub fn apply_swap(
+        &mut self,
+        is_x: bool,
+        amount_in: u64,
+        share: u64,
+        amount_out: u64,
+    ) -> Result<u64> {
+        let amount_in = amount_in.checked_sub(share).ok_or(AmmError::Underflow)?;
+
+        let (reserve_in, reserve_out) = match is_x {
+            true => (&mut self.reserve_x, &mut self.reserve_y),
+            false => (&mut self.reserve_y, &mut self.reserve_x),
+        };
+        *reserve_in = reserve_in
+            .checked_add(amount_in)
+            .ok_or(AmmError::Overflow)?;
+        *reserve_out = reserve_out
+            .checked_sub(amount_out)
+            .ok_or(AmmError::Underflow)?;
+        Ok(share)
+    }
+
+    /// Like apply_swap, but the fee was taken from the output, so the protocol's
+    /// `share` of it leaves the output side together with `amount_out`.
+    pub fn apply_swap_output_fee(
+        &mut self,
+        is_x: bool,
+        amount_in: u64,
+        share: u64,
+        amount_out: u64,
+    ) -> Result<u64> {
+        let amount_out = amount_out.checked_add(share).ok_or(AmmError::Overflow)?;
+
+        let (reserve_in, reserve_out) = match is_x {
+            true => (&mut self.reserve_x, &mut self.reserve_y),
+            false => (&mut self.reserve_y, &mut self.reserve_x),
+        };
+        *reserve_in = reserve_in
+            .checked_add(amount_in)
+            .ok_or(AmmError::Overflow)?;
+        *reserve_out = reserve_out
+            .checked_sub(amount_out)
+            .ok_or(AmmError::Underflow)?;
+        Ok(share)
+    }
 }
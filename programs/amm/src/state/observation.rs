@@ -0,0 +1,79 @@
+use anchor_lang::prelude::*;
+
+use crate::constants::OBSERVATION_CAPACITY;
+
+#[zero_copy(unsafe)]
+pub struct Observation {
+    pub timestamp: i64,
+    pub price_cumulative_x: u128,
+    pub price_cumulative_y: u128,
+}
+
+impl Observation {
+    /// Time-weighted Q64.64 prices of x and y between `older` and this observation.
+    pub fn twap_since(&self, older: &Observation) -> Option<(u128, u128)> {
+        let elapsed = self.timestamp.checked_sub(older.timestamp)?;
+        if elapsed <= 0 {
+            return None;
+        }
+
+        // the accumulators wrap, so differences are taken with wrapping arithmetic
+        let price_x = self
+            .price_cumulative_x
+            .wrapping_sub(older.price_cumulative_x)
+            / elapsed as u128;
+        let price_y = self
+            .price_cumulative_y
+            .wrapping_sub(older.price_cumulative_y)
+            / elapsed as u128;
+        Some((price_x, price_y))
+    }
+}
+
+#[account(zero_copy(unsafe))]
+pub struct ObservationState {
+    pub config: Pubkey,
+    /// Slot the next observation is written to.
+    pub index: u64,
+    /// Number of slots written so far, saturating at OBSERVATION_CAPACITY.
+    pub count: u64,
+    pub observations: [Observation; 32],
+}
+
+impl ObservationState {
+    pub fn latest(&self) -> Option<Observation> {
+        match self.count {
+            0 => None,
+            _ => {
+                let index = (self.index as usize + OBSERVATION_CAPACITY - 1) % OBSERVATION_CAPACITY;
+                Some(self.observations[index])
+            }
+        }
+    }
+
+    /// Oldest observation still held in the ring.
+    pub fn oldest(&self) -> Option<Observation> {
+        match self.count as usize {
+            0 => None,
+            count if count < OBSERVATION_CAPACITY => Some(self.observations[0]),
+            _ => Some(self.observations[self.index as usize]),
+        }
+    }
+
+    /// Writes a new observation, skipping it if one was already taken at this timestamp.
+    pub fn write(&mut self, timestamp: i64, price_cumulative_x: u128, price_cumulative_y: u128) {
+        if let Some(latest) = self.latest() {
+            if latest.timestamp >= timestamp {
+                return;
+            }
+        }
+
+        self.observations[self.index as usize] = Observation {
+            timestamp,
+            price_cumulative_x,
+            price_cumulative_y,
+        };
+        self.index = (self.index + 1) % OBSERVATION_CAPACITY as u64;
+        self.count = (self.count + 1).min(OBSERVATION_CAPACITY as u64);
+    }
+}
@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+
+/// Admin parameter change that only takes effect once the timelock has elapsed.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum ParameterChange {
+    Fee {
+        fee: u16,
+    },
+    ProtocolFee {
+        protocol_fee_bps: u16,
+    },
+    MaxPriceImpact {
+        max_price_impact_bps: u16,
+    },
+    CircuitBreaker {
+        circuit_breaker_bps: u16,
+    },
+    MaxVolumePerSlot {
+        max_volume_per_slot_x: u64,
+        max_volume_per_slot_y: u64,
+    },
+    SkimToLps {
+        skim_to_lps: bool,
+    },
+    /// Proposes `authority`, which still has to accept_authority once executed.
+    Authority {
+        authority: Pubkey,
+    },
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct PendingChange {
+    pub change: ParameterChange,
+    pub effective_ts: i64,
+}
@@ -0,0 +1,28 @@
+use anchor_lang::prelude::*;
+
+/// Program-wide switch for the protocol's share of swap fees, at `[b"protocol"]`.
+/// Read by every swap, so a change reaches all pools at once.
+#[account]
+#[derive(InitSpace)]
+pub struct ProtocolState {
+    /// May change the fields below and hand itself over.
+    pub authority: Pubkey,
+    /// Proposed by the authority, takes over once it accepts.
+    pub pending_authority: Option<Pubkey>,
+    /// Off, no swap routes a protocol share and the whole fee stays with the LPs.
+    pub protocol_fee_enabled: bool,
+    /// Share of the fee the protocol takes, in bps, capped by each pool's
+    /// `protocol_fee_bps`.
+    pub default_protocol_fee_bps: u16,
+    pub bump: u8,
+}
+
+impl ProtocolState {
+    /// The share of the fee a pool routes to the protocol given its own cap.
+    pub fn protocol_fee_bps(&self, pool_cap: u16) -> u16 {
+        match self.protocol_fee_enabled {
+            true => self.default_protocol_fee_bps.min(pool_cap),
+            false => 0,
+        }
+    }
+}
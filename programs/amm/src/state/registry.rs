@@ -0,0 +1,14 @@
+use anchor_lang::prelude::*;
+
+use super::CurveType;
+
+/// Points at the pool of a pair and fee tier from `[b"registry", mint_x, mint_y,
+/// fee_tier]`, so a wallet can look a pool up by its mints with a single fetch.
+/// Created with the pool and closed with it.
+#[account]
+#[derive(InitSpace)]
+pub struct PoolRegistryEntry {
+    pub config: Pubkey,
+    pub curve: CurveType,
+    pub bump: u8,
+}
@@ -0,0 +1,239 @@
+use anchor_lang::{
+    event::{EVENT_AUTHORITY_SEED, EVENT_IX_TAG_LE},
+    prelude::*,
+    solana_program::{instruction::Instruction, program::invoke_signed},
+    Event,
+};
+use anchor_spl::{
+    token::Token,
+    token_2022::spl_token_2022::{
+        extension::{
+            transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType,
+            StateWithExtensions,
+        },
+        onchain::invoke_transfer_checked,
+        state::Mint as MintState,
+    },
+    token_interface::{transfer_checked, Mint, TokenAccount, TransferChecked},
+};
+
+use crate::{
+    error::AmmError,
+    state::{Config, CurveType},
+};
+
+/// Fee withheld by the TransferFeeConfig extension when `amount` of `mint` is transferred.
+/// Always zero for legacy SPL mints and Token-2022 mints without the extension.
+pub fn transfer_fee(mint: &InterfaceAccount<Mint>, amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner == Token::id() {
+        return Ok(0);
+    }
+
+    let data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<MintState>::unpack(&data)?;
+    match mint.get_extension::<TransferFeeConfig>() {
+        Ok(config) => Ok(config
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or(AmmError::Overflow)?),
+        Err(_) => Ok(0),
+    }
+}
+
+/// Whether transfers of `mint` withhold a fee in the receiving account, which only
+/// Token-2022 mints with the TransferFeeConfig extension do.
+pub fn withholds_transfer_fee(mint: &InterfaceAccount<Mint>) -> Result<bool> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner == Token::id() {
+        return Ok(false);
+    }
+
+    let data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<MintState>::unpack(&data)?;
+    Ok(mint.get_extension::<TransferFeeConfig>().is_ok())
+}
+
+/// Reads a token account of `token_program` that is not known to be one before the
+/// instruction runs, like a vault handed to initialize.
+pub fn load_token_account(info: &AccountInfo, token_program: &Pubkey) -> Result<TokenAccount> {
+    require_keys_eq!(
+        *info.owner,
+        *token_program,
+        ErrorCode::AccountOwnedByWrongProgram
+    );
+    TokenAccount::try_deserialize(&mut &info.try_borrow_data()?[..])
+}
+
+/// Fails on Token-2022 mints whose extensions hand a third party control over the
+/// vault balances. NonTransferable mints could never leave the vaults and are always
+/// refused, TransferHook and PermanentDelegate only unless `allow_unsafe` is set.
+pub fn check_mint_extensions(mint: &InterfaceAccount<Mint>, allow_unsafe: bool) -> Result<()> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner == Token::id() {
+        return Ok(());
+    }
+
+    let data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<MintState>::unpack(&data)?;
+    for extension in mint.get_extension_types()? {
+        let supported = match extension {
+            ExtensionType::NonTransferable => false,
+            ExtensionType::TransferHook | ExtensionType::PermanentDelegate => allow_unsafe,
+            _ => true,
+        };
+        if !supported {
+            msg!("extension: {:?}", extension);
+            return err!(AmmError::UnsupportedMintExtension);
+        }
+    }
+    Ok(())
+}
+
+/// transfer_checked that also passes the context's remaining accounts on to a
+/// TransferHook program, Token-2022 picks the ones the hook needs out of them.
+pub fn transfer_checked_with_hook<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>,
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    if ctx.remaining_accounts.is_empty() {
+        return transfer_checked(ctx, amount, decimals);
+    }
+    invoke_transfer_checked(
+        ctx.program.key,
+        ctx.accounts.from,
+        ctx.accounts.mint,
+        ctx.accounts.to,
+        ctx.accounts.authority,
+        &ctx.remaining_accounts,
+        amount,
+        decimals,
+        ctx.signer_seeds,
+    )
+    .map_err(Into::into)
+}
+
+/// Records `event` as the data of a self-CPI signed by the event authority, which
+/// unlike a log line RPC nodes never truncate. `emit_cpi!` for the accounts struct
+/// methods, which have no `ctx` and get the authority's bump passed down instead.
+pub fn emit_event_cpi<E: Event>(
+    event_authority: &AccountInfo,
+    event_authority_bump: u8,
+    event: &E,
+) -> Result<()> {
+    let data: Vec<u8> = EVENT_IX_TAG_LE
+        .iter()
+        .copied()
+        .chain(event.data())
+        .collect();
+    let ix = Instruction::new_with_bytes(
+        crate::ID,
+        &data,
+        vec![AccountMeta::new_readonly(event_authority.key(), true)],
+    );
+    invoke_signed(
+        &ix,
+        &[event_authority.clone()],
+        &[&[EVENT_AUTHORITY_SEED, &[event_authority_bump]]],
+    )
+    .map_err(Into::into)
+}
+
+/// Fails once the unix timestamp `deadline` has passed, zero means no deadline.
+pub fn check_deadline(deadline: i64) -> Result<()> {
+    if deadline != 0 {
+        require!(
+            Clock::get()?.unix_timestamp <= deadline,
+            AmmError::DeadlineExceeded
+        );
+    }
+    Ok(())
+}
+
+/// Config address of the pool for a pair and fee tier. The mints may be given in
+/// either order, they are sorted the same way `initialize` requires.
+pub fn derive_pool_address(mint_a: &Pubkey, mint_b: &Pubkey, fee: u16) -> (Pubkey, u8) {
+    derive_pair_address(b"config", mint_a, mint_b, fee)
+}
+
+/// Address of the PoolRegistryEntry for a pair and fee tier, mints in either order.
+pub fn derive_registry_address(mint_a: &Pubkey, mint_b: &Pubkey, fee: u16) -> (Pubkey, u8) {
+    derive_pair_address(b"registry", mint_a, mint_b, fee)
+}
+
+fn derive_pair_address(prefix: &[u8], mint_a: &Pubkey, mint_b: &Pubkey, fee: u16) -> (Pubkey, u8) {
+    let (mint_x, mint_y) = match mint_a < mint_b {
+        true => (mint_a, mint_b),
+        false => (mint_b, mint_a),
+    };
+    Pubkey::find_program_address(
+        &[prefix, mint_x.as_ref(), mint_y.as_ref(), &fee.to_le_bytes()],
+        &crate::ID,
+    )
+}
+
+/// Integer square root, rounded down.
+pub fn sqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n;
+    let mut y = n / 2 + n % 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+/// Moves the protocol's `share` of a swap fee from `vault` into the fee vault of the
+/// same mint, so the main vaults only ever hold the reserves. `hook_accounts` are
+/// forwarded to a transfer hook on the mint, if any.
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_protocol_share<'info>(
+    config: &Account<'info, Config>,
+    vault_authority: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault: AccountInfo<'info>,
+    fee_vault: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    hook_accounts: &[AccountInfo<'info>],
+    share: u64,
+) -> Result<()> {
+    if share == 0 {
+        return Ok(());
+    }
+
+    let accounts = TransferChecked {
+        from: vault,
+        mint: mint.to_account_info(),
+        to: fee_vault,
+        authority: vault_authority,
+    };
+
+    let config_key = config.key();
+    let seeds = &[&b"auth"[..], config_key.as_ref(), &[config.auth_bump]];
+    let signer_seeds = &[&seeds[..]];
+
+    let ctx = CpiContext::new_with_signer(token_program, accounts, signer_seeds)
+        .with_remaining_accounts(hook_accounts.to_vec());
+    transfer_checked_with_hook(ctx, share, mint.decimals)
+}
+
+/// Defense in depth behind the quoting math: fails unless the curve's invariant of
+/// the vault balances, which hold no protocol fees, did not decrease across a swap.
+/// `scales` are the pool's math::precision_scales.
+pub fn check_invariant(
+    curve: CurveType,
+    scales: (u64, u64),
+    before: (u64, u64),
+    after: (u64, u64),
+) -> Result<()> {
+    require!(
+        curve
+            .invariant_holds(before, after, scales)
+            .ok_or(AmmError::Overflow)?,
+        AmmError::InvariantViolated
+    );
+    Ok(())
+}
@@ -0,0 +1,399 @@
+//! Compute units of each handler against its budget, so a change that makes one
+//! heavier fails here once it runs more than BUDGET_SLACK_PCT over. Run with
+//! `cargo test-sbf --test bench_cu -- --nocapture` for the summary table.
+//! Aggregators allot around 60-80k units a hop, which the swap budgets stay under.
+//! create_lp_metadata is benched on its own, as it needs the token metadata program
+//! lp_metadata.rs loads.
+
+mod common;
+
+use amm::{constants::TIMELOCK_SECONDS, state::ParameterChange, CurveType};
+use anchor_lang::prelude::Clock;
+use anchor_spl::{metadata::ID as METADATA_ID, token::spl_token::native_mint};
+use common::{Simulation, TestPool};
+use solana_sdk::signature::{Keypair, Signer};
+
+const LIQUIDITY: u64 = 100_000_000;
+const DAY: i64 = 24 * 60 * 60;
+/// Of mint x, lent by the benched flash swap.
+const BORROWED: u64 = 1_000_000;
+
+/// Compute unit budget of each benched instruction, the one place they are set.
+const BUDGETS: &[(&str, u64)] = &[
+    ("initialize_protocol", 25_000),
+    ("initialize", 150_000),
+    ("initialize_with_liquidity", 200_000),
+    ("deposit (first)", 60_000),
+    ("deposit", 50_000),
+    ("deposit_amounts", 55_000),
+    ("deposit_single", 90_000),
+    ("swap x to y", 40_000),
+    ("swap y to x", 40_000),
+    ("swap (stable)", 80_000),
+    ("swap_exact_out", 45_000),
+    ("swap_to_limit", 45_000),
+    ("swap_partial", 45_000),
+    ("swap_batch (2 legs)", 90_000),
+    ("swap_sol_in", 60_000),
+    ("swap_sol_out", 60_000),
+    ("swap_route", 90_000),
+    ("begin_flash_swap", 40_000),
+    ("end_flash_swap", 45_000),
+    ("sync", 15_000),
+    ("skim", 40_000),
+    ("donate", 35_000),
+    ("withdraw", 50_000),
+    ("withdraw_all", 55_000),
+    ("get_pool_state", 15_000),
+    ("quote", 20_000),
+    ("preview_deposit", 15_000),
+    ("preview_withdraw", 15_000),
+    ("update_observation", 20_000),
+    ("schedule_update", 10_000),
+    ("execute_update", 10_000),
+    ("set_pause_flags", 15_000),
+    ("set_fee_recipient", 10_000),
+    ("reset_breaker", 10_000),
+    ("set_open_time", 10_000),
+    ("accept_authority", 10_000),
+    ("renounce_authority", 10_000),
+    ("create_allowlist", 15_000),
+    ("add_to_allowlist", 15_000),
+    ("remove_from_allowlist", 15_000),
+    ("create_fee_exemptions", 15_000),
+    ("add_fee_exemption", 15_000),
+    ("remove_fee_exemption", 15_000),
+    ("set_protocol_fee", 10_000),
+    ("propose_protocol_authority", 10_000),
+    ("accept_protocol_authority", 10_000),
+    ("collect_protocol_fees", 40_000),
+    ("harvest_withheld_fees", 20_000),
+    ("close_pool", 90_000),
+    ("migrate_config", 30_000),
+    ("create_lp_metadata", 80_000),
+    ("lock_pool", 10_000),
+    ("unlock_pool", 10_000),
+    ("set_lock", 10_000),
+    ("ramp_amp", 10_000),
+    ("stop_ramp", 10_000),
+    ("extend_lp_lock", 10_000),
+    ("claim_locked_lp", 40_000),
+];
+
+/// How far past its budget an instruction may run before the bench fails.
+const BUDGET_SLACK_PCT: u64 = 10;
+
+/// Compute units of each instruction benched so far, in order.
+#[derive(Default)]
+struct Bench {
+    rows: Vec<(&'static str, u64)>,
+}
+
+impl Bench {
+    /// Records what the amm used in the pool's last simulated transaction.
+    fn record(&mut self, name: &'static str, pool: &mut TestPool) {
+        let simulation = pool
+            .last_simulation
+            .take()
+            .unwrap_or_else(|| panic!("{name} was not simulated"));
+        self.push(name, &simulation);
+    }
+
+    /// Records initialize_protocol, which the pool's build sent before initialize.
+    fn record_protocol(&mut self, pool: &mut TestPool) {
+        let simulation = pool
+            .protocol_simulation
+            .take()
+            .expect("initialize_protocol was not simulated");
+        self.push("initialize_protocol", &simulation);
+    }
+
+    fn push(&mut self, name: &'static str, simulation: &Simulation) {
+        let (consumed, _) = simulation
+            .consumed(&amm::ID)
+            .unwrap_or_else(|| panic!("{name} logged no compute units"));
+        self.rows.push((name, consumed));
+    }
+
+    /// record for a transaction of several amm instructions, one name each in order.
+    fn record_each(&mut self, names: &[&'static str], pool: &mut TestPool) {
+        let simulation = pool
+            .last_simulation
+            .take()
+            .unwrap_or_else(|| panic!("{names:?} were not simulated"));
+        let each = simulation.consumed_each(&amm::ID);
+        assert_eq!(each.len(), names.len(), "{names:?} logged {each:?}");
+        for (name, (consumed, _)) in names.iter().zip(each) {
+            self.rows.push((name, consumed));
+        }
+    }
+
+    /// Prints the summary table and fails for every instruction past its budget by
+    /// more than the slack.
+    fn finish(self) {
+        println!(
+            "{:<28} {:>10} {:>10} {:>8}",
+            "instruction", "consumed", "budget", "used"
+        );
+        let mut over = Vec::new();
+        for (name, consumed) in &self.rows {
+            let budget = budget(name);
+            println!(
+                "{:<28} {:>10} {:>10} {:>7}%",
+                name,
+                consumed,
+                budget,
+                consumed * 100 / budget
+            );
+            if consumed * 100 > budget * (100 + BUDGET_SLACK_PCT) {
+                over.push(format!("{name} used {consumed} of {budget}"));
+            }
+        }
+        assert!(over.is_empty(), "over budget: {}", over.join(", "));
+    }
+}
+
+fn budget(name: &str) -> u64 {
+    BUDGETS
+        .iter()
+        .find(|(budgeted, _)| *budgeted == name)
+        .unwrap_or_else(|| panic!("no budget for {name}"))
+        .1
+}
+
+#[tokio::test]
+async fn instructions_stay_within_budget() {
+    let mut bench = Bench::default();
+
+    let mut pool = TestPool::builder().simulate_first().build().await;
+    bench.record_protocol(&mut pool);
+    bench.record("initialize", &mut pool);
+    let payer = pool.payer();
+    pool.deposit(&payer, 1, LIQUIDITY, LIQUIDITY).await.unwrap();
+    bench.record("deposit (first)", &mut pool);
+    pool.deposit(&payer, LIQUIDITY / 10, u64::MAX, u64::MAX)
+        .await
+        .unwrap();
+    bench.record("deposit", &mut pool);
+    pool.deposit_amounts(&payer, LIQUIDITY / 10, LIQUIDITY / 10)
+        .await
+        .unwrap();
+    bench.record("deposit_amounts", &mut pool);
+
+    let user = pool.new_user().await;
+    pool.deposit_single(&user, true, 1_000_000).await.unwrap();
+    bench.record("deposit_single", &mut pool);
+    pool.swap(&user, true, 1_000_000, 1).await.unwrap();
+    bench.record("swap x to y", &mut pool);
+    pool.swap(&user, false, 1_000_000, 1).await.unwrap();
+    bench.record("swap y to x", &mut pool);
+    pool.swap_exact_out(&user, true, 500_000, u64::MAX)
+        .await
+        .unwrap();
+    bench.record("swap_exact_out", &mut pool);
+    // fills part of the input, until x is down to 0.9 y
+    pool.swap_to_limit(&user, true, 10_000_000, (9, 10))
+        .await
+        .unwrap();
+    bench.record("swap_to_limit", &mut pool);
+    pool.swap_partial(&user, false, 10_000_000, 0, 100)
+        .await
+        .unwrap();
+    bench.record("swap_partial", &mut pool);
+    pool.swap_batch(&user, &[(true, 1_000_000), (false, 1_000_000)])
+        .await
+        .unwrap();
+    bench.record("swap_batch (2 legs)", &mut pool);
+    let next = pool.add_pool(LIQUIDITY).await;
+    pool.swap_route(&user, &next, 1_000_000, 1).await.unwrap();
+    bench.record("swap_route", &mut pool);
+
+    // repaid with the fee, rounded up
+    let repaid = BORROWED * 10_000 / (10_000 - pool.fee as u64) + 1;
+    let flash_swap = [
+        pool.flash_begin_instruction(&user, BORROWED, 0),
+        pool.transfer_to_vault_instruction(&user, pool.mint_x, repaid),
+        pool.flash_end_instruction(&user),
+    ];
+    pool.process_all(&flash_swap, &[&user]).await.unwrap();
+    bench.record_each(&["begin_flash_swap", "end_flash_swap"], &mut pool);
+    for (name, instruction) in [
+        ("sync", pool.sync_instruction()),
+        ("skim", pool.skim_instruction(&user)),
+    ] {
+        let donation = pool.transfer_to_vault_instruction(&user, pool.mint_x, 1_000);
+        pool.process_all(&[donation, instruction], &[&user])
+            .await
+            .unwrap();
+        bench.record(name, &mut pool);
+    }
+
+    pool.donate(&user, 1_000, 1_000).await.unwrap();
+    bench.record("donate", &mut pool);
+    pool.withdraw(&payer, LIQUIDITY / 10).await.unwrap();
+    bench.record("withdraw", &mut pool);
+    pool.pool_state().await;
+    bench.record("get_pool_state", &mut pool);
+    pool.quote(true, 1_000_000).await;
+    bench.record("quote", &mut pool);
+    pool.preview_deposit(1_000_000, 1_000_000).await;
+    bench.record("preview_deposit", &mut pool);
+    pool.preview_withdraw(LIQUIDITY / 10).await;
+    bench.record("preview_withdraw", &mut pool);
+    pool.update_observation().await.unwrap();
+    bench.record("update_observation", &mut pool);
+
+    pool.schedule_update(ParameterChange::SkimToLps { skim_to_lps: true })
+        .await
+        .unwrap();
+    bench.record("schedule_update", &mut pool);
+    pool.warp(TIMELOCK_SECONDS).await;
+    pool.execute_update().await.unwrap();
+    bench.record("execute_update", &mut pool);
+    pool.set_pause_flags(0).await.unwrap();
+    bench.record("set_pause_flags", &mut pool);
+    pool.set_fee_recipient(payer.pubkey()).await.unwrap();
+    bench.record("set_fee_recipient", &mut pool);
+    pool.reset_breaker().await.unwrap();
+    bench.record("reset_breaker", &mut pool);
+    pool.set_open_time(0).await.unwrap();
+    bench.record("set_open_time", &mut pool);
+
+    pool.create_allowlist().await.unwrap();
+    bench.record("create_allowlist", &mut pool);
+    pool.add_to_allowlist(user.pubkey()).await.unwrap();
+    bench.record("add_to_allowlist", &mut pool);
+    pool.remove_from_allowlist(user.pubkey()).await.unwrap();
+    bench.record("remove_from_allowlist", &mut pool);
+    pool.create_fee_exemptions().await.unwrap();
+    bench.record("create_fee_exemptions", &mut pool);
+    pool.add_fee_exemption(user.pubkey()).await.unwrap();
+    bench.record("add_fee_exemption", &mut pool);
+    pool.remove_fee_exemption(user.pubkey()).await.unwrap();
+    bench.record("remove_fee_exemption", &mut pool);
+
+    pool.set_protocol_fee(true, 2_000).await.unwrap();
+    bench.record("set_protocol_fee", &mut pool);
+    let protocol_authority = Keypair::new();
+    pool.propose_protocol_authority(protocol_authority.pubkey())
+        .await
+        .unwrap();
+    bench.record("propose_protocol_authority", &mut pool);
+    pool.accept_protocol_authority(&protocol_authority)
+        .await
+        .unwrap();
+    bench.record("accept_protocol_authority", &mut pool);
+
+    pool.lock().await.unwrap();
+    bench.record("lock_pool", &mut pool);
+    pool.unlock().await.unwrap();
+    bench.record("unlock_pool", &mut pool);
+    pool.lock_for(DAY).await.unwrap();
+    bench.record("set_lock", &mut pool);
+
+    // swaps paid in and out in lamports, by a wallet holding no tokens
+    let mut pool = TestPool::builder()
+        .wrapped_sol()
+        .simulate_first()
+        .build()
+        .await;
+    let payer = pool.payer();
+    pool.deposit(&payer, 1, LIQUIDITY, LIQUIDITY).await.unwrap();
+    let sol_is_x = pool.mint_x == native_mint::ID;
+    let wallet = pool.new_wallet().await;
+    pool.swap_sol_in(&wallet, sol_is_x, 1_000_000)
+        .await
+        .unwrap();
+    bench.record("swap_sol_in", &mut pool);
+    let mint = pool.keys().other_mint(native_mint::ID);
+    let bought = pool.balance(&wallet.pubkey(), &mint).await;
+    pool.swap_sol_out(&wallet, !sol_is_x, bought).await.unwrap();
+    bench.record("swap_sol_out", &mut pool);
+
+    // the authority handed over, then renounced
+    let mut pool = TestPool::builder().simulate_first().build().await;
+    let authority = pool.new_wallet().await;
+    let change = ParameterChange::Authority {
+        authority: authority.pubkey(),
+    };
+    pool.schedule_update(change).await.unwrap();
+    pool.accept_authority(&authority).await.unwrap();
+    bench.record("accept_authority", &mut pool);
+    pool.renounce_authority(&authority).await.unwrap();
+    bench.record("renounce_authority", &mut pool);
+
+    // a protocol share to collect, then the pool emptied and closed
+    let mut pool = TestPool::builder()
+        .protocol_fee_bps(2_000)
+        .simulate_first()
+        .build()
+        .await;
+    let payer = pool.payer();
+    pool.deposit(&payer, 1, LIQUIDITY, LIQUIDITY).await.unwrap();
+    let user = pool.new_user().await;
+    pool.swap(&user, true, 1_000_000, 1).await.unwrap();
+    pool.collect_protocol_fees(&user).await.unwrap();
+    bench.record("collect_protocol_fees", &mut pool);
+    pool.harvest_withheld_fees().await.unwrap();
+    bench.record("harvest_withheld_fees", &mut pool);
+    pool.withdraw_all(&payer).await.unwrap();
+    bench.record("withdraw_all", &mut pool);
+    pool.close_pool().await.unwrap();
+    bench.record("close_pool", &mut pool);
+
+    let mut pool = TestPool::builder().simulate_first().build().await;
+    let payer = pool.payer();
+    pool.deposit(&payer, 1, LIQUIDITY, LIQUIDITY).await.unwrap();
+    pool.downgrade(false).await;
+    pool.migrate().await.unwrap();
+    bench.record("migrate_config", &mut pool);
+
+    let mut pool = TestPool::builder()
+        .curve(CurveType::Stable { amp: 100 })
+        .simulate_first()
+        .build()
+        .await;
+    let payer = pool.payer();
+    pool.deposit(&payer, 1, LIQUIDITY, LIQUIDITY).await.unwrap();
+    let user = pool.new_user().await;
+    pool.swap(&user, true, 1_000_000, 1).await.unwrap();
+    bench.record("swap (stable)", &mut pool);
+    pool.ramp_amp(200, DAY).await.unwrap();
+    bench.record("ramp_amp", &mut pool);
+    pool.stop_ramp().await.unwrap();
+    bench.record("stop_ramp", &mut pool);
+
+    let mut pool = TestPool::builder()
+        .creator_lock(LIQUIDITY, DAY)
+        .simulate_first()
+        .build()
+        .await;
+    bench.record("initialize_with_liquidity", &mut pool);
+    let payer = pool.payer();
+    let clock: Clock = pool.context.banks_client.get_sysvar().await.unwrap();
+    pool.extend_lp_lock(&payer, &payer.pubkey(), clock.unix_timestamp + DAY)
+        .await
+        .unwrap();
+    bench.record("extend_lp_lock", &mut pool);
+    pool.warp(DAY + 1).await;
+    pool.claim_locked_lp(&payer, &payer.pubkey()).await.unwrap();
+    bench.record("claim_locked_lp", &mut pool);
+
+    bench.finish();
+}
+
+#[tokio::test]
+async fn lp_metadata_stays_within_budget() {
+    let mut bench = Bench::default();
+    let mut pool = TestPool::builder()
+        .program("mpl_token_metadata", METADATA_ID)
+        .simulate_first()
+        .build()
+        .await;
+    let payer = pool.payer();
+    pool.create_lp_metadata(&payer, "https://example.com/lp.json")
+        .await
+        .unwrap();
+    bench.record("create_lp_metadata", &mut pool);
+    bench.finish();
+}
@@ -0,0 +1,124 @@
+//! Checks the off-chain quotes of `amm::client` against what the program does.
+//!
+//! Run with `cargo test-sbf`, which builds the program this loads first.
+
+mod common;
+
+use amm::client::{quote_deposit, quote_swap, quote_withdraw, DepositQuote, WithdrawQuote};
+use common::TestPool;
+use solana_sdk::signature::{Keypair, Signer};
+
+const AMOUNTS: [u64; 5] = [1, 999, 123_457, 10_000_000, 77_777_777];
+
+/// Balances of `user` in mint_x, mint_y and mint_lp.
+async fn balances(pool: &mut TestPool, user: &Keypair) -> [u64; 3] {
+    let mut balances = [0; 3];
+    for (balance, mint) in balances
+        .iter_mut()
+        .zip([pool.mint_x, pool.mint_y, pool.mint_lp])
+    {
+        *balance = pool.balance(&user.pubkey(), &mint).await;
+    }
+    balances
+}
+
+#[tokio::test]
+async fn quotes_match_the_program() {
+    let mut pool = TestPool::builder().fee(30).build().await;
+    let user = pool.new_user().await;
+
+    // the first deposit prices the LP off both amounts
+    let state = pool.pool_state().await;
+    let quote = quote_deposit(
+        state.reserve_x,
+        state.reserve_y,
+        state.lp_supply,
+        400_000_000,
+        100_000_000,
+    )
+    .unwrap();
+    let before = balances(&mut pool, &user).await;
+    pool.deposit_amounts(&user, 400_000_000, 100_000_000)
+        .await
+        .unwrap();
+    let after = balances(&mut pool, &user).await;
+    assert_eq!(
+        quote,
+        DepositQuote {
+            lp: after[2] - before[2],
+            amount_x: before[0] - after[0],
+            amount_y: before[1] - after[1],
+        }
+    );
+
+    for amount_in in AMOUNTS {
+        for x_to_y in [true, false] {
+            let state = pool.pool_state().await;
+            let (reserve_in, reserve_out) = match x_to_y {
+                true => (state.reserve_x, state.reserve_y),
+                false => (state.reserve_y, state.reserve_x),
+            };
+            let quote = quote_swap(reserve_in, reserve_out, state.fee, amount_in);
+            let before = balances(&mut pool, &user).await;
+            let result = pool.swap(&user, x_to_y, amount_in, 0).await;
+            let after = balances(&mut pool, &user).await;
+            let Some(quote) = quote else {
+                assert!(result.is_err(), "{amount_in} quoted as failing");
+                continue;
+            };
+            result.unwrap();
+            let received = match x_to_y {
+                true => after[1] - before[1],
+                false => after[0] - before[0],
+            };
+            assert_eq!(quote.amount_out, received, "{amount_in} x_to_y {x_to_y}");
+        }
+    }
+
+    for amount_x in AMOUNTS {
+        for amount_y in [1_000, 5_000_000] {
+            let state = pool.pool_state().await;
+            let quote = quote_deposit(
+                state.reserve_x,
+                state.reserve_y,
+                state.lp_supply,
+                amount_x,
+                amount_y,
+            );
+            let before = balances(&mut pool, &user).await;
+            let result = pool.deposit_amounts(&user, amount_x, amount_y).await;
+            let after = balances(&mut pool, &user).await;
+            let Some(quote) = quote else {
+                assert!(result.is_err(), "{amount_x}, {amount_y} quoted as failing");
+                continue;
+            };
+            result.unwrap();
+            let taken = DepositQuote {
+                lp: after[2] - before[2],
+                amount_x: before[0] - after[0],
+                amount_y: before[1] - after[1],
+            };
+            assert_eq!(quote, taken, "{amount_x}, {amount_y}");
+        }
+    }
+
+    let held = balances(&mut pool, &user).await[2];
+    for lp in [1, 77, 4_321, 1_000_000, held + 1] {
+        let state = pool.pool_state().await;
+        let quote = quote_withdraw(state.reserve_x, state.reserve_y, state.lp_supply, lp);
+        let before = balances(&mut pool, &user).await;
+        let result = pool.withdraw(&user, lp).await;
+        let after = balances(&mut pool, &user).await;
+        // burning more than the user holds fails on their balance, not the supply
+        let Some(quote) = quote.filter(|_| lp <= before[2]) else {
+            assert!(result.is_err(), "{lp} quoted as failing");
+            continue;
+        };
+        result.unwrap();
+        let paid = WithdrawQuote {
+            amount_x: after[0] - before[0],
+            amount_y: after[1] - before[1],
+        };
+        assert_eq!(quote, paid, "{lp}");
+    }
+}
@@ -0,0 +1,1829 @@
+//! Reusable harness for the integration tests: a Zoro/Gear5-style pool of two fresh
+//! mints on a solana-program-test bank, run against the built program with
+//! `cargo test-sbf`.
+
+#![allow(dead_code)]
+
+use std::fmt::Debug;
+
+use amm::{
+    constants::CONFIG_VERSION,
+    state::{Config, ParameterChange},
+    utils::{derive_pool_address, derive_registry_address},
+    CurveType, DepositPreview, FeeMode, InitializeParams, PoolSnapshot, SwapLeg, WithdrawPreview,
+};
+use anchor_lang::{
+    prelude::Pubkey,
+    solana_program::sysvar::{self, clock::Clock},
+    AccountDeserialize, AccountSerialize, AnchorDeserialize, AnchorSerialize, InstructionData,
+    Space, ToAccountMetas,
+};
+use anchor_spl::{
+    associated_token::{
+        get_associated_token_address, spl_associated_token_account::instruction as ata,
+    },
+    metadata::ID as METADATA_ID,
+    token::spl_token::{self, instruction as token, native_mint},
+};
+use solana_program_test::{
+    find_file, read_file, BanksClientError, ProgramTest, ProgramTestContext,
+};
+use solana_sdk::{
+    account::Account,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    instruction::{Instruction, InstructionError},
+    program_pack::Pack,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_instruction, system_program,
+    transaction::{Transaction, TransactionError},
+};
+
+pub const DECIMALS: u8 = 6;
+/// Minted to every user per side.
+pub const FUNDS: u64 = 1_000_000 * 1_000_000;
+
+pub type TxResult = Result<(), BanksClientError>;
+
+/// What a simulated transaction reported.
+#[derive(Clone)]
+pub struct Simulation {
+    pub units_consumed: u64,
+    pub logs: Vec<String>,
+    pub return_data: Vec<u8>,
+}
+
+impl Simulation {
+    /// Units the outermost invocation of `program` used and was allowed, from its
+    /// "Program <id> consumed N of M compute units" log.
+    pub fn consumed(&self, program: &Pubkey) -> Option<(u64, u64)> {
+        // an invocation logs when it returns, so the outermost one comes last
+        self.logs
+            .iter()
+            .rev()
+            .find_map(|log| units_consumed(log, program))
+    }
+
+    /// consumed for every instruction of the transaction that is `program`'s, in
+    /// order, leaving out the invocations they make of it in turn.
+    pub fn consumed_each(&self, program: &Pubkey) -> Vec<(u64, u64)> {
+        let mut depth = 0;
+        let mut each = Vec::new();
+        for log in &self.logs {
+            let Some((id, event)) = log
+                .strip_prefix("Program ")
+                .and_then(|log| log.split_once(' '))
+            else {
+                continue;
+            };
+            // what programs log themselves, "Program log: ..." and the like
+            if id.ends_with(':') {
+                continue;
+            }
+            if let Some(level) = event.strip_prefix("invoke [") {
+                depth = level.trim_end_matches(']').parse().unwrap();
+            } else if event == "success" || event.starts_with("failed: ") {
+                depth -= 1;
+            } else if depth == 1 {
+                each.extend(units_consumed(log, program));
+            }
+        }
+        each
+    }
+}
+
+/// The units used and allowed in a "Program <id> consumed N of M compute units" log
+/// of `program`.
+fn units_consumed(log: &str, program: &Pubkey) -> Option<(u64, u64)> {
+    let (consumed, limit) = log
+        .strip_prefix(&format!("Program {program} consumed "))?
+        .strip_suffix(" compute units")?
+        .split_once(" of ")?;
+    Some((consumed.parse().ok()?, limit.parse().ok()?))
+}
+
+pub struct TestPool {
+    pub context: ProgramTestContext,
+    pub fee: u16,
+    pub mint_x: Pubkey,
+    pub mint_y: Pubkey,
+    pub config: Pubkey,
+    pub mint_lp: Pubkey,
+    pub vault_authority: Pubkey,
+    pub vault_x: Pubkey,
+    pub vault_y: Pubkey,
+    /// The program's upgrade authority, and so the protocol state's authority.
+    pub protocol_authority: Keypair,
+    /// Whether process simulates every transaction before sending it.
+    pub simulate_first: bool,
+    /// What the last simulation reported, including those simulate_first makes.
+    pub last_simulation: Option<Simulation>,
+    /// What simulate_first reported for the initialize_protocol the build sends.
+    pub protocol_simulation: Option<Simulation>,
+}
+
+/// The addresses of a pool TestPool keeps, of its own pool or another one in the
+/// same bank.
+#[derive(Clone, Copy)]
+pub struct PoolKeys {
+    pub mint_x: Pubkey,
+    pub mint_y: Pubkey,
+    pub config: Pubkey,
+    pub mint_lp: Pubkey,
+    pub vault_authority: Pubkey,
+    pub vault_x: Pubkey,
+    pub vault_y: Pubkey,
+}
+
+impl PoolKeys {
+    /// The pool of `mint_x` and `mint_y`, in that order, at `fee`, with its vaults
+    /// at the vault authority's ATAs.
+    pub fn new(mint_x: Pubkey, mint_y: Pubkey, fee: u16) -> Self {
+        let (config, _) = derive_pool_address(&mint_x, &mint_y, fee);
+        let vault_authority = pda(&[b"auth", config.as_ref()]);
+        Self {
+            mint_x,
+            mint_y,
+            config,
+            mint_lp: pda(&[b"lp", config.as_ref()]),
+            vault_authority,
+            vault_x: get_associated_token_address(&vault_authority, &mint_x),
+            vault_y: get_associated_token_address(&vault_authority, &mint_y),
+        }
+    }
+
+    /// The mint of this pool other than `mint`.
+    pub fn other_mint(&self, mint: Pubkey) -> Pubkey {
+        match mint == self.mint_x {
+            true => self.mint_y,
+            false => self.mint_x,
+        }
+    }
+
+    /// The vault of `mint`, which has to be one of the pair.
+    pub fn vault(&self, mint: Pubkey) -> Pubkey {
+        match mint == self.mint_x {
+            true => self.vault_x,
+            false => self.vault_y,
+        }
+    }
+
+    /// The fee vault on the side of `mint`.
+    pub fn fee_vault(&self, mint: Pubkey) -> Pubkey {
+        let seed: &[u8] = match mint == self.mint_x {
+            true => b"fee_vault_x",
+            false => b"fee_vault_y",
+        };
+        pda(&[seed, self.config.as_ref()])
+    }
+}
+
+/// Builds a TestPool, with a 30 bps constant product pool unless told otherwise.
+pub struct TestPoolBuilder {
+    fee: u16,
+    curve: CurveType,
+    opens_in: i64,
+    /// The payer unless set.
+    authority: Option<Option<Pubkey>>,
+    protocol_fee_bps: u16,
+    min_trade_size: u64,
+    /// Of mint x and mint y.
+    decimals: (u8, u8),
+    /// Amount of each side and lock duration for initialize_with_liquidity.
+    creator_lock: Option<(u64, i64)>,
+    custom_vaults: bool,
+    vault_delegate: Option<Pubkey>,
+    programs: Vec<(&'static str, Pubkey)>,
+    simulate_first: bool,
+    wrapped_sol: bool,
+}
+
+impl Default for TestPoolBuilder {
+    fn default() -> Self {
+        Self {
+            fee: 30,
+            curve: CurveType::ConstantProduct,
+            opens_in: 0,
+            authority: None,
+            protocol_fee_bps: 0,
+            min_trade_size: 0,
+            decimals: (DECIMALS, DECIMALS),
+            creator_lock: None,
+            custom_vaults: false,
+            vault_delegate: None,
+            programs: Vec::new(),
+            simulate_first: false,
+            wrapped_sol: false,
+        }
+    }
+}
+
+impl TestPoolBuilder {
+    pub fn fee(mut self, fee: u16) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    pub fn curve(mut self, curve: CurveType) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    pub fn authority(mut self, authority: Option<Pubkey>) -> Self {
+        self.authority = Some(authority);
+        self
+    }
+
+    pub fn protocol_fee_bps(mut self, protocol_fee_bps: u16) -> Self {
+        self.protocol_fee_bps = protocol_fee_bps;
+        self
+    }
+
+    pub fn min_trade_size(mut self, min_trade_size: u64) -> Self {
+        self.min_trade_size = min_trade_size;
+        self
+    }
+
+    /// Creates mint x and mint y with these decimals instead of DECIMALS.
+    pub fn decimals(mut self, decimals_x: u8, decimals_y: u8) -> Self {
+        self.decimals = (decimals_x, decimals_y);
+        self
+    }
+
+    /// Initializes with initialize_with_liquidity instead, paying in `amount` of each
+    /// side and locking the payer's LP for `duration` seconds.
+    pub fn creator_lock(mut self, amount: u64, duration: i64) -> Self {
+        self.creator_lock = Some((amount, duration));
+        self
+    }
+
+    /// Vaults at fresh addresses instead of the vault authority's ATAs.
+    pub fn custom_vaults(mut self) -> Self {
+        self.custom_vaults = true;
+        self
+    }
+
+    /// Custom vaults with `delegate` set on both, which initialize has to refuse.
+    pub fn vault_delegate(mut self, delegate: Pubkey) -> Self {
+        self.custom_vaults = true;
+        self.vault_delegate = Some(delegate);
+        self
+    }
+
+    /// Loads another program next to the amm, from `tests/fixtures/<name>.so`.
+    pub fn program(mut self, name: &'static str, id: Pubkey) -> Self {
+        self.programs.push((name, id));
+        self
+    }
+
+    /// Opens the pool to swaps `seconds` after the bank's clock at build time.
+    pub fn opens_in(mut self, seconds: i64) -> Self {
+        self.opens_in = seconds;
+        self
+    }
+
+    /// Simulates every transaction the pool sends before sending it, initialize
+    /// included, keeping the last in last_simulation.
+    pub fn simulate_first(mut self) -> Self {
+        self.simulate_first = true;
+        self
+    }
+
+    /// The native mint on one side, for swap_sol_in and swap_sol_out. That side has
+    /// its 9 decimals whatever `decimals` says.
+    pub fn wrapped_sol(mut self) -> Self {
+        self.wrapped_sol = true;
+        self
+    }
+
+    /// Starts the bank, creates both mints with the payer as mint authority, funds
+    /// the payer with FUNDS of each and initializes the pool with it as authority.
+    /// The initialize result is returned next to the pool for tests of its errors.
+    pub async fn try_build(self) -> (TestPool, TxResult) {
+        // deployed upgradeable, as initialize_protocol checks the upgrade authority
+        let protocol_authority = Keypair::new();
+        let mut program_test = ProgramTest::default();
+        program_test.prefer_bpf(true);
+        add_upgradeable_program(
+            &mut program_test,
+            "amm",
+            amm::ID,
+            protocol_authority.pubkey(),
+        );
+        program_test.add_account(
+            protocol_authority.pubkey(),
+            Account::new(1_000_000_000, 0, &system_program::ID),
+        );
+        for (name, id) in self.programs {
+            program_test.add_program(name, id, None);
+        }
+        let mut context = program_test.start_with_context().await;
+        let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+
+        let mut mints = [Keypair::new(), Keypair::new()];
+        mints.sort_by_key(|mint| mint.pubkey());
+        let (mint_x, mint_y) = match self.wrapped_sol {
+            false => (mints[0].pubkey(), mints[1].pubkey()),
+            true if mints[0].pubkey() < native_mint::ID => (mints[0].pubkey(), native_mint::ID),
+            true => (native_mint::ID, mints[0].pubkey()),
+        };
+        let keys = PoolKeys::new(mint_x, mint_y, self.fee);
+        let mut pool = TestPool {
+            context,
+            fee: self.fee,
+            mint_x: keys.mint_x,
+            mint_y: keys.mint_y,
+            config: keys.config,
+            mint_lp: keys.mint_lp,
+            vault_authority: keys.vault_authority,
+            vault_x: keys.vault_x,
+            vault_y: keys.vault_y,
+            protocol_authority,
+            simulate_first: self.simulate_first,
+            last_simulation: None,
+            protocol_simulation: None,
+        };
+        for (mint, decimals) in [(mint_x, self.decimals.0), (mint_y, self.decimals.1)] {
+            match mints.iter().find(|keypair| keypair.pubkey() == mint) {
+                Some(keypair) => pool.create_mint(keypair, decimals).await,
+                None => pool.create_native_mint().await,
+            }
+        }
+        if self.custom_vaults {
+            pool.vault_x = pool.create_vault(pool.mint_x, self.vault_delegate);
+            pool.vault_y = pool.create_vault(pool.mint_y, self.vault_delegate);
+        }
+        let payer = pool.payer();
+        pool.fund(&payer, FUNDS, FUNDS).await;
+
+        // every swap reads the protocol state, so it is created with the pool's own
+        // share in full effect
+        let authority = pool.protocol_authority.insecure_clone();
+        let accounts = amm::accounts::InitializeProtocol {
+            authority: authority.pubkey(),
+            protocol: pda(&[b"protocol"]),
+            program: amm::ID,
+            program_data: program_data_address(&amm::ID),
+            system_program: system_program::ID,
+        };
+        let data = amm::instruction::InitializeProtocol {
+            protocol_fee_enabled: true,
+            default_protocol_fee_bps: 10_000,
+        };
+        pool.process(instruction(accounts, data), &[&authority])
+            .await
+            .unwrap();
+        pool.protocol_simulation = pool.last_simulation.take();
+
+        let params = InitializeParams {
+            fee: self.fee,
+            authority: self.authority.unwrap_or(Some(payer.pubkey())),
+            fee_mode: FeeMode::Input,
+            allow_unsafe_extensions: false,
+            open_time: match self.opens_in {
+                0 => 0,
+                seconds => clock.unix_timestamp + seconds,
+            },
+            curve: self.curve,
+            protocol_fee_bps: self.protocol_fee_bps,
+            min_trade_size: self.min_trade_size,
+        };
+        let initialize = match self.creator_lock {
+            None => instruction(
+                pool.initialize_accounts(),
+                amm::instruction::Initialize { params },
+            ),
+            Some((amount, duration)) => {
+                let lock_position = pool.lock_position(&payer.pubkey());
+                let accounts = amm::accounts::InitializeWithLiquidity {
+                    pool: pool.initialize_accounts(),
+                    initializer_x: get_associated_token_address(&payer.pubkey(), &pool.mint_x),
+                    initializer_y: get_associated_token_address(&payer.pubkey(), &pool.mint_y),
+                    initializer_lp: None,
+                    lock_position: Some(lock_position),
+                    lock_position_lp: Some(get_associated_token_address(
+                        &lock_position,
+                        &pool.mint_lp,
+                    )),
+                    associated_token_program: anchor_spl::associated_token::ID,
+                    system_program: system_program::ID,
+                };
+                let data = amm::instruction::InitializeWithLiquidity {
+                    params,
+                    amount_x: amount,
+                    amount_y: amount,
+                    creator_lock_duration: duration,
+                };
+                instruction(accounts, data)
+            }
+        };
+        let result = pool.process(initialize, &[&payer]).await;
+        (pool, result)
+    }
+
+    pub async fn build(self) -> TestPool {
+        let (pool, result) = self.try_build().await;
+        result.unwrap();
+        pool
+    }
+}
+
+impl TestPool {
+    pub fn builder() -> TestPoolBuilder {
+        TestPoolBuilder::default()
+    }
+
+    /// The Initialize accounts of this pool, with the payer as initializer.
+    pub fn initialize_accounts(&self) -> amm::accounts::Initialize {
+        amm::accounts::Initialize {
+            initializer: self.payer().pubkey(),
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            mint_lp: self.mint_lp,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            observation: pda(&[b"observation", self.config.as_ref()]),
+            vault_x: self.vault_x,
+            vault_y: self.vault_y,
+            locked_lp: get_associated_token_address(&self.vault_authority, &self.mint_lp),
+            fee_vault_x: pda(&[b"fee_vault_x", self.config.as_ref()]),
+            fee_vault_y: pda(&[b"fee_vault_y", self.config.as_ref()]),
+            registry: derive_registry_address(&self.mint_x, &self.mint_y, self.fee).0,
+            token_program: spl_token::ID,
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        }
+    }
+
+    /// The LockPosition of `owner`'s LP in this pool.
+    pub fn lock_position(&self, owner: &Pubkey) -> Pubkey {
+        pda(&[b"lock", self.config.as_ref(), owner.as_ref()])
+    }
+
+    /// claim_locked_lp of `owner`'s position, sent by `user`.
+    pub async fn claim_locked_lp(&mut self, user: &Keypair, owner: &Pubkey) -> TxResult {
+        let lock_position = self.lock_position(owner);
+        let accounts = amm::accounts::ClaimLockedLp {
+            owner: user.pubkey(),
+            config: self.config,
+            mint_lp: self.mint_lp,
+            lock_position,
+            lock_position_lp: get_associated_token_address(&lock_position, &self.mint_lp),
+            owner_lp: get_associated_token_address(&user.pubkey(), &self.mint_lp),
+            token_program: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        };
+        self.process(
+            instruction(accounts, amm::instruction::ClaimLockedLp {}),
+            &[user],
+        )
+        .await
+    }
+
+    /// extend_lp_lock of `owner`'s position to `unlock_ts`, sent by `user`.
+    pub async fn extend_lp_lock(
+        &mut self,
+        user: &Keypair,
+        owner: &Pubkey,
+        unlock_ts: i64,
+    ) -> TxResult {
+        let accounts = amm::accounts::ExtendLpLock {
+            owner: user.pubkey(),
+            config: self.config,
+            lock_position: self.lock_position(owner),
+        };
+        let data = amm::instruction::ExtendLpLock { unlock_ts };
+        self.process(instruction(accounts, data), &[user]).await
+    }
+
+    pub fn payer(&self) -> Keypair {
+        self.context.payer.insecure_clone()
+    }
+
+    /// Sends `instruction` signed by `signers`, the first paying, on a fresh blockhash
+    /// so repeating an identical instruction is a new transaction.
+    pub async fn process(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TxResult {
+        self.process_all(&[instruction], signers).await
+    }
+
+    /// process with several instructions in one transaction, so in the same slot.
+    pub async fn process_all(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> TxResult {
+        let blockhash = self.context.get_new_latest_blockhash().await.unwrap();
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&signers[0].pubkey()),
+            signers,
+            blockhash,
+        );
+        if self.simulate_first {
+            // a failure shows up again when the transaction is sent
+            let _ = self.record_simulation(transaction.clone()).await;
+        }
+        self.context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+    }
+
+    /// The pool as get_pool_state returns it, decoded from the simulation's return
+    /// data.
+    pub async fn pool_state(&mut self) -> PoolSnapshot {
+        self.read(amm::instruction::GetPoolState {}).await
+    }
+
+    /// What quote returns for selling `amount_in`.
+    pub async fn quote(&mut self, x_to_y: bool, amount_in: u64) -> u64 {
+        self.read(amm::instruction::Quote { x_to_y, amount_in })
+            .await
+    }
+
+    pub async fn preview_deposit(&mut self, amount_x: u64, amount_y: u64) -> DepositPreview {
+        self.read(amm::instruction::PreviewDeposit { amount_x, amount_y })
+            .await
+    }
+
+    pub async fn preview_withdraw(&mut self, lp_amount: u64) -> WithdrawPreview {
+        self.read(amm::instruction::PreviewWithdraw { lp_amount })
+            .await
+    }
+
+    /// Simulates a Quote instruction, decoding what it returns.
+    async fn read<T: AnchorDeserialize>(&mut self, data: impl InstructionData) -> T {
+        let accounts = amm::accounts::Quote {
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            mint_lp: self.mint_lp,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            vault_x: self.vault(self.mint_x),
+            vault_y: self.vault(self.mint_y),
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+        };
+        let payer = self.payer();
+        let simulation = self.simulate(instruction(accounts, data), &[&payer]).await;
+        T::deserialize(&mut &simulation.return_data[..]).unwrap()
+    }
+
+    /// Simulates `instruction` so nothing lands. Panics if it fails.
+    async fn simulate(&mut self, instruction: Instruction, signers: &[&Keypair]) -> Simulation {
+        let blockhash = self.context.get_new_latest_blockhash().await.unwrap();
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&signers[0].pubkey()),
+            signers,
+            blockhash,
+        );
+        self.record_simulation(transaction).await.unwrap();
+        self.last_simulation.clone().unwrap()
+    }
+
+    /// Simulates `transaction`, keeping what it reported as last_simulation.
+    async fn record_simulation(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<(), TransactionError> {
+        let simulation = self
+            .context
+            .banks_client
+            .simulate_transaction(transaction)
+            .await
+            .unwrap();
+        self.last_simulation = simulation.simulation_details.map(|details| Simulation {
+            units_consumed: details.units_consumed,
+            logs: details.logs,
+            // only the amm sets return data in the transactions simulated here
+            return_data: details
+                .return_data
+                .map(|return_data| return_data.data)
+                .unwrap_or_default(),
+        });
+        simulation.result.unwrap()
+    }
+
+    /// A token account of `mint` owned by the vault authority at a fresh address. It
+    /// is written straight into the bank, so it can carry a delegate the vault
+    /// authority never approved.
+    fn create_vault(&mut self, mint: Pubkey, delegate: Option<Pubkey>) -> Pubkey {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner: self.vault_authority,
+            delegate: delegate.into(),
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        let account = Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: spl_token::ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        let address = Pubkey::new_unique();
+        self.context.set_account(&address, &account.into());
+        address
+    }
+
+    async fn create_mint(&mut self, mint: &Keypair, decimals: u8) {
+        let payer = self.payer();
+        let rent = self
+            .context
+            .banks_client
+            .get_rent()
+            .await
+            .unwrap()
+            .minimum_balance(spl_token::state::Mint::LEN);
+        let blockhash = self.context.get_new_latest_blockhash().await.unwrap();
+        let transaction = Transaction::new_signed_with_payer(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &mint.pubkey(),
+                    rent,
+                    spl_token::state::Mint::LEN as u64,
+                    &spl_token::ID,
+                ),
+                token::initialize_mint2(
+                    &spl_token::ID,
+                    &mint.pubkey(),
+                    &payer.pubkey(),
+                    None,
+                    decimals,
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+            &[&payer, mint],
+            blockhash,
+        );
+        self.context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap();
+    }
+
+    /// The native mint, which the bank may not hold at genesis.
+    async fn create_native_mint(&mut self) {
+        let banks_client = &mut self.context.banks_client;
+        if banks_client
+            .get_account(native_mint::ID)
+            .await
+            .unwrap()
+            .is_some()
+        {
+            return;
+        }
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            decimals: native_mint::DECIMALS,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        let account = Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: spl_token::ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        self.context.set_account(&native_mint::ID, &account.into());
+    }
+
+    /// Adds `amount` to the wrapped SOL account at `address`, lamports and balance
+    /// both, as the native mint has nothing to mint with.
+    async fn wrap_sol(&mut self, address: Pubkey, amount: u64) {
+        let mut account = self
+            .context
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut token = spl_token::state::Account::unpack(&account.data).unwrap();
+        token.amount += amount;
+        token.pack_into_slice(&mut account.data);
+        account.lamports += amount;
+        self.context.set_account(&address, &account.into());
+    }
+
+    /// Creates `user`'s ATAs of both mints and mints `amount_x` and `amount_y` to them.
+    pub async fn fund(&mut self, user: &Keypair, amount_x: u64, amount_y: u64) {
+        let payer = self.payer();
+        for (mint, amount) in [(self.mint_x, amount_x), (self.mint_y, amount_y)] {
+            let account = get_associated_token_address(&user.pubkey(), &mint);
+            let create = ata::create_associated_token_account_idempotent(
+                &payer.pubkey(),
+                &user.pubkey(),
+                &mint,
+                &spl_token::ID,
+            );
+            self.process(create, &[&payer]).await.unwrap();
+            if mint == native_mint::ID {
+                self.wrap_sol(account, amount).await;
+                continue;
+            }
+            let mint_to = token::mint_to(
+                &spl_token::ID,
+                &mint,
+                &account,
+                &payer.pubkey(),
+                &[],
+                amount,
+            )
+            .unwrap();
+            self.process(mint_to, &[&payer]).await.unwrap();
+        }
+    }
+
+    /// A new user with lamports for fees and rent, holding FUNDS of each mint.
+    pub async fn new_user(&mut self) -> Keypair {
+        let user = self.new_wallet().await;
+        self.fund(&user, FUNDS, FUNDS).await;
+        user
+    }
+
+    /// A new user with lamports for fees and rent and no token accounts.
+    pub async fn new_wallet(&mut self) -> Keypair {
+        let user = Keypair::new();
+        let payer = self.payer();
+        let transfer = system_instruction::transfer(&payer.pubkey(), &user.pubkey(), 1_000_000_000);
+        self.process(transfer, &[&payer]).await.unwrap();
+        user
+    }
+
+    pub async fn deposit(
+        &mut self,
+        user: &Keypair,
+        amount: u64,
+        max_x: u64,
+        max_y: u64,
+    ) -> TxResult {
+        let data = amm::instruction::Deposit {
+            amount,
+            max_x,
+            max_y,
+            min_lp_out: 0,
+        };
+        self.process(instruction(self.deposit_accounts(user), data), &[user])
+            .await
+    }
+
+    pub async fn donate(&mut self, user: &Keypair, amount_x: u64, amount_y: u64) -> TxResult {
+        let data = amm::instruction::Donate { amount_x, amount_y };
+        self.process(instruction(self.deposit_accounts(user), data), &[user])
+            .await
+    }
+
+    /// deposit_amounts by `user` of at most `amount_x` and `amount_y`.
+    pub async fn deposit_amounts(
+        &mut self,
+        user: &Keypair,
+        amount_x: u64,
+        amount_y: u64,
+    ) -> TxResult {
+        let data = amm::instruction::DepositAmounts {
+            amount_x,
+            amount_y,
+            min_lp_out: 0,
+        };
+        self.process(instruction(self.deposit_accounts(user), data), &[user])
+            .await
+    }
+
+    /// deposit_single by `user` of `amount_in` of mint_x or mint_y.
+    pub async fn deposit_single(&mut self, user: &Keypair, is_x: bool, amount_in: u64) -> TxResult {
+        let data = amm::instruction::DepositSingle {
+            is_x,
+            amount_in,
+            min_lp_out: 0,
+        };
+        self.process(instruction(self.deposit_accounts(user), data), &[user])
+            .await
+    }
+
+    /// The Deposit accounts for `user` paying from and minting to their own accounts.
+    fn deposit_accounts(&self, user: &Keypair) -> amm::accounts::Deposit {
+        amm::accounts::Deposit {
+            user: user.pubkey(),
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            mint_lp: self.mint_lp,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            vault_x: self.vault(self.mint_x),
+            vault_y: self.vault(self.mint_y),
+            user_x: get_associated_token_address(&user.pubkey(), &self.mint_x),
+            user_y: get_associated_token_address(&user.pubkey(), &self.mint_y),
+            user_lp: get_associated_token_address(&user.pubkey(), &self.mint_lp),
+            locked_lp: self.vault(self.mint_lp),
+            fee_vault_x: pda(&[b"fee_vault_x", self.config.as_ref()]),
+            fee_vault_y: pda(&[b"fee_vault_y", self.config.as_ref()]),
+            protocol: pda(&[b"protocol"]),
+            token_program: spl_token::ID,
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+            event_authority: pda(&[b"__event_authority"]),
+            program: amm::ID,
+        }
+    }
+
+    pub async fn swap(
+        &mut self,
+        user: &Keypair,
+        x_to_y: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> TxResult {
+        let swap = self.swap_instruction(user, x_to_y, amount_in, min_amount_out);
+        self.process(swap, &[user]).await
+    }
+
+    /// A plain swap by `user`, without referral, recipient, source or limits.
+    pub fn swap_instruction(
+        &self,
+        user: &Keypair,
+        x_to_y: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Instruction {
+        let data = amm::instruction::Swap {
+            x_to_y,
+            amount_in,
+            min_amount_out,
+            referral_fee_bps: 0,
+            deadline: 0,
+            max_price_impact_bps: 0,
+        };
+        instruction(self.swap_accounts(user), data)
+    }
+
+    pub async fn swap_exact_out(
+        &mut self,
+        user: &Keypair,
+        x_to_y: bool,
+        amount_out: u64,
+        max_amount_in: u64,
+    ) -> TxResult {
+        let data = amm::instruction::SwapExactOut {
+            x_to_y,
+            amount_out,
+            max_amount_in,
+            deadline: 0,
+        };
+        let swap = instruction(self.swap_accounts(user), data);
+        self.process(swap, &[user]).await
+    }
+
+    /// swap_to_limit by `user` at `limit` of the output per input, without a minimum.
+    pub async fn swap_to_limit(
+        &mut self,
+        user: &Keypair,
+        x_to_y: bool,
+        amount_in: u64,
+        limit: (u64, u64),
+    ) -> TxResult {
+        let data = amm::instruction::SwapToLimit {
+            x_to_y,
+            amount_in,
+            min_amount_out: 0,
+            limit_price_num: limit.0,
+            limit_price_denom: limit.1,
+            deadline: 0,
+        };
+        let swap = instruction(self.swap_accounts(user), data);
+        self.process(swap, &[user]).await
+    }
+
+    /// swap_partial by `user` within `max_price_impact_bps`.
+    pub async fn swap_partial(
+        &mut self,
+        user: &Keypair,
+        x_to_y: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        max_price_impact_bps: u16,
+    ) -> TxResult {
+        let data = amm::instruction::SwapPartial {
+            x_to_y,
+            amount_in,
+            min_amount_out,
+            max_price_impact_bps,
+            deadline: 0,
+        };
+        let swap = instruction(self.swap_accounts(user), data);
+        self.process(swap, &[user]).await
+    }
+
+    /// swap_batch by `user` of `legs`, each an `(x_to_y, amount_in)` without a
+    /// minimum.
+    pub async fn swap_batch(&mut self, user: &Keypair, legs: &[(bool, u64)]) -> TxResult {
+        let legs = legs
+            .iter()
+            .map(|&(x_to_y, amount_in)| SwapLeg {
+                x_to_y,
+                amount_in,
+                min_amount_out: 0,
+            })
+            .collect();
+        let data = amm::instruction::SwapBatch { legs, deadline: 0 };
+        let swap = instruction(self.swap_accounts(user), data);
+        self.process(swap, &[user]).await
+    }
+
+    /// swap_route by `user` of `amount_in` of mint_x for mint_y in this pool, and that
+    /// for the other mint of `next`.
+    pub async fn swap_route(
+        &mut self,
+        user: &Keypair,
+        next: &PoolKeys,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> TxResult {
+        let (pool, user_key) = (self.keys(), user.pubkey());
+        let (mint_in, mint_mid) = (pool.mint_x, pool.mint_y);
+        let mint_out = next.other_mint(mint_mid);
+        let accounts = amm::accounts::SwapRoute {
+            user: user_key,
+            mint_in,
+            mint_mid,
+            mint_out,
+            config_a: pool.config,
+            vault_authority_a: pool.vault_authority,
+            mint_lp_a: pool.mint_lp,
+            vault_a_in: pool.vault(mint_in),
+            vault_a_mid: pool.vault(mint_mid),
+            // the fee is charged in what goes into either pool
+            fee_vault_a: pool.fee_vault(mint_in),
+            config_b: next.config,
+            vault_authority_b: next.vault_authority,
+            mint_lp_b: next.mint_lp,
+            vault_b_mid: next.vault(mint_mid),
+            vault_b_out: next.vault(mint_out),
+            fee_vault_b: next.fee_vault(mint_mid),
+            protocol: pda(&[b"protocol"]),
+            user_in: get_associated_token_address(&user_key, &mint_in),
+            user_mid: get_associated_token_address(&user_key, &mint_mid),
+            user_out: get_associated_token_address(&user_key, &mint_out),
+            token_program_in: spl_token::ID,
+            token_program_mid: spl_token::ID,
+            token_program_out: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        };
+        let data = amm::instruction::SwapRoute {
+            amount_in,
+            min_amount_out,
+        };
+        self.process(instruction(accounts, data), &[user]).await
+    }
+
+    /// The Swap accounts for `user` trading from and to their own token accounts.
+    /// swap_sol_in by `user`, wrapping `amount_in` lamports of the side `x_to_y`
+    /// sells without a minimum.
+    pub async fn swap_sol_in(&mut self, user: &Keypair, x_to_y: bool, amount_in: u64) -> TxResult {
+        let data = amm::instruction::SwapSolIn {
+            x_to_y,
+            amount_in,
+            min_amount_out: 0,
+        };
+        self.process(instruction(self.swap_accounts(user), data), &[user])
+            .await
+    }
+
+    /// swap_sol_out by `user` of `amount_in`, unwrapping the SOL it buys, without a
+    /// minimum.
+    pub async fn swap_sol_out(&mut self, user: &Keypair, x_to_y: bool, amount_in: u64) -> TxResult {
+        let data = amm::instruction::SwapSolOut {
+            x_to_y,
+            amount_in,
+            min_amount_out: 0,
+        };
+        self.process(instruction(self.swap_accounts(user), data), &[user])
+            .await
+    }
+
+    /// update_observation paid for by the payer.
+    pub async fn update_observation(&mut self) -> TxResult {
+        let payer = self.payer();
+        let accounts = amm::accounts::UpdateObservation {
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            observation: pda(&[b"observation", self.config.as_ref()]),
+            vault_x: self.vault(self.mint_x),
+            vault_y: self.vault(self.mint_y),
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+        };
+        self.process(
+            instruction(accounts, amm::instruction::UpdateObservation {}),
+            &[&payer],
+        )
+        .await
+    }
+
+    fn swap_accounts(&self, user: &Keypair) -> amm::accounts::Swap {
+        amm::accounts::Swap {
+            user: user.pubkey(),
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            mint_lp: self.mint_lp,
+            config: self.config,
+            protocol: pda(&[b"protocol"]),
+            vault_authority: self.vault_authority,
+            vault_x: self.vault(self.mint_x),
+            vault_y: self.vault(self.mint_y),
+            fee_vault_x: pda(&[b"fee_vault_x", self.config.as_ref()]),
+            fee_vault_y: pda(&[b"fee_vault_y", self.config.as_ref()]),
+            user_x: get_associated_token_address(&user.pubkey(), &self.mint_x),
+            user_y: get_associated_token_address(&user.pubkey(), &self.mint_y),
+            referral: None,
+            recipient: None,
+            source: None,
+            allowlist: None,
+            fee_exemptions: None,
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+            event_authority: pda(&[b"__event_authority"]),
+            program: amm::ID,
+        }
+    }
+
+    pub async fn withdraw(&mut self, user: &Keypair, amount: u64) -> TxResult {
+        let data = amm::instruction::Withdraw {
+            amount,
+            min_x: 0,
+            min_y: 0,
+        };
+        self.process(instruction(self.withdraw_accounts(user), data), &[user])
+            .await
+    }
+
+    /// withdraw_all of `user`'s LP, which closes their LP account.
+    pub async fn withdraw_all(&mut self, user: &Keypair) -> TxResult {
+        let data = amm::instruction::WithdrawAll { min_x: 0, min_y: 0 };
+        self.process(instruction(self.withdraw_accounts(user), data), &[user])
+            .await
+    }
+
+    /// The Withdraw accounts for `user` burning from and paying to their own accounts.
+    fn withdraw_accounts(&self, user: &Keypair) -> amm::accounts::Withdraw {
+        amm::accounts::Withdraw {
+            user: user.pubkey(),
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            mint_lp: self.mint_lp,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            vault_x: self.vault(self.mint_x),
+            vault_y: self.vault(self.mint_y),
+            user_x: get_associated_token_address(&user.pubkey(), &self.mint_x),
+            user_y: get_associated_token_address(&user.pubkey(), &self.mint_y),
+            user_lp: get_associated_token_address(&user.pubkey(), &self.mint_lp),
+            token_program: spl_token::ID,
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+            event_authority: pda(&[b"__event_authority"]),
+            program: amm::ID,
+        }
+    }
+
+    /// migrate_config paid for by the payer.
+    pub async fn migrate(&mut self) -> TxResult {
+        let payer = self.payer();
+        let accounts = amm::accounts::MigrateConfig {
+            payer: payer.pubkey(),
+            config: self.config,
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            system_program: system_program::ID,
+        };
+        self.process(
+            instruction(accounts, amm::instruction::MigrateConfig {}),
+            &[&payer],
+        )
+        .await
+    }
+
+    /// lock_pool signed by the payer, the pool's authority.
+    pub async fn lock(&mut self) -> TxResult {
+        self.update(amm::instruction::LockPool {}).await
+    }
+
+    pub async fn unlock(&mut self) -> TxResult {
+        self.update(amm::instruction::UnlockPool {}).await
+    }
+
+    /// set_lock until `seconds` after the bank's clock.
+    pub async fn lock_for(&mut self, seconds: i64) -> TxResult {
+        let clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        let data = amm::instruction::SetLock {
+            until: clock.unix_timestamp + seconds,
+        };
+        self.update(data).await
+    }
+
+    /// ramp_amp to `target_amp`, ending `seconds` after the bank's clock.
+    pub async fn ramp_amp(&mut self, target_amp: u64, seconds: i64) -> TxResult {
+        let clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        let data = amm::instruction::RampAmp {
+            target_amp,
+            ramp_end_ts: clock.unix_timestamp + seconds,
+        };
+        self.update(data).await
+    }
+
+    pub async fn stop_ramp(&mut self) -> TxResult {
+        self.update(amm::instruction::StopRamp {}).await
+    }
+
+    pub async fn reset_breaker(&mut self) -> TxResult {
+        self.update(amm::instruction::ResetBreaker {}).await
+    }
+
+    pub async fn set_open_time(&mut self, open_time: i64) -> TxResult {
+        self.update(amm::instruction::SetOpenTime { open_time })
+            .await
+    }
+
+    pub async fn set_pause_flags(&mut self, flags: u8) -> TxResult {
+        self.update(amm::instruction::SetPauseFlags { flags }).await
+    }
+
+    pub async fn set_fee_recipient(&mut self, fee_recipient: Pubkey) -> TxResult {
+        self.update(amm::instruction::SetFeeRecipient { fee_recipient })
+            .await
+    }
+
+    /// accept_authority signed by `pending_authority`, once an Authority change named
+    /// it.
+    pub async fn accept_authority(&mut self, pending_authority: &Keypair) -> TxResult {
+        self.update_by(pending_authority, amm::instruction::AcceptAuthority {})
+            .await
+    }
+
+    /// renounce_authority signed by `authority`.
+    pub async fn renounce_authority(&mut self, authority: &Keypair) -> TxResult {
+        self.update_by(authority, amm::instruction::RenounceAuthority {})
+            .await
+    }
+
+    /// An Update instruction signed by the payer, the pool's authority.
+    async fn update(&mut self, data: impl InstructionData) -> TxResult {
+        let payer = self.payer();
+        self.update_by(&payer, data).await
+    }
+
+    async fn update_by(&mut self, user: &Keypair, data: impl InstructionData) -> TxResult {
+        let accounts = amm::accounts::Update {
+            user: user.pubkey(),
+            config: self.config,
+        };
+        self.process(instruction(accounts, data), &[user]).await
+    }
+
+    /// set_protocol_fee by the protocol authority.
+    pub async fn set_protocol_fee(
+        &mut self,
+        protocol_fee_enabled: bool,
+        default_protocol_fee_bps: u16,
+    ) -> TxResult {
+        let authority = self.protocol_authority.insecure_clone();
+        let accounts = amm::accounts::UpdateProtocol {
+            authority: authority.pubkey(),
+            protocol: pda(&[b"protocol"]),
+        };
+        let data = amm::instruction::SetProtocolFee {
+            protocol_fee_enabled,
+            default_protocol_fee_bps,
+        };
+        self.process(instruction(accounts, data), &[&authority])
+            .await
+    }
+
+    pub async fn propose_protocol_authority(&mut self, authority: Pubkey) -> TxResult {
+        let signer = self.protocol_authority.insecure_clone();
+        let accounts = amm::accounts::UpdateProtocol {
+            authority: signer.pubkey(),
+            protocol: pda(&[b"protocol"]),
+        };
+        let data = amm::instruction::ProposeProtocolAuthority { authority };
+        self.process(instruction(accounts, data), &[&signer]).await
+    }
+
+    /// accept_protocol_authority signed by `pending_authority` with the payer paying,
+    /// which the harness then signs protocol updates with.
+    pub async fn accept_protocol_authority(&mut self, pending_authority: &Keypair) -> TxResult {
+        let payer = self.payer();
+        let accounts = amm::accounts::AcceptProtocolAuthority {
+            pending_authority: pending_authority.pubkey(),
+            protocol: pda(&[b"protocol"]),
+        };
+        let data = amm::instruction::AcceptProtocolAuthority {};
+        self.process(instruction(accounts, data), &[&payer, pending_authority])
+            .await?;
+        self.protocol_authority = pending_authority.insecure_clone();
+        Ok(())
+    }
+
+    /// begin_flash_swap by `user`, which an end_flash_swap has to close later in the
+    /// same transaction.
+    pub fn flash_begin_instruction(
+        &self,
+        user: &Keypair,
+        amount_x: u64,
+        amount_y: u64,
+    ) -> Instruction {
+        let accounts = amm::accounts::BeginFlashSwap {
+            user: user.pubkey(),
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            flash: pda(&[b"flash", self.config.as_ref()]),
+            vault_x: self.vault(self.mint_x),
+            vault_y: self.vault(self.mint_y),
+            user_x: get_associated_token_address(&user.pubkey(), &self.mint_x),
+            user_y: get_associated_token_address(&user.pubkey(), &self.mint_y),
+            instructions: sysvar::instructions::ID,
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        };
+        instruction(
+            accounts,
+            amm::instruction::BeginFlashSwap { amount_x, amount_y },
+        )
+    }
+
+    pub fn flash_end_instruction(&self, user: &Keypair) -> Instruction {
+        let accounts = amm::accounts::EndFlashSwap {
+            user: user.pubkey(),
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            flash: pda(&[b"flash", self.config.as_ref()]),
+            vault_x: self.vault(self.mint_x),
+            vault_y: self.vault(self.mint_y),
+            fee_vault_x: pda(&[b"fee_vault_x", self.config.as_ref()]),
+            fee_vault_y: pda(&[b"fee_vault_y", self.config.as_ref()]),
+            protocol: pda(&[b"protocol"]),
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+        };
+        instruction(accounts, amm::instruction::EndFlashSwap {})
+    }
+
+    /// A plain token transfer of `amount` of `mint` from `user` into its vault, as a
+    /// flash swap repayment or a donation outside the program.
+    pub fn transfer_to_vault_instruction(
+        &self,
+        user: &Keypair,
+        mint: Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        token::transfer(
+            &spl_token::ID,
+            &get_associated_token_address(&user.pubkey(), &mint),
+            &self.vault(mint),
+            &user.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap()
+    }
+
+    pub fn sync_instruction(&self) -> Instruction {
+        let accounts = amm::accounts::SyncReserves {
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            vault_x: self.vault(self.mint_x),
+            vault_y: self.vault(self.mint_y),
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+        };
+        instruction(accounts, amm::instruction::Sync {})
+    }
+
+    /// skim paid for by `user`, sweeping to the payer as the fee recipient.
+    pub fn skim_instruction(&self, user: &Keypair) -> Instruction {
+        let fee_recipient = self.payer().pubkey();
+        let accounts = amm::accounts::Skim {
+            user: user.pubkey(),
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            fee_recipient,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            vault_x: self.vault(self.mint_x),
+            vault_y: self.vault(self.mint_y),
+            recipient_x: get_associated_token_address(&fee_recipient, &self.mint_x),
+            recipient_y: get_associated_token_address(&fee_recipient, &self.mint_y),
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        };
+        instruction(accounts, amm::instruction::Skim {})
+    }
+
+    /// create_lp_metadata with `uri` by `payer`, which has to be the pool's authority
+    /// for it to succeed. Needs the token metadata program loaded.
+    pub async fn create_lp_metadata(&mut self, payer: &Keypair, uri: &str) -> TxResult {
+        let accounts = amm::accounts::CreateLpMetadata {
+            payer: payer.pubkey(),
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            mint_lp: self.mint_lp,
+            config: self.config,
+            metadata: metadata_address(&self.mint_lp),
+            metadata_program: METADATA_ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        };
+        let data = amm::instruction::CreateLpMetadata {
+            uri: uri.to_string(),
+        };
+        self.process(instruction(accounts, data), &[payer]).await
+    }
+
+    /// collect_protocol_fees paid for by `user`, to the payer as the fee recipient.
+    pub async fn collect_protocol_fees(&mut self, user: &Keypair) -> TxResult {
+        let fee_recipient = self.payer().pubkey();
+        let accounts = amm::accounts::CollectProtocolFees {
+            user: user.pubkey(),
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            fee_recipient,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            fee_vault_x: pda(&[b"fee_vault_x", self.config.as_ref()]),
+            fee_vault_y: pda(&[b"fee_vault_y", self.config.as_ref()]),
+            recipient_x: get_associated_token_address(&fee_recipient, &self.mint_x),
+            recipient_y: get_associated_token_address(&fee_recipient, &self.mint_y),
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        };
+        self.process(
+            instruction(accounts, amm::instruction::CollectProtocolFees {}),
+            &[user],
+        )
+        .await
+    }
+
+    /// harvest_withheld_fees paid for by the payer, a no-op for the classic SPL mints
+    /// the harness creates.
+    pub async fn harvest_withheld_fees(&mut self) -> TxResult {
+        let payer = self.payer();
+        let accounts = amm::accounts::HarvestWithheldFees {
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            vault_x: self.vault(self.mint_x),
+            vault_y: self.vault(self.mint_y),
+            fee_vault_x: pda(&[b"fee_vault_x", self.config.as_ref()]),
+            fee_vault_y: pda(&[b"fee_vault_y", self.config.as_ref()]),
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+        };
+        self.process(
+            instruction(accounts, amm::instruction::HarvestWithheldFees {}),
+            &[&payer],
+        )
+        .await
+    }
+
+    /// close_pool by the payer, the pool's authority, who also gets what is left.
+    pub async fn close_pool(&mut self) -> TxResult {
+        let payer = self.payer();
+        let accounts = amm::accounts::ClosePool {
+            user: payer.pubkey(),
+            recipient: payer.pubkey(),
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            mint_lp: self.mint_lp,
+            config: self.config,
+            vault_authority: self.vault_authority,
+            observation: pda(&[b"observation", self.config.as_ref()]),
+            registry: derive_registry_address(&self.mint_x, &self.mint_y, self.fee).0,
+            vault_x: self.vault(self.mint_x),
+            vault_y: self.vault(self.mint_y),
+            locked_lp: self.vault(self.mint_lp),
+            fee_vault_x: pda(&[b"fee_vault_x", self.config.as_ref()]),
+            fee_vault_y: pda(&[b"fee_vault_y", self.config.as_ref()]),
+            recipient_x: get_associated_token_address(&payer.pubkey(), &self.mint_x),
+            recipient_y: get_associated_token_address(&payer.pubkey(), &self.mint_y),
+            token_program: spl_token::ID,
+            token_program_x: spl_token::ID,
+            token_program_y: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        };
+        self.process(
+            instruction(accounts, amm::instruction::ClosePool {}),
+            &[&payer],
+        )
+        .await
+    }
+
+    /// schedule_update of `change` by the payer, the pool's authority.
+    pub async fn schedule_update(&mut self, change: ParameterChange) -> TxResult {
+        self.update(amm::instruction::ScheduleUpdate { change })
+            .await
+    }
+
+    pub async fn execute_update(&mut self) -> TxResult {
+        self.update(amm::instruction::ExecuteUpdate {}).await
+    }
+
+    /// create_allowlist by the payer, the pool's authority, after which only its
+    /// members can swap.
+    pub async fn create_allowlist(&mut self) -> TxResult {
+        let payer = self.payer();
+        let accounts = amm::accounts::CreateAllowlist {
+            user: payer.pubkey(),
+            config: self.config,
+            allowlist: pda(&[b"allowlist", self.config.as_ref()]),
+            system_program: system_program::ID,
+        };
+        self.process(
+            instruction(accounts, amm::instruction::CreateAllowlist {}),
+            &[&payer],
+        )
+        .await
+    }
+
+    pub async fn add_to_allowlist(&mut self, member: Pubkey) -> TxResult {
+        let payer = self.payer();
+        let accounts = amm::accounts::AddToAllowlist {
+            user: payer.pubkey(),
+            config: self.config,
+            allowlist: pda(&[b"allowlist", self.config.as_ref()]),
+            system_program: system_program::ID,
+        };
+        let data = amm::instruction::AddToAllowlist { member };
+        self.process(instruction(accounts, data), &[&payer]).await
+    }
+
+    pub async fn remove_from_allowlist(&mut self, member: Pubkey) -> TxResult {
+        let payer = self.payer();
+        let accounts = amm::accounts::RemoveFromAllowlist {
+            user: payer.pubkey(),
+            config: self.config,
+            allowlist: pda(&[b"allowlist", self.config.as_ref()]),
+            system_program: system_program::ID,
+        };
+        let data = amm::instruction::RemoveFromAllowlist { member };
+        self.process(instruction(accounts, data), &[&payer]).await
+    }
+
+    /// create_fee_exemptions by the payer, the pool's authority.
+    pub async fn create_fee_exemptions(&mut self) -> TxResult {
+        let payer = self.payer();
+        let accounts = amm::accounts::CreateFeeExemptions {
+            user: payer.pubkey(),
+            config: self.config,
+            fee_exemptions: pda(&[b"fee_exemptions", self.config.as_ref()]),
+            system_program: system_program::ID,
+        };
+        self.process(
+            instruction(accounts, amm::instruction::CreateFeeExemptions {}),
+            &[&payer],
+        )
+        .await
+    }
+
+    pub async fn add_fee_exemption(&mut self, member: Pubkey) -> TxResult {
+        let payer = self.payer();
+        let accounts = amm::accounts::AddFeeExemption {
+            user: payer.pubkey(),
+            config: self.config,
+            fee_exemptions: pda(&[b"fee_exemptions", self.config.as_ref()]),
+            system_program: system_program::ID,
+        };
+        let data = amm::instruction::AddFeeExemption { member };
+        self.process(instruction(accounts, data), &[&payer]).await
+    }
+
+    pub async fn remove_fee_exemption(&mut self, member: Pubkey) -> TxResult {
+        let payer = self.payer();
+        let accounts = amm::accounts::RemoveFeeExemption {
+            user: payer.pubkey(),
+            config: self.config,
+            fee_exemptions: pda(&[b"fee_exemptions", self.config.as_ref()]),
+            system_program: system_program::ID,
+        };
+        let data = amm::instruction::RemoveFeeExemption { member };
+        self.process(instruction(accounts, data), &[&payer]).await
+    }
+
+    /// The addresses of this pool.
+    pub fn keys(&self) -> PoolKeys {
+        PoolKeys {
+            mint_x: self.mint_x,
+            mint_y: self.mint_y,
+            config: self.config,
+            mint_lp: self.mint_lp,
+            vault_authority: self.vault_authority,
+            vault_x: self.vault_x,
+            vault_y: self.vault_y,
+        }
+    }
+
+    /// Points the harness at the pool of `keys`, which has the same fee.
+    fn use_keys(&mut self, keys: PoolKeys) {
+        self.mint_x = keys.mint_x;
+        self.mint_y = keys.mint_y;
+        self.config = keys.config;
+        self.mint_lp = keys.mint_lp;
+        self.vault_authority = keys.vault_authority;
+        self.vault_x = keys.vault_x;
+        self.vault_y = keys.vault_y;
+    }
+
+    /// A second constant product pool of the same fee in this bank, of mint_y and a
+    /// fresh mint, into which the payer deposits `liquidity` of either side. The
+    /// harness stays on its own pool.
+    pub async fn add_pool(&mut self, liquidity: u64) -> PoolKeys {
+        let own = self.keys();
+        let mint = Keypair::new();
+        self.create_mint(&mint, DECIMALS).await;
+        let mut mints = [self.mint_y, mint.pubkey()];
+        mints.sort();
+        self.use_keys(PoolKeys::new(mints[0], mints[1], self.fee));
+
+        let payer = self.payer();
+        self.fund(&payer, FUNDS, FUNDS).await;
+        let params = InitializeParams {
+            fee: self.fee,
+            authority: Some(payer.pubkey()),
+            fee_mode: FeeMode::Input,
+            allow_unsafe_extensions: false,
+            open_time: 0,
+            curve: CurveType::ConstantProduct,
+            protocol_fee_bps: 0,
+            min_trade_size: 0,
+        };
+        let initialize = instruction(
+            self.initialize_accounts(),
+            amm::instruction::Initialize { params },
+        );
+        self.process(initialize, &[&payer]).await.unwrap();
+        self.deposit(&payer, liquidity, liquidity, liquidity)
+            .await
+            .unwrap();
+
+        let added = self.keys();
+        self.use_keys(own);
+        added
+    }
+
+    /// Rewrites the config as it was laid out before `version` and the fields after
+    /// it, in an account sized and funded for that layout. A `renounced` pool is
+    /// written as one that gave up its authority after the config was, so the shorter
+    /// layout leaves the end of the longer one behind it.
+    pub async fn downgrade(&mut self, renounced: bool) {
+        let config = self.config().await;
+        let mut data = self.old_layout(&config);
+        if renounced {
+            let config = Config {
+                authority: None,
+                ..config
+            };
+            let shorter = self.old_layout(&config);
+            data[..shorter.len()].copy_from_slice(&shorter);
+        }
+        let old_len = 8 + Config::INIT_SPACE - 1 - (1 + 32) - 2 * 32 - (8 + 8 + 16 + 2 + 8 + 1) - 2;
+        data.resize(old_len, 0);
+
+        let account = Account {
+            lamports: Rent::default().minimum_balance(old_len),
+            data,
+            owner: amm::ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        self.context.set_account(&self.config, &account.into());
+    }
+
+    /// `config` as it was laid out before `version` and the fields after it.
+    fn old_layout(&self, config: &Config) -> Vec<u8> {
+        let mut data = Vec::new();
+        config.try_serialize(&mut data).unwrap();
+        // version, fee_exemptions as None, the vaults, then the minimum trade size, the
+        // last swap, no timed lock, no amp ramp and the mint decimals
+        assert_eq!(data.split_off(data.len() - 2), [DECIMALS, DECIMALS]);
+        let mut last_swap = Vec::new();
+        (
+            config.min_trade_size,
+            config.last_swap_slot,
+            config.last_swap_price_q64,
+            config.trades_in_last_slot,
+            config.locked_until,
+            config.amp_ramp,
+        )
+            .serialize(&mut last_swap)
+            .unwrap();
+        assert_eq!(
+            data.split_off(data.len() - (8 + 8 + 16 + 2 + 8 + 1)),
+            last_swap
+        );
+        let vaults = data.split_off(data.len() - 2 * 32);
+        assert_eq!(
+            vaults,
+            [self.vault_x.to_bytes(), self.vault_y.to_bytes()].concat()
+        );
+        assert_eq!(data.split_off(data.len() - 2), [CONFIG_VERSION, 0]);
+        data
+    }
+
+    /// The vault of mint_x or mint_y, or the vault authority's ATA of any other mint.
+    pub fn vault(&self, mint: Pubkey) -> Pubkey {
+        match mint {
+            mint if mint == self.mint_x => self.vault_x,
+            mint if mint == self.mint_y => self.vault_y,
+            mint => get_associated_token_address(&self.vault_authority, &mint),
+        }
+    }
+
+    pub async fn config(&mut self) -> Config {
+        let account = self
+            .context
+            .banks_client
+            .get_account(self.config)
+            .await
+            .unwrap()
+            .unwrap();
+        Config::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    /// Token balance of `owner`'s ATA of `mint`, zero if it does not exist.
+    pub async fn balance(&mut self, owner: &Pubkey, mint: &Pubkey) -> u64 {
+        self.token_balance(get_associated_token_address(owner, mint))
+            .await
+    }
+
+    /// Protocol fees held for collection on the x or y side.
+    pub async fn fee_vault_balance(&mut self, is_x: bool) -> u64 {
+        let seed: &[u8] = match is_x {
+            true => b"fee_vault_x",
+            false => b"fee_vault_y",
+        };
+        self.token_balance(pda(&[seed, self.config.as_ref()])).await
+    }
+
+    /// Overwrites the balance of the token account at `address`, for states no
+    /// instruction can reach, such as a vault short of the reserves.
+    pub async fn set_token_balance(&mut self, address: Pubkey, amount: u64) {
+        let mut account = self
+            .context
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut token = spl_token::state::Account::unpack(&account.data).unwrap();
+        token.amount = amount;
+        token.pack_into_slice(&mut account.data);
+        self.context.set_account(&address, &account.into());
+    }
+
+    /// Token balance of the account at `address`, zero if it does not exist.
+    pub async fn token_balance(&mut self, address: Pubkey) -> u64 {
+        match self
+            .context
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+        {
+            Some(account) => {
+                spl_token::state::Account::unpack(&account.data)
+                    .unwrap()
+                    .amount
+            }
+            None => 0,
+        }
+    }
+
+    pub async fn assert_balance(&mut self, owner: &Pubkey, mint: &Pubkey, expected: u64) {
+        assert_eq!(self.balance(owner, mint).await, expected);
+    }
+
+    /// Moves the bank's clock `seconds` forward, and a slot so the next transaction
+    /// runs against it.
+    pub async fn warp(&mut self, seconds: i64) {
+        let mut clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp += seconds;
+        self.context.set_sysvar(&clock);
+        self.context.warp_to_slot(clock.slot + 1).unwrap();
+    }
+}
+
+/// An instruction of the amm from its typed accounts and arguments.
+pub fn instruction(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: amm::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+pub fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &amm::ID).0
+}
+
+/// The token metadata account of `mint`.
+pub fn metadata_address(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"metadata", METADATA_ID.as_ref(), mint.as_ref()],
+        &METADATA_ID,
+    )
+    .0
+}
+
+/// Where the upgradeable loader keeps the ELF and upgrade authority of `program_id`.
+pub fn program_data_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::ID).0
+}
+
+/// Adds the built `name.so` at `program_id` the way `solana program deploy` leaves
+/// it, a program account pointing at program data owned by `upgrade_authority`.
+/// ProgramTest::add_program deploys with the non-upgradeable loader instead.
+fn add_upgradeable_program(
+    program_test: &mut ProgramTest,
+    name: &str,
+    program_id: Pubkey,
+    upgrade_authority: Pubkey,
+) {
+    let elf = read_file(
+        find_file(&format!("{name}.so")).unwrap_or_else(|| panic!("{name}.so is not built")),
+    );
+    let programdata_address = program_data_address(&program_id);
+    let metadata_len = UpgradeableLoaderState::size_of_programdata_metadata();
+    let mut programdata = Account::new_data_with_space(
+        Rent::default().minimum_balance(metadata_len + elf.len()),
+        &UpgradeableLoaderState::ProgramData {
+            slot: 0,
+            upgrade_authority_address: Some(upgrade_authority),
+        },
+        metadata_len + elf.len(),
+        &bpf_loader_upgradeable::ID,
+    )
+    .unwrap();
+    programdata.data[metadata_len..].copy_from_slice(&elf);
+    program_test.add_account(programdata_address, programdata);
+
+    let mut program = Account::new_data(
+        Rent::default().minimum_balance(UpgradeableLoaderState::size_of_program()),
+        &UpgradeableLoaderState::Program {
+            programdata_address,
+        },
+        &bpf_loader_upgradeable::ID,
+    )
+    .unwrap();
+    program.executable = true;
+    program_test.add_account(program_id, program);
+}
+
+/// Fails unless `result` is the transaction failing with `error`.
+/// Fails unless `result` is the program error `error`, an AmmError or one of
+/// Anchor's ErrorCodes.
+pub fn expect_err<E: Into<u32> + Clone + Debug>(result: TxResult, error: E) {
+    match result {
+        Err(BanksClientError::TransactionError(TransactionError::InstructionError(
+            _,
+            InstructionError::Custom(code),
+        ))) => assert_eq!(code, error.clone().into(), "expected {error:?}"),
+        other => panic!("expected {error:?}, got {other:?}"),
+    }
+}
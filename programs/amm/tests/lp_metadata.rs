@@ -0,0 +1,54 @@
+//! Needs the token metadata program at `tests/fixtures/mpl_token_metadata.so`,
+//! dumped from mainnet with
+//! `solana program dump -u m metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s tests/fixtures/mpl_token_metadata.so`.
+
+mod common;
+
+use amm::error::AmmError;
+use anchor_spl::metadata::{mpl_token_metadata::accounts::Metadata, ID as METADATA_ID};
+use common::{expect_err, metadata_address, TestPool};
+
+#[tokio::test]
+async fn names_the_lp_mint_once() {
+    let mut pool = TestPool::builder()
+        .program("mpl_token_metadata", METADATA_ID)
+        .build()
+        .await;
+    let payer = pool.payer();
+
+    // only the pool's authority picks the uri
+    let stranger = pool.new_user().await;
+    let result = pool
+        .create_lp_metadata(&stranger, "https://example.com/lp.json")
+        .await;
+    expect_err(result, AmmError::Unauthorized);
+
+    pool.create_lp_metadata(&payer, "https://example.com/lp.json")
+        .await
+        .unwrap();
+    let account = pool
+        .context
+        .banks_client
+        .get_account(metadata_address(&pool.mint_lp))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.owner, METADATA_ID);
+    let metadata = Metadata::safe_deserialize(&account.data).unwrap();
+    let (x, y) = (pool.mint_x.to_string(), pool.mint_y.to_string());
+    let name = format!("TRUBIN-LP {}/{}", &x[..4], &y[..4]);
+    assert_eq!(metadata.mint, pool.mint_lp);
+    assert_eq!(metadata.name.trim_end_matches('\0'), name);
+    assert_eq!(metadata.symbol.trim_end_matches('\0'), "TRB-LP");
+    assert_eq!(
+        metadata.uri.trim_end_matches('\0'),
+        "https://example.com/lp.json"
+    );
+    assert_eq!(metadata.update_authority, pool.config);
+    assert!(!metadata.is_mutable);
+
+    let result = pool
+        .create_lp_metadata(&payer, "https://example.com/other.json")
+        .await;
+    expect_err(result, AmmError::LpMetadataExists);
+}
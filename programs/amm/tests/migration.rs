@@ -0,0 +1,91 @@
+//! Pools created before Config was versioned, rewritten into the bank in their
+//! old layout.
+
+mod common;
+
+use amm::{constants::CONFIG_VERSION, error::AmmError, state::Config};
+use anchor_lang::{error::ErrorCode, prelude::Pubkey, Space};
+use common::{expect_err, TestPool, DECIMALS};
+use solana_sdk::rent::Rent;
+
+const LIQUIDITY: u64 = 100_000_000;
+
+#[tokio::test]
+async fn migrates_an_old_layout_and_swaps() {
+    let mut pool = TestPool::builder().build().await;
+    let payer = pool.payer();
+    pool.deposit(&payer, LIQUIDITY, LIQUIDITY, LIQUIDITY)
+        .await
+        .unwrap();
+    pool.downgrade(false).await;
+
+    // the old layout decodes with the version and vaults zeroed, and is refused until
+    // migrated, by the vault check before it gets to OutdatedConfig
+    assert_eq!(pool.config().await.version, 0);
+    let result = pool.swap(&payer, true, 1_000_000, 1).await;
+    expect_err(result, ErrorCode::ConstraintHasOne);
+
+    pool.migrate().await.unwrap();
+    let account = pool
+        .context
+        .banks_client
+        .get_account(pool.config)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), 8 + Config::INIT_SPACE);
+    assert!(account.lamports >= Rent::default().minimum_balance(account.data.len()));
+    let config = pool.config().await;
+    assert_eq!(config.version, CONFIG_VERSION);
+    assert_eq!((config.reserve_x, config.reserve_y), (LIQUIDITY, LIQUIDITY));
+    assert_eq!(
+        (config.vault_x, config.vault_y),
+        (pool.vault_x, pool.vault_y)
+    );
+    assert_eq!((config.decimals_x, config.decimals_y), (DECIMALS, DECIMALS));
+
+    pool.swap(&payer, true, 1_000_000, 1).await.unwrap();
+    expect_err(pool.migrate().await, AmmError::ConfigUpToDate);
+}
+
+#[tokio::test]
+async fn migrates_a_renounced_pool() {
+    let mut pool = TestPool::builder().build().await;
+    let payer = pool.payer();
+    pool.deposit(&payer, LIQUIDITY, LIQUIDITY, LIQUIDITY)
+        .await
+        .unwrap();
+    // leaves the slot and its volume at the end of the old layout
+    pool.swap(&payer, true, 1_000_000, 1).await.unwrap();
+    pool.downgrade(true).await;
+
+    // which then decodes as the first vault
+    let config = pool.config().await;
+    assert_eq!((config.authority, config.version), (None, 0));
+    assert_ne!(config.vault_x, Pubkey::default());
+    assert_ne!(config.vault_x, pool.vault_x);
+
+    pool.migrate().await.unwrap();
+    let config = pool.config().await;
+    assert_eq!(config.version, CONFIG_VERSION);
+    assert_eq!(config.authority, None);
+    assert_eq!(config.fee_exemptions, None);
+    assert_eq!(
+        (config.vault_x, config.vault_y),
+        (pool.vault_x, pool.vault_y)
+    );
+    assert_eq!(config.min_trade_size, 0);
+    assert_eq!(
+        (
+            config.last_swap_slot,
+            config.last_swap_price_q64,
+            config.trades_in_last_slot
+        ),
+        (0, 0, 0)
+    );
+    assert_eq!(config.locked_until, 0);
+    assert!(config.amp_ramp.is_none());
+    assert_eq!((config.decimals_x, config.decimals_y), (DECIMALS, DECIMALS));
+
+    pool.swap(&payer, false, 1_000_000, 1).await.unwrap();
+}
@@ -0,0 +1,960 @@
+//! Run with `cargo test-sbf`, which builds the program this loads first.
+
+mod common;
+
+use amm::{
+    constants::{
+        MAX_AMP, MAX_FEE_BPS, MAX_LOCK_DURATION, MAX_MIN_TRADE_SIZE, MINIMUM_LIQUIDITY,
+        MIN_WEIGHT_BPS, PAUSE_DEPOSITS, PAUSE_SWAPS, TIMELOCK_SECONDS,
+    },
+    error::AmmError,
+    math::{fee_amount, get_amount_out, stable},
+    state::ParameterChange,
+    CurveType, FeeMode, InitializeParams,
+};
+use anchor_lang::prelude::{Clock, Pubkey};
+use anchor_spl::associated_token::get_associated_token_address;
+use common::{expect_err, instruction, pda, program_data_address, TestPool, FUNDS};
+use solana_sdk::{signature::Signer, system_program};
+
+const LIQUIDITY: u64 = 100_000_000;
+/// SystemError::AccountAlreadyInUse, raised by `init` on an account that exists.
+const ACCOUNT_ALREADY_IN_USE: u32 = 0;
+
+async fn funded_pool() -> TestPool {
+    let mut pool = TestPool::builder().build().await;
+    let payer = pool.payer();
+    pool.deposit(&payer, LIQUIDITY, LIQUIDITY, LIQUIDITY)
+        .await
+        .unwrap();
+    pool
+}
+
+#[tokio::test]
+async fn initialize() {
+    let mut pool = TestPool::builder().fee(25).build().await;
+    let config = pool.config().await;
+    assert_eq!(config.mint_x, pool.mint_x);
+    assert_eq!(config.mint_y, pool.mint_y);
+    assert_eq!(config.mint_lp, pool.mint_lp);
+    assert_eq!(config.fee, 25);
+    assert_eq!(config.authority, Some(pool.payer().pubkey()));
+    assert_eq!(config.reserve_x, 0);
+    assert_eq!(config.reserve_y, 0);
+    let vault_x = get_associated_token_address(&pool.vault_authority, &pool.mint_x);
+    let vault_y = get_associated_token_address(&pool.vault_authority, &pool.mint_y);
+    assert_eq!((config.vault_x, config.vault_y), (vault_x, vault_y));
+}
+
+#[tokio::test]
+async fn custom_vaults() {
+    let mut pool = TestPool::builder().custom_vaults().build().await;
+    let config = pool.config().await;
+    assert_eq!(
+        (config.vault_x, config.vault_y),
+        (pool.vault_x, pool.vault_y)
+    );
+    assert_ne!(
+        pool.vault_x,
+        get_associated_token_address(&pool.vault_authority, &pool.mint_x)
+    );
+
+    let payer = pool.payer();
+    pool.deposit(&payer, LIQUIDITY, LIQUIDITY, LIQUIDITY)
+        .await
+        .unwrap();
+    let user = pool.new_user().await;
+    pool.swap(&user, true, 1_000_000, 1).await.unwrap();
+    let config = pool.config().await;
+    assert_eq!(config.reserve_x, LIQUIDITY + 1_000_000);
+}
+
+#[tokio::test]
+async fn initialize_with_delegated_vault() {
+    let (_, result) = TestPool::builder()
+        .vault_delegate(Pubkey::new_unique())
+        .try_build()
+        .await;
+    expect_err(result, AmmError::InvalidVault);
+}
+
+#[tokio::test]
+async fn initialize_immutable() {
+    let mut pool = TestPool::builder()
+        .authority(None)
+        .protocol_fee_bps(2_000)
+        .build()
+        .await;
+    let config = pool.config().await;
+    assert_eq!(config.authority, None);
+    assert_eq!(config.fee_recipient, pool.payer().pubkey());
+    assert_eq!(config.protocol_fee_bps, 2_000);
+}
+
+#[tokio::test]
+async fn initialize_with_invalid_params() {
+    let (_, result) = TestPool::builder().fee(MAX_FEE_BPS + 1).try_build().await;
+    expect_err(result, AmmError::InvalidFee);
+
+    let (_, result) = TestPool::builder()
+        .protocol_fee_bps(10_001)
+        .try_build()
+        .await;
+    expect_err(result, AmmError::InvalidFee);
+
+    let (_, result) = TestPool::builder()
+        .authority(Some(Pubkey::default()))
+        .try_build()
+        .await;
+    expect_err(result, AmmError::InvalidAuthority);
+
+    let (_, result) = TestPool::builder()
+        .curve(CurveType::Stable { amp: MAX_AMP + 1 })
+        .try_build()
+        .await;
+    expect_err(result, AmmError::InvalidAmplification);
+
+    let (_, result) = TestPool::builder()
+        .curve(CurveType::Weighted {
+            weight_x_bps: MIN_WEIGHT_BPS - 1,
+        })
+        .try_build()
+        .await;
+    expect_err(result, AmmError::InvalidWeight);
+
+    let (_, result) = TestPool::builder()
+        .min_trade_size(MAX_MIN_TRADE_SIZE + 1)
+        .try_build()
+        .await;
+    expect_err(result, AmmError::InvalidMinTradeSize);
+}
+
+#[tokio::test]
+async fn reinitialize() {
+    let mut pool = funded_pool().await;
+    let attacker = pool.new_user().await;
+    let mut accounts = pool.initialize_accounts();
+    accounts.initializer = attacker.pubkey();
+    let data = amm::instruction::Initialize {
+        params: InitializeParams {
+            fee: pool.fee,
+            authority: Some(attacker.pubkey()),
+            fee_mode: FeeMode::Output,
+            allow_unsafe_extensions: true,
+            open_time: 0,
+            curve: CurveType::ConstantProduct,
+            protocol_fee_bps: 0,
+            min_trade_size: 0,
+        },
+    };
+    let result = pool
+        .process(instruction(accounts, data), &[&attacker])
+        .await;
+    expect_err(result, ACCOUNT_ALREADY_IN_USE);
+
+    let config = pool.config().await;
+    assert_eq!(config.authority, Some(pool.payer().pubkey()));
+    assert!(config.fee_mode == FeeMode::Input);
+    assert_eq!((config.reserve_x, config.reserve_y), (LIQUIDITY, LIQUIDITY));
+}
+
+#[tokio::test]
+async fn first_deposit_locks_minimum_liquidity() {
+    let mut pool = funded_pool().await;
+    let payer = pool.payer().pubkey();
+    let (mint_x, mint_y, mint_lp) = (pool.mint_x, pool.mint_y, pool.mint_lp);
+    let vault_authority = pool.vault_authority;
+    pool.assert_balance(&payer, &mint_x, FUNDS - LIQUIDITY)
+        .await;
+    pool.assert_balance(&payer, &mint_y, FUNDS - LIQUIDITY)
+        .await;
+    pool.assert_balance(&payer, &mint_lp, LIQUIDITY - MINIMUM_LIQUIDITY)
+        .await;
+    pool.assert_balance(&vault_authority, &mint_lp, MINIMUM_LIQUIDITY)
+        .await;
+}
+
+#[tokio::test]
+async fn swap() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let expected = get_amount_out(LIQUIDITY, LIQUIDITY, 1_000_000, pool.fee).unwrap();
+    pool.swap(&user, true, 1_000_000, expected).await.unwrap();
+
+    let (mint_x, mint_y) = (pool.mint_x, pool.mint_y);
+    pool.assert_balance(&user.pubkey(), &mint_x, FUNDS - 1_000_000)
+        .await;
+    pool.assert_balance(&user.pubkey(), &mint_y, FUNDS + expected)
+        .await;
+    let config = pool.config().await;
+    assert_eq!(config.reserve_y, LIQUIDITY - expected);
+    assert_eq!(config.swap_count, 1);
+}
+
+#[tokio::test]
+async fn swap_below_minimum_out() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let expected = get_amount_out(LIQUIDITY, LIQUIDITY, 1_000_000, pool.fee).unwrap();
+    let result = pool.swap(&user, true, 1_000_000, expected + 1).await;
+    expect_err(result, AmmError::SlippageExceeded);
+}
+
+#[tokio::test]
+async fn swap_below_min_trade_size() {
+    let mut pool = TestPool::builder().min_trade_size(1_000).build().await;
+    let payer = pool.payer();
+    pool.deposit(&payer, LIQUIDITY, LIQUIDITY, LIQUIDITY)
+        .await
+        .unwrap();
+    let user = pool.new_user().await;
+    for x_to_y in [true, false] {
+        pool.swap(&user, x_to_y, 1_000, 1).await.unwrap();
+        let result = pool.swap(&user, x_to_y, 999, 0).await;
+        expect_err(result, AmmError::AmountTooSmall);
+    }
+}
+
+// the fee left in the input reserve counts towards the price a limit of 9/10 of
+// the output per input stops at, with or without a protocol share taken out of it
+#[tokio::test]
+async fn swap_to_limit_fills_partially() {
+    for (x_to_y, protocol_fee_bps) in [(true, 0), (false, 0), (true, 2_000)] {
+        let mut pool = TestPool::builder()
+            .protocol_fee_bps(protocol_fee_bps)
+            .build()
+            .await;
+        let payer = pool.payer();
+        pool.deposit(&payer, LIQUIDITY, LIQUIDITY, LIQUIDITY)
+            .await
+            .unwrap();
+        let user = pool.new_user().await;
+        let (mint_in, mint_out) = match x_to_y {
+            true => (pool.mint_x, pool.mint_y),
+            false => (pool.mint_y, pool.mint_x),
+        };
+        pool.swap_to_limit(&user, x_to_y, 50_000_000, (9, 10))
+            .await
+            .unwrap();
+
+        let sold = FUNDS - pool.balance(&user.pubkey(), &mint_in).await;
+        assert!(sold < 50_000_000);
+        let expected = get_amount_out(LIQUIDITY, LIQUIDITY, sold, pool.fee).unwrap();
+        pool.assert_balance(&user.pubkey(), &mint_out, FUNDS + expected)
+            .await;
+
+        // the price is left at the limit, and a unit less of the output or more of
+        // the input would have it past
+        let config = pool.config().await;
+        let (reserve_in, reserve_out) = match x_to_y {
+            true => (config.reserve_x, config.reserve_y),
+            false => (config.reserve_y, config.reserve_x),
+        };
+        assert!(reserve_out * 10 >= reserve_in * 9);
+        assert!((reserve_out - 1) * 10 < (reserve_in + 1) * 9);
+    }
+}
+
+#[tokio::test]
+async fn swap_to_limit_fills_in_full() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let mint_x = pool.mint_x;
+    // a zero limit is a plain swap, a loose one never binds
+    for limit in [(0, 0), (1, 2)] {
+        pool.swap_to_limit(&user, true, 1_000_000, limit)
+            .await
+            .unwrap();
+    }
+    pool.assert_balance(&user.pubkey(), &mint_x, FUNDS - 2_000_000)
+        .await;
+}
+
+#[tokio::test]
+async fn swap_to_limit_past_limit() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let result = pool.swap_to_limit(&user, true, 1_000_000, (1, 1)).await;
+    expect_err(result, AmmError::PriceLimitReached);
+
+    let mut pool = TestPool::builder()
+        .curve(CurveType::Stable { amp: MAX_AMP })
+        .build()
+        .await;
+    let payer = pool.payer();
+    pool.deposit(&payer, LIQUIDITY, LIQUIDITY, LIQUIDITY)
+        .await
+        .unwrap();
+    let result = pool.swap_to_limit(&payer, true, 1_000_000, (9, 10)).await;
+    expect_err(result, AmmError::UnsupportedLimitCurve);
+}
+
+#[tokio::test]
+async fn swaps_count_per_slot() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let swaps: Vec<_> = [true, false, true]
+        .into_iter()
+        .map(|x_to_y| pool.swap_instruction(&user, x_to_y, 1_000_000, 0))
+        .collect();
+    pool.process_all(&swaps, &[&user]).await.unwrap();
+    let config = pool.config().await;
+    let clock: Clock = pool.context.banks_client.get_sysvar().await.unwrap();
+    assert_eq!(config.last_swap_slot, clock.slot);
+    assert_eq!(config.trades_in_last_slot, 3);
+    assert_eq!(
+        config.last_swap_price_q64,
+        ((config.reserve_y as u128) << 64) / config.reserve_x as u128
+    );
+
+    // the count starts over in the next slot
+    pool.warp(1).await;
+    pool.swap(&user, true, 1_000_000, 0).await.unwrap();
+    let config = pool.config().await;
+    assert_eq!(config.last_swap_slot, clock.slot + 1);
+    assert_eq!(config.trades_in_last_slot, 1);
+}
+
+// a 1% limit on 1:1 reserves lets through what prices at most 1% of the input
+// reserve after the fee
+#[tokio::test]
+async fn swap_partial_fills_up_to_the_impact_limit() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let mint_x = pool.mint_x;
+    pool.swap_partial(&user, true, 1_000_000, 900_000, 100)
+        .await
+        .unwrap();
+    pool.assert_balance(&user.pubkey(), &mint_x, FUNDS - 1_000_000)
+        .await;
+
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let mint_x = pool.mint_x;
+    let limit = LIQUIDITY / 100;
+    // a minimum over what 5x the limit would pay, but not over its share of it
+    pool.swap_partial(&user, true, 5 * limit, 4 * limit, 100)
+        .await
+        .unwrap();
+    let sold = FUNDS - pool.balance(&user.pubkey(), &mint_x).await;
+    assert!(sold - fee_amount(sold, pool.fee).unwrap() <= limit);
+    let next = sold + 1;
+    assert!(next - fee_amount(next, pool.fee).unwrap() > limit);
+}
+
+#[tokio::test]
+async fn swap_partial_with_nothing_to_fill() {
+    let mut pool = TestPool::builder().build().await;
+    let payer = pool.payer();
+    pool.deposit(&payer, 1, 2_000, 2_000).await.unwrap();
+    let result = pool.swap_partial(&payer, true, 1_000, 0, 1).await;
+    expect_err(result, AmmError::PriceImpactTooHigh);
+}
+
+#[tokio::test]
+async fn swap_opens_at_open_time() {
+    let mut pool = TestPool::builder().opens_in(3_600).build().await;
+    let payer = pool.payer();
+    pool.deposit(&payer, LIQUIDITY, LIQUIDITY, LIQUIDITY)
+        .await
+        .unwrap();
+    let result = pool.swap(&payer, true, 1_000, 0).await;
+    expect_err(result, AmmError::PoolNotOpenYet);
+
+    pool.warp(3_600).await;
+    pool.swap(&payer, true, 1_000, 0).await.unwrap();
+}
+
+// lock_pool pauses swaps, PoolLocked being kept for reentry during a flash swap
+#[tokio::test]
+async fn swap_on_locked_pool() {
+    let mut pool = funded_pool().await;
+    pool.lock().await.unwrap();
+    let user = pool.new_user().await;
+    let result = pool.swap(&user, true, 1_000, 0).await;
+    expect_err(result, AmmError::SwapsPaused);
+}
+
+#[tokio::test]
+async fn lock_pool_by_stranger() {
+    let mut pool = funded_pool().await;
+    let stranger = pool.new_user().await;
+    let accounts = amm::accounts::Update {
+        user: stranger.pubkey(),
+        config: pool.config,
+    };
+    let lock = instruction(accounts, amm::instruction::LockPool {});
+    let result = pool.process(lock, &[&stranger]).await;
+    expect_err(result, AmmError::Unauthorized);
+}
+
+#[tokio::test]
+async fn timed_lock_expires() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    pool.lock_for(3_600).await.unwrap();
+    let result = pool.swap(&user, true, 1_000, 0).await;
+    expect_err(result, AmmError::PoolLockedUntil);
+    // withdrawals stay open, like under lock_pool
+    let payer = pool.payer();
+    pool.withdraw(&payer, 1_000).await.unwrap();
+
+    pool.warp(3_600).await;
+    pool.swap(&user, true, 1_000, 0).await.unwrap();
+
+    // and unlock_pool ends one early
+    pool.lock_for(3_600).await.unwrap();
+    pool.unlock().await.unwrap();
+    pool.swap(&user, true, 1_000, 0).await.unwrap();
+}
+
+#[tokio::test]
+async fn timed_lock_past_max_duration() {
+    let mut pool = funded_pool().await;
+    let result = pool.lock_for(MAX_LOCK_DURATION + 1).await;
+    expect_err(result, AmmError::InvalidLockDuration);
+    let result = pool.lock_for(0).await;
+    expect_err(result, AmmError::InvalidLockDuration);
+    pool.lock_for(MAX_LOCK_DURATION).await.unwrap();
+}
+
+async fn stable_pool(amp: u64) -> TestPool {
+    let mut pool = TestPool::builder()
+        .curve(CurveType::Stable { amp })
+        .build()
+        .await;
+    let payer = pool.payer();
+    pool.deposit(&payer, LIQUIDITY, LIQUIDITY, LIQUIDITY)
+        .await
+        .unwrap();
+    pool
+}
+
+/// Swaps 1_000_000 x and checks it paid what `amp` prices off the untouched pool.
+async fn assert_swaps_at_amp(pool: &mut TestPool, amp: u64) {
+    let user = pool.new_user().await;
+    let expected = stable::get_amount_out(amp, LIQUIDITY, LIQUIDITY, 1_000_000, pool.fee).unwrap();
+    pool.swap(&user, true, 1_000_000, 0).await.unwrap();
+    let mint_y = pool.mint_y;
+    pool.assert_balance(&user.pubkey(), &mint_y, FUNDS + expected)
+        .await;
+}
+
+const DAY: i64 = 24 * 60 * 60;
+
+#[tokio::test]
+async fn amp_ramps_linearly() {
+    let mut pool = stable_pool(10).await;
+    pool.ramp_amp(100, 2 * DAY).await.unwrap();
+    pool.warp(DAY).await;
+    assert_swaps_at_amp(&mut pool, 55).await;
+}
+
+#[tokio::test]
+async fn amp_ramp_stops_where_it_is() {
+    let mut pool = stable_pool(10).await;
+    pool.ramp_amp(100, 2 * DAY).await.unwrap();
+    pool.warp(DAY).await;
+    pool.stop_ramp().await.unwrap();
+    pool.warp(DAY).await;
+    let config = pool.config().await;
+    assert!(config.curve == CurveType::Stable { amp: 55 });
+    assert!(config.amp_ramp.is_none());
+    assert_swaps_at_amp(&mut pool, 55).await;
+}
+
+#[tokio::test]
+async fn amp_ramp_out_of_bounds() {
+    let mut pool = stable_pool(10).await;
+    // faster than a day, past 10x, or outside the amp bounds
+    let result = pool.ramp_amp(100, DAY - 1).await;
+    expect_err(result, AmmError::InvalidAmpRamp);
+    let result = pool.ramp_amp(101, DAY).await;
+    expect_err(result, AmmError::InvalidAmpRamp);
+    let result = pool.ramp_amp(0, DAY).await;
+    expect_err(result, AmmError::InvalidAmplification);
+    pool.ramp_amp(1, DAY).await.unwrap();
+
+    let mut pool = funded_pool().await;
+    let result = pool.ramp_amp(100, DAY).await;
+    expect_err(result, AmmError::InvalidAmpRamp);
+}
+
+/// Decimals of mint x and mint y the normalization is checked on.
+const DECIMAL_PAIRS: [(u8, u8); 2] = [(6, 9), (0, 9)];
+
+/// A pool on `curve` of 100 whole tokens a side, from mints of `decimals`.
+async fn pool_with_decimals(curve: CurveType, (decimals_x, decimals_y): (u8, u8)) -> TestPool {
+    let mut pool = TestPool::builder()
+        .curve(curve)
+        .decimals(decimals_x, decimals_y)
+        .build()
+        .await;
+    let payer = pool.payer();
+    let (x, y) = (
+        100 * 10u64.pow(decimals_x as u32),
+        100 * 10u64.pow(decimals_y as u32),
+    );
+    pool.deposit(&payer, 1, x, y).await.unwrap();
+    pool
+}
+
+#[tokio::test]
+async fn stable_pool_normalizes_decimals() {
+    for decimals in DECIMAL_PAIRS {
+        let mut pool = pool_with_decimals(CurveType::Stable { amp: 100 }, decimals).await;
+        let config = pool.config().await;
+        assert_eq!((config.decimals_x, config.decimals_y), decimals);
+        let scales = config.precision().unwrap();
+
+        // 10 tokens of x buy close to 10 of y, not what the raw units would price
+        let amount_in = config.reserve_x / 10;
+        let expected = stable::get_amount_out_scaled(
+            100,
+            config.reserve_x,
+            config.reserve_y,
+            amount_in,
+            pool.fee,
+            scales,
+        )
+        .unwrap();
+        let priced = (amount_in - fee_amount(amount_in, pool.fee).unwrap()) * scales.0;
+        assert!(expected > priced * 99 / 100 && expected < priced);
+
+        let user = pool.new_user().await;
+        pool.swap(&user, true, amount_in, expected).await.unwrap();
+        let mint_y = pool.mint_y;
+        pool.assert_balance(&user.pubkey(), &mint_y, FUNDS + expected)
+            .await;
+    }
+}
+
+#[tokio::test]
+async fn twap_accrues_whole_token_prices() {
+    for decimals in DECIMAL_PAIRS {
+        // a token of x for a token of y, whatever the raw units
+        let mut pool = pool_with_decimals(CurveType::ConstantProduct, decimals).await;
+        let since = pool.config().await.last_update_ts;
+        pool.warp(10).await;
+        let user = pool.new_user().await;
+        let amount_in = pool.config().await.reserve_x / 10;
+        pool.swap(&user, true, amount_in, 1).await.unwrap();
+
+        let config = pool.config().await;
+        let elapsed = (config.last_update_ts - since) as u128;
+        assert_eq!(config.price_cumulative_x, elapsed << 64);
+        assert_eq!(config.price_cumulative_y, elapsed << 64);
+        // the price the swap left, x now somewhat cheaper than a token of y
+        assert!(config.last_swap_price_q64 < 1 << 64);
+        assert!(config.last_swap_price_q64 > (4 << 64) / 5);
+    }
+}
+
+#[tokio::test]
+async fn weighted_prices_apply_the_weights() {
+    // 80/20 on even reserves, a unit of x worth four of y
+    let mut pool = TestPool::builder()
+        .curve(CurveType::Weighted {
+            weight_x_bps: 8_000,
+        })
+        .build()
+        .await;
+    let payer = pool.payer();
+    pool.deposit(&payer, 1, LIQUIDITY, LIQUIDITY).await.unwrap();
+    assert_eq!(pool.pool_state().await.spot_price_q64, 4 << 64);
+
+    let since = pool.config().await.last_update_ts;
+    pool.warp(10).await;
+    let user = pool.new_user().await;
+    pool.swap(&user, true, LIQUIDITY / 100, 1).await.unwrap();
+    let config = pool.config().await;
+    let elapsed = (config.last_update_ts - since) as u128;
+    assert_eq!(config.price_cumulative_x, elapsed * (4 << 64));
+    assert_eq!(config.price_cumulative_y, elapsed * ((1 << 64) / 4));
+}
+
+#[tokio::test]
+async fn donate_raises_lp_value() {
+    let mut pool = funded_pool().await;
+    let donor = pool.new_user().await;
+    let before = pool.config().await;
+    pool.donate(&donor, 1_000_000, 2_000_000).await.unwrap();
+
+    let after = pool.config().await;
+    assert_eq!(
+        (after.reserve_x, after.reserve_y),
+        (LIQUIDITY + 1_000_000, LIQUIDITY + 2_000_000)
+    );
+    // nothing was minted for it, so every LP unit is worth more of both
+    let (mint_lp, payer) = (pool.mint_lp, pool.payer().pubkey());
+    pool.assert_balance(&donor.pubkey(), &mint_lp, 0).await;
+    pool.assert_balance(&payer, &mint_lp, LIQUIDITY - MINIMUM_LIQUIDITY)
+        .await;
+    let lp = 1_000_000;
+    let (old_x, old_y) = before.withdraw_amounts(lp, LIQUIDITY).unwrap();
+    let (new_x, new_y) = after.withdraw_amounts(lp, LIQUIDITY).unwrap();
+    assert!(new_x > old_x && new_y > old_y);
+}
+
+#[tokio::test]
+async fn donate_without_lp() {
+    let mut pool = TestPool::builder().build().await;
+    let payer = pool.payer();
+    let result = pool.donate(&payer, 1_000_000, 1_000_000).await;
+    expect_err(result, AmmError::NoLiquidityToDonate);
+}
+
+const CREATOR_LOCK: i64 = 30 * DAY;
+
+#[tokio::test]
+async fn creator_lp_unlocks_after_lock() {
+    let mut pool = TestPool::builder()
+        .creator_lock(LIQUIDITY, CREATOR_LOCK)
+        .build()
+        .await;
+    let payer = pool.payer();
+    let (owner, mint_lp) = (payer.pubkey(), pool.mint_lp);
+    let position = pool.lock_position(&owner);
+    // the LP went to the position, so no account was opened for the initializer
+    let owner_lp = get_associated_token_address(&owner, &mint_lp);
+    let account = pool.context.banks_client.get_account(owner_lp).await;
+    assert!(account.unwrap().is_none());
+    pool.assert_balance(&position, &mint_lp, LIQUIDITY - MINIMUM_LIQUIDITY)
+        .await;
+
+    let result = pool.claim_locked_lp(&payer, &owner).await;
+    expect_err(result, AmmError::LpStillLocked);
+    let stranger = pool.new_user().await;
+    pool.warp(CREATOR_LOCK).await;
+    let result = pool.claim_locked_lp(&stranger, &owner).await;
+    expect_err(result, AmmError::NotLockOwner);
+
+    pool.claim_locked_lp(&payer, &owner).await.unwrap();
+    pool.assert_balance(&owner, &mint_lp, LIQUIDITY - MINIMUM_LIQUIDITY)
+        .await;
+    pool.assert_balance(&position, &mint_lp, 0).await;
+}
+
+#[tokio::test]
+async fn creator_lock_only_extends() {
+    let mut pool = TestPool::builder()
+        .creator_lock(LIQUIDITY, CREATOR_LOCK)
+        .build()
+        .await;
+    let payer = pool.payer();
+    let owner = payer.pubkey();
+    let clock: Clock = pool.context.banks_client.get_sysvar().await.unwrap();
+    let unlock_ts = clock.unix_timestamp + CREATOR_LOCK;
+
+    let result = pool.extend_lp_lock(&payer, &owner, unlock_ts - 1).await;
+    expect_err(result, AmmError::LockShortened);
+    let stranger = pool.new_user().await;
+    let result = pool
+        .extend_lp_lock(&stranger, &owner, unlock_ts + DAY)
+        .await;
+    expect_err(result, AmmError::NotLockOwner);
+
+    pool.extend_lp_lock(&payer, &owner, unlock_ts + DAY)
+        .await
+        .unwrap();
+    pool.warp(CREATOR_LOCK).await;
+    let result = pool.claim_locked_lp(&payer, &owner).await;
+    expect_err(result, AmmError::LpStillLocked);
+    pool.warp(DAY).await;
+    pool.claim_locked_lp(&payer, &owner).await.unwrap();
+}
+
+#[tokio::test]
+async fn creator_lock_without_duration() {
+    let (_, result) = TestPool::builder()
+        .creator_lock(LIQUIDITY, 0)
+        .try_build()
+        .await;
+    expect_err(result, AmmError::InvalidCreatorLock);
+}
+
+#[tokio::test]
+async fn withdraw_more_than_supply() {
+    let mut pool = funded_pool().await;
+    let payer = pool.payer();
+    let result = pool.withdraw(&payer, LIQUIDITY + 1).await;
+    expect_err(result, AmmError::InsufficientBalance);
+}
+
+/// A leg tripping the breaker ends the batch, so the trip lands with the legs before
+/// it rather than failing on the next leg and rolling back.
+#[tokio::test]
+async fn swap_batch_stops_at_tripped_leg() {
+    let mut pool = funded_pool().await;
+    let change = ParameterChange::CircuitBreaker {
+        circuit_breaker_bps: 500,
+    };
+    pool.schedule_update(change).await.unwrap();
+    pool.warp(TIMELOCK_SECONDS).await;
+    pool.execute_update().await.unwrap();
+    let user = pool.new_user().await;
+
+    // the first leg moves the price about 2%, the second well past the 5% breaker
+    let legs = [(true, 1_000_000), (true, LIQUIDITY / 2), (false, 1_000_000)];
+    pool.swap_batch(&user, &legs).await.unwrap();
+    let expected = get_amount_out(LIQUIDITY, LIQUIDITY, 1_000_000, pool.fee).unwrap();
+    let (mint_x, mint_y) = (pool.mint_x, pool.mint_y);
+    pool.assert_balance(&user.pubkey(), &mint_x, FUNDS - 1_000_000)
+        .await;
+    pool.assert_balance(&user.pubkey(), &mint_y, FUNDS + expected)
+        .await;
+    let config = pool.config().await;
+    assert!(config.breaker_tripped);
+    assert_eq!(config.reserve_y, LIQUIDITY - expected);
+    assert_eq!(config.swap_count, 1);
+
+    let result = pool.swap(&user, false, 1_000_000, 0).await;
+    expect_err(result, AmmError::CircuitBreakerTripped);
+}
+
+/// Routing back through the same pool would price the second hop on the reserves
+/// before the first, then book it over the first.
+#[tokio::test]
+async fn swap_route_through_one_pool() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let keys = pool.keys();
+    let result = pool.swap_route(&user, &keys, 1_000_000, 1).await;
+    expect_err(result, AmmError::SamePool);
+}
+
+// the pool can pay out anything short of its whole reserve, so a minimum of all of
+// it is a malformed parameter while one below is plain slippage
+#[tokio::test]
+async fn swap_minimum_out_bounds() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let result = pool.swap(&user, true, 1_000_000, LIQUIDITY).await;
+    expect_err(result, AmmError::SlippageParamTooLarge);
+    let result = pool.swap(&user, true, 1_000_000, LIQUIDITY - 1).await;
+    expect_err(result, AmmError::SlippageExceeded);
+}
+
+#[tokio::test]
+async fn swap_against_empty_pool() {
+    let mut pool = TestPool::builder().build().await;
+    let user = pool.new_user().await;
+    let result = pool.swap(&user, true, 1_000, 0).await;
+    expect_err(result, AmmError::NoLiquidityInPool);
+    let result = pool.swap_exact_out(&user, true, 1_000, u64::MAX).await;
+    expect_err(result, AmmError::NoLiquidityInPool);
+}
+
+#[tokio::test]
+async fn swap_rounding_to_zero() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let result = pool.swap(&user, true, 1, 0).await;
+    expect_err(result, AmmError::AmountOutTooSmall);
+}
+
+#[tokio::test]
+async fn swap_exact_out_above_reserve() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let result = pool.swap_exact_out(&user, true, LIQUIDITY, u64::MAX).await;
+    expect_err(result, AmmError::InsufficientPoolLiquidity);
+}
+
+/// Whatever a handler reads from the vaults after its transfers comes from reloaded
+/// accounts, so the reserves it books match what the vaults hold on chain.
+#[tokio::test]
+async fn reserves_match_vault_balances() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let payer = pool.payer();
+    pool.swap(&user, true, 1_000_000, 1).await.unwrap();
+    assert_reserves_match_vaults(&mut pool).await;
+    pool.swap(&user, false, 3_000_000, 1).await.unwrap();
+    assert_reserves_match_vaults(&mut pool).await;
+    pool.swap_exact_out(&user, true, 500_000, u64::MAX)
+        .await
+        .unwrap();
+    assert_reserves_match_vaults(&mut pool).await;
+    pool.withdraw(&payer, LIQUIDITY / 2).await.unwrap();
+    assert_reserves_match_vaults(&mut pool).await;
+}
+
+async fn assert_reserves_match_vaults(pool: &mut TestPool) {
+    let config = pool.config().await;
+    let (vault_authority, mint_x, mint_y) = (pool.vault_authority, pool.mint_x, pool.mint_y);
+    pool.assert_balance(&vault_authority, &mint_x, config.reserve_x)
+        .await;
+    pool.assert_balance(&vault_authority, &mint_y, config.reserve_y)
+        .await;
+}
+
+#[tokio::test]
+async fn pool_state_snapshots_the_pool() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    pool.swap(&user, true, 1_000_000, 1).await.unwrap();
+    pool.lock().await.unwrap();
+    pool.lock_for(DAY).await.unwrap();
+
+    let clock: Clock = pool.context.banks_client.get_sysvar().await.unwrap();
+    let state = pool.pool_state().await;
+    let config = pool.config().await;
+    assert!(state.slot >= clock.slot);
+    assert_eq!(
+        (state.reserve_x, state.reserve_y),
+        (config.reserve_x, config.reserve_y)
+    );
+    assert_eq!(
+        (state.vault_x, state.vault_y),
+        (config.reserve_x, config.reserve_y)
+    );
+    assert_eq!(state.lp_supply, LIQUIDITY);
+    assert_eq!((state.fee, state.protocol_fee_bps), (30, 0));
+    assert_eq!(state.pause_flags, PAUSE_SWAPS | PAUSE_DEPOSITS);
+    assert_eq!(state.locked_until, config.locked_until);
+    assert!(state.locked_until > clock.unix_timestamp);
+    assert!(!state.breaker_tripped);
+    assert_eq!(
+        state.spot_price_q64,
+        ((config.reserve_y as u128) << 64) / config.reserve_x as u128
+    );
+    assert_eq!(state.last_update_ts, config.last_update_ts);
+}
+
+/// Borrowed by the flash swap tests, a tenth of the x reserve.
+const BORROWED: u64 = LIQUIDITY / 10;
+
+#[tokio::test]
+async fn flash_swap_repaid_with_fee() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let repaid = BORROWED * 10_000 / 9_970 + 1;
+    let instructions = [
+        pool.flash_begin_instruction(&user, BORROWED, 0),
+        pool.transfer_to_vault_instruction(&user, pool.mint_x, repaid),
+        pool.flash_end_instruction(&user),
+    ];
+    pool.process_all(&instructions, &[&user]).await.unwrap();
+    assert_reserves_match_vaults(&mut pool).await;
+}
+
+/// A sync between the repayment and end_flash_swap would fold the repayment into
+/// the reserves, and end would count it again, so half the loan would do.
+#[tokio::test]
+async fn sync_mid_flash_swap() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let instructions = [
+        pool.flash_begin_instruction(&user, BORROWED, 0),
+        pool.transfer_to_vault_instruction(&user, pool.mint_x, BORROWED / 2 + BORROWED / 100),
+        pool.sync_instruction(),
+        pool.flash_end_instruction(&user),
+    ];
+    let result = pool.process_all(&instructions, &[&user]).await;
+    expect_err(result, AmmError::PoolLocked);
+}
+
+/// Mid flash swap a repayment already in the vault is no surplus, and skim must not
+/// sweep it to the fee recipient or fold it into the reserves.
+#[tokio::test]
+async fn skim_mid_flash_swap() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let instructions = [
+        pool.flash_begin_instruction(&user, BORROWED, 0),
+        pool.transfer_to_vault_instruction(&user, pool.mint_x, BORROWED * 10_000 / 9_970 + 1),
+        pool.skim_instruction(&user),
+        pool.flash_end_instruction(&user),
+    ];
+    let result = pool.process_all(&instructions, &[&user]).await;
+    expect_err(result, AmmError::PoolLocked);
+}
+
+/// end_flash_swap takes its protocol share as the protocol state decides, like a
+/// swap, rather than at the pool's own rate.
+#[tokio::test]
+async fn flash_swap_protocol_share_follows_protocol_state() {
+    let mut pool = TestPool::builder().protocol_fee_bps(2_000).build().await;
+    let payer = pool.payer();
+    pool.deposit(&payer, LIQUIDITY, LIQUIDITY, LIQUIDITY)
+        .await
+        .unwrap();
+    let user = pool.new_user().await;
+    let flash = |pool: &TestPool, borrowed: u64| {
+        let repaid = borrowed * 10_000 / 9_970 + 1;
+        [
+            pool.flash_begin_instruction(&user, borrowed, 0),
+            pool.transfer_to_vault_instruction(&user, pool.mint_x, repaid),
+            pool.flash_end_instruction(&user),
+        ]
+    };
+
+    let instructions = flash(&pool, BORROWED);
+    pool.process_all(&instructions, &[&user]).await.unwrap();
+    let fee = fee_amount(BORROWED * 10_000 / 9_970 + 1, 30).unwrap();
+    assert_eq!(pool.fee_vault_balance(true).await, fee * 2_000 / 10_000);
+
+    // switched off, the whole fee stays with the LPs
+    pool.set_protocol_fee(false, 10_000).await.unwrap();
+    let instructions = flash(&pool, BORROWED / 2);
+    pool.process_all(&instructions, &[&user]).await.unwrap();
+    assert_eq!(pool.fee_vault_balance(true).await, fee * 2_000 / 10_000);
+    assert_reserves_match_vaults(&mut pool).await;
+}
+
+/// Only the upgrade authority may create the protocol state, which the harness
+/// already did, so another signer is turned away before the init is even tried.
+#[tokio::test]
+async fn initialize_protocol_requires_upgrade_authority() {
+    let mut pool = funded_pool().await;
+    let user = pool.new_user().await;
+    let accounts = amm::accounts::InitializeProtocol {
+        authority: user.pubkey(),
+        program: amm::ID,
+        program_data: program_data_address(&amm::ID),
+        protocol: pda(&[b"protocol"]),
+        system_program: system_program::ID,
+    };
+    let data = amm::instruction::InitializeProtocol {
+        protocol_fee_enabled: false,
+        default_protocol_fee_bps: 0,
+    };
+    let result = pool.process(instruction(accounts, data), &[&user]).await;
+    expect_err(result, AmmError::InvalidProtocolAuthority);
+}
+
+#[tokio::test]
+async fn protocol_authority_changes_once_accepted() {
+    let mut pool = funded_pool().await;
+    let old = pool.protocol_authority.insecure_clone();
+    let new = pool.new_user().await;
+    let result = pool.accept_protocol_authority(&new).await;
+    expect_err(result, AmmError::NoPendingProtocolAuthority);
+
+    pool.propose_protocol_authority(new.pubkey()).await.unwrap();
+    // still the old authority's until accepted, and only by the proposed key
+    pool.set_protocol_fee(true, 5_000).await.unwrap();
+    let stranger = pool.new_user().await;
+    let result = pool.accept_protocol_authority(&stranger).await;
+    expect_err(result, AmmError::InvalidProtocolAuthority);
+
+    pool.accept_protocol_authority(&new).await.unwrap();
+    pool.set_protocol_fee(true, 10_000).await.unwrap();
+    pool.protocol_authority = old;
+    let result = pool.set_protocol_fee(false, 0).await;
+    expect_err(result, AmmError::InvalidProtocolAuthority);
+}
+
+/// The vault balance check behind the quoting math: with the x vault short of its
+/// reserve, a sale of y quoted off the reserves pays out more x than the vaults'
+/// own product allows.
+#[tokio::test]
+async fn swap_fails_once_the_vaults_lose_their_invariant() {
+    let mut pool = funded_pool().await;
+    let vault_x = pool.vault(pool.mint_x);
+    pool.set_token_balance(vault_x, LIQUIDITY / 2).await;
+    let user = pool.new_user().await;
+    let result = pool.swap(&user, false, 1_000_000, 1).await;
+    expect_err(result, AmmError::InvariantViolated);
+}
@@ -1,13 +1,159 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { Amm } from "../target/types/amm";
+import { AmmCaller } from "../target/types/amm_caller";
 // import wallet from "/home/ghostgamer/.config/solana/id.json"
-import { PublicKey } from "@solana/web3.js"
+import { Keypair, PublicKey, SYSVAR_INSTRUCTIONS_PUBKEY, SystemProgram, Transaction, TransactionInstruction, sendAndConfirmTransaction } from "@solana/web3.js"
 // import { bytes } from "@coral-xyz/anchor/dist/cjs/utils";
 import { expect } from "chai";
 import { SendTransactionError } from "@solana/web3.js";
 
-import { getAccount, getAssociatedTokenAddress, getOrCreateAssociatedTokenAccount } from "@solana/spl-token";
+import {
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  ExtensionType,
+  approve,
+  NATIVE_MINT,
+  TOKEN_2022_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  createInitializeMintInstruction,
+  createInitializeNonTransferableMintInstruction,
+  createInitializePermanentDelegateInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createInitializeTransferHookInstruction,
+  createMint,
+  createSyncNativeInstruction,
+  createTransferInstruction,
+  getAccount,
+  getAssociatedTokenAddress,
+  getAssociatedTokenAddressSync,
+  getMint,
+  getMintLen,
+  getOrCreateAssociatedTokenAccount,
+  getTransferFeeAmount,
+  getTransferFeeConfig,
+  mintTo,
+  revoke,
+} from "@solana/spl-token";
+
+const sortMints = (mints: PublicKey[]) =>
+  [...mints].sort((a, b) => Buffer.compare(a.toBuffer(), b.toBuffer()));
+
+// mirrors utils::derive_pool_address
+const poolAddress = (programId: PublicKey, mintA: PublicKey, mintB: PublicKey, fee: number) => {
+  const [mintX, mintY] = sortMints([mintA, mintB]);
+  const feeBytes = Buffer.alloc(2);
+  feeBytes.writeUInt16LE(fee);
+  const [config] = PublicKey.findProgramAddressSync([
+    Buffer.from("config"),
+    mintX.toBuffer(),
+    mintY.toBuffer(),
+    feeBytes,
+  ],
+    programId,
+  );
+  return config;
+};
+
+// mirrors utils::derive_registry_address
+const registryAddress = (programId: PublicKey, mintA: PublicKey, mintB: PublicKey, fee: number) => {
+  const [mintX, mintY] = sortMints([mintA, mintB]);
+  const feeBytes = Buffer.alloc(2);
+  feeBytes.writeUInt16LE(fee);
+  const [registry] = PublicKey.findProgramAddressSync([
+    Buffer.from("registry"),
+    mintX.toBuffer(),
+    mintY.toBuffer(),
+    feeBytes,
+  ],
+    programId,
+  );
+  return registry;
+};
+
+// fee vault holding the protocol's share of the pool's x or y side
+const feeVaultAddress = (programId: PublicKey, config: PublicKey, side: "x" | "y") => {
+  const [feeVault] = PublicKey.findProgramAddressSync([
+    Buffer.from(`fee_vault_${side}`),
+    config.toBuffer(),
+  ],
+    programId,
+  );
+  return feeVault;
+};
+
+// PDA owning the pool's token accounts and signing for them
+const vaultAuthorityAddress = (programId: PublicKey, config: PublicKey) => {
+  const [authority] = PublicKey.findProgramAddressSync([
+    Buffer.from("auth"),
+    config.toBuffer(),
+  ],
+    programId,
+  );
+  return authority;
+};
+
+const protocolAddress = (programId: PublicKey) => {
+  const [protocol] = PublicKey.findProgramAddressSync([Buffer.from("protocol")], programId);
+  return protocol;
+};
+
+// program data of the upgradeable deployment, holding the upgrade authority that
+// initialize_protocol requires
+const programDataAddress = (programId: PublicKey) => {
+  const [programData] = PublicKey.findProgramAddressSync(
+    [programId.toBuffer()],
+    new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111"),
+  );
+  return programData;
+};
+
+const eventAuthorityAddress = (programId: PublicKey) => {
+  const [authority] = PublicKey.findProgramAddressSync([Buffer.from("__event_authority")], programId);
+  return authority;
+};
+
+// swap, deposit and withdraw record their events as self-CPIs rather than logs, the
+// event following the 8 byte tag in the inner instruction's data
+const cpiEvents = (program: Program<Amm>, tx: anchor.web3.VersionedTransactionResponse) => {
+  const keys = tx.transaction.message.getAccountKeys();
+  return tx.meta.innerInstructions
+    .flatMap((inner) => inner.instructions)
+    .filter((ix) => keys.get(ix.programIdIndex).equals(program.programId))
+    .map((ix) => program.coder.events.decode(anchor.utils.bytes.base64.encode(anchor.utils.bytes.bs58.decode(ix.data).subarray(8))))
+    .filter((event) => event !== null);
+};
+
+// the vault authority's ATAs, the vaults initialize creates unless handed other accounts
+const ataVaults = (programId: PublicKey, { config, mintX, mintY, tokenProgramX = TOKEN_PROGRAM_ID, tokenProgramY = TOKEN_PROGRAM_ID }) => {
+  const authority = vaultAuthorityAddress(programId, config);
+  return {
+    vaultX: getAssociatedTokenAddressSync(mintX, authority, true, tokenProgramX),
+    vaultY: getAssociatedTokenAddressSync(mintY, authority, true, tokenProgramY),
+  };
+};
+
+// initialize params for a constant product pool with an input fee, open right away
+const initializeParams = (fee: number, authority: PublicKey | null, overrides: object = {}) => ({
+  fee,
+  authority,
+  feeMode: { input: {} },
+  allowUnsafeExtensions: false,
+  openTime: new anchor.BN(0),
+  curve: { constantProduct: {} },
+  protocolFeeBps: 0,
+  minTradeSize: new anchor.BN(0),
+  ...overrides,
+});
+
+// mirror the PAUSE_* flags in constants.rs
+const PAUSE_SWAPS = 1 << 0;
+const PAUSE_DEPOSITS = 1 << 1;
+const PAUSE_WITHDRAWALS = 1 << 2;
+
+// fresh mint keypairs, ordered the way initialize expects mint_x and mint_y
+const sortedMintKeypairs = (count: number) =>
+  Array.from({ length: count }, () => Keypair.generate())
+    .sort((a, b) => Buffer.compare(a.publicKey.toBuffer(), b.publicKey.toBuffer()));
 
 describe("amm", () => {
   const provider = anchor.AnchorProvider.env()
@@ -17,11 +163,6 @@ describe("amm", () => {
 
   const wallet = provider.wallet
 
-  const seed = new anchor.BN(Math.random() * 10_000)
-  console.log(seed.toString())
-  // const seed = new anchor.BN(866);
-  const seed2 = new anchor.BN(Math.random() * 100_000)
-
   const fee = 30
 
   const Zoro = new PublicKey("6QRY8Stw4VzgGSLeTavBNAcpVNHV2ArFnpg7GKQx5vrL")
@@ -30,12 +171,18 @@ describe("amm", () => {
   const max_zoro = new anchor.BN(50_000 * 1_000_000)
   const max_g5 = new anchor.BN(50_000 * 1_000_000)
 
-  const [config] = PublicKey.findProgramAddressSync([
-    Buffer.from("config"),
-    seed.toArrayLike(Buffer, "le", 8)
-  ],
-    program.programId,
-  );
+  const tokenPrograms = {
+    tokenProgram: TOKEN_PROGRAM_ID,
+    tokenProgramX: TOKEN_PROGRAM_ID,
+    tokenProgramY: TOKEN_PROGRAM_ID,
+  };
+
+  const getAmountOut = (reserveIn: bigint, reserveOut: bigint, amountIn: bigint) => {
+    const amountInWithFee = amountIn * BigInt(10_000 - fee) / BigInt(10_000);
+    return reserveOut * amountInWithFee / (reserveIn + amountInWithFee);
+  };
+
+  const config = poolAddress(program.programId, Zoro, G5, fee);
   const [lp] = PublicKey.findProgramAddressSync([
     Buffer.from("lp"),
     config.toBuffer(),
@@ -43,36 +190,167 @@ describe("amm", () => {
     program.programId,
   );
 
+  // every swap reads the global protocol state, the later suites included. Created
+  // with each pool's own protocol share in full effect
+  before(async () => {
+    await program.methods
+      .initializeProtocol(true, 10_000)
+      .accountsPartial({ authority: wallet.publicKey, programData: programDataAddress(program.programId) })
+      .rpc();
+  });
 
   it("Is initialized with authority!", async () => {
-    console.log("seed: ", seed.toString())
     // Add your test here.
-    const tx = await program.methods.initialize(seed, fee, wallet.publicKey)
+    const tx = await program.methods.initialize(initializeParams(fee, wallet.publicKey))
       .accountsPartial({
         initializer: wallet.publicKey,
         mintX: Zoro,
         mintY: G5,
         mintLp: lp,
         config: config,
+        ...tokenPrograms,
+        ...ataVaults(program.programId, { config, mintX: Zoro, mintY: G5, ...tokenPrograms }),
       })
       .rpc();
     const configAccount = await program.account.config.fetch(config);
     expect(configAccount.authority.equals(wallet.publicKey)).to.be.true;
-    expect(configAccount.seed.toString()).to.equal(seed.toString());
     expect(configAccount.fee.toString()).to.equal(fee.toString());
+    expect(configAccount.feeTier.toString()).to.equal(fee.toString());
+    expect(configAccount.mintLp.equals(lp)).to.be.true;
+
+  });
+
+  it("Owns the vaults by the vault authority instead of the config!", async () => {
+    const vaultAuthority = vaultAuthorityAddress(program.programId, config);
+    const configAccount = await program.account.config.fetch(config);
+    const [, authBump] = PublicKey.findProgramAddressSync([
+      Buffer.from("auth"),
+      config.toBuffer(),
+    ],
+      program.programId,
+    );
+    expect(configAccount.authBump).to.equal(authBump);
+
+    for (const mint of [Zoro, G5]) {
+      const vault = await getAccount(provider.connection, await getAssociatedTokenAddress(mint, vaultAuthority, true));
+      expect(vault.owner.equals(vaultAuthority)).to.be.true;
+      expect(vault.owner.equals(config)).to.be.false;
+
+      // the config-owned vaults of the old layout are never created, so nothing is
+      // left for config seeds to sign for
+      const oldVault = await getAssociatedTokenAddress(mint, config, true);
+      expect(await provider.connection.getAccountInfo(oldVault)).to.be.null;
+    }
+    for (const side of ["x", "y"] as const) {
+      const feeVault = await getAccount(provider.connection, feeVaultAddress(program.programId, config, side));
+      expect(feeVault.owner.equals(vaultAuthority)).to.be.true;
+    }
+  });
+
+  it("Flags a mint with a freeze authority at initialize!", async () => {
+    // only x can be frozen
+    const [keypairX, keypairY] = sortedMintKeypairs(2);
+    const mintX = await createMint(provider.connection, wallet.payer, wallet.publicKey, wallet.publicKey, 6, keypairX);
+    const mintY = await createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6, keypairY);
+    const poolConfig = poolAddress(program.programId, mintX, mintY, fee);
+
+    const signature = await program.methods.initialize(initializeParams(fee, wallet.publicKey))
+      .accountsPartial({
+        initializer: wallet.publicKey,
+        mintX,
+        mintY,
+        config: poolConfig,
+        ...tokenPrograms,
+        ...ataVaults(program.programId, { config: poolConfig, mintX, mintY, ...tokenPrograms }),
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const pool = await program.account.config.fetch(poolConfig, "confirmed");
+    expect(pool.mintXFreezable).to.be.true;
+    expect(pool.mintYFreezable).to.be.false;
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+    const event = [...parser.parseLogs(tx.meta.logMessages)].find((event) => event.name === "poolCreated");
+    expect(event.data.config.equals(poolConfig)).to.be.true;
+    expect(event.data.fee).to.equal(fee);
+    expect(event.data.mintXFreezable).to.be.true;
+    expect(event.data.mintYFreezable).to.be.false;
+  });
+
+  it("Caches every PDA bump in the config!", async () => {
+    // all signer seeds and seed constraints read these back instead of searching
+    // for the bump on-chain
+    const configAccount = await program.account.config.fetch(config);
+    const bump = (seeds: Buffer[]) => PublicKey.findProgramAddressSync(seeds, program.programId)[1];
+    expect(configAccount.configBump).to.equal(bump([
+      Buffer.from("config"),
+      Zoro.toBuffer(),
+      G5.toBuffer(),
+      new anchor.BN(fee).toArrayLike(Buffer, "le", 2),
+    ]));
+    expect(configAccount.lpBump).to.equal(bump([Buffer.from("lp"), config.toBuffer()]));
+    expect(configAccount.authBump).to.equal(bump([Buffer.from("auth"), config.toBuffer()]));
+    expect(configAccount.feeVaultXBump).to.equal(bump([Buffer.from("fee_vault_x"), config.toBuffer()]));
+    expect(configAccount.feeVaultYBump).to.equal(bump([Buffer.from("fee_vault_y"), config.toBuffer()]));
+    expect(configAccount.observationBump).to.equal(bump([Buffer.from("observation"), config.toBuffer()]));
+  });
+
+  it("Initializes a pool whose vaults were created ahead of it!", async () => {
+    const [mintX, mintY] = await Promise.all(sortedMintKeypairs(2).map((keypair) =>
+      createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6, keypair)
+    ));
+    const poolConfig = poolAddress(program.programId, mintX, mintY, fee);
+    const vaultAuthority = vaultAuthorityAddress(program.programId, poolConfig);
+
+    // anyone can create the vault ATAs before the pool exists
+    for (const mint of [mintX, mintY]) {
+      await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, mint, vaultAuthority, true);
+    }
+
+    await program.methods.initialize(initializeParams(fee, wallet.publicKey))
+      .accountsPartial({
+        initializer: wallet.publicKey,
+        mintX,
+        mintY,
+        config: poolConfig,
+        ...tokenPrograms,
+        ...ataVaults(program.programId, { config: poolConfig, mintX, mintY, ...tokenPrograms }),
+      })
+      .rpc();
+
+    for (const mint of [mintX, mintY]) {
+      const ata = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, mint, wallet.publicKey);
+      await mintTo(provider.connection, wallet.payer, mint, ata.address, wallet.payer, 1_000 * 1_000_000);
+    }
+    await program.methods
+      .deposit(new anchor.BN(1), new anchor.BN(100 * 1_000_000), new anchor.BN(100 * 1_000_000), new anchor.BN(0))
+      .accountsPartial({ user: wallet.publicKey, mintX, mintY, config: poolConfig, ...tokenPrograms })
+      .rpc();
+    await program.methods
+      .swap(true, new anchor.BN(1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({ user: wallet.publicKey, mintX, mintY, config: poolConfig, ...tokenPrograms })
+      .rpc();
 
+    const pool = await program.account.config.fetch(poolConfig);
+    expect(pool.reserveX.toNumber()).to.equal(101 * 1_000_000);
   });
 
-  it("fails initialize with same seed!", async () => {
+  it("fails to initialize the same pair and fee twice!", async () => {
     try {
 
-      const tx = await program.methods.initialize(seed, fee, wallet.publicKey)
+      const tx = await program.methods.initialize(initializeParams(fee, wallet.publicKey))
         .accountsPartial({
           initializer: wallet.publicKey,
           mintX: Zoro,
           mintY: G5,
           mintLp: lp,
           config: config,
+          ...tokenPrograms,
+          ...ataVaults(program.programId, { config, mintX: Zoro, mintY: G5, ...tokenPrograms }),
         })
         .rpc();
       throw new Error("Expected initialize to fail, but it succeeded");
@@ -82,6 +360,93 @@ describe("amm", () => {
     }
   });
 
+  it("fails initialize with a fee above the maximum!", async () => {
+    for (const badFee of [1_001, 10_000]) {
+      const badConfig = poolAddress(program.programId, Zoro, G5, badFee);
+      const [badLp] = PublicKey.findProgramAddressSync([
+        Buffer.from("lp"),
+        badConfig.toBuffer(),
+      ],
+        program.programId,
+      );
+
+      try {
+        await program.methods.initialize(initializeParams(badFee, wallet.publicKey))
+          .accountsPartial({
+            initializer: wallet.publicKey,
+            mintX: Zoro,
+            mintY: G5,
+            mintLp: badLp,
+            config: badConfig,
+            ...tokenPrograms,
+            ...ataVaults(program.programId, { config: badConfig, mintX: Zoro, mintY: G5, ...tokenPrograms }),
+          })
+          .rpc();
+        expect.fail("Initialize should have failed due to invalid fee");
+      } catch (e) {
+        expect(e).to.have.property("logs");
+        expect(e.logs.join("\n")).to.include("Fee is greater than 100%.");
+      }
+    }
+  });
+
+  it("fails initialize with identical mints!", async () => {
+    const badConfig = poolAddress(program.programId, Zoro, Zoro, fee);
+    const [badLp] = PublicKey.findProgramAddressSync([
+      Buffer.from("lp"),
+      badConfig.toBuffer(),
+    ],
+      program.programId,
+    );
+
+    try {
+      await program.methods.initialize(initializeParams(fee, wallet.publicKey))
+        .accountsPartial({
+          initializer: wallet.publicKey,
+          mintX: Zoro,
+          mintY: Zoro,
+          mintLp: badLp,
+          config: badConfig,
+          ...tokenPrograms,
+          ...ataVaults(program.programId, { config: badConfig, mintX: Zoro, mintY: Zoro, ...tokenPrograms }),
+        })
+        .rpc();
+      expect.fail("Initialize should have failed due to identical mints");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Pool mints must be different.");
+    }
+  });
+
+  it("fails initialize with the mints out of order!", async () => {
+    const badFee = 31;
+    const badConfig = poolAddress(program.programId, G5, Zoro, badFee);
+    const [badLp] = PublicKey.findProgramAddressSync([
+      Buffer.from("lp"),
+      badConfig.toBuffer(),
+    ],
+      program.programId,
+    );
+
+    try {
+      await program.methods.initialize(initializeParams(badFee, wallet.publicKey))
+        .accountsPartial({
+          initializer: wallet.publicKey,
+          mintX: G5,
+          mintY: Zoro,
+          mintLp: badLp,
+          config: badConfig,
+          ...tokenPrograms,
+          ...ataVaults(program.programId, { config: badConfig, mintX: G5, mintY: Zoro, ...tokenPrograms }),
+        })
+        .rpc();
+      expect.fail("Initialize should have failed due to the mint order");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Pool mints must be ordered with mint_x < mint_y.");
+    }
+  });
+
   it("Deposit!", async () => {
     const userZoroATA = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, Zoro, wallet.publicKey);
     const userG5ATA = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, G5, wallet.publicKey);
@@ -92,13 +457,14 @@ describe("amm", () => {
     const userZoroBefore = await getAccount(provider.connection, userZoroATA.address);
     const userG5Before = await getAccount(provider.connection, userG5ATA.address);
     const userLpBefore = await getAccount(provider.connection, userLpATA.address);
-    const tx = await program.methods.deposit(new anchor.BN(depositAmount), max_zoro, max_g5)
+    const tx = await program.methods.deposit(new anchor.BN(depositAmount), max_zoro, max_g5, new anchor.BN(0))
       .accountsPartial({
         user: wallet.publicKey,
         mintX: Zoro,
         mintY: G5,
         mintLp: lp,
         config: config,
+        ...tokenPrograms,
       })
       .rpc();
     const userZoroAfter = await getAccount(provider.connection, userZoroATA.address);
@@ -115,13 +481,14 @@ describe("amm", () => {
 
     try {
       await program.methods
-        .deposit(depositAmount, max_zoro, max_g5)
+        .deposit(depositAmount, max_zoro, max_g5, new anchor.BN(0))
         .accountsPartial({
           user: wallet.publicKey,
           mintX: Zoro,
           mintY: G5,
           mintLp: lp,
           config: config,
+          ...tokenPrograms,
         })
         .rpc();
       expect.fail("Deposit should have failed due to invalid amount");
@@ -131,11 +498,86 @@ describe("amm", () => {
     }
   });
 
+  it("fails to deposit when max amounts break the pool ratio!", async () => {
+    const depositAmount = new anchor.BN(1_000 * 1_000_000);
+
+    try {
+      await program.methods
+        .deposit(depositAmount, new anchor.BN(1), max_g5, new anchor.BN(0))
+        .accountsPartial({
+          user: wallet.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          mintLp: lp,
+          config: config,
+          ...tokenPrograms,
+        })
+        .rpc();
+      expect.fail("Deposit should have failed due to slippage");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Slippage exceeded.");
+    }
+  });
+
+  it("Deposits Zoro only and keeps at most dust!", async () => {
+    const userZoro = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const userG5 = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const userLp = await getAssociatedTokenAddress(lp, wallet.publicKey);
+
+    const zoroBefore = (await getAccount(provider.connection, userZoro)).amount;
+    const g5Before = (await getAccount(provider.connection, userG5)).amount;
+    const lpBefore = (await getAccount(provider.connection, userLp)).amount;
+
+    const amountIn = BigInt(1_000 * 1_000_000);
+    await program.methods
+      .depositSingle(true, new anchor.BN(amountIn.toString()), new anchor.BN(1))
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        mintLp: lp,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    const zoroSpent = zoroBefore - (await getAccount(provider.connection, userZoro)).amount;
+    const g5Refund = (await getAccount(provider.connection, userG5)).amount - g5Before;
+    const lpAfter = (await getAccount(provider.connection, userLp)).amount;
+
+    // what is left over is LP rounding, a few units at most
+    expect(zoroSpent <= amountIn).to.be.true;
+    expect(amountIn - zoroSpent <= BigInt(5)).to.be.true;
+    expect(g5Refund <= BigInt(1)).to.be.true;
+    expect(lpAfter > lpBefore).to.be.true;
+  });
+
+  it("fails a single-sided deposit below min lp out!", async () => {
+    try {
+      await program.methods
+        .depositSingle(true, new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000))
+        .accountsPartial({
+          user: wallet.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          mintLp: lp,
+          config: config,
+          ...tokenPrograms,
+        })
+        .rpc();
+      expect.fail("Deposit should have failed below min lp out");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Slippage exceeded.");
+    }
+  });
+
   it("Swapping Zoro to Gear5!", async () => {
     const userZoroATA = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
     const userG5ATA = await getAssociatedTokenAddress(G5, wallet.publicKey);
-    const vaultZoro = await getAssociatedTokenAddress(Zoro, config, true);
-    const vaultG5 = await getAssociatedTokenAddress(G5, config, true);
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, config), true);
+    const vaultG5 = await getAssociatedTokenAddress(G5, vaultAuthorityAddress(program.programId, config), true);
 
     const userZoroBefore = await getAccount(provider.connection, userZoroATA);
     const userG5Before = await getAccount(provider.connection, userG5ATA);
@@ -143,15 +585,16 @@ describe("amm", () => {
     const vaultG5Before = await getAccount(provider.connection, vaultG5);
 
     const amountIn = new anchor.BN(50 * 1_000_000);
-    const slippage = 500;
+    const minAmountOut = new anchor.BN(1);
 
     const tx = await program.methods
-      .swap(true, amountIn, slippage)
+      .swap(true, amountIn, minAmountOut, 0, new anchor.BN(0), 0)
       .accountsPartial({
         user: wallet.publicKey,
         mintX: Zoro,
         mintY: G5,
         config: config,
+        ...tokenPrograms,
       })
       .rpc();
 
@@ -169,8 +612,8 @@ describe("amm", () => {
   it("Swapping Gear5 to Zoro!", async () => {
     const userZoroATA = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
     const userG5ATA = await getAssociatedTokenAddress(G5, wallet.publicKey);
-    const vaultZoro = await getAssociatedTokenAddress(Zoro, config, true);
-    const vaultG5 = await getAssociatedTokenAddress(G5, config, true);
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, config), true);
+    const vaultG5 = await getAssociatedTokenAddress(G5, vaultAuthorityAddress(program.programId, config), true);
 
     const userZoroBefore = await getAccount(provider.connection, userZoroATA);
     const userG5Before = await getAccount(provider.connection, userG5ATA);
@@ -178,15 +621,16 @@ describe("amm", () => {
     const vaultG5Before = await getAccount(provider.connection, vaultG5);
 
     const amountIn = new anchor.BN(50 * 1_000_000);
-    const slippage = 500;
+    const minAmountOut = new anchor.BN(1);
 
     const tx = await program.methods
-      .swap(false, amountIn, slippage)
+      .swap(false, amountIn, minAmountOut, 0, new anchor.BN(0), 0)
       .accountsPartial({
         user: wallet.publicKey,
         mintX: Zoro,
         mintY: G5,
         config: config,
+        ...tokenPrograms,
       })
       .rpc();
 
@@ -199,6 +643,3645 @@ describe("amm", () => {
     expect(Number(userZoroAfter.amount)).to.be.greaterThan(Number(userZoroBefore.amount));
     expect(Number(vaultG5After.amount)).to.be.greaterThan(Number(vaultG5Before.amount));
     expect(Number(vaultZoroAfter.amount)).to.be.lessThan(Number(vaultZoroBefore.amount));
+    });
+
+  it("Emits a SwapEvent with post-swap reserves!", async () => {
+    const userG5ATA = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, config), true);
+    const vaultG5 = await getAssociatedTokenAddress(G5, vaultAuthorityAddress(program.programId, config), true);
+
+    const userG5Before = await getAccount(provider.connection, userG5ATA);
+    const amountIn = new anchor.BN(10 * 1_000_000);
+
+    const signature = await program.methods
+      .swap(true, amountIn, new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    // recorded by the program to itself, so truncated logs cannot drop it
+    expect([...new anchor.EventParser(program.programId, program.coder).parseLogs(tx.meta.logMessages)]).to.be.empty;
+    const swapEvent = cpiEvents(program, tx).find((event) => event.name === "swapEvent");
+    expect(swapEvent).to.not.be.undefined;
+
+    const userG5After = await getAccount(provider.connection, userG5ATA);
+    const vaultZoroAfter = await getAccount(provider.connection, vaultZoro);
+    const vaultG5After = await getAccount(provider.connection, vaultG5);
+
+    expect(swapEvent.data.user.equals(wallet.publicKey)).to.be.true;
+    expect(swapEvent.data.recipient.equals(userG5ATA)).to.be.true;
+    expect(swapEvent.data.xToY).to.be.true;
+    expect(swapEvent.data.amountIn.toString()).to.equal(amountIn.toString());
+    expect(swapEvent.data.amountOut.toString()).to.equal((userG5After.amount - userG5Before.amount).toString());
+    expect(swapEvent.data.vaultX.toString()).to.equal(vaultZoroAfter.amount.toString());
+    expect(swapEvent.data.vaultY.toString()).to.equal(vaultG5After.amount.toString());
+  });
+
+  it("Returns what swap, deposit and withdraw paid out!", async () => {
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: lp,
+      config: config,
+      ...tokenPrograms,
+    };
+    // what a calling program reads back with get_return_data after the CPI
+    const returnData = async (signature: string) => {
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      expect(tx.meta.returnData.programId).to.equal(program.programId.toBase58());
+      return Buffer.from(tx.meta.returnData.data[0], "base64");
+    };
+    const balance = async (mint: PublicKey) =>
+      (await getAccount(provider.connection, await getAssociatedTokenAddress(mint, wallet.publicKey), "confirmed")).amount;
+
+    const g5Before = await balance(G5);
+    const swapped = await returnData(await program.methods
+      .swap(true, new anchor.BN(10 * 1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial(accounts)
+      .rpc({ commitment: "confirmed" }));
+    expect(swapped.readBigUInt64LE(0)).to.equal(await balance(G5) - g5Before);
+
+    const lpBefore = await balance(lp);
+    const minted = await returnData(await program.methods
+      .deposit(new anchor.BN(1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc({ commitment: "confirmed" }));
+    expect(minted.readBigUInt64LE(0)).to.equal(await balance(lp) - lpBefore);
+
+    const [zoroBefore, g5BeforeWithdraw] = [await balance(Zoro), await balance(G5)];
+    const withdrawn = await returnData(await program.methods
+      .withdraw(new anchor.BN(1_000_000), new anchor.BN(0), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc({ commitment: "confirmed" }));
+    expect(withdrawn.readBigUInt64LE(0)).to.equal(await balance(Zoro) - zoroBefore);
+    expect(withdrawn.readBigUInt64LE(8)).to.equal(await balance(G5) - g5BeforeWithdraw);
+  });
+
+  it("Quotes exactly what the swap pays out!", async () => {
+    const userG5ATA = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const amountIn = new anchor.BN(20 * 1_000_000);
+
+    const quoted = await program.methods
+      .quote(true, amountIn)
+      .accountsPartial({
+        mintX: Zoro,
+        mintY: G5,
+        mintLp: lp,
+        config: config,
+        tokenProgramX: TOKEN_PROGRAM_ID,
+        tokenProgramY: TOKEN_PROGRAM_ID,
+      })
+      .view();
+
+    const userG5Before = await getAccount(provider.connection, userG5ATA);
+    await program.methods
+      .swap(true, amountIn, quoted, 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+    const userG5After = await getAccount(provider.connection, userG5ATA);
+
+    expect((userG5After.amount - userG5Before.amount).toString()).to.equal(quoted.toString());
+  });
+
+  it("Ignores direct vault transfers until sync!", async () => {
+    const quoteAccounts = {
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: lp,
+      config: config,
+      tokenProgramX: TOKEN_PROGRAM_ID,
+      tokenProgramY: TOKEN_PROGRAM_ID,
+    };
+    const amountIn = new anchor.BN(20 * 1_000_000);
+    const quoteBefore = await program.methods.quote(true, amountIn).accountsPartial(quoteAccounts).view();
+
+    // donate Zoro straight to the vault
+    const userZoro = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, config), true);
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(createTransferInstruction(userZoro, vaultZoro, wallet.publicKey, 5_000 * 1_000_000)),
+      [wallet.payer],
+    );
+
+    const quoteDonated = await program.methods.quote(true, amountIn).accountsPartial(quoteAccounts).view();
+    expect(quoteDonated.toString()).to.equal(quoteBefore.toString());
+
+    await program.methods
+      .sync()
+      .accountsPartial({
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        tokenProgramX: TOKEN_PROGRAM_ID,
+        tokenProgramY: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // more x in the pool makes x cheaper
+    const quoteSynced = await program.methods.quote(true, amountIn).accountsPartial(quoteAccounts).view();
+    expect(quoteSynced.lt(quoteBefore)).to.be.true;
+
+    const configAccount = await program.account.config.fetch(config);
+    const vault = await getAccount(provider.connection, vaultZoro);
+    expect(configAccount.reserveX.toString()).to.equal(vault.amount.toString());
+  });
+
+  it("Pays the referral share of the output!", async () => {
+    const referrer = Keypair.generate();
+    const referralG5 = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, G5, referrer.publicKey);
+    const userG5ATA = await getAssociatedTokenAddress(G5, wallet.publicKey);
+
+    const userG5Before = await getAccount(provider.connection, userG5ATA);
+
+    await program.methods
+      .swap(true, new anchor.BN(10 * 1_000_000), new anchor.BN(1), 100, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        referral: referralG5.address,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    const userG5After = await getAccount(provider.connection, userG5ATA);
+    const referralAfter = await getAccount(provider.connection, referralG5.address);
+
+    const userReceived = userG5After.amount - userG5Before.amount;
+    const total = userReceived + referralAfter.amount;
+    expect(referralAfter.amount).to.equal(total * BigInt(100) / BigInt(10_000));
+  });
+
+  it("fails to swap with a referral fee above the cap!", async () => {
+    try {
+      await program.methods
+        .swap(true, new anchor.BN(10 * 1_000_000), new anchor.BN(1), 501, new anchor.BN(0), 0)
+        .accountsPartial({
+          user: wallet.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          config: config,
+          ...tokenPrograms,
+        })
+        .rpc();
+      expect.fail("Swap should have failed due to the referral fee");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Referral fee exceeds the maximum.");
+    }
+  });
+
+  it("fails to swap a zero amount or for more than the pool holds!", async () => {
+    const swap = (amount: anchor.BN, minOut: anchor.BN) => program.methods
+      .swap(true, amount, minOut, 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    try {
+      await swap(new anchor.BN(0), new anchor.BN(1));
+      expect.fail("Swap should have failed due to the zero amount");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Swap input must be greater than zero.");
+      expect(e.logs.join("\n")).to.include("amount_in: 0");
+    }
+
+    const { reserveY } = await program.account.config.fetch(config);
+    try {
+      await swap(new anchor.BN(1_000_000), reserveY);
+      expect.fail("Swap should have failed due to the min amount out");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Minimum amount out exceeds what the pool holds.");
+    }
   });
 
+  it("Creates the output account of a first-time recipient in both directions!", async () => {
+    const rent = await provider.connection.getMinimumBalanceForRentExemption(165);
+
+    for (const [mintIn, mintOut, xToY] of [[Zoro, G5, true], [G5, Zoro, false]] as const) {
+      // a fresh user holding only the input token
+      const trader = Keypair.generate();
+      await sendAndConfirmTransaction(provider.connection, new Transaction().add(
+        SystemProgram.transfer({ fromPubkey: wallet.publicKey, toPubkey: trader.publicKey, lamports: 100_000_000 }),
+      ), [wallet.payer]);
+      const traderIn = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, mintIn, trader.publicKey);
+      const walletIn = await getAssociatedTokenAddress(mintIn, wallet.publicKey);
+      await sendAndConfirmTransaction(provider.connection, new Transaction().add(
+        createTransferInstruction(walletIn, traderIn.address, wallet.publicKey, 10 * 1_000_000),
+      ), [wallet.payer]);
+
+      const traderOut = await getAssociatedTokenAddress(mintOut, trader.publicKey);
+      expect(await provider.connection.getAccountInfo(traderOut)).to.be.null;
+      const lamportsBefore = await provider.connection.getBalance(trader.publicKey);
+
+      await program.methods
+        .swap(xToY, new anchor.BN(10 * 1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+        .accountsPartial({
+          user: trader.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          config: config,
+          ...tokenPrograms,
+        })
+        .signers([trader])
+        .rpc();
+
+      // the signer funds the new account
+      expect((await getAccount(provider.connection, traderOut)).amount > BigInt(0)).to.be.true;
+      const lamportsAfter = await provider.connection.getBalance(trader.publicKey);
+      expect(lamportsBefore - lamportsAfter >= rent).to.be.true;
+    }
+  });
+
+  it("Delivers the swap output to a separate recipient!", async () => {
+    const bob = Keypair.generate();
+    const bobG5 = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, G5, bob.publicKey);
+    const aliceG5 = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const aliceG5Before = (await getAccount(provider.connection, aliceG5)).amount;
+
+    const amountIn = new anchor.BN(10 * 1_000_000);
+    const pool = await program.account.config.fetch(config);
+    const expected = getAmountOut(BigInt(pool.reserveX.toString()), BigInt(pool.reserveY.toString()), BigInt(amountIn.toString()));
+
+    const signature = await program.methods
+      .swap(true, amountIn, new anchor.BN(expected.toString()), 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        recipient: bobG5.address,
+        ...tokenPrograms,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    // the whole output lands with bob, none with alice
+    const bobReceived = (await getAccount(provider.connection, bobG5.address)).amount;
+    expect(bobReceived).to.equal(expected);
+    expect((await getAccount(provider.connection, aliceG5)).amount).to.equal(aliceG5Before);
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const swapEvent = cpiEvents(program, tx).find((event) => event.name === "swapEvent");
+    expect(swapEvent.data.recipient.equals(bobG5.address)).to.be.true;
+    expect(swapEvent.data.amountOut.toString()).to.equal(bobReceived.toString());
+  });
+
+  it("fails to deliver the swap output to a recipient of the wrong mint!", async () => {
+    const bob = Keypair.generate();
+    const bobZoro = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, Zoro, bob.publicKey);
+
+    try {
+      await program.methods
+        .swap(true, new anchor.BN(10 * 1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+        .accountsPartial({
+          user: wallet.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          config: config,
+          recipient: bobZoro.address,
+          ...tokenPrograms,
+        })
+        .rpc();
+      expect.fail("Swap should have failed due to the recipient mint");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Invalid token.");
+    }
+  });
+
+  const swapBatch = (legs: { xToY: boolean, amountIn: anchor.BN, minAmountOut: anchor.BN }[]) => program.methods
+    .swapBatch(legs, new anchor.BN(0))
+    .accountsPartial({
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      config: config,
+      ...tokenPrograms,
+    });
+
+  it("Prices each leg of a swap batch against the reserves the previous one left!", async () => {
+    const userG5 = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const pool = await program.account.config.fetch(config);
+    const feeBps = BigInt(pool.fee);
+    const protocolBps = BigInt(pool.protocolFeeBps);
+
+    // replay both legs off-chain, booking the protocol share out of the input
+    let reserveX = BigInt(pool.reserveX.toString());
+    let reserveY = BigInt(pool.reserveY.toString());
+    const amountIn = BigInt(50 * 1_000_000);
+    let expected = BigInt(0);
+    for (let i = 0; i < 2; i++) {
+      const feeAmount = (amountIn * feeBps + BigInt(9_999)) / BigInt(10_000);
+      const out = reserveY * (amountIn - feeAmount) / (reserveX + amountIn - feeAmount);
+      reserveX += amountIn - feeAmount * protocolBps / BigInt(10_000);
+      reserveY -= out;
+      expected += out;
+    }
+
+    const before = (await getAccount(provider.connection, userG5)).amount;
+    const leg = { xToY: true, amountIn: new anchor.BN(amountIn.toString()), minAmountOut: new anchor.BN(1) };
+    await swapBatch([leg, leg]).rpc();
+    expect((await getAccount(provider.connection, userG5)).amount - before).to.equal(expected);
+
+    const after = await program.account.config.fetch(config);
+    expect(after.reserveX.toString()).to.equal(reserveX.toString());
+    expect(after.reserveY.toString()).to.equal(reserveY.toString());
+  });
+
+  it("Fits a full swap batch in the default compute budget!", async () => {
+    const leg = (xToY: boolean) => ({ xToY, amountIn: new anchor.BN(1_000_000), minAmountOut: new anchor.BN(1) });
+    const signature = await swapBatch([leg(true), leg(false), leg(true), leg(false)])
+      .rpc({ commitment: "confirmed" });
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    expect(tx.meta.err).to.be.null;
+    expect(tx.meta.computeUnitsConsumed).to.be.lessThan(200_000);
+  });
+
+  it("fails the whole swap batch when a later leg misses its minimum!", async () => {
+    const pool = await program.account.config.fetch(config);
+    const amountIn = new anchor.BN(50 * 1_000_000);
+    // what a lone leg would get; the second leg trades after the first and gets less
+    const quoted = getAmountOut(BigInt(pool.reserveX.toString()), BigInt(pool.reserveY.toString()), BigInt(amountIn.toString()));
+
+    try {
+      await swapBatch([
+        { xToY: true, amountIn, minAmountOut: new anchor.BN(1) },
+        { xToY: true, amountIn, minAmountOut: new anchor.BN(quoted.toString()) },
+      ]).rpc();
+      expect.fail("Batch should have failed on its second leg");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Slippage exceeded.");
+    }
+
+    // the first leg is rolled back with it
+    const after = await program.account.config.fetch(config);
+    expect(after.reserveX.toString()).to.equal(pool.reserveX.toString());
+    expect(after.reserveY.toString()).to.equal(pool.reserveY.toString());
+  });
+
+  it("fails a swap batch with more than the maximum legs!", async () => {
+    const leg = { xToY: true, amountIn: new anchor.BN(1_000_000), minAmountOut: new anchor.BN(1) };
+
+    try {
+      await swapBatch([leg, leg, leg, leg, leg]).rpc();
+      expect.fail("Batch should have failed due to the leg count");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Swap batch needs between one and MAX_SWAP_LEGS legs.");
+    }
+  });
+
+  it("Swaps as a delegate and pays the owner, within the allowance only!", async () => {
+    const keeper = Keypair.generate();
+    await sendAndConfirmTransaction(provider.connection, new Transaction().add(
+      SystemProgram.transfer({ fromPubkey: wallet.publicKey, toPubkey: keeper.publicKey, lamports: 100_000_000 }),
+    ), [wallet.payer]);
+    const ownerZoro = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const ownerG5 = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const allowance = 20 * 1_000_000;
+    await approve(provider.connection, wallet.payer, ownerZoro, keeper.publicKey, wallet.payer, allowance);
+
+    const delegateSwap = (amount: number, recipient: PublicKey) => program.methods
+      .swap(true, new anchor.BN(amount), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: keeper.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        source: ownerZoro,
+        recipient,
+        ...tokenPrograms,
+      })
+      .signers([keeper])
+      .rpc();
+
+    const zoroBefore = (await getAccount(provider.connection, ownerZoro)).amount;
+    const g5Before = (await getAccount(provider.connection, ownerG5)).amount;
+    await delegateSwap(15 * 1_000_000, ownerG5);
+    const owner = await getAccount(provider.connection, ownerZoro);
+    expect(zoroBefore - owner.amount).to.equal(BigInt(15 * 1_000_000));
+    expect(owner.delegatedAmount).to.equal(BigInt(5 * 1_000_000));
+    expect((await getAccount(provider.connection, ownerG5)).amount > g5Before).to.be.true;
+
+    // the output cannot be sent anywhere but the owner
+    const keeperG5 = await getAssociatedTokenAddress(G5, keeper.publicKey);
+    try {
+      await delegateSwap(1_000_000, keeperG5);
+      expect.fail("Delegate swap should have failed due to the recipient");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Delegated swaps pay out to the owner's associated token account.");
+    }
+
+    try {
+      await delegateSwap(10 * 1_000_000, ownerG5);
+      expect.fail("Delegate swap should have failed due to the allowance");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Swap exceeds the delegated allowance.");
+    }
+
+    await revoke(provider.connection, wallet.payer, ownerZoro, wallet.payer);
+    try {
+      await delegateSwap(1_000_000, ownerG5);
+      expect.fail("Delegate swap should have failed after the revoke");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("User is not the delegate of the source account.");
+    }
+  });
+
+  it("Never pays out more than it takes on tiny round trips!", async () => {
+    const userZoro = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const userG5 = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const swap = (xToY: boolean, amount: bigint) => program.methods
+      .swap(xToY, new anchor.BN(amount.toString()), new anchor.BN(0), 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    // the rounded-up fee swallows a single unit whole
+    try {
+      await swap(true, BigInt(1));
+      expect.fail("Swap should have failed with nothing to pay out");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Swap output rounds down to zero.");
+    }
+
+    const zoroBefore = (await getAccount(provider.connection, userZoro)).amount;
+    for (let i = 0; i < 5; i++) {
+      const g5Before = (await getAccount(provider.connection, userG5)).amount;
+      await swap(true, BigInt(1_000));
+      const bought = (await getAccount(provider.connection, userG5)).amount - g5Before;
+      await swap(false, bought);
+    }
+    const zoroAfter = (await getAccount(provider.connection, userZoro)).amount;
+    expect(zoroAfter < zoroBefore).to.be.true;
+  });
+
+  it("Accumulates the time-weighted price across swaps!", async () => {
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, config), true);
+    const vaultG5 = await getAssociatedTokenAddress(G5, vaultAuthorityAddress(program.programId, config), true);
+
+    const swap = () => program.methods
+      .swap(true, new anchor.BN(1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    await swap();
+    const before = await program.account.config.fetch(config, "confirmed");
+    const vaultZoroBefore = await getAccount(provider.connection, vaultZoro, "confirmed");
+    const vaultG5Before = await getAccount(provider.connection, vaultG5, "confirmed");
+
+    await new Promise((resolve) => setTimeout(resolve, 3_000));
+    await swap();
+    const after = await program.account.config.fetch(config, "confirmed");
+
+    const elapsed = BigInt(after.lastUpdateTs.sub(before.lastUpdateTs).toString());
+    expect(elapsed > BigInt(0)).to.be.true;
+
+    // the price over the interval is the spot price left behind by the first swap
+    const reserveX = vaultZoroBefore.amount;
+    const reserveY = vaultG5Before.amount;
+    const priceX = (reserveY << BigInt(64)) / reserveX;
+    const delta = BigInt(after.priceCumulativeX.sub(before.priceCumulativeX).toString());
+    expect(delta).to.equal(priceX * elapsed);
+  });
+
+  it("Counts volume, fees and swaps across several swaps!", async () => {
+    const swap = (xToY: boolean, amount: number) => program.methods
+      .swap(xToY, new anchor.BN(amount), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc({ commitment: "confirmed" });
+    const feeOn = (amount: number) => Math.ceil(amount * fee / 10_000);
+
+    const before = await program.account.config.fetch(config, "confirmed");
+    await swap(true, 1_000_000);
+    await swap(true, 333_333);
+    await swap(false, 2_500_000);
+    const after = await program.account.config.fetch(config, "confirmed");
+
+    // input fee mode, so every fee is charged in the mint sold
+    expect(after.cumulativeVolumeX.sub(before.cumulativeVolumeX).toNumber()).to.equal(1_333_333);
+    expect(after.cumulativeVolumeY.sub(before.cumulativeVolumeY).toNumber()).to.equal(2_500_000);
+    expect(after.cumulativeFeesX.sub(before.cumulativeFeesX).toNumber())
+      .to.equal(feeOn(1_000_000) + feeOn(333_333));
+    expect(after.cumulativeFeesY.sub(before.cumulativeFeesY).toNumber()).to.equal(feeOn(2_500_000));
+    expect(after.swapCount.sub(before.swapCount).toNumber()).to.equal(3);
+  });
+
+  it("Wraps the observation ring and reports the TWAP!", async () => {
+    const [observation] = PublicKey.findProgramAddressSync([
+      Buffer.from("observation"),
+      config.toBuffer(),
+    ],
+      program.programId,
+    );
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, config), true);
+    const vaultG5 = await getAssociatedTokenAddress(G5, vaultAuthorityAddress(program.programId, config), true);
+
+    const capacity = 32;
+    for (let i = 0; i < capacity + 2; i++) {
+      await program.methods
+        .updateObservation()
+        .accountsPartial({
+          mintX: Zoro,
+          mintY: G5,
+          config: config,
+          tokenProgramX: TOKEN_PROGRAM_ID,
+          tokenProgramY: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" });
+      await new Promise((resolve) => setTimeout(resolve, 1_100));
+    }
+
+    const state = await program.account.observationState.fetch(observation, "confirmed");
+    expect(state.count.toNumber()).to.equal(capacity);
+
+    const newest = state.observations[(state.index.toNumber() + capacity - 1) % capacity];
+    const oldest = state.observations[state.index.toNumber()];
+    expect(newest.timestamp.gt(oldest.timestamp)).to.be.true;
+
+    // no trades in between, so the TWAP is just the spot price
+    const reserveX = (await getAccount(provider.connection, vaultZoro)).amount;
+    const reserveY = (await getAccount(provider.connection, vaultG5)).amount;
+    const elapsed = BigInt(newest.timestamp.sub(oldest.timestamp).toString());
+    const twapX = BigInt(newest.priceCumulativeX.sub(oldest.priceCumulativeX).toString()) / elapsed;
+    expect(twapX).to.equal((reserveY << BigInt(64)) / reserveX);
+  });
+
+  it("fails to swap after the deadline!", async () => {
+    const deadline = new anchor.BN(Math.floor(Date.now() / 1_000) - 60);
+
+    try {
+      await program.methods
+        .swap(true, new anchor.BN(1_000_000), new anchor.BN(1), 0, deadline, 0)
+        .accountsPartial({
+          user: wallet.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          config: config,
+          ...tokenPrograms,
+        })
+        .rpc();
+      expect.fail("Swap should have failed due to the deadline");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Transaction deadline exceeded.");
+    }
+
+    await program.methods
+      .swap(true, new anchor.BN(1_000_000), new anchor.BN(1), 0, new anchor.BN(Math.floor(Date.now() / 1_000) + 60), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+  });
+
+  it("fails to lock the pool without the authority!", async () => {
+    const stranger = Keypair.generate();
+
+    try {
+      await program.methods
+        .lockPool()
+        .accountsPartial({
+          user: stranger.publicKey,
+          config: config,
+        })
+        .signers([stranger])
+        .rpc();
+      expect.fail("Lock should have failed due to invalid authority");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Signer is not the pool's authority.");
+    }
+  });
+
+  it("Locked pool rejects swaps until unlocked!", async () => {
+    await program.methods
+      .lockPool()
+      .accountsPartial({
+        user: wallet.publicKey,
+        config: config,
+      })
+      .rpc();
+
+    const configAccount = await program.account.config.fetch(config);
+    expect(configAccount.pauseFlags).to.equal(PAUSE_SWAPS | PAUSE_DEPOSITS);
+
+    try {
+      await program.methods
+        .swap(true, new anchor.BN(1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+        .accountsPartial({
+          user: wallet.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          config: config,
+          ...tokenPrograms,
+        })
+        .rpc();
+      expect.fail("Swap should have failed on a locked pool");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Swaps are paused.");
+    }
+
+    try {
+      await program.methods
+        .deposit(new anchor.BN(1_000_000), max_zoro, max_g5, new anchor.BN(0))
+        .accountsPartial({
+          user: wallet.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          mintLp: lp,
+          config: config,
+          ...tokenPrograms,
+        })
+        .rpc();
+      expect.fail("Deposit should have failed on a locked pool");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Deposits are paused.");
+    }
+
+    await program.methods
+      .unlockPool()
+      .accountsPartial({
+        user: wallet.publicKey,
+        config: config,
+      })
+      .rpc();
+
+    await program.methods
+      .swap(true, new anchor.BN(1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+  });
+
+  it("Each pause flag gates only its own instruction!", async () => {
+    const setFlags = (flags: number) =>
+      program.methods
+        .setPauseFlags(flags)
+        .accountsPartial({ user: wallet.publicKey, config: config })
+        .rpc();
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: lp,
+      config: config,
+      ...tokenPrograms,
+    };
+    const swap = () =>
+      program.methods
+        .swap(true, new anchor.BN(1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+        .accountsPartial(accounts)
+        .rpc();
+    const deposit = () =>
+      program.methods
+        .deposit(new anchor.BN(1_000_000), max_zoro, max_g5, new anchor.BN(0))
+        .accountsPartial(accounts)
+        .rpc();
+    const withdraw = () =>
+      program.methods
+        .withdraw(new anchor.BN(1_000_000), new anchor.BN(0), new anchor.BN(0))
+        .accountsPartial(accounts)
+        .rpc();
+
+    const cases: [number, () => Promise<string>, string][] = [
+      [PAUSE_SWAPS, swap, "Swaps are paused."],
+      [PAUSE_DEPOSITS, deposit, "Deposits are paused."],
+      [PAUSE_WITHDRAWALS, withdraw, "Withdrawals are halted by the emergency pause."],
+    ];
+    for (const [flag, paused, msg] of cases) {
+      await setFlags(flag);
+      try {
+        await paused();
+        expect.fail(`${msg} should have been enforced`);
+      } catch (e) {
+        expect(e).to.have.property("logs");
+        expect(e.logs.join("\n")).to.include(msg);
+      }
+      for (const [, other] of cases.filter(([f]) => f != flag)) {
+        await other();
+      }
+    }
+
+    // lock_pool never touches the emergency withdrawal flag
+    await setFlags(PAUSE_WITHDRAWALS);
+    await program.methods
+      .unlockPool()
+      .accountsPartial({ user: wallet.publicKey, config: config })
+      .rpc();
+    expect((await program.account.config.fetch(config)).pauseFlags).to.equal(PAUSE_WITHDRAWALS);
+
+    await setFlags(0);
+    await withdraw();
+  });
+
+  it("fails to set unknown pause flags!", async () => {
+    try {
+      await program.methods
+        .setPauseFlags(1 << 3)
+        .accountsPartial({ user: wallet.publicKey, config: config })
+        .rpc();
+      expect.fail("Setting unknown flags should have failed");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Unknown pause flags.");
+    }
+  });
+
+  // a Zoro/Gear5 pool at another fee tier, nothing deposited yet
+  const emptyPool = async (poolFee: number, openTime = 0, curve: any = { constantProduct: {} }) => {
+    const poolConfig = poolAddress(program.programId, Zoro, G5, poolFee);
+    const [poolLp] = PublicKey.findProgramAddressSync([
+      Buffer.from("lp"),
+      poolConfig.toBuffer(),
+    ],
+      program.programId,
+    );
+    await program.methods.initialize(initializeParams(poolFee, wallet.publicKey, { openTime: new anchor.BN(openTime), curve }))
+      .accountsPartial({
+        initializer: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        mintLp: poolLp,
+        config: poolConfig,
+        ...tokenPrograms,
+        ...ataVaults(program.programId, { config: poolConfig, mintX: Zoro, mintY: G5, ...tokenPrograms }),
+      })
+      .rpc();
+    return { config: poolConfig, lp: poolLp };
+  };
+
+  it("Timelocks a fee change on a pool with liquidity!", async () => {
+    await program.methods
+      .scheduleUpdate({ fee: { fee: 50 } })
+      .accountsPartial({ user: wallet.publicKey, config: config })
+      .rpc();
+
+    let configAccount = await program.account.config.fetch(config);
+    expect(configAccount.fee).to.equal(fee);
+    expect(configAccount.pendingChange.change.fee.fee).to.equal(50);
+    const now = Math.floor(Date.now() / 1_000);
+    expect(configAccount.pendingChange.effectiveTs.toNumber()).to.be.closeTo(now + 24 * 60 * 60, 60);
+
+    try {
+      await program.methods
+        .executeUpdate()
+        .accountsPartial({ user: wallet.publicKey, config: config })
+        .rpc();
+      expect.fail("Execute should have failed before the timelock elapsed");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Timelock has not elapsed.");
+    }
+
+    // a second schedule replaces the first
+    await program.methods
+      .scheduleUpdate({ protocolFee: { protocolFeeBps: 1_000 } })
+      .accountsPartial({ user: wallet.publicKey, config: config })
+      .rpc();
+    configAccount = await program.account.config.fetch(config);
+    expect(configAccount.pendingChange.change.fee).to.be.undefined;
+    expect(configAccount.pendingChange.change.protocolFee.protocolFeeBps).to.equal(1_000);
+    expect(configAccount.fee).to.equal(fee);
+    expect(configAccount.protocolFeeBps).to.equal(0);
+  });
+
+  it("fails to schedule a fee change without the authority!", async () => {
+    const stranger = Keypair.generate();
+
+    try {
+      await program.methods
+        .scheduleUpdate({ fee: { fee: 50 } })
+        .accountsPartial({
+          user: stranger.publicKey,
+          config: config,
+        })
+        .signers([stranger])
+        .rpc();
+      expect.fail("Fee update should have failed due to invalid authority");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Signer is not the pool's authority.");
+    }
+  });
+
+  it("fails to schedule a fee above the maximum!", async () => {
+    try {
+      await program.methods
+        .scheduleUpdate({ fee: { fee: 1_001 } })
+        .accountsPartial({
+          user: wallet.publicKey,
+          config: config,
+        })
+        .rpc();
+      expect.fail("Fee update should have failed due to invalid fee");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Fee is greater than 100%.");
+    }
+  });
+
+  it("Transfers authority in two steps!", async () => {
+    // nothing is deposited, so the proposal applies without waiting out the timelock
+    const pool = await emptyPool(5);
+    const stale = Keypair.generate();
+    const next = Keypair.generate();
+
+    await program.methods
+      .scheduleUpdate({ authority: { authority: stale.publicKey } })
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+
+    // a newer proposal overwrites the stale one
+    await program.methods
+      .scheduleUpdate({ authority: { authority: next.publicKey } })
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+
+    try {
+      await program.methods
+        .acceptAuthority()
+        .accountsPartial({ user: stale.publicKey, config: pool.config })
+        .signers([stale])
+        .rpc();
+      expect.fail("Stale proposal should not be acceptable");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Invalid update authority.");
+    }
+
+    await program.methods
+      .acceptAuthority()
+      .accountsPartial({ user: next.publicKey, config: pool.config })
+      .signers([next])
+      .rpc();
+
+    const configAccount = await program.account.config.fetch(pool.config);
+    expect(configAccount.authority.equals(next.publicKey)).to.be.true;
+    expect(configAccount.pendingAuthority).to.be.null;
+    expect(configAccount.pendingChange).to.be.null;
+  });
+
+  it("Collects protocol fees to the configured fee recipient only!", async () => {
+    const pool = await emptyPool(40);
+    const treasury = Keypair.generate();
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+
+    try {
+      const stranger = Keypair.generate();
+      await program.methods
+        .setFeeRecipient(stranger.publicKey)
+        .accountsPartial({ user: stranger.publicKey, config: pool.config })
+        .signers([stranger])
+        .rpc();
+      expect.fail("Only the authority should set the fee recipient");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Signer is not the pool's authority.");
+    }
+
+    const signature = await program.methods
+      .setFeeRecipient(treasury.publicKey)
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc({ commitment: "confirmed" });
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+    const event = [...parser.parseLogs(tx.meta.logMessages)].find((event) => event.name === "feeRecipientUpdated");
+    expect(event.data.old.equals(wallet.publicKey)).to.be.true;
+    expect(event.data.new.equals(treasury.publicKey)).to.be.true;
+    expect((await program.account.config.fetch(pool.config)).feeRecipient.equals(treasury.publicKey)).to.be.true;
+
+    await program.methods
+      .scheduleUpdate({ protocolFee: { protocolFeeBps: 2_000 } })
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+    await program.methods
+      .swap(true, new anchor.BN(100 * 1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial(accounts)
+      .rpc();
+    const feesX = (await getAccount(provider.connection, feeVaultAddress(program.programId, pool.config, "x"))).amount;
+    expect(feesX > BigInt(0)).to.be.true;
+
+    const collect = (feeRecipient: PublicKey, recipientX?: PublicKey, cranker = wallet.payer) => program.methods
+      .collectProtocolFees()
+      .accountsPartial({
+        user: cranker.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        feeRecipient,
+        config: pool.config,
+        ...(recipientX ? { recipientX } : {}),
+        tokenProgramX: TOKEN_PROGRAM_ID,
+        tokenProgramY: TOKEN_PROGRAM_ID,
+      })
+      .signers([cranker])
+      .rpc();
+
+    // the authority can no longer send the fees to itself
+    try {
+      await collect(wallet.publicKey);
+      expect.fail("Collecting to the old recipient should fail");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("ConstraintHasOne");
+    }
+    // nor name the treasury and slip in its own token account
+    try {
+      await collect(treasury.publicKey, await getAssociatedTokenAddress(Zoro, wallet.publicKey));
+      expect.fail("Collecting to a forged destination should fail");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("ConstraintAssociated");
+    }
+
+    // anyone can crank it, paying for the treasury's token accounts
+    const cranker = Keypair.generate();
+    await sendAndConfirmTransaction(provider.connection, new Transaction().add(
+      SystemProgram.transfer({ fromPubkey: wallet.publicKey, toPubkey: cranker.publicKey, lamports: 100_000_000 }),
+    ), [wallet.payer]);
+    await collect(treasury.publicKey, undefined, cranker);
+    const treasuryZoro = await getAccount(provider.connection, await getAssociatedTokenAddress(Zoro, treasury.publicKey));
+    expect(treasuryZoro.amount).to.equal(feesX);
+  });
+
+  it("Lets only allowlisted wallets swap in a permissioned pool!", async () => {
+    const pool = await emptyPool(45);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    const [allowlist] = PublicKey.findProgramAddressSync([
+      Buffer.from("allowlist"),
+      pool.config.toBuffer(),
+    ],
+      program.programId,
+    );
+    await program.methods
+      .deposit(new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    const swap = (withAllowlist: boolean) => program.methods
+      .swap(true, new anchor.BN(1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({ ...accounts, allowlist: withAllowlist ? allowlist : null })
+      .rpc();
+    const expectRejected = async (withAllowlist: boolean) => {
+      try {
+        await swap(withAllowlist);
+        expect.fail("Swap should have been rejected");
+      } catch (e) {
+        expect(e).to.have.property("logs");
+        expect(e.logs.join("\n")).to.include("User is not on the pool's allowlist.");
+      }
+    };
+    const allowlistSize = async () => (await provider.connection.getAccountInfo(allowlist)).data.length;
+
+    // open to everyone until an allowlist is created
+    await swap(false);
+    await program.methods
+      .createAllowlist()
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    expect((await program.account.config.fetch(pool.config)).allowlist.equals(allowlist)).to.be.true;
+    await expectRejected(false);
+    await expectRejected(true);
+
+    try {
+      const stranger = Keypair.generate();
+      await program.methods
+        .addToAllowlist(stranger.publicKey)
+        .accountsPartial({ user: stranger.publicKey, config: pool.config })
+        .signers([stranger])
+        .rpc();
+      expect.fail("Only the authority should manage the allowlist");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Signer is not the pool's authority.");
+    }
+
+    // every member grows the account by one key
+    const emptySize = await allowlistSize();
+    for (const member of [Keypair.generate().publicKey, wallet.publicKey, Keypair.generate().publicKey]) {
+      await program.methods
+        .addToAllowlist(member)
+        .accountsPartial({ user: wallet.publicKey, config: pool.config })
+        .rpc();
+    }
+    expect(await allowlistSize()).to.equal(emptySize + 3 * 32);
+    await swap(true);
+    // the allowlist is still required, not just membership
+    await expectRejected(false);
+
+    try {
+      await program.methods
+        .addToAllowlist(wallet.publicKey)
+        .accountsPartial({ user: wallet.publicKey, config: pool.config })
+        .rpc();
+      expect.fail("Adding a member twice should fail");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("User is already on the pool's allowlist.");
+    }
+
+    await program.methods
+      .removeFromAllowlist(wallet.publicKey)
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    expect(await allowlistSize()).to.equal(emptySize + 2 * 32);
+    const members = (await program.account.allowlist.fetch(allowlist)).members;
+    expect(members.some((member) => member.equals(wallet.publicKey))).to.be.false;
+    await expectRejected(true);
+  });
+
+  it("Caps the price impact of a single swap!", async () => {
+    const poolFee = 50;
+    const pool = await emptyPool(poolFee);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    // applied right away on a pool without liquidity
+    await program.methods
+      .scheduleUpdate({ maxPriceImpact: { maxPriceImpactBps: 100 } })
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    // 100 bps of the 1000 token reserve is 10 tokens priced after the 50 bps fee,
+    // which 10.050252 tokens in come to exactly
+    const limit = 10_050_252;
+    const priced = (amount: number) => amount - Math.ceil(amount * poolFee / 10_000);
+    expect(priced(limit)).to.equal(10_000_000);
+    expect(priced(limit + 1)).to.equal(10_000_001);
+
+    const swap = (amount: number, maxPriceImpactBps: number) => program.methods
+      .swap(true, new anchor.BN(amount), new anchor.BN(1), 0, new anchor.BN(0), maxPriceImpactBps)
+      .accountsPartial(accounts)
+      .rpc();
+    const expectTooHigh = async (amount: number, maxPriceImpactBps: number) => {
+      try {
+        await swap(amount, maxPriceImpactBps);
+        expect.fail("Swap should exceed the price impact limit");
+      } catch (e) {
+        expect(e).to.have.property("logs");
+        expect(e.logs.join("\n")).to.include("Swap moves the price more than the pool allows.");
+      }
+    };
+
+    await expectTooHigh(limit + 1, 0);
+    // a looser limit of the caller does not override the pool's
+    await expectTooHigh(limit + 1, 200);
+    // a tighter one applies
+    await expectTooHigh(limit, 99);
+    await swap(limit, 100);
+  });
+
+  it("Opens a pool for trading only at its open time!", async () => {
+    const now = await provider.connection.getBlockTime(await provider.connection.getSlot());
+    const openTime = now + 6;
+    const pool = await emptyPool(55, openTime);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    const swap = () => program.methods
+      .swap(true, new anchor.BN(1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial(accounts)
+      .rpc();
+    const setOpenTime = (time: number) => program.methods
+      .setOpenTime(new anchor.BN(time))
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+
+    // liquidity can be staged ahead of the start
+    await program.methods
+      .deposit(new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+    try {
+      await swap();
+      expect.fail("Swap before the open time should fail");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Pool is not open for trading yet.");
+    }
+
+    try {
+      await setOpenTime(openTime + 60);
+      expect.fail("Open time should not move later");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Open time can only be moved earlier.");
+    }
+    await setOpenTime(openTime - 3);
+    expect((await program.account.config.fetch(pool.config)).openTime.toNumber()).to.equal(openTime - 3);
+
+    // wait out the moved start
+    while (await provider.connection.getBlockTime(await provider.connection.getSlot()) < openTime - 3) {
+      await new Promise((resolve) => setTimeout(resolve, 500));
+    }
+    await swap();
+  });
+
+  it("Accrues and collects protocol fees!", async () => {
+    const poolFee = 25;
+    const pool = await emptyPool(poolFee);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+
+    await program.methods
+      .scheduleUpdate({ protocolFee: { protocolFeeBps: 2_000 } })
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(10_000 * 1_000_000), new anchor.BN(10_000 * 1_000_000), new anchor.BN(10_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    const amountIn = new anchor.BN(100 * 1_000_000);
+    for (const xToY of [true, true, false]) {
+      await program.methods
+        .swap(xToY, amountIn, new anchor.BN(1), 0, new anchor.BN(0), 0)
+        .accountsPartial(accounts)
+        .rpc();
+    }
+
+    // 25 bps of each swap is the fee, 20% of that goes straight to the fee vaults
+    const expectedPerSwap = (100 * 1_000_000 * poolFee / 10_000) * 2_000 / 10_000;
+    const feeVaultX = feeVaultAddress(program.programId, pool.config, "x");
+    const feeVaultY = feeVaultAddress(program.programId, pool.config, "y");
+    const feesX = Number((await getAccount(provider.connection, feeVaultX)).amount);
+    const feesY = Number((await getAccount(provider.connection, feeVaultY)).amount);
+    expect(feesX).to.be.closeTo(2 * expectedPerSwap, 2);
+    expect(feesY).to.be.closeTo(expectedPerSwap, 1);
+
+    // and the main vaults hold exactly the reserves
+    const configAccount = await program.account.config.fetch(pool.config);
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, pool.config), true);
+    expect((await getAccount(provider.connection, vaultZoro)).amount.toString()).to.equal(configAccount.reserveX.toString());
+
+    const recipientZoro = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const recipientZoroBefore = await getAccount(provider.connection, recipientZoro);
+
+    await program.methods
+      .collectProtocolFees()
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        feeRecipient: wallet.publicKey,
+        config: pool.config,
+        tokenProgramX: TOKEN_PROGRAM_ID,
+        tokenProgramY: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const recipientZoroAfter = await getAccount(provider.connection, recipientZoro);
+    expect(Number(recipientZoroAfter.amount - recipientZoroBefore.amount)).to.equal(feesX);
+    expect(Number((await getAccount(provider.connection, feeVaultX)).amount)).to.equal(0);
+    expect(Number((await getAccount(provider.connection, feeVaultY)).amount)).to.equal(0);
+  });
+
+  const flashSwap = async (amountX: bigint, amountY: bigint, repayX: bigint, repayY: bigint, end = true) => {
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      config: config,
+      tokenProgramX: TOKEN_PROGRAM_ID,
+      tokenProgramY: TOKEN_PROGRAM_ID,
+    };
+    const userZoro = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const userG5 = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, config), true);
+    const vaultG5 = await getAssociatedTokenAddress(G5, vaultAuthorityAddress(program.programId, config), true);
+
+    const tx = new Transaction().add(
+      await program.methods
+        .beginFlashSwap(new anchor.BN(amountX.toString()), new anchor.BN(amountY.toString()))
+        .accountsPartial({ ...accounts, instructions: SYSVAR_INSTRUCTIONS_PUBKEY })
+        .instruction()
+    );
+    if (repayX > BigInt(0)) tx.add(createTransferInstruction(userZoro, vaultZoro, wallet.publicKey, repayX));
+    if (repayY > BigInt(0)) tx.add(createTransferInstruction(userG5, vaultG5, wallet.publicKey, repayY));
+    if (end) tx.add(await program.methods.endFlashSwap().accountsPartial(accounts).instruction());
+    return provider.sendAndConfirm(tx);
+  };
+
+  const poolK = async () => {
+    const vaultZoro = await getAccount(provider.connection, await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, config), true));
+    const vaultG5 = await getAccount(provider.connection, await getAssociatedTokenAddress(G5, vaultAuthorityAddress(program.programId, config), true));
+    return { x: vaultZoro.amount, y: vaultG5.amount, k: vaultZoro.amount * vaultG5.amount };
+  };
+
+  it("Flash borrows Gear5 and repays it with the fee!", async () => {
+    const before = await poolK();
+    const amountY = BigInt(1_000 * 1_000_000);
+
+    await flashSwap(BigInt(0), amountY, BigInt(0), amountY * BigInt(10_000) / BigInt(10_000 - fee) + BigInt(2));
+
+    const after = await poolK();
+    expect(after.k >= before.k).to.be.true;
+    expect(after.y > before.y).to.be.true;
+
+    const [flash] = PublicKey.findProgramAddressSync([Buffer.from("flash"), config.toBuffer()], program.programId);
+    expect(await program.account.flashSwapState.fetchNullable(flash)).to.be.null;
+    expect((await program.account.config.fetch(config)).pauseFlags).to.equal(0);
+  });
+
+  it("Flash borrows Gear5 and repays in Zoro!", async () => {
+    const before = await poolK();
+    const amountY = BigInt(1_000 * 1_000_000);
+
+    // x needed to keep k, grossed up for the fee
+    const inAfterFee = before.x * amountY / (before.y - amountY) + BigInt(1);
+    await flashSwap(BigInt(0), amountY, inAfterFee * BigInt(10_000) / BigInt(10_000 - fee) + BigInt(2), BigInt(0));
+
+    const after = await poolK();
+    expect(after.k >= before.k).to.be.true;
+    expect(after.x > before.x).to.be.true;
+    expect(after.y).to.equal(before.y - amountY);
+  });
+
+  it("fails to end a flash swap without paying the fee!", async () => {
+    const amountY = BigInt(1_000 * 1_000_000);
+
+    try {
+      await flashSwap(BigInt(0), amountY, BigInt(0), amountY);
+      expect.fail("Flash swap should have failed without the fee");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Flash swap was not repaid.");
+    }
+  });
+
+  it("fails to begin a flash swap without ending it!", async () => {
+    try {
+      await flashSwap(BigInt(0), BigInt(1_000 * 1_000_000), BigInt(0), BigInt(0), false);
+      expect.fail("Flash swap should have failed without end_flash_swap");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Flash swap must be closed by end_flash_swap in the same transaction.");
+    }
+  });
+
+  it("Swapping Zoro for an exact amount of Gear5!", async () => {
+    const userG5ATA = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, config), true);
+    const vaultG5 = await getAssociatedTokenAddress(G5, vaultAuthorityAddress(program.programId, config), true);
+
+    const userG5Before = await getAccount(provider.connection, userG5ATA);
+    const vaultZoroBefore = await getAccount(provider.connection, vaultZoro);
+    const vaultG5Before = await getAccount(provider.connection, vaultG5);
+
+    const amountOut = new anchor.BN(25 * 1_000_000);
+
+    await program.methods
+      .swapExactOut(true, amountOut, new anchor.BN(100 * 1_000_000), new anchor.BN(0))
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    const userG5After = await getAccount(provider.connection, userG5ATA);
+    const vaultZoroAfter = await getAccount(provider.connection, vaultZoro);
+    const vaultG5After = await getAccount(provider.connection, vaultG5);
+
+    const kBefore = vaultZoroBefore.amount * vaultG5Before.amount;
+    const kAfter = vaultZoroAfter.amount * vaultG5After.amount;
+
+    expect(kAfter >= kBefore).to.be.true;
+    expect(userG5After.amount - userG5Before.amount >= BigInt(amountOut.toString())).to.be.true;
+  });
+
+  it("fails to swap exact out above max amount in!", async () => {
+    try {
+      await program.methods
+        .swapExactOut(true, new anchor.BN(25 * 1_000_000), new anchor.BN(1), new anchor.BN(0))
+        .accountsPartial({
+          user: wallet.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          config: config,
+          ...tokenPrograms,
+        })
+        .rpc();
+      expect.fail("Swap should have failed due to slippage");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Slippage exceeded.");
+      // the input the output costs is logged next to the limit it broke
+      expect(e.logs.join("\n")).to.match(/amount_out: 25000000, amount_in: \d+, max_amount_in: 1\b/);
+    }
+  });
+
+  it("fails to swap when reserves shift between quote and execution!", async () => {
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, config), true);
+    const vaultG5 = await getAssociatedTokenAddress(G5, vaultAuthorityAddress(program.programId, config), true);
+
+    const vaultZoroBefore = await getAccount(provider.connection, vaultZoro);
+    const vaultG5Before = await getAccount(provider.connection, vaultG5);
+
+    const amountIn = new anchor.BN(100 * 1_000_000);
+    const quoted = getAmountOut(vaultZoroBefore.amount, vaultG5Before.amount, BigInt(amountIn.toString()));
+
+    // front-run in the same direction, moving the price against the quote
+    await program.methods
+      .swap(true, new anchor.BN(5_000 * 1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    try {
+      await program.methods
+        .swap(true, amountIn, new anchor.BN(quoted.toString()), 0, new anchor.BN(0), 0)
+        .accountsPartial({
+          user: wallet.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          config: config,
+          ...tokenPrograms,
+        })
+        .rpc();
+      expect.fail("Swap should have failed due to slippage");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Slippage exceeded.");
+      expect(e.logs.join("\n")).to.match(new RegExp(`amount_in: ${amountIn}, amount_out: \\d+, min_amount_out: ${quoted}\\b`));
+    }
+  });
+
+  it("fails to swap more than the user holds, logging the balance!", async () => {
+    const trader = Keypair.generate();
+    await sendAndConfirmTransaction(provider.connection, new Transaction().add(
+      SystemProgram.transfer({ fromPubkey: wallet.publicKey, toPubkey: trader.publicKey, lamports: 100_000_000 }),
+    ), [wallet.payer]);
+
+    try {
+      await program.methods
+        .swap(true, new anchor.BN(10 * 1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+        .accountsPartial({
+          user: trader.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          config: config,
+          ...tokenPrograms,
+        })
+        .signers([trader])
+        .rpc();
+      expect.fail("Swap should have failed due to the empty input account");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Insufficient balance.");
+      expect(e.logs.join("\n")).to.include("amount_in: 10000000, balance: 0");
+    }
+  });
+
+  // the share of the reserves `amount` LP withdraws, rounded down like the program
+  const withdrawShare = async (amount: anchor.BN) => {
+    const pool = await program.account.config.fetch(config);
+    const supply = new anchor.BN((await getMint(provider.connection, lp)).supply.toString());
+    return {
+      x: pool.reserveX.mul(amount).div(supply),
+      y: pool.reserveY.mul(amount).div(supply),
+    };
+  };
+
+  const withdrawWithMins = (amount: anchor.BN, minX: anchor.BN, minY: anchor.BN) =>
+    program.methods
+      .withdraw(amount, minX, minY)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        mintLp: lp,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+  it("fails to withdraw below min x!", async () => {
+    const withdrawAmount = new anchor.BN(1_000 * 1_000_000);
+    const share = await withdrawShare(withdrawAmount);
+
+    try {
+      await withdrawWithMins(withdrawAmount, share.x.addn(1), new anchor.BN(0));
+      expect.fail("Withdraw should have failed below min x");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Withdrawal below the minimum amounts.");
+    }
+  });
+
+  it("fails to withdraw below min y!", async () => {
+    const withdrawAmount = new anchor.BN(1_000 * 1_000_000);
+    const share = await withdrawShare(withdrawAmount);
+
+    try {
+      await withdrawWithMins(withdrawAmount, new anchor.BN(0), share.y.addn(1));
+      expect.fail("Withdraw should have failed below min y");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Withdrawal below the minimum amounts.");
+    }
+  });
+
+  it("Withdraws exactly the min amounts!", async () => {
+    const userZoroATA = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const userG5ATA = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const userZoroBefore = await getAccount(provider.connection, userZoroATA);
+    const userG5Before = await getAccount(provider.connection, userG5ATA);
+
+    const withdrawAmount = new anchor.BN(100 * 1_000_000);
+    const share = await withdrawShare(withdrawAmount);
+    await withdrawWithMins(withdrawAmount, share.x, share.y);
+
+    const userZoroAfter = await getAccount(provider.connection, userZoroATA);
+    const userG5After = await getAccount(provider.connection, userG5ATA);
+    expect((userZoroAfter.amount - userZoroBefore.amount).toString()).to.equal(share.x.toString());
+    expect((userG5After.amount - userG5Before.amount).toString()).to.equal(share.y.toString());
+  });
+
+  it("Partial withdraw!", async () => {
+    const userZoroATA = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const userG5ATA = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const userLpATA = await getAssociatedTokenAddress(lp, wallet.publicKey);
+
+    const userZoroBefore = await getAccount(provider.connection, userZoroATA);
+    const userG5Before = await getAccount(provider.connection, userG5ATA);
+    const userLpBefore = await getAccount(provider.connection, userLpATA);
+
+    const withdrawAmount = new anchor.BN(1_000 * 1_000_000);
+
+    await program.methods
+      .withdraw(withdrawAmount, new anchor.BN(0), new anchor.BN(0))
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        mintLp: lp,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    const userZoroAfter = await getAccount(provider.connection, userZoroATA);
+    const userG5After = await getAccount(provider.connection, userG5ATA);
+    const userLpAfter = await getAccount(provider.connection, userLpATA);
+
+    expect(Number(userZoroAfter.amount)).to.be.greaterThan(Number(userZoroBefore.amount));
+    expect(Number(userG5After.amount)).to.be.greaterThan(Number(userG5Before.amount));
+    expect(Number(userLpBefore.amount) - Number(userLpAfter.amount)).to.equal(withdrawAmount.toNumber());
+  });
+
+  it("Full withdraw!", async () => {
+    const userLpATA = await getAssociatedTokenAddress(lp, wallet.publicKey);
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, config), true);
+    const vaultG5 = await getAssociatedTokenAddress(G5, vaultAuthorityAddress(program.programId, config), true);
+
+    const userLpBefore = await getAccount(provider.connection, userLpATA);
+
+    await program.methods
+      .withdraw(new anchor.BN(userLpBefore.amount.toString()), new anchor.BN(0), new anchor.BN(0))
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        mintLp: lp,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    const userLpAfter = await getAccount(provider.connection, userLpATA);
+    const vaultZoroAfter = await getAccount(provider.connection, vaultZoro);
+    const vaultG5After = await getAccount(provider.connection, vaultG5);
+
+    expect(Number(userLpAfter.amount)).to.equal(0);
+    // the minimum liquidity locked by the first deposit stays in the pool
+    const lpMint = await getMint(provider.connection, lp);
+    expect(Number(lpMint.supply)).to.equal(1_000);
+    expect(Number(vaultZoroAfter.amount)).to.be.greaterThan(0);
+    expect(Number(vaultG5After.amount)).to.be.greaterThan(0);
+  });
+
+  it("Renounces authority permanently!", async () => {
+    await program.methods
+      .renounceAuthority()
+      .accountsPartial({ user: wallet.publicKey, config: config })
+      .rpc();
+
+    const configAccount = await program.account.config.fetch(config);
+    expect(configAccount.authority).to.be.null;
+    expect(configAccount.pendingAuthority).to.be.null;
+    expect(configAccount.pendingChange).to.be.null;
+
+    try {
+      await program.methods
+        .lockPool()
+        .accountsPartial({ user: wallet.publicKey, config: config })
+        .rpc();
+      expect.fail("Lock should have failed without an authority");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("No update authority set.");
+    }
+  });
+
+  it("Skims a donation to the fee recipient without touching the reserves!", async () => {
+    const pool = await emptyPool(60);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    await program.methods
+      .deposit(new anchor.BN(100 * 1_000_000), new anchor.BN(100 * 1_000_000), new anchor.BN(100 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    const userZoro = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, pool.config), true);
+    const donation = 7 * 1_000_000;
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(createTransferInstruction(userZoro, vaultZoro, wallet.publicKey, donation)),
+      [wallet.payer],
+    );
+
+    const skim = () => program.methods
+      .skim()
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        feeRecipient: wallet.publicKey,
+        config: pool.config,
+        tokenProgramX: TOKEN_PROGRAM_ID,
+        tokenProgramY: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // the fee recipient is the wallet, so the donation comes straight back
+    const before = await program.account.config.fetch(pool.config);
+    const userBefore = (await getAccount(provider.connection, userZoro)).amount;
+    await skim();
+    const after = await program.account.config.fetch(pool.config);
+    const userAfter = (await getAccount(provider.connection, userZoro)).amount;
+    expect((userAfter - userBefore).toString()).to.equal(donation.toString());
+    expect(after.reserveX.toString()).to.equal(before.reserveX.toString());
+    expect(after.reserveY.toString()).to.equal(before.reserveY.toString());
+    const vault = await getAccount(provider.connection, vaultZoro);
+    expect(vault.amount.toString()).to.equal(after.reserveX.toString());
+
+    // nothing left to skim is not an error
+    await skim();
+    expect((await getAccount(provider.connection, userZoro)).amount.toString()).to.equal(userAfter.toString());
+  });
+
+  it("Skims a donation into the reserves when the pool gives it to LPs!", async () => {
+    const pool = await emptyPool(65);
+    await program.methods
+      .scheduleUpdate({ skimToLps: { skimToLps: true } })
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(100 * 1_000_000), new anchor.BN(100 * 1_000_000), new anchor.BN(100 * 1_000_000), new anchor.BN(0))
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        mintLp: pool.lp,
+        config: pool.config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    const userZoro = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const vaultZoro = await getAssociatedTokenAddress(Zoro, vaultAuthorityAddress(program.programId, pool.config), true);
+    const donation = 7 * 1_000_000;
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(createTransferInstruction(userZoro, vaultZoro, wallet.publicKey, donation)),
+      [wallet.payer],
+    );
+
+    const before = await program.account.config.fetch(pool.config);
+    await program.methods
+      .skim()
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        feeRecipient: wallet.publicKey,
+        config: pool.config,
+        tokenProgramX: TOKEN_PROGRAM_ID,
+        tokenProgramY: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+    const after = await program.account.config.fetch(pool.config);
+    expect(after.skimToLps).to.be.true;
+    expect(after.reserveX.sub(before.reserveX).toNumber()).to.equal(donation);
+    expect(after.reserveY.toString()).to.equal(before.reserveY.toString());
+  });
+
+  it("Bootstraps a pool with liquidity in one instruction!", async () => {
+    const poolFee = 70;
+    const poolConfig = poolAddress(program.programId, Zoro, G5, poolFee);
+    const [poolLp] = PublicKey.findProgramAddressSync([
+      Buffer.from("lp"),
+      poolConfig.toBuffer(),
+    ],
+      program.programId,
+    );
+    const amountX = BigInt(1_000 * 1_000_000);
+    const amountY = BigInt(4_000 * 1_000_000);
+    const signature = await program.methods
+      .initializeWithLiquidity(initializeParams(poolFee, wallet.publicKey), new anchor.BN(amountX.toString()), new anchor.BN(amountY.toString()), new anchor.BN(0))
+      .accountsPartial({
+        // no creator lock
+        lockPosition: null,
+        lockPositionLp: null,
+        pool: {
+          initializer: wallet.publicKey,
+          mintX: Zoro,
+          mintY: G5,
+          mintLp: poolLp,
+          config: poolConfig,
+          ...tokenPrograms,
+          ...ataVaults(program.programId, { config: poolConfig, mintX: Zoro, mintY: G5, ...tokenPrograms }),
+        },
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    expect(tx.meta.err).to.be.null;
+    expect(tx.meta.computeUnitsConsumed).to.be.lessThan(200_000);
+
+    // sqrt(1000 * 4000) tokens of LP, less the locked minimum
+    const configAccount = await program.account.config.fetch(poolConfig);
+    expect(configAccount.reserveX.toString()).to.equal(amountX.toString());
+    expect(configAccount.reserveY.toString()).to.equal(amountY.toString());
+    const userLp = await getAccount(provider.connection, await getAssociatedTokenAddress(poolLp, wallet.publicKey));
+    expect(userLp.amount.toString()).to.equal((BigInt(2_000 * 1_000_000) - BigInt(1_000)).toString());
+
+    // swappable right away at the seeded 1 : 4 price
+    const amountIn = BigInt(10 * 1_000_000);
+    const priced = amountIn - (amountIn * BigInt(poolFee) + BigInt(9_999)) / BigInt(10_000);
+    const expected = priced * amountY / (amountX + priced);
+    const userG5 = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const before = (await getAccount(provider.connection, userG5)).amount;
+    await program.methods
+      .swap(true, new anchor.BN(amountIn.toString()), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX: Zoro,
+        mintY: G5,
+        mintLp: poolLp,
+        config: poolConfig,
+        ...tokenPrograms,
+      })
+      .rpc();
+    expect(((await getAccount(provider.connection, userG5)).amount - before).toString()).to.equal(expected.toString());
+  });
+
+  it("Pulls only what a skewed deposit is worth at the pool ratio!", async () => {
+    const pool = await emptyPool(75);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    const userZoro = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const userG5 = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const balances = async () => [
+      (await getAccount(provider.connection, userZoro)).amount,
+      (await getAccount(provider.connection, userG5)).amount,
+    ];
+    const depositAmounts = (amountX: number, amountY: number) => program.methods
+      .depositAmounts(new anchor.BN(amountX), new anchor.BN(amountY), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    // the first deposit takes both sides in full and sets a 1 : 2 ratio
+    let [x, y] = await balances();
+    await depositAmounts(100 * 1_000_000, 200 * 1_000_000);
+    let [xAfter, yAfter] = await balances();
+    expect((x - xAfter).toString()).to.equal((100 * 1_000_000).toString());
+    expect((y - yAfter).toString()).to.equal((200 * 1_000_000).toString());
+
+    // 10 x only backs 20 y, the other 980 y stay in the wallet
+    [x, y] = [xAfter, yAfter];
+    await depositAmounts(10 * 1_000_000, 1_000 * 1_000_000);
+    [xAfter, yAfter] = await balances();
+    const paidX = x - xAfter;
+    const paidY = y - yAfter;
+    expect(Number(paidX)).to.be.within(10 * 1_000_000 - 1, 10 * 1_000_000);
+    expect(Number(paidY)).to.be.within(20 * 1_000_000 - 2, 20 * 1_000_000);
+
+    // nothing beyond what was paid reached the pool
+    const configAccount = await program.account.config.fetch(pool.config);
+    expect(configAccount.reserveX.toString()).to.equal((BigInt(100 * 1_000_000) + paidX).toString());
+    expect(configAccount.reserveY.toString()).to.equal((BigInt(200 * 1_000_000) + paidY).toString());
+
+    // and the other way round
+    [x, y] = [xAfter, yAfter];
+    await depositAmounts(1_000 * 1_000_000, 20 * 1_000_000);
+    [xAfter, yAfter] = await balances();
+    expect(Number(x - xAfter)).to.be.within(10 * 1_000_000 - 1, 10 * 1_000_000);
+    expect(Number(y - yAfter)).to.be.within(20 * 1_000_000 - 2, 20 * 1_000_000);
+  });
+
+  it("Withdraws everything and closes the LP account, even with a delegate approved!", async () => {
+    const pool = await emptyPool(80);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    await program.methods
+      .deposit(new anchor.BN(100 * 1_000_000), new anchor.BN(100 * 1_000_000), new anchor.BN(100 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    // a delegate approved for part of the LP does not keep the account open
+    const userLp = await getAssociatedTokenAddress(pool.lp, wallet.publicKey);
+    const lp = (await getAccount(provider.connection, userLp)).amount;
+    await approve(provider.connection, wallet.payer, userLp, Keypair.generate().publicKey, wallet.payer, lp / BigInt(2));
+
+    const userZoro = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const zoroBefore = (await getAccount(provider.connection, userZoro)).amount;
+    await program.methods
+      .withdrawAll(new anchor.BN(0), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(userLp)).to.be.null;
+    // everything but the locked minimum liquidity comes back
+    const configAccount = await program.account.config.fetch(pool.config);
+    const zoroAfter = (await getAccount(provider.connection, userZoro)).amount;
+    expect((zoroAfter - zoroBefore + BigInt(configAccount.reserveX.toString())).toString()).to.equal((100 * 1_000_000).toString());
+    expect((await getMint(provider.connection, pool.lp)).supply.toString()).to.equal("1000");
+
+    // with the account gone there is nothing left to withdraw
+    try {
+      await program.methods
+        .withdrawAll(new anchor.BN(0), new anchor.BN(0))
+        .accountsPartial(accounts)
+        .rpc();
+      expect.fail("withdraw_all should have failed without an LP account");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("AccountNotInitialized");
+    }
+  });
+
+  it("Prices a stable pool flatter than constant product around the peg!", async () => {
+    const poolFee = 85;
+    const pool = await emptyPool(poolFee, 0, { stable: { amp: new anchor.BN(100) } });
+    expect((await program.account.config.fetch(pool.config)).curve.stable.amp.toNumber()).to.equal(100);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    const reserve = BigInt(1_000 * 1_000_000);
+    await program.methods
+      .deposit(new anchor.BN(reserve.toString()), new anchor.BN(reserve.toString()), new anchor.BN(reserve.toString()), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    // what a constant product pool with the same reserves and fee pays
+    const amountIn = BigInt(50 * 1_000_000);
+    const priced = amountIn - (amountIn * BigInt(poolFee) + BigInt(9_999)) / BigInt(10_000);
+    const constantProduct = priced * reserve / (reserve + priced);
+
+    const quote = await program.methods
+      .quote(true, new anchor.BN(amountIn.toString()))
+      .accountsPartial({ mintX: Zoro, mintY: G5, mintLp: pool.lp, config: pool.config, tokenProgramX: TOKEN_PROGRAM_ID, tokenProgramY: TOKEN_PROGRAM_ID })
+      .view();
+    expect(BigInt(quote.toString()) > constantProduct).to.be.true;
+    // and close to 1 : 1, less the fee
+    expect(BigInt(quote.toString()) > priced * BigInt(99) / BigInt(100)).to.be.true;
+
+    const userG5 = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const before = (await getAccount(provider.connection, userG5)).amount;
+    await program.methods
+      .swap(true, new anchor.BN(amountIn.toString()), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial(accounts)
+      .rpc();
+    expect(((await getAccount(provider.connection, userG5)).amount - before).toString()).to.equal(quote.toString());
+  });
+
+  it("fails to initialize a stable pool with an amplification out of range!", async () => {
+    for (const amp of [0, 10_001]) {
+      try {
+        await emptyPool(90, 0, { stable: { amp: new anchor.BN(amp) } });
+        expect.fail("Initialize should have failed for amp " + amp);
+      } catch (e) {
+        expect(e).to.have.property("logs");
+        expect(e.logs.join("\n")).to.include("Stable swap amplification is out of range.");
+      }
+    }
+  });
+
+  it("Prices an 80/20 weighted pool by its weights!", async () => {
+    const poolFee = 95;
+    const pool = await emptyPool(poolFee, 0, { weighted: { weightXBps: 8_000 } });
+    expect((await program.account.config.fetch(pool.config)).curve.weighted.weightXBps).to.equal(8_000);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    // at a 1 : 1 price, x holds 80% of the value
+    const reserveX = BigInt(800 * 1_000_000);
+    const reserveY = BigInt(200 * 1_000_000);
+    await program.methods
+      .deposit(new anchor.BN(1), new anchor.BN(reserveX.toString()), new anchor.BN(reserveY.toString()), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    // reserve_y * (1 - (reserve_x / (reserve_x + priced))^(80 / 20)), off by at most
+    // the rounding margin and float error
+    const amountIn = BigInt(10 * 1_000_000);
+    const priced = amountIn - (amountIn * BigInt(poolFee) + BigInt(9_999)) / BigInt(10_000);
+    const exact = Number(reserveY) * (1 - Math.pow(Number(reserveX) / Number(reserveX + priced), 4));
+
+    const quote = await program.methods
+      .quote(true, new anchor.BN(amountIn.toString()))
+      .accountsPartial({ mintX: Zoro, mintY: G5, mintLp: pool.lp, config: pool.config, tokenProgramX: TOKEN_PROGRAM_ID, tokenProgramY: TOKEN_PROGRAM_ID })
+      .view();
+    expect(quote.toNumber()).to.be.at.most(Math.ceil(exact));
+    expect(quote.toNumber()).to.be.at.least(Math.floor(exact) - 2);
+
+    const userG5 = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const before = (await getAccount(provider.connection, userG5)).amount;
+    await program.methods
+      .swap(true, new anchor.BN(amountIn.toString()), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial(accounts)
+      .rpc();
+    expect(((await getAccount(provider.connection, userG5)).amount - before).toString()).to.equal(quote.toString());
+
+    // joins and exits stay proportional to both reserves whatever the weights
+    const config = await program.account.config.fetch(pool.config);
+    const supply = BigInt((await getMint(provider.connection, pool.lp)).supply.toString());
+    const lp = supply / BigInt(10);
+    await program.methods
+      .deposit(new anchor.BN(lp.toString()), new anchor.BN(reserveX.toString()), new anchor.BN(reserveY.toString()), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+    const after = await program.account.config.fetch(pool.config);
+    const paidX = BigInt(after.reserveX.sub(config.reserveX).toString());
+    const ceil = (reserve: bigint) => (reserve * lp + supply - BigInt(1)) / supply;
+    expect(paidX.toString()).to.equal(ceil(BigInt(config.reserveX.toString())).toString());
+    expect(after.reserveY.sub(config.reserveY).toString()).to.equal(ceil(BigInt(config.reserveY.toString())).toString());
+  });
+
+  it("fails to initialize a weighted pool with a weight out of range!", async () => {
+    for (const weightXBps of [99, 9_901]) {
+      try {
+        await emptyPool(100, 0, { weighted: { weightXBps } });
+        expect.fail("Initialize should have failed for weight " + weightXBps);
+      } catch (e) {
+        expect(e).to.have.property("logs");
+        expect(e.logs.join("\n")).to.include("Weighted pool weight is out of range.");
+      }
+    }
+  });
+
+  it("fails seed verification for a pool passed with another pair's mint!", async () => {
+    const pool = await emptyPool(105);
+    const other = await createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6);
+    try {
+      await program.methods
+        .swap(true, new anchor.BN(1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+        .accountsPartial({
+          user: wallet.publicKey,
+          mintX: other,
+          mintY: G5,
+          mintLp: pool.lp,
+          config: pool.config,
+          ...tokenPrograms,
+        })
+        .rpc();
+      expect.fail("Swap should have failed for a mismatched mint");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("ConstraintSeeds");
+    }
+  });
+
+  it("Steps the fee by at most the maximum step, then waits out the cooldown!", async () => {
+    // nothing is deposited, so each change applies right away
+    const pool = await emptyPool(110);
+    const update = (fee: number) => program.methods
+      .scheduleUpdate({ fee: { fee } })
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+
+    // a first change has no cooldown to wait for, and a full step is allowed
+    await update(160);
+    const configAccount = await program.account.config.fetch(pool.config);
+    expect(configAccount.fee).to.equal(160);
+    const now = Math.floor(Date.now() / 1_000);
+    expect(configAccount.lastFeeUpdateTs.toNumber()).to.be.closeTo(now, 60);
+
+    // the next step is within bounds, but too soon after the last
+    try {
+      await update(150);
+      expect.fail("Fee update should have failed within the cooldown");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Fee was changed too recently.");
+    }
+    expect((await program.account.config.fetch(pool.config)).fee).to.equal(160);
+  });
+
+  it("fails to move the fee by more than the maximum step!", async () => {
+    const pool = await emptyPool(115);
+    for (const fee of [166, 64]) {
+      try {
+        await program.methods
+          .scheduleUpdate({ fee: { fee } })
+          .accountsPartial({ user: wallet.publicKey, config: pool.config })
+          .rpc();
+        expect.fail("Fee update should have failed for a step to " + fee);
+      } catch (e) {
+        expect(e).to.have.property("logs");
+        expect(e.logs.join("\n")).to.include("Fee change is larger than the maximum step.");
+      }
+    }
+    expect((await program.account.config.fetch(pool.config)).fee).to.equal(115);
+  });
+
+  it("Swaps through another program by CPI and returns amount_out to it!", async () => {
+    const caller = anchor.workspace.ammCaller as Program<AmmCaller>;
+    const pool = await emptyPool(120);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    await program.methods
+      .deposit(new anchor.BN(1), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    // the caller only forwards the accounts, so every one is passed explicitly
+    const vaultAuthority = vaultAuthorityAddress(program.programId, pool.config);
+    const userG5 = await getAssociatedTokenAddress(G5, wallet.publicKey);
+    const before = (await getAccount(provider.connection, userG5)).amount;
+    const signature = await caller.methods
+      .swapThroughAmm(true, new anchor.BN(10 * 1_000_000), new anchor.BN(1))
+      .accountsPartial({
+        ...accounts,
+        vaultAuthority,
+        vaultX: await getAssociatedTokenAddress(Zoro, vaultAuthority, true),
+        vaultY: await getAssociatedTokenAddress(G5, vaultAuthority, true),
+        feeVaultX: feeVaultAddress(program.programId, pool.config, "x"),
+        feeVaultY: feeVaultAddress(program.programId, pool.config, "y"),
+        userX: await getAssociatedTokenAddress(Zoro, wallet.publicKey),
+        userY: userG5,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        protocol: protocolAddress(program.programId),
+        eventAuthority: eventAuthorityAddress(program.programId),
+        ammProgram: program.programId,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    // the caller's return data carries the amount_out the amm returned to it
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    expect(tx.meta.returnData.programId.toString()).to.equal(caller.programId.toString());
+    const amountOut = Buffer.from(tx.meta.returnData.data[0], "base64").readBigUInt64LE();
+    expect(amountOut > BigInt(0)).to.be.true;
+    const received = (await getAccount(provider.connection, userG5)).amount - before;
+    expect(received.toString()).to.equal(amountOut.toString());
+  });
+
+  it("Registers a pool under its pair and fee tier!", async () => {
+    const { config: poolConfig } = await emptyPool(125, 0, { stable: { amp: new anchor.BN(100) } });
+
+    // found from the mints alone, in either order
+    const registry = registryAddress(program.programId, G5, Zoro, 125);
+    let entry = await program.account.poolRegistryEntry.fetch(registry);
+    expect(entry.config.equals(poolConfig)).to.be.true;
+    expect(entry.curve).to.have.property("stable");
+
+    // a second pool of the pair and tier can't take the entry over
+    try {
+      await emptyPool(125);
+      expect.fail("Initialize should have failed for a registered pair and fee");
+    } catch (e) {
+      expect(e.message).to.include("already in use");
+    }
+    entry = await program.account.poolRegistryEntry.fetch(registry);
+    expect(entry.config.equals(poolConfig)).to.be.true;
+  });
+
+  it("Limits the volume sold into a pool per slot!", async () => {
+    const pool = await emptyPool(130);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    // applied right away on a pool without liquidity, y stays unlimited
+    await program.methods
+      .scheduleUpdate({ maxVolumePerSlot: { maxVolumePerSlotX: new anchor.BN(3_000_000), maxVolumePerSlotY: new anchor.BN(0) } })
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    const swapIx = (xToY: boolean, amount: number) => program.methods
+      .swap(xToY, new anchor.BN(amount), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial(accounts)
+      .instruction();
+    // swaps of one transaction always share a slot
+    const swapInOneSlot = async (xToY: boolean, amounts: number[]) => {
+      const tx = new Transaction();
+      for (const amount of amounts) tx.add(await swapIx(xToY, amount));
+      return provider.sendAndConfirm(tx);
+    };
+
+    await swapInOneSlot(true, [1_000_000, 2_000_000]);
+    try {
+      await swapInOneSlot(true, [2_000_000, 1_000_001]);
+      expect.fail("Swaps over the slot's limit should fail");
+    } catch (e) {
+      expect(e.logs.join("\n")).to.include("Swap volume of this slot is over the pool's limit.");
+    }
+    await swapInOneSlot(false, [5_000_000, 5_000_000]);
+
+    // the volume starts over with the next slot
+    const { currentSlot } = await program.account.config.fetch(pool.config);
+    while (await provider.connection.getSlot() <= currentSlot.toNumber()) {
+      await new Promise((resolve) => setTimeout(resolve, 100));
+    }
+    await swapInOneSlot(true, [3_000_000]);
+    const config = await program.account.config.fetch(pool.config);
+    expect(config.currentSlot.gt(currentSlot)).to.be.true;
+    expect(config.slotVolumeX.toNumber()).to.equal(3_000_000);
+  });
+
+  it("Trips the circuit breaker on a swap moving the price too far!", async () => {
+    const pool = await emptyPool(135);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    await program.methods
+      .scheduleUpdate({ circuitBreaker: { circuitBreakerBps: 100 } })
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+    const swap = (amount: number) => program.methods
+      .swap(true, new anchor.BN(amount), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial(accounts)
+      .rpc({ commitment: "confirmed" });
+    const userZoro = await getAssociatedTokenAddress(Zoro, wallet.publicKey);
+    const balance = async () => (await getAccount(provider.connection, userZoro, "confirmed")).amount;
+
+    // 5 tokens into 1000 with the 135 bps fee move the price 98 bps
+    await swap(5_000_000);
+
+    // 6 more would move it about 118, so nothing is traded and the breaker trips
+    const before = await balance();
+    const signature = await swap(6_000_000);
+    expect(await balance()).to.equal(before);
+    let configAccount = await program.account.config.fetch(pool.config, "confirmed");
+    expect(configAccount.breakerTripped).to.be.true;
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const tripped = cpiEvents(program, tx).find((event) => event.name === "circuitBreakerTripped");
+    expect(tripped.data.priceMoveBps.toNumber()).to.be.greaterThan(100);
+
+    // every swap is refused until the authority resets it
+    try {
+      await swap(1_000_000);
+      expect.fail("Swap should fail while the breaker is tripped");
+    } catch (e) {
+      expect(e.logs.join("\n")).to.include("Circuit breaker tripped, swaps are halted until it is reset.");
+    }
+    await program.methods
+      .resetBreaker()
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    await swap(1_000_000);
+    configAccount = await program.account.config.fetch(pool.config, "confirmed");
+    expect(configAccount.breakerTripped).to.be.false;
+  });
+
+
+  it("Records deposits and withdrawals with the LP supply after them!", async () => {
+    const pool = await emptyPool(140);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    const events = async (signature: string) => {
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return cpiEvents(program, tx);
+    };
+    const supply = async () => (await getMint(provider.connection, pool.lp, "confirmed")).supply.toString();
+
+    let signature = await program.methods
+      .deposit(new anchor.BN(1), new anchor.BN(100 * 1_000_000), new anchor.BN(100 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc({ commitment: "confirmed" });
+    const added = (await events(signature)).find((event) => event.name === "liquidityAdded");
+    expect(added.data.config.equals(pool.config)).to.be.true;
+    expect(added.data.user.equals(wallet.publicKey)).to.be.true;
+    expect(added.data.amountX.toNumber()).to.equal(100 * 1_000_000);
+    expect(added.data.amountY.toNumber()).to.equal(100 * 1_000_000);
+    // the locked MINIMUM_LIQUIDITY is in the supply but not in what the user got
+    expect(added.data.lpMinted.toNumber()).to.equal(100 * 1_000_000 - 1_000);
+    expect(added.data.lpSupplyAfter.toString()).to.equal(await supply());
+
+    signature = await program.methods
+      .withdraw(new anchor.BN(10 * 1_000_000), new anchor.BN(0), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc({ commitment: "confirmed" });
+    const removed = (await events(signature)).find((event) => event.name === "liquidityRemoved");
+    expect(removed.data.lpBurned.toNumber()).to.equal(10 * 1_000_000);
+    expect(removed.data.amountX.toNumber()).to.equal(10 * 1_000_000);
+    expect(removed.data.lpSupplyAfter.toString()).to.equal(await supply());
+    expect(removed.data.lpSupplyAfter.toNumber()).to.equal(added.data.lpSupplyAfter.toNumber() - 10 * 1_000_000);
+  });
+
+
+  it("Previews deposits and withdrawals exactly as they execute!", async () => {
+    const pool = await emptyPool(145);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    const previewAccounts = {
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      tokenProgramX: TOKEN_PROGRAM_ID,
+      tokenProgramY: TOKEN_PROGRAM_ID,
+    };
+    const balance = async (mint: PublicKey) =>
+      (await getAccount(provider.connection, await getAssociatedTokenAddress(mint, wallet.publicKey))).amount;
+    const lpBalance = async () => {
+      try {
+        return await balance(pool.lp);
+      } catch {
+        return BigInt(0);
+      }
+    };
+
+    // the first deposit, then one off the pool ratio that only part of y is used for
+    for (const [amountX, amountY] of [[40_000_000, 90_000_000], [7_000_003, 2_000_000]]) {
+      const preview = await program.methods
+        .previewDeposit(new anchor.BN(amountX), new anchor.BN(amountY))
+        .accountsPartial(previewAccounts)
+        .view();
+      const [x, y, lp] = [await balance(Zoro), await balance(G5), await lpBalance()];
+      await program.methods
+        .depositAmounts(new anchor.BN(amountX), new anchor.BN(amountY), new anchor.BN(0))
+        .accountsPartial(accounts)
+        .rpc();
+      expect((x - await balance(Zoro)).toString()).to.equal(preview.usedX.toString());
+      expect((y - await balance(G5)).toString()).to.equal(preview.usedY.toString());
+      expect((await lpBalance() - lp).toString()).to.equal(preview.lpOut.toString());
+    }
+
+    const lpAmount = new anchor.BN(12_345_678);
+    const preview = await program.methods.previewWithdraw(lpAmount).accountsPartial(previewAccounts).view();
+    const [x, y] = [await balance(Zoro), await balance(G5)];
+    await program.methods
+      .withdraw(lpAmount, new anchor.BN(0), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+    expect((await balance(Zoro) - x).toString()).to.equal(preview.xOut.toString());
+    expect((await balance(G5) - y).toString()).to.equal(preview.yOut.toString());
+  });
+
+
+  it("Swaps at a zero fee for wallets on the fee exemption list!", async () => {
+    const pool = await emptyPool(150);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    const [feeExemptions] = PublicKey.findProgramAddressSync([
+      Buffer.from("fee_exemptions"),
+      pool.config.toBuffer(),
+    ],
+      program.programId,
+    );
+    await program.methods
+      .deposit(new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+    await program.methods
+      .createFeeExemptions()
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    await program.methods
+      .addFeeExemption(wallet.publicKey)
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    expect((await program.account.config.fetch(pool.config)).feeExemptions.equals(feeExemptions)).to.be.true;
+
+    const amountIn = BigInt(10 * 1_000_000);
+    // get_amount_out of math.rs, the fee rounded up
+    const amountOut = async (feeBps: bigint) => {
+      const { reserveX, reserveY } = await program.account.config.fetch(pool.config, "confirmed");
+      const priced = amountIn - (amountIn * feeBps + BigInt(9_999)) / BigInt(10_000);
+      return BigInt(reserveY.toString()) * priced / (BigInt(reserveX.toString()) + priced);
+    };
+    const swap = (exempt: boolean, minOut = BigInt(1)) => program.methods
+      .swap(true, new anchor.BN(amountIn.toString()), new anchor.BN(minOut.toString()), 0, new anchor.BN(0), 0)
+      .accountsPartial({ ...accounts, feeExemptions: exempt ? feeExemptions : null })
+      .rpc({ commitment: "confirmed" });
+    const event = async (signature: string) => {
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return cpiEvents(program, tx).find((event) => event.name === "swapEvent").data;
+    };
+
+    // from the same reserves the exempt trade gets the zero fee output, more than
+    // the pool fee would leave
+    const [free, charged] = [await amountOut(BigInt(0)), await amountOut(BigInt(150))];
+    expect(free > charged).to.be.true;
+    // and is still held to its minimum
+    try {
+      await swap(true, free + BigInt(1));
+      expect.fail("Exempt swap should still respect slippage");
+    } catch (e) {
+      expect(e.logs.join("\n")).to.include("Slippage exceeded.");
+    }
+    const exempt = await event(await swap(true));
+    expect(exempt.feeExempt).to.be.true;
+    expect(exempt.feeAmount.toNumber()).to.equal(0);
+    expect(exempt.amountOut.toString()).to.equal(free.toString());
+
+    // without the list the same wallet pays the fee
+    const expected = await amountOut(BigInt(150));
+    const paid = await event(await swap(false));
+    expect(paid.feeExempt).to.be.false;
+    expect(paid.feeAmount.toNumber()).to.be.greaterThan(0);
+    expect(paid.amountOut.toString()).to.equal(expected.toString());
+
+    await program.methods
+      .removeFeeExemption(wallet.publicKey)
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    const removed = await event(await swap(true));
+    expect(removed.feeExempt).to.be.false;
+    try {
+      await program.methods
+        .removeFeeExemption(wallet.publicKey)
+        .accountsPartial({ user: wallet.publicKey, config: pool.config })
+        .rpc();
+      expect.fail("Removing a wallet that is not exempt should fail");
+    } catch (e) {
+      expect(e.logs.join("\n")).to.include("Wallet is not fee exempt.");
+    }
+  });
+
+
+  it("Switches the protocol share of every pool from the global protocol state!", async () => {
+    const pool = await emptyPool(155);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    await program.methods
+      .scheduleUpdate({ protocolFee: { protocolFeeBps: 2_000 } })
+      .accountsPartial({ user: wallet.publicKey, config: pool.config })
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    const amountIn = BigInt(100 * 1_000_000);
+    // the input fee is rounded up, the protocol share of it down
+    const fee = (amountIn * BigInt(155) + BigInt(9_999)) / BigInt(10_000);
+    const feeVault = feeVaultAddress(program.programId, pool.config, "x");
+    const swapShare = async () => {
+      const before = (await getAccount(provider.connection, feeVault)).amount;
+      await program.methods
+        .swap(true, new anchor.BN(amountIn.toString()), new anchor.BN(1), 0, new anchor.BN(0), 0)
+        .accountsPartial(accounts)
+        .rpc();
+      return (await getAccount(provider.connection, feeVault)).amount - before;
+    };
+    const setProtocolFee = (enabled: boolean, bps: number, authority = wallet.payer) => program.methods
+      .setProtocolFee(enabled, bps)
+      .accountsPartial({ authority: authority.publicKey })
+      .signers([authority])
+      .rpc();
+
+    // the pool's 2_000 bps while the global rate is uncapped
+    expect(await swapShare()).to.equal(fee * BigInt(2_000) / BigInt(10_000));
+
+    // switched off, the same pool routes nothing from its very next swap
+    await setProtocolFee(false, 10_000);
+    expect(await swapShare()).to.equal(BigInt(0));
+
+    // back on at a global rate below the pool's, which then only caps it
+    await setProtocolFee(true, 500);
+    expect(await swapShare()).to.equal(fee * BigInt(500) / BigInt(10_000));
+
+    try {
+      await setProtocolFee(false, 0, Keypair.generate());
+      expect.fail("Only the protocol authority should set the protocol fee");
+    } catch (e) {
+      expect(e.logs.join("\n")).to.include("Invalid protocol authority.");
+    }
+    try {
+      await program.methods
+        .initializeProtocol(false, 0)
+        .accountsPartial({ authority: wallet.publicKey, programData: programDataAddress(program.programId) })
+        .rpc();
+      expect.fail("The protocol state should only be created once");
+    } catch (e) {
+      expect(e.logs.join("\n")).to.include("already in use");
+    }
+
+    // leave the later suites with the pools' own shares
+    await setProtocolFee(true, 10_000);
+    const protocol = await program.account.protocolState.fetch(protocolAddress(program.programId));
+    expect(protocol.protocolFeeEnabled).to.be.true;
+    expect(protocol.authority.equals(wallet.publicKey)).to.be.true;
+  });
+
+  it("Hands the protocol over only once the proposed authority accepts!", async () => {
+    const protocolState = () => program.account.protocolState.fetch(protocolAddress(program.programId));
+    const propose = (authority: PublicKey, signer = wallet.payer) => program.methods
+      .proposeProtocolAuthority(authority)
+      .accountsPartial({ authority: signer.publicKey })
+      .signers([signer])
+      .rpc();
+    const accept = (signer: Keypair) => program.methods
+      .acceptProtocolAuthority()
+      .accountsPartial({ pendingAuthority: signer.publicKey })
+      .signers([signer])
+      .rpc();
+
+    const next = Keypair.generate();
+    await propose(next.publicKey);
+    expect((await protocolState()).authority.equals(wallet.publicKey)).to.be.true;
+    expect((await protocolState()).pendingAuthority.equals(next.publicKey)).to.be.true;
+
+    try {
+      await accept(Keypair.generate());
+      expect.fail("Only the proposed authority should accept");
+    } catch (e) {
+      expect(e.logs.join("\n")).to.include("Invalid protocol authority.");
+    }
+
+    await accept(next);
+    expect((await protocolState()).authority.equals(next.publicKey)).to.be.true;
+    expect((await protocolState()).pendingAuthority).to.be.null;
+
+    // and back, for the later suites
+    await propose(wallet.publicKey, next);
+    await accept(wallet.payer);
+    expect((await protocolState()).authority.equals(wallet.publicKey)).to.be.true;
+  });
+
+  it("Takes a caller price impact limit up to 10000 bps only!", async () => {
+    const pool = await emptyPool(160);
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: Zoro,
+      mintY: G5,
+      mintLp: pool.lp,
+      config: pool.config,
+      ...tokenPrograms,
+    };
+    await program.methods
+      .deposit(new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+
+    // 10_000 lets a swap sell up to the whole reserve
+    await program.methods
+      .swap(true, new anchor.BN(10 * 1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 10_000)
+      .accountsPartial(accounts)
+      .rpc();
+
+    try {
+      await program.methods
+        .swap(true, new anchor.BN(10 * 1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 10_001)
+        .accountsPartial(accounts)
+        .rpc();
+      expect.fail("Swap should have failed due to the price impact limit");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Price impact limit must be at most 10000 bps.");
+    }
+  });
+
+  it("Caps a timed lock at MAX_LOCK_DURATION!", async () => {
+    const maxLockDuration = 7 * 24 * 60 * 60;
+    const now = Math.floor(Date.now() / 1000);
+    try {
+      await program.methods
+        .setLock(new anchor.BN(now + maxLockDuration + 3_600))
+        .accountsPartial({ user: wallet.publicKey, config: config })
+        .rpc();
+      expect.fail("Lock should have failed due to its duration");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Lock must end in the future and within MAX_LOCK_DURATION.");
+    }
+    const configAccount = await program.account.config.fetch(config);
+    expect(configAccount.lockedUntil.toNumber()).to.equal(0);
+  });
+
+});
+
+describe("amm token-2022", () => {
+  const provider = anchor.AnchorProvider.env()
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.amm as Program<Amm>;
+
+  const wallet = provider.wallet
+
+  const fee = 30
+
+  const tokenPrograms = {
+    tokenProgram: TOKEN_2022_PROGRAM_ID,
+    tokenProgramX: TOKEN_2022_PROGRAM_ID,
+    tokenProgramY: TOKEN_2022_PROGRAM_ID,
+  };
+
+  let config: PublicKey;
+  let lp: PublicKey;
+
+  let mintX: PublicKey;
+  let mintY: PublicKey;
+  // the pool with a transfer fee on its x mint
+  let feeAccounts;
+
+  before(async () => {
+    const [keypairX, keypairY] = sortedMintKeypairs(2);
+    mintX = await createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6, keypairX, undefined, TOKEN_2022_PROGRAM_ID);
+    mintY = await createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6, keypairY, undefined, TOKEN_2022_PROGRAM_ID);
+
+    config = poolAddress(program.programId, mintX, mintY, fee);
+    [lp] = PublicKey.findProgramAddressSync([
+      Buffer.from("lp"),
+      config.toBuffer(),
+    ],
+      program.programId,
+    );
+
+    for (const mint of [mintX, mintY]) {
+      const ata = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, mint, wallet.publicKey, false, undefined, undefined, TOKEN_2022_PROGRAM_ID);
+      await mintTo(provider.connection, wallet.payer, mint, ata.address, wallet.payer, 1_000_000 * 1_000_000, [], undefined, TOKEN_2022_PROGRAM_ID);
+    }
+  });
+
+  it("Initializes, deposits and swaps a Token-2022 pool!", async () => {
+    await program.methods.initialize(initializeParams(fee, wallet.publicKey))
+      .accountsPartial({
+        initializer: wallet.publicKey,
+        mintX,
+        mintY,
+        mintLp: lp,
+        config: config,
+        ...tokenPrograms,
+        ...ataVaults(program.programId, { config, mintX, mintY, ...tokenPrograms }),
+      })
+      .rpc();
+
+    await program.methods
+      .deposit(new anchor.BN(10_000 * 1_000_000), new anchor.BN(10_000 * 1_000_000), new anchor.BN(10_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX,
+        mintY,
+        mintLp: lp,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    const userY = await getAssociatedTokenAddress(mintY, wallet.publicKey, false, TOKEN_2022_PROGRAM_ID);
+    const userYBefore = await getAccount(provider.connection, userY, undefined, TOKEN_2022_PROGRAM_ID);
+
+    await program.methods
+      .swap(true, new anchor.BN(100 * 1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX,
+        mintY,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    const userYAfter = await getAccount(provider.connection, userY, undefined, TOKEN_2022_PROGRAM_ID);
+    expect(userYAfter.amount > userYBefore.amount).to.be.true;
+
+    await program.methods
+      .withdraw(new anchor.BN(1_000 * 1_000_000), new anchor.BN(0), new anchor.BN(0))
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX,
+        mintY,
+        mintLp: lp,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+  });
+
+  it("Keeps the invariant with a 1% transfer fee mint!", async () => {
+    // 100 bps transfer fee, effectively uncapped. It must sort before mintY to
+    // be the x side of the pool
+    let feeMint = Keypair.generate();
+    while (Buffer.compare(feeMint.publicKey.toBuffer(), mintY.toBuffer()) >= 0) {
+      feeMint = Keypair.generate();
+    }
+    const feeConfig = poolAddress(program.programId, feeMint.publicKey, mintY, fee);
+    const [feeLp] = PublicKey.findProgramAddressSync([
+      Buffer.from("lp"),
+      feeConfig.toBuffer(),
+    ],
+      program.programId,
+    );
+
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    const lamports = await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: wallet.publicKey,
+          newAccountPubkey: feeMint.publicKey,
+          space: mintLen,
+          lamports,
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(feeMint.publicKey, wallet.publicKey, wallet.publicKey, 100, BigInt(1_000_000 * 1_000_000), TOKEN_2022_PROGRAM_ID),
+        createInitializeMintInstruction(feeMint.publicKey, 6, wallet.publicKey, null, TOKEN_2022_PROGRAM_ID),
+      ),
+      [wallet.payer, feeMint],
+    );
+    const feeAta = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, feeMint.publicKey, wallet.publicKey, false, undefined, undefined, TOKEN_2022_PROGRAM_ID);
+    await mintTo(provider.connection, wallet.payer, feeMint.publicKey, feeAta.address, wallet.payer, 1_000_000 * 1_000_000, [], undefined, TOKEN_2022_PROGRAM_ID);
+
+    const accounts = {
+      mintX: feeMint.publicKey,
+      mintY: mintY,
+      mintLp: feeLp,
+      config: feeConfig,
+      ...tokenPrograms,
+    };
+    feeAccounts = accounts;
+
+    await program.methods.initialize(initializeParams(fee, wallet.publicKey))
+      .accountsPartial({ initializer: wallet.publicKey, ...accounts, ...ataVaults(program.programId, accounts) })
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(10_000 * 1_000_000), new anchor.BN(10_000 * 1_000_000), new anchor.BN(10_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+
+    const vaultX = await getAssociatedTokenAddress(feeMint.publicKey, vaultAuthorityAddress(program.programId, feeConfig), true, TOKEN_2022_PROGRAM_ID);
+    const vaultY = await getAssociatedTokenAddress(mintY, vaultAuthorityAddress(program.programId, feeConfig), true, TOKEN_2022_PROGRAM_ID);
+
+    for (let i = 0; i < 10; i++) {
+      const before = (await getAccount(provider.connection, vaultX, undefined, TOKEN_2022_PROGRAM_ID)).amount
+        * (await getAccount(provider.connection, vaultY, undefined, TOKEN_2022_PROGRAM_ID)).amount;
+
+      await program.methods
+        .swap(i % 2 == 0, new anchor.BN(100 * 1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+        .accountsPartial({ user: wallet.publicKey, ...accounts })
+        .rpc();
+
+      const after = (await getAccount(provider.connection, vaultX, undefined, TOKEN_2022_PROGRAM_ID)).amount
+        * (await getAccount(provider.connection, vaultY, undefined, TOKEN_2022_PROGRAM_ID)).amount;
+      expect(after >= before).to.be.true;
+    }
+  });
+
+  it("Mints LP only for what the vaults received and enforces min lp out!", async () => {
+    const amount = new anchor.BN(1_000 * 1_000_000);
+    const max = new anchor.BN(10_000 * 1_000_000);
+
+    // the 1% withheld on the x leg leaves the deposit short of `amount` LP
+    try {
+      await program.methods
+        .deposit(amount, max, max, amount)
+        .accountsPartial({ user: wallet.publicKey, ...feeAccounts })
+        .rpc();
+      expect.fail("Deposit should have failed below min lp out");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Slippage exceeded.");
+    }
+
+    const userLp = await getAssociatedTokenAddress(feeAccounts.mintLp, wallet.publicKey, false, TOKEN_2022_PROGRAM_ID);
+    const lpBefore = (await getAccount(provider.connection, userLp, undefined, TOKEN_2022_PROGRAM_ID)).amount;
+    await program.methods
+      .deposit(amount, max, max, new anchor.BN(0))
+      .accountsPartial({ user: wallet.publicKey, ...feeAccounts })
+      .rpc();
+    const minted = (await getAccount(provider.connection, userLp, undefined, TOKEN_2022_PROGRAM_ID)).amount - lpBefore;
+
+    expect(minted < BigInt(amount.toString())).to.be.true;
+    expect(minted > BigInt(amount.toString()) * BigInt(98) / BigInt(100)).to.be.true;
+  });
+
+  it("Harvests the transfer fees withheld in the vaults without touching the reserves!", async () => {
+    const vaultX = await getAssociatedTokenAddress(feeAccounts.mintX, vaultAuthorityAddress(program.programId, feeAccounts.config), true, TOKEN_2022_PROGRAM_ID);
+    const withheld = async () =>
+      getTransferFeeAmount(await getAccount(provider.connection, vaultX, undefined, TOKEN_2022_PROGRAM_ID)).withheldAmount;
+    const mintWithheld = async () =>
+      getTransferFeeConfig(await getMint(provider.connection, feeAccounts.mintX, undefined, TOKEN_2022_PROGRAM_ID)).withheldAmount;
+
+    // the swaps and deposits above left 1% of every x transfer into the vault withheld
+    const accrued = await withheld();
+    expect(accrued > BigInt(0)).to.be.true;
+    const mintBefore = await mintWithheld();
+    const vaultBefore = (await getAccount(provider.connection, vaultX, undefined, TOKEN_2022_PROGRAM_ID)).amount;
+    const configBefore = await program.account.config.fetch(feeAccounts.config);
+
+    // no signer needed, and the y mint without the extension is skipped
+    await program.methods
+      .harvestWithheldFees()
+      .accountsPartial(feeAccounts)
+      .rpc();
+
+    expect(await withheld()).to.equal(BigInt(0));
+    expect(await mintWithheld() - mintBefore >= accrued).to.be.true;
+    expect((await getAccount(provider.connection, vaultX, undefined, TOKEN_2022_PROGRAM_ID)).amount).to.equal(vaultBefore);
+    const configAfter = await program.account.config.fetch(feeAccounts.config);
+    expect(configAfter.reserveX.toString()).to.equal(configBefore.reserveX.toString());
+    expect(configAfter.reserveY.toString()).to.equal(configBefore.reserveY.toString());
+  });
+
+  // a Token-2022 mint sorting before mintY with a single extension set up by `init`
+  const extensionMint = async (extension: ExtensionType, init: (mint: PublicKey) => TransactionInstruction) => {
+    let mint = Keypair.generate();
+    while (Buffer.compare(mint.publicKey.toBuffer(), mintY.toBuffer()) >= 0) {
+      mint = Keypair.generate();
+    }
+    const mintLen = getMintLen([extension]);
+    const lamports = await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: wallet.publicKey,
+          newAccountPubkey: mint.publicKey,
+          space: mintLen,
+          lamports,
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        init(mint.publicKey),
+        createInitializeMintInstruction(mint.publicKey, 6, wallet.publicKey, null, TOKEN_2022_PROGRAM_ID),
+      ),
+      [wallet.payer, mint],
+    );
+    return mint.publicKey;
+  };
+
+  const initializeWith = (mint: PublicKey, allowUnsafeExtensions: boolean, overrides: object = {}) => program.methods
+    .initialize(initializeParams(fee, wallet.publicKey, { allowUnsafeExtensions, ...overrides }))
+    .accountsPartial({
+      initializer: wallet.publicKey,
+      mintX: mint,
+      mintY,
+      config: poolAddress(program.programId, mint, mintY, fee),
+      ...tokenPrograms,
+      ...ataVaults(program.programId, { config: poolAddress(program.programId, mint, mintY, fee), mintX: mint, mintY, ...tokenPrograms }),
+    })
+    .rpc();
+
+  it("Rejects transfer hook, permanent delegate and non-transferable mints!", async () => {
+    const mints = [
+      await extensionMint(ExtensionType.TransferHook, (mint) =>
+        createInitializeTransferHookInstruction(mint, wallet.publicKey, Keypair.generate().publicKey, TOKEN_2022_PROGRAM_ID)),
+      await extensionMint(ExtensionType.PermanentDelegate, (mint) =>
+        createInitializePermanentDelegateInstruction(mint, wallet.publicKey, TOKEN_2022_PROGRAM_ID)),
+      await extensionMint(ExtensionType.NonTransferable, (mint) =>
+        createInitializeNonTransferableMintInstruction(mint, TOKEN_2022_PROGRAM_ID)),
+    ];
+    for (const mint of mints) {
+      try {
+        await initializeWith(mint, false);
+        expect.fail("Pool over an unsafe mint should not initialize");
+      } catch (e) {
+        expect(e).to.have.property("logs");
+        expect(e.logs.join("\n")).to.include("Mint has an extension the pool does not support.");
+      }
+    }
+
+    // the creator can accept a hook or a permanent delegate, never a mint that
+    // cannot leave the vaults
+    try {
+      await initializeWith(mints[2], true);
+      expect.fail("Non-transferable mints are never supported");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Mint has an extension the pool does not support.");
+    }
+    await initializeWith(mints[1], true);
+  });
+
+  it("Deposits, swaps, withdraws and collects fees of a transfer hook mint the creator allowed!", async () => {
+    // no hook program is set, so transfers need no extra accounts
+    const hookMint = await extensionMint(ExtensionType.TransferHook, (mint) =>
+      createInitializeTransferHookInstruction(mint, wallet.publicKey, PublicKey.default, TOKEN_2022_PROGRAM_ID));
+    const hookAta = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, hookMint, wallet.publicKey, false, undefined, undefined, TOKEN_2022_PROGRAM_ID);
+    await mintTo(provider.connection, wallet.payer, hookMint, hookAta.address, wallet.payer, 1_000_000 * 1_000_000, [], undefined, TOKEN_2022_PROGRAM_ID);
+
+    await initializeWith(hookMint, true, { protocolFeeBps: 2_000 });
+    const accounts = {
+      user: wallet.publicKey,
+      mintX: hookMint,
+      mintY,
+      config: poolAddress(program.programId, hookMint, mintY, fee),
+      ...tokenPrograms,
+    };
+    await program.methods
+      .deposit(new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+    for (const xToY of [true, false]) {
+      await program.methods
+        .swap(xToY, new anchor.BN(10 * 1_000_000), new anchor.BN(1), 0, new anchor.BN(0), 0)
+        .accountsPartial(accounts)
+        .rpc();
+    }
+    // the other paths moving the hook mint forward their remaining accounts the same way
+    await program.methods
+      .swapExactOut(false, new anchor.BN(5 * 1_000_000), new anchor.BN(10 * 1_000_000), new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+    await program.methods
+      .swapBatch([
+        { xToY: true, amountIn: new anchor.BN(5 * 1_000_000), minAmountOut: new anchor.BN(1) },
+        { xToY: false, amountIn: new anchor.BN(5 * 1_000_000), minAmountOut: new anchor.BN(1) },
+      ], new anchor.BN(0))
+      .accountsPartial(accounts)
+      .rpc();
+    await program.methods
+      .depositSingle(true, new anchor.BN(10 * 1_000_000), new anchor.BN(1))
+      .accountsPartial(accounts)
+      .rpc();
+    await program.methods
+      .withdraw(new anchor.BN(100 * 1_000_000), new anchor.BN(1), new anchor.BN(1))
+      .accountsPartial(accounts)
+      .rpc();
+    // the protocol's share leaves the fee vaults through the hook too
+    const feeVault = feeVaultAddress(program.programId, accounts.config, "x");
+    expect((await getAccount(provider.connection, feeVault, undefined, TOKEN_2022_PROGRAM_ID)).amount > BigInt(0)).to.be.true;
+    await program.methods
+      .collectProtocolFees()
+      .accountsPartial({ ...accounts, feeRecipient: wallet.publicKey })
+      .rpc();
+    expect((await getAccount(provider.connection, feeVault, undefined, TOKEN_2022_PROGRAM_ID)).amount).to.equal(BigInt(0));
+
+    const vault = await getAssociatedTokenAddress(hookMint, vaultAuthorityAddress(program.programId, accounts.config), true, TOKEN_2022_PROGRAM_ID);
+    const pool = await program.account.config.fetch(accounts.config);
+    expect((await getAccount(provider.connection, vault, undefined, TOKEN_2022_PROGRAM_ID)).amount.toString())
+      .to.equal(pool.reserveX.toString());
+  });
+
+  it("fails to close a pool with liquidity!", async () => {
+    try {
+      await program.methods
+        .closePool()
+        .accountsPartial({
+          user: wallet.publicKey,
+          recipient: wallet.publicKey,
+          mintX,
+          mintY,
+          mintLp: lp,
+          config: config,
+          ...tokenPrograms,
+        })
+        .rpc();
+      expect.fail("Close should have failed while liquidity remains");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Pool still has liquidity.");
+    }
+  });
+
+  it("Closes an empty pool and reclaims rent!", async () => {
+    const userLp = await getAssociatedTokenAddress(lp, wallet.publicKey, false, TOKEN_2022_PROGRAM_ID);
+    const userLpAccount = await getAccount(provider.connection, userLp, undefined, TOKEN_2022_PROGRAM_ID);
+
+    await program.methods
+      .withdraw(new anchor.BN(userLpAccount.amount.toString()), new anchor.BN(0), new anchor.BN(0))
+      .accountsPartial({
+        user: wallet.publicKey,
+        mintX,
+        mintY,
+        mintLp: lp,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    await program.methods
+      .closePool()
+      .accountsPartial({
+        user: wallet.publicKey,
+        recipient: wallet.publicKey,
+        mintX,
+        mintY,
+        mintLp: lp,
+        config: config,
+        ...tokenPrograms,
+      })
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(config)).to.be.null;
+    expect(await provider.connection.getAccountInfo(registryAddress(program.programId, mintX, mintY, fee))).to.be.null;
+    const vaultX = await getAssociatedTokenAddress(mintX, vaultAuthorityAddress(program.programId, config), true, TOKEN_2022_PROGRAM_ID);
+    expect(await provider.connection.getAccountInfo(vaultX)).to.be.null;
+  });
+
+});
+
+describe("amm native sol", () => {
+  const provider = anchor.AnchorProvider.env()
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.amm as Program<Amm>;
+
+  const wallet = provider.wallet
+
+  const fee = 30
+
+  const tokenPrograms = {
+    tokenProgram: TOKEN_PROGRAM_ID,
+    tokenProgramX: TOKEN_PROGRAM_ID,
+    tokenProgramY: TOKEN_PROGRAM_ID,
+  };
+
+  let config: PublicKey;
+  let lp: PublicKey;
+
+  let mintY: PublicKey;
+  let accounts;
+
+  const wrap = async (lamports: number) => {
+    const wsol = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, NATIVE_MINT, wallet.publicKey);
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(
+        SystemProgram.transfer({ fromPubkey: wallet.publicKey, toPubkey: wsol.address, lamports }),
+        createSyncNativeInstruction(wsol.address),
+      ),
+      [wallet.payer],
+    );
+    return wsol.address;
+  };
+
+  before(async () => {
+    // WSOL is the x side, so the other mint has to sort after it
+    let keypairY = Keypair.generate();
+    while (Buffer.compare(keypairY.publicKey.toBuffer(), NATIVE_MINT.toBuffer()) <= 0) {
+      keypairY = Keypair.generate();
+    }
+    mintY = await createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6, keypairY);
+    config = poolAddress(program.programId, NATIVE_MINT, mintY, fee);
+    [lp] = PublicKey.findProgramAddressSync([
+      Buffer.from("lp"),
+      config.toBuffer(),
+    ],
+      program.programId,
+    );
+    const ata = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, mintY, wallet.publicKey);
+    await mintTo(provider.connection, wallet.payer, mintY, ata.address, wallet.payer, 1_000_000 * 1_000_000);
+
+    accounts = {
+      mintX: NATIVE_MINT,
+      mintY,
+      mintLp: lp,
+      config: config,
+      ...tokenPrograms,
+    };
+
+    await program.methods.initialize(initializeParams(fee, wallet.publicKey))
+      .accountsPartial({ initializer: wallet.publicKey, ...accounts, ...ataVaults(program.programId, accounts) })
+      .rpc();
+
+    // seed the pool with 1 SOL and 1_000 Y, leaving the WSOL account empty
+    await wrap(1_000_000_000);
+    await program.methods
+      .deposit(new anchor.BN(1_000 * 1_000_000), new anchor.BN(1_000_000_000), new anchor.BN(1_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+  });
+
+  it("Swaps native SOL in and closes the WSOL account!", async () => {
+    const wsol = await getAssociatedTokenAddress(NATIVE_MINT, wallet.publicKey);
+
+    await program.methods
+      .swapSolIn(true, new anchor.BN(10_000_000), new anchor.BN(1))
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(wsol)).to.be.null;
+  });
+
+  it("Swaps into native SOL and unwraps the output!", async () => {
+    const wsol = await getAssociatedTokenAddress(NATIVE_MINT, wallet.publicKey);
+    const lamportsBefore = await provider.connection.getBalance(wallet.publicKey);
+
+    await program.methods
+      .swapSolOut(false, new anchor.BN(10 * 1_000_000), new anchor.BN(1))
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(wsol)).to.be.null;
+    expect(await provider.connection.getBalance(wallet.publicKey)).to.be.greaterThan(lamportsBefore);
+  });
+
+  it("Leaves a pre-existing WSOL balance untouched!", async () => {
+    const wsol = await wrap(5_000_000);
+
+    await program.methods
+      .swapSolIn(true, new anchor.BN(10_000_000), new anchor.BN(1))
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+
+    const wsolAfter = await getAccount(provider.connection, wsol);
+    expect(Number(wsolAfter.amount)).to.equal(5_000_000);
+  });
+
+});
+
+describe("amm multi-hop", () => {
+  const provider = anchor.AnchorProvider.env()
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.amm as Program<Amm>;
+
+  const wallet = provider.wallet
+
+  const fee = 30
+
+  const tokenPrograms = {
+    tokenProgram: TOKEN_PROGRAM_ID,
+    tokenProgramX: TOKEN_PROGRAM_ID,
+    tokenProgramY: TOKEN_PROGRAM_ID,
+  };
+
+  const pool = (mintX: PublicKey, mintY: PublicKey) => {
+    const config = poolAddress(program.programId, mintX, mintY, fee);
+    const [lp] = PublicKey.findProgramAddressSync([
+      Buffer.from("lp"),
+      config.toBuffer(),
+    ],
+      program.programId,
+    );
+    return { config, lp };
+  };
+
+  let poolAB;
+  let poolBC;
+
+  let mintA: PublicKey;
+  let mintB: PublicKey;
+  let mintC: PublicKey;
+
+  const createPool = async ({ config, lp }, mintX: PublicKey, mintY: PublicKey, params = initializeParams(fee, wallet.publicKey)) => {
+    const accounts = { mintX, mintY, mintLp: lp, config, ...tokenPrograms };
+    await program.methods.initialize(params)
+      .accountsPartial({ initializer: wallet.publicKey, ...accounts, ...ataVaults(program.programId, accounts) })
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(10_000 * 1_000_000), new anchor.BN(10_000 * 1_000_000), new anchor.BN(10_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+  };
+
+  const route = (mintIn: PublicKey, mintMid: PublicKey, mintOut: PublicKey, a, b) => ({
+    user: wallet.publicKey,
+    mintIn,
+    mintMid,
+    mintOut,
+    configA: a.config,
+    mintLpA: a.lp,
+    configB: b.config,
+    mintLpB: b.lp,
+    // the pools hold their reserves in the default ATA vaults
+    vaultAIn: getAssociatedTokenAddressSync(mintIn, vaultAuthorityAddress(program.programId, a.config), true),
+    vaultAMid: getAssociatedTokenAddressSync(mintMid, vaultAuthorityAddress(program.programId, a.config), true),
+    vaultBMid: getAssociatedTokenAddressSync(mintMid, vaultAuthorityAddress(program.programId, b.config), true),
+    vaultBOut: getAssociatedTokenAddressSync(mintOut, vaultAuthorityAddress(program.programId, b.config), true),
+    // both pools charge the fee on the input, so the fee vaults are those of mintIn/mintMid
+    feeVaultA: feeVaultAddress(program.programId, a.config, sortMints([mintIn, mintMid])[0].equals(mintIn) ? "x" : "y"),
+    feeVaultB: feeVaultAddress(program.programId, b.config, sortMints([mintMid, mintOut])[0].equals(mintMid) ? "x" : "y"),
+    tokenProgramIn: TOKEN_PROGRAM_ID,
+    tokenProgramMid: TOKEN_PROGRAM_ID,
+    tokenProgramOut: TOKEN_PROGRAM_ID,
+  });
+
+  before(async () => {
+    // sorted so that A < B < C, matching the x/y sides of both pools
+    [mintA, mintB, mintC] = await Promise.all(sortedMintKeypairs(3).map((keypair) =>
+      createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6, keypair)
+    ));
+    poolAB = pool(mintA, mintB);
+    poolBC = pool(mintB, mintC);
+    for (const mint of [mintA, mintB, mintC]) {
+      const ata = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, mint, wallet.publicKey);
+      await mintTo(provider.connection, wallet.payer, mint, ata.address, wallet.payer, 1_000_000 * 1_000_000);
+    }
+
+    await createPool(poolAB, mintA, mintB);
+    // B is the y side of the first pool and the x side of the second
+    await createPool(poolBC, mintB, mintC);
+  });
+
+  it("Routes A to C through B!", async () => {
+    const userB = await getAssociatedTokenAddress(mintB, wallet.publicKey);
+    const userC = await getAssociatedTokenAddress(mintC, wallet.publicKey);
+    const userBBefore = await getAccount(provider.connection, userB);
+    const userCBefore = await getAccount(provider.connection, userC);
+
+    await program.methods
+      .swapRoute(new anchor.BN(100 * 1_000_000), new anchor.BN(1))
+      .accountsPartial(route(mintA, mintB, mintC, poolAB, poolBC))
+      .rpc();
+
+    const userBAfter = await getAccount(provider.connection, userB);
+    const userCAfter = await getAccount(provider.connection, userC);
+    expect(userBAfter.amount).to.equal(userBBefore.amount);
+    expect(userCAfter.amount > userCBefore.amount).to.be.true;
+  });
+
+  it("Routes C to A through B!", async () => {
+    const userA = await getAssociatedTokenAddress(mintA, wallet.publicKey);
+    const userABefore = await getAccount(provider.connection, userA);
+
+    await program.methods
+      .swapRoute(new anchor.BN(100 * 1_000_000), new anchor.BN(1))
+      .accountsPartial(route(mintC, mintB, mintA, poolBC, poolAB))
+      .rpc();
+
+    const userAAfter = await getAccount(provider.connection, userA);
+    expect(userAAfter.amount > userABefore.amount).to.be.true;
+  });
+
+  it("Routes no protocol share while the protocol fee is switched off!", async () => {
+    // a fee tier of their own, so both pools can take a protocol share
+    const protocolFee = 35;
+    const tiered = (mintX: PublicKey, mintY: PublicKey) => {
+      const config = poolAddress(program.programId, mintX, mintY, protocolFee);
+      const [lp] = PublicKey.findProgramAddressSync([Buffer.from("lp"), config.toBuffer()], program.programId);
+      return { config, lp };
+    };
+    const [a, b] = [tiered(mintA, mintB), tiered(mintB, mintC)];
+    const params = initializeParams(protocolFee, wallet.publicKey, { protocolFeeBps: 2_000 });
+    await createPool(a, mintA, mintB, params);
+    await createPool(b, mintB, mintC, params);
+
+    const accounts = route(mintA, mintB, mintC, a, b);
+    const shares = () => Promise.all([accounts.feeVaultA, accounts.feeVaultB].map(async (vault) =>
+      (await getAccount(provider.connection, vault)).amount
+    ));
+    const swapRoute = (amount: number) => program.methods
+      .swapRoute(new anchor.BN(amount), new anchor.BN(1))
+      .accountsPartial(accounts)
+      .rpc();
+    const setProtocolFee = (enabled: boolean) => program.methods
+      .setProtocolFee(enabled, 10_000)
+      .accountsPartial({ authority: wallet.publicKey })
+      .rpc();
+
+    await swapRoute(100 * 1_000_000);
+    const [shareA, shareB] = await shares();
+    expect(shareA > BigInt(0)).to.be.true;
+    expect(shareB > BigInt(0)).to.be.true;
+
+    // neither hop routes anything once the protocol state switches the share off
+    await setProtocolFee(false);
+    await swapRoute(50 * 1_000_000);
+    expect(await shares()).to.deep.equal([shareA, shareB]);
+
+    await setProtocolFee(true);
+  });
+
+  it("fails a route below the final min amount out!", async () => {
+    try {
+      await program.methods
+        .swapRoute(new anchor.BN(100 * 1_000_000), new anchor.BN(100 * 1_000_000))
+        .accountsPartial(route(mintA, mintB, mintC, poolAB, poolBC))
+        .rpc();
+      expect.fail("Route should have failed due to slippage");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Slippage exceeded.");
+    }
+  });
+
+});
+
+describe("amm minimum liquidity", () => {
+  const provider = anchor.AnchorProvider.env()
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.amm as Program<Amm>;
+
+  const wallet = provider.wallet
+
+  const fee = 30
+
+  const tokenPrograms = {
+    tokenProgram: TOKEN_PROGRAM_ID,
+    tokenProgramX: TOKEN_PROGRAM_ID,
+    tokenProgramY: TOKEN_PROGRAM_ID,
+  };
+
+  let mintX: PublicKey;
+  let mintY: PublicKey;
+  let config: PublicKey;
+  let lp: PublicKey;
+  let accounts;
+
+  before(async () => {
+    [mintX, mintY] = await Promise.all(sortedMintKeypairs(2).map((keypair) =>
+      createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6, keypair)
+    ));
+    for (const mint of [mintX, mintY]) {
+      const ata = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, mint, wallet.publicKey);
+      await mintTo(provider.connection, wallet.payer, mint, ata.address, wallet.payer, 1_000_000 * 1_000_000);
+    }
+
+    config = poolAddress(program.programId, mintX, mintY, fee);
+    [lp] = PublicKey.findProgramAddressSync([
+      Buffer.from("lp"),
+      config.toBuffer(),
+    ],
+      program.programId,
+    );
+    accounts = { mintX, mintY, mintLp: lp, config, ...tokenPrograms };
+
+    await program.methods.initialize(initializeParams(fee, wallet.publicKey))
+      .accountsPartial({ initializer: wallet.publicKey, ...accounts, ...ataVaults(program.programId, accounts) })
+      .rpc();
+  });
+
+  it("fails a first deposit below the minimum liquidity!", async () => {
+    try {
+      await program.methods
+        .deposit(new anchor.BN(1), new anchor.BN(1_000), new anchor.BN(1_000), new anchor.BN(0))
+        .accountsPartial({ user: wallet.publicKey, ...accounts })
+        .rpc();
+      expect.fail("Deposit should have failed below the minimum liquidity");
+    } catch (e) {
+      expect(e).to.have.property("logs");
+      expect(e.logs.join("\n")).to.include("Actual liquidity is less than minimum.");
+    }
+  });
+
+  it("Locks the minimum liquidity so inflating the first deposit does not pay!", async () => {
+    const userX = await getAssociatedTokenAddress(mintX, wallet.publicKey);
+    const userY = await getAssociatedTokenAddress(mintY, wallet.publicKey);
+    const userLp = await getAssociatedTokenAddress(lp, wallet.publicKey);
+    const lockedLp = await getAssociatedTokenAddress(lp, vaultAuthorityAddress(program.programId, config), true);
+
+    // the attacker opens the pool as small as allowed
+    await program.methods
+      .deposit(new anchor.BN(1), new anchor.BN(2_000), new anchor.BN(2_000), new anchor.BN(0))
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+    expect(Number((await getAccount(provider.connection, lockedLp)).amount)).to.equal(1_000);
+    const attackerLp = (await getAccount(provider.connection, userLp)).amount;
+    expect(Number(attackerLp)).to.equal(1_000);
+
+    const xBefore = (await getAccount(provider.connection, userX)).amount;
+    const yBefore = (await getAccount(provider.connection, userY)).amount;
+
+    // then inflates the LP unit with a donation. Without the lock they would own the
+    // whole supply and get all of it back, with it half goes to the locked liquidity
+    const donation = 1_000 * 1_000_000;
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(
+        createTransferInstruction(userX, await getAssociatedTokenAddress(mintX, vaultAuthorityAddress(program.programId, config), true), wallet.publicKey, donation),
+        createTransferInstruction(userY, await getAssociatedTokenAddress(mintY, vaultAuthorityAddress(program.programId, config), true), wallet.publicKey, donation),
+      ),
+      [wallet.payer],
+    );
+    await program.methods
+      .sync()
+      .accountsPartial({ mintX, mintY, config, tokenProgramX: TOKEN_PROGRAM_ID, tokenProgramY: TOKEN_PROGRAM_ID })
+      .rpc();
+
+    await program.methods
+      .withdraw(new anchor.BN(attackerLp.toString()), new anchor.BN(0), new anchor.BN(0))
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+
+    const xLost = xBefore - (await getAccount(provider.connection, userX)).amount;
+    const yLost = yBefore - (await getAccount(provider.connection, userY)).amount;
+    expect(xLost >= BigInt(donation / 2)).to.be.true;
+    expect(yLost >= BigInt(donation / 2)).to.be.true;
+  });
+});
+
+describe("amm fee modes", () => {
+  const provider = anchor.AnchorProvider.env()
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.amm as Program<Amm>;
+
+  const wallet = provider.wallet
+
+  const fee = 30
+
+  const tokenPrograms = {
+    tokenProgram: TOKEN_PROGRAM_ID,
+    tokenProgramX: TOKEN_PROGRAM_ID,
+    tokenProgramY: TOKEN_PROGRAM_ID,
+  };
+
+  // same fee and liquidity, one pool per fee mode
+  let inputPool;
+  let outputPool;
+
+  const createPool = async (feeMode) => {
+    const [mintX, mintY] = await Promise.all(sortedMintKeypairs(2).map((keypair) =>
+      createMint(provider.connection, wallet.payer, wallet.publicKey, null, 6, keypair)
+    ));
+    for (const mint of [mintX, mintY]) {
+      const ata = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, mint, wallet.publicKey);
+      await mintTo(provider.connection, wallet.payer, mint, ata.address, wallet.payer, 1_000_000 * 1_000_000);
+    }
+
+    const config = poolAddress(program.programId, mintX, mintY, fee);
+    const [mintLp] = PublicKey.findProgramAddressSync([
+      Buffer.from("lp"),
+      config.toBuffer(),
+    ],
+      program.programId,
+    );
+    const accounts = { mintX, mintY, mintLp, config, ...tokenPrograms };
+    await program.methods.initialize(initializeParams(fee, wallet.publicKey, { feeMode }))
+      .accountsPartial({ initializer: wallet.publicKey, ...accounts, ...ataVaults(program.programId, accounts) })
+      .rpc();
+    // nothing deposited yet, so this applies right away
+    await program.methods
+      .scheduleUpdate({ protocolFee: { protocolFeeBps: 2_000 } })
+      .accountsPartial({ user: wallet.publicKey, config })
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(10_000 * 1_000_000), new anchor.BN(10_000 * 1_000_000), new anchor.BN(10_000 * 1_000_000), new anchor.BN(0))
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+    return accounts;
+  };
+
+  const reserves = async (accounts) => {
+    const pool = await program.account.config.fetch(accounts.config);
+    return { x: BigInt(pool.reserveX.toString()), y: BigInt(pool.reserveY.toString()) };
+  };
+
+  const swap = async (accounts, xToY: boolean, amountIn: number) => {
+    const userOut = await getAssociatedTokenAddress(xToY ? accounts.mintY : accounts.mintX, wallet.publicKey);
+    const before = (await getAccount(provider.connection, userOut)).amount;
+    await program.methods
+      .swap(xToY, new anchor.BN(amountIn), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+    return (await getAccount(provider.connection, userOut)).amount - before;
+  };
+
+  before(async () => {
+    inputPool = await createPool({ input: {} });
+    outputPool = await createPool({ output: {} });
+  });
+
+  it("Charges the fee in the output mint!", async () => {
+    const amountIn = 100 * 1_000_000;
+    const { x, y } = await reserves(outputPool);
+    const out = await swap(outputPool, true, amountIn);
+
+    // the whole input is priced, then the rounded-up fee comes off the output
+    const gross = y * BigInt(amountIn) / (x + BigInt(amountIn));
+    const feeAmount = (gross * BigInt(fee) + BigInt(9_999)) / BigInt(10_000);
+    expect(out.toString()).to.equal((gross - feeAmount).toString());
+
+    // so the protocol's share lands in the y fee vault
+    const feeVaultX = await getAccount(provider.connection, feeVaultAddress(program.programId, outputPool.config, "x"));
+    const feeVaultY = await getAccount(provider.connection, feeVaultAddress(program.programId, outputPool.config, "y"));
+    expect(Number(feeVaultX.amount)).to.equal(0);
+    expect(Number(feeVaultY.amount)).to.equal(Number(feeAmount * BigInt(2_000) / BigInt(10_000)));
+  });
+
+  it("Pays economically equivalent amounts in both modes and never lowers k!", async () => {
+    const trades: [boolean, number][] = [[false, 100], [true, 37], [false, 1], [true, 250], [false, 5], [true, 1_000]];
+    for (const [xToY, amountIn] of trades) {
+      const amount = amountIn * 1_000_000;
+
+      // each output as a share of what the spot price alone would pay
+      const shares = [];
+      for (const pool of [inputPool, outputPool]) {
+        const before = await reserves(pool);
+        const out = await swap(pool, xToY, amount);
+        const after = await reserves(pool);
+        expect(after.x * after.y >= before.x * before.y).to.be.true;
+
+        const [reserveIn, reserveOut] = xToY ? [before.x, before.y] : [before.y, before.x];
+        shares.push({ share: Number(out) * Number(reserveIn) / (Number(reserveOut) * amount), impact: amount / Number(reserveIn) });
+      }
+
+      // the fee on the input also shrinks the price impact, which is all that sets
+      // the two apart: at most the fee on the impact, plus rounding
+      const [input, output] = shares;
+      expect(input.share >= output.share - 1e-5).to.be.true;
+      expect(input.share - output.share).to.be.lessThan(fee / 10_000 * input.impact + 1e-5);
+    }
+  });
+
+  it("Swaps exactly out with the fee on the output!", async () => {
+    const userY = await getAssociatedTokenAddress(outputPool.mintY, wallet.publicKey);
+    const before = (await getAccount(provider.connection, userY)).amount;
+    const amountOut = new anchor.BN(50 * 1_000_000);
+
+    await program.methods
+      .swapExactOut(true, amountOut, new anchor.BN(100 * 1_000_000), new anchor.BN(0))
+      .accountsPartial({ user: wallet.publicKey, ...outputPool })
+      .rpc();
+
+    const received = (await getAccount(provider.connection, userY)).amount - before;
+    expect(received >= BigInt(amountOut.toString())).to.be.true;
+    expect(received <= BigInt(amountOut.toString()) + BigInt(1)).to.be.true;
+  });
+});
+
+describe("amm extreme reserves", () => {
+  const provider = anchor.AnchorProvider.env()
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.amm as Program<Amm>;
+
+  const wallet = provider.wallet
+
+  const fee = 30
+
+  const tokenPrograms = {
+    tokenProgram: TOKEN_PROGRAM_ID,
+    tokenProgramX: TOKEN_PROGRAM_ID,
+    tokenProgramY: TOKEN_PROGRAM_ID,
+  };
+
+  // 1 : 10^18, the widest ratio a u64 vault can hold
+  const reserveY = BigInt("1000000000000000000");
+
+  let accounts;
+
+  // mirrors Config::quote_swap for FeeMode::Input
+  const amountOut = (reserveIn: bigint, reserveOut: bigint, amountIn: bigint) => {
+    const feeAmount = (amountIn * BigInt(fee) + BigInt(9_999)) / BigInt(10_000);
+    const priced = amountIn - feeAmount;
+    return reserveOut * priced / (reserveIn + priced);
+  };
+
+  const reserves = async () => {
+    const pool = await program.account.config.fetch(accounts.config);
+    return { x: BigInt(pool.reserveX.toString()), y: BigInt(pool.reserveY.toString()) };
+  };
+
+  const swap = async (xToY: boolean, amountIn: bigint) => {
+    const userOut = await getAssociatedTokenAddress(xToY ? accounts.mintY : accounts.mintX, wallet.publicKey);
+    const before = (await getAccount(provider.connection, userOut)).amount;
+    await program.methods
+      .swap(xToY, new anchor.BN(amountIn.toString()), new anchor.BN(1), 0, new anchor.BN(0), 0)
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+    return (await getAccount(provider.connection, userOut)).amount - before;
+  };
+
+  before(async () => {
+    const [mintX, mintY] = await Promise.all(sortedMintKeypairs(2).map((keypair) =>
+      createMint(provider.connection, wallet.payer, wallet.publicKey, null, 9, keypair)
+    ));
+    for (const mint of [mintX, mintY]) {
+      const ata = await getOrCreateAssociatedTokenAccount(provider.connection, wallet.payer, mint, wallet.publicKey);
+      await mintTo(provider.connection, wallet.payer, mint, ata.address, wallet.payer, reserveY * BigInt(2));
+    }
+
+    const config = poolAddress(program.programId, mintX, mintY, fee);
+    const [mintLp] = PublicKey.findProgramAddressSync([
+      Buffer.from("lp"),
+      config.toBuffer(),
+    ],
+      program.programId,
+    );
+    accounts = { mintX, mintY, mintLp, config, ...tokenPrograms };
+    await program.methods.initialize(initializeParams(fee, wallet.publicKey))
+      .accountsPartial({ initializer: wallet.publicKey, ...accounts, ...ataVaults(program.programId, accounts) })
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(1), new anchor.BN(1), new anchor.BN(reserveY.toString()), new anchor.BN(0))
+      .accountsPartial({ user: wallet.publicKey, ...accounts })
+      .rpc();
+  });
+
+  it("Prices swaps exactly in both directions at a 1 : 10^18 ratio!", async () => {
+    let before = await reserves();
+    expect(before.x).to.equal(BigInt(1));
+    expect(before.y).to.equal(reserveY);
+
+    // the scarce side buys most of the deep side
+    const bought = await swap(true, BigInt(10));
+    expect(bought).to.equal(amountOut(before.x, before.y, BigInt(10)));
+    let after = await reserves();
+    expect(after.x * after.y >= before.x * before.y).to.be.true;
+
+    // and the deep side still buys a handful of the scarce one
+    before = after;
+    const sold = await swap(false, reserveY);
+    expect(sold).to.equal(amountOut(before.y, before.x, reserveY));
+    expect(sold > BigInt(0)).to.be.true;
+    after = await reserves();
+    expect(after.x * after.y >= before.x * before.y).to.be.true;
+  });
 });